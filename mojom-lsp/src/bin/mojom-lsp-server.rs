@@ -12,11 +12,139 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::net::{Ipv4Addr, SocketAddr};
+
+const USAGE: &str = "Usage: mojom-lsp-server [OPTIONS]
+
+A language server for Mojom IDL.
+
+Options:
+    --stdio            Communicate over stdin/stdout (default)
+    --socket <PORT>    Listen on 127.0.0.1:<PORT> and communicate over the
+                       first accepted connection
+    --version          Print version information and exit
+    --help             Print this message and exit";
+
+#[derive(Debug, PartialEq)]
+enum Transport {
+    Stdio,
+    Socket(u16),
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    // Runs the server. Unknown arguments are ignored with warnings.
+    RunServer(Transport, Vec<String>),
+    PrintVersion,
+    PrintHelp,
+    InvalidArgument(String),
+}
+
+fn parse_port(port: Option<&str>) -> Option<u16> {
+    port.and_then(|port| port.parse::<u16>().ok())
+}
+
+fn parse_args<I>(args: I) -> Action
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut transport = Transport::Stdio;
+    let mut unknown_args = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdio" => transport = Transport::Stdio,
+            "--socket" => match parse_port(args.next().as_deref()) {
+                Some(port) => transport = Transport::Socket(port),
+                None => return Action::InvalidArgument(arg),
+            },
+            "--version" | "-V" => return Action::PrintVersion,
+            "--help" | "-h" => return Action::PrintHelp,
+            _ if arg.starts_with("--socket=") => match parse_port(arg.strip_prefix("--socket=")) {
+                Some(port) => transport = Transport::Socket(port),
+                None => return Action::InvalidArgument(arg),
+            },
+            // Clients may pass arguments for other servers, e.g.
+            // `--clientProcessId=N`. Don't refuse to start.
+            _ => unknown_args.push(arg),
+        }
+    }
+    Action::RunServer(transport, unknown_args)
+}
+
 pub fn main() -> anyhow::Result<()> {
+    let (transport, unknown_args) = match parse_args(std::env::args().skip(1)) {
+        Action::RunServer(transport, unknown_args) => (transport, unknown_args),
+        Action::PrintVersion => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Action::PrintHelp => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Action::InvalidArgument(arg) => {
+            eprintln!("Invalid argument: {}\n\n{}", arg, USAGE);
+            std::process::exit(2);
+        }
+    };
+
     env_logger::init();
+    for arg in unknown_args {
+        log::warn!("Ignored unknown argument: {}", arg);
+    }
 
-    let stdin = std::io::stdin();
-    let stdout = std::io::stdout();
-    let exit_code = mojom_lsp::server::start(stdin, stdout)?;
+    let exit_code = match transport {
+        Transport::Stdio => {
+            let stdin = std::io::stdin();
+            let stdout = std::io::stdout();
+            mojom_lsp::server::start(stdin, stdout)?
+        }
+        Transport::Socket(port) => {
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+            mojom_lsp::server::start_tcp(addr)?
+        }
+    };
     std::process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Action {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(Action::RunServer(Transport::Stdio, vec![]), parse(&[]));
+        assert_eq!(
+            Action::RunServer(Transport::Stdio, vec![]),
+            parse(&["--stdio"])
+        );
+        assert_eq!(
+            Action::RunServer(Transport::Socket(8080), vec![]),
+            parse(&["--socket", "8080"])
+        );
+        assert_eq!(
+            Action::RunServer(Transport::Socket(8080), vec![]),
+            parse(&["--socket=8080"])
+        );
+        assert_eq!(
+            Action::InvalidArgument("--socket=http".to_owned()),
+            parse(&["--socket=http"])
+        );
+        assert_eq!(
+            Action::InvalidArgument("--socket".to_owned()),
+            parse(&["--socket", "http"])
+        );
+        assert_eq!(Action::PrintVersion, parse(&["--version"]));
+        assert_eq!(Action::PrintHelp, parse(&["--stdio", "--help"]));
+        assert_eq!(Action::PrintHelp, parse(&["--foo", "--help"]));
+        assert_eq!(
+            Action::RunServer(Transport::Stdio, vec!["--clientProcessId=42".to_owned()]),
+            parse(&["--stdio", "--clientProcessId=42"])
+        );
+    }
+}
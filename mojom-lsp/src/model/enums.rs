@@ -0,0 +1,121 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::syntax;
+
+/// An enum member with its evaluated value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumMember<'a> {
+    pub name: &'a str,
+    /// `None` when the value can't be evaluated, e.g. it refers to a constant
+    /// outside the enum.
+    pub value: Option<i64>,
+}
+
+fn parse_integer(value: &str) -> Option<i64> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => value.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Evaluates values of members of `stmt`. A member without an explicit value
+/// is the previous value plus one. Members can refer to preceding members of
+/// the same enum.
+pub fn evaluate_enum<'a>(text: &'a str, stmt: &syntax::Enum) -> Vec<EnumMember<'a>> {
+    let enum_name = text.get(stmt.name.start..stmt.name.end).unwrap_or("");
+    let mut members: Vec<EnumMember> = Vec::new();
+    for value in &stmt.values {
+        let name = text.get(value.name.start..value.name.end).unwrap_or("");
+        let evaluated = match value.value {
+            Some(ref range) => {
+                let value = text.get(range.start..range.end).unwrap_or("");
+                let member = value
+                    .strip_prefix(enum_name)
+                    .and_then(|rest| rest.strip_prefix('.'))
+                    .unwrap_or(value);
+                parse_integer(value).or_else(|| {
+                    members
+                        .iter()
+                        .find(|prev| prev.name == member)
+                        .and_then(|prev| prev.value)
+                })
+            }
+            None => match members.last() {
+                Some(prev) => prev.value.and_then(|value| value.checked_add(1)),
+                None => Some(0),
+            },
+        };
+        members.push(EnumMember {
+            name: name,
+            value: evaluated,
+        });
+    }
+    members
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(text: &str) -> Vec<(&str, Option<i64>)> {
+        let mojom = syntax::parse(text).unwrap();
+        let stmt = match mojom.stmts.into_iter().next() {
+            Some(syntax::Statement::Enum(stmt)) => stmt,
+            _ => unreachable!(),
+        };
+        evaluate_enum(text, &stmt)
+            .into_iter()
+            .map(|member| (member.name, member.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate_enum() {
+        let values = evaluate("enum E { kA, kB, kC = 10, kD, kE = -0x2, kF };");
+        assert_eq!(
+            vec![
+                ("kA", Some(0)),
+                ("kB", Some(1)),
+                ("kC", Some(10)),
+                ("kD", Some(11)),
+                ("kE", Some(-2)),
+                ("kF", Some(-1)),
+            ],
+            values
+        );
+
+        let values = evaluate("enum E { kA = 3, kB = kA, kC = E.kA, kD };");
+        assert_eq!(
+            vec![
+                ("kA", Some(3)),
+                ("kB", Some(3)),
+                ("kC", Some(3)),
+                ("kD", Some(4)),
+            ],
+            values
+        );
+
+        let values = evaluate("enum E { kA = kOther, kB };");
+        assert_eq!(vec![("kA", None), ("kB", None)], values);
+    }
+}
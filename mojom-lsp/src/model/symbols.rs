@@ -0,0 +1,377 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{Location, Position, Range, Url};
+
+use crate::syntax::{self, partial_text, preorder, MojomFile, Traversal};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Interface,
+    Struct,
+    Union,
+    Enum,
+    Const,
+}
+
+/// A declaration which can be referred by name.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    /// The name qualified by enclosing declarations, e.g. `MyInterface.MyEnum`.
+    /// Module names aren't included.
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The range of the declared name.
+    pub range: Range,
+}
+
+/// Symbols declared in a mojom file.
+#[derive(Debug, Clone)]
+pub struct FileSymbols {
+    pub uri: Url,
+    pub module: Option<String>,
+    pub symbols: Vec<Symbol>,
+}
+
+fn position(text: &str, offset: usize) -> Position {
+    let pos = syntax::line_col(text, offset);
+    Position::new(pos.line as u64, pos.col as u64)
+}
+
+fn lsp_range(text: &str, range: &syntax::Range) -> Range {
+    Range::new(position(text, range.start), position(text, range.end))
+}
+
+impl FileSymbols {
+    pub fn from_mojom(uri: Url, text: &str, mojom: &MojomFile) -> FileSymbols {
+        let mut module = None;
+        let mut symbols = Vec::new();
+        let mut path: Vec<&str> = Vec::new();
+        let mut add = |path: &Vec<&str>, name: &syntax::Range, kind: SymbolKind| {
+            let mut qualified = path.clone();
+            qualified.push(partial_text(text, name));
+            symbols.push(Symbol {
+                name: qualified.join("."),
+                kind: kind,
+                range: lsp_range(text, name),
+            });
+        };
+        for traversal in preorder(mojom) {
+            match traversal {
+                Traversal::Module(node) => {
+                    if module.is_none() {
+                        module = Some(partial_text(text, &node.name).to_owned());
+                    }
+                }
+                Traversal::EnterInterface(node) => {
+                    add(&path, &node.name, SymbolKind::Interface);
+                    path.push(partial_text(text, &node.name));
+                }
+                Traversal::LeaveInterface(_) => {
+                    path.pop();
+                }
+                Traversal::EnterStruct(node) => {
+                    add(&path, &node.name, SymbolKind::Struct);
+                    path.push(partial_text(text, &node.name));
+                }
+                Traversal::LeaveStruct(_) => {
+                    path.pop();
+                }
+                Traversal::Union(node) => add(&path, &node.name, SymbolKind::Union),
+                Traversal::Enum(node) => add(&path, &node.name, SymbolKind::Enum),
+                Traversal::Const(node) => add(&path, &node.name, SymbolKind::Const),
+                _ => (),
+            }
+        }
+        FileSymbols {
+            uri: uri,
+            module: module,
+            symbols: symbols,
+        }
+    }
+
+    /// Finds a symbol by its qualified name.
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+}
+
+/// A symbol with the file which declares it.
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolRef<'a> {
+    pub uri: &'a Url,
+    /// The module of the file.
+    pub module: Option<&'a str>,
+    pub symbol: &'a Symbol,
+}
+
+impl<'a> SymbolRef<'a> {
+    fn new(file: &'a FileSymbols, symbol: &'a Symbol) -> SymbolRef<'a> {
+        SymbolRef {
+            uri: &file.uri,
+            module: file.module.as_deref(),
+            symbol: symbol,
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        Location::new(self.uri.clone(), self.symbol.range)
+    }
+
+    /// The name qualified by the module, e.g. `foo.mojom.MyInterface`.
+    pub fn qualified_name(&self) -> String {
+        match self.module {
+            Some(module) => format!("{}.{}", module, self.symbol.name),
+            None => self.symbol.name.clone(),
+        }
+    }
+}
+
+/// Resolves names in a mojom file and its imports.
+#[derive(Debug)]
+pub struct SymbolTable {
+    // The first one is the file itself. Others are imported files.
+    files: Vec<FileSymbols>,
+}
+
+impl SymbolTable {
+    pub fn new<I>(file: FileSymbols, imports: I) -> SymbolTable
+    where
+        I: IntoIterator<Item = FileSymbols>,
+    {
+        let mut files = vec![file];
+        files.extend(imports);
+        SymbolTable { files: files }
+    }
+
+    fn find(&self, name: &str) -> Option<SymbolRef> {
+        self.files
+            .iter()
+            .find_map(|file| file.find(name).map(|symbol| SymbolRef::new(file, symbol)))
+    }
+
+    /// Looks up `name` as it appears in `scope`. `scope` is a list of
+    /// enclosing declaration names, outermost first. Inner scopes are searched
+    /// first, then the top level. `name` can also be qualified by a module
+    /// name.
+    pub fn lookup(&self, name: &str, scope: &[&str]) -> Option<SymbolRef> {
+        for depth in (0..=scope.len()).rev() {
+            let mut qualified = scope[..depth].to_vec();
+            qualified.push(name);
+            if let Some(found) = self.find(&qualified.join(".")) {
+                return Some(found);
+            }
+        }
+
+        for file in &self.files {
+            let module = match file.module {
+                Some(ref module) => module,
+                None => continue,
+            };
+            let rest = name
+                .strip_prefix(module.as_str())
+                .and_then(|rest| rest.strip_prefix('.'));
+            if let Some(symbol) = rest.and_then(|rest| file.find(rest)) {
+                return Some(SymbolRef::new(file, symbol));
+            }
+        }
+        None
+    }
+
+    /// Returns a table for `file` which sees the same files as this table.
+    /// Used to resolve names in one of the imported files.
+    pub fn with_file(&self, file: FileSymbols) -> SymbolTable {
+        let others: Vec<_> = self
+            .files
+            .iter()
+            .filter(|other| other.uri != file.uri)
+            .cloned()
+            .collect();
+        SymbolTable::new(file, others)
+    }
+
+    /// Returns URIs of all files in this table.
+    pub fn uris(&self) -> impl Iterator<Item = &Url> {
+        self.files.iter().map(|file| &file.uri)
+    }
+
+    /// Returns all symbols in this table, the file itself first.
+    pub fn symbols(&self) -> impl Iterator<Item = SymbolRef> {
+        self.files.iter().flat_map(|file| {
+            file.symbols
+                .iter()
+                .map(move |symbol| SymbolRef::new(file, symbol))
+        })
+    }
+
+    /// Returns top-level symbols declared in `uri`, which other files can
+    /// refer to once they import it. Nested declarations aren't included.
+    pub fn exports(&self, uri: &Url) -> Vec<SymbolRef> {
+        let file = match self.files.iter().find(|file| file.uri == *uri) {
+            Some(file) => file,
+            None => return Vec::new(),
+        };
+        file.symbols
+            .iter()
+            .filter(|symbol| !symbol.name.contains('.'))
+            .map(|symbol| SymbolRef::new(file, symbol))
+            .collect()
+    }
+}
+
+/// Returns names of declarations which enclose `offset`, outermost first.
+pub fn enclosing_scope<'a>(text: &'a str, mojom: &MojomFile, offset: usize) -> Vec<&'a str> {
+    let contains = |range: &syntax::Range| range.start <= offset && offset < range.end;
+    let mut scope = Vec::new();
+    for stmt in &mojom.stmts {
+        match stmt {
+            syntax::Statement::Interface(stmt) if contains(&stmt.range) => {
+                scope.push(partial_text(text, &stmt.name))
+            }
+            syntax::Statement::Struct(stmt) if contains(&stmt.range) => {
+                scope.push(partial_text(text, &stmt.name))
+            }
+            _ => (),
+        }
+    }
+    scope
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_symbols(uri: &str, text: &str) -> FileSymbols {
+        let uri = Url::parse(uri).unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        FileSymbols::from_mojom(uri, text, &mojom)
+    }
+
+    fn create_table() -> SymbolTable {
+        let local = file_symbols(
+            "file:///local.mojom",
+            "module foo.mojom;
+            const int32 kValue = 1;
+            struct Foo { enum Kind { kA }; };
+            interface Bar { enum Kind { kB }; const int32 kValue = 2; };",
+        );
+        let imported = file_symbols(
+            "file:///imported.mojom",
+            "module baz.mojom;
+            struct Baz { enum Kind { kC }; };
+            struct Foo {};",
+        );
+        SymbolTable::new(local, vec![imported])
+    }
+
+    #[test]
+    fn test_lookup_local() {
+        let table = create_table();
+        let found = table.lookup("kValue", &[]).unwrap();
+        assert_eq!("file:///local.mojom", found.uri.as_str());
+        assert_eq!("kValue", found.symbol.name);
+        assert_eq!(SymbolKind::Const, found.symbol.kind);
+        assert_eq!(Position::new(1, 24), found.symbol.range.start);
+
+        // Local declarations shadow imported ones.
+        let found = table.lookup("Foo", &[]).unwrap();
+        assert_eq!("file:///local.mojom", found.uri.as_str());
+
+        assert!(table.lookup("Kind", &[]).is_none());
+        assert!(table.lookup("Unknown", &[]).is_none());
+    }
+
+    #[test]
+    fn test_lookup_nested() {
+        let table = create_table();
+        let found = table.lookup("Kind", &["Foo"]).unwrap();
+        assert_eq!("Foo.Kind", found.symbol.name);
+        assert_eq!(SymbolKind::Enum, found.symbol.kind);
+
+        let found = table.lookup("kValue", &["Bar"]).unwrap();
+        assert_eq!("Bar.kValue", found.symbol.name);
+
+        // Falls back to outer scopes.
+        let found = table.lookup("Foo", &["Bar"]).unwrap();
+        assert_eq!("Foo", found.symbol.name);
+
+        let found = table.lookup("Foo.Kind", &["Bar"]).unwrap();
+        assert_eq!("Foo.Kind", found.symbol.name);
+    }
+
+    #[test]
+    fn test_lookup_imported() {
+        let table = create_table();
+        let found = table.lookup("Baz", &[]).unwrap();
+        assert_eq!("file:///imported.mojom", found.uri.as_str());
+        assert_eq!(SymbolKind::Struct, found.symbol.kind);
+
+        let found = table.lookup("Baz.Kind", &["Foo"]).unwrap();
+        assert_eq!("file:///imported.mojom", found.uri.as_str());
+    }
+
+    #[test]
+    fn test_lookup_qualified() {
+        let table = create_table();
+        let found = table.lookup("baz.mojom.Foo", &[]).unwrap();
+        assert_eq!("file:///imported.mojom", found.uri.as_str());
+
+        let found = table.lookup("foo.mojom.Bar.Kind", &[]).unwrap();
+        assert_eq!("file:///local.mojom", found.uri.as_str());
+        assert_eq!("Bar.Kind", found.symbol.name);
+
+        assert!(table.lookup("foo.mojom.Baz", &[]).is_none());
+    }
+
+    #[test]
+    fn test_enclosing_scope() {
+        let text = "struct Foo { int32 a; };\ninterface Bar { Baz(); };";
+        let mojom = syntax::parse(text).unwrap();
+        assert_eq!(vec!["Foo"], enclosing_scope(text, &mojom, 15));
+        assert_eq!(vec!["Bar"], enclosing_scope(text, &mojom, 42));
+        assert!(enclosing_scope(text, &mojom, 24).is_empty());
+    }
+
+    #[test]
+    fn test_exports() {
+        let imported = file_symbols(
+            "file:///imported.mojom",
+            "module baz.mojom;
+            interface Foo { enum Kind { kA }; };
+            interface Bar {};
+            struct Baz { const int32 kValue = 1; };",
+        );
+        let local = file_symbols("file:///local.mojom", "struct Local {};");
+        let table = SymbolTable::new(local, vec![imported]);
+
+        let uri = Url::parse("file:///imported.mojom").unwrap();
+        let names: Vec<_> = table
+            .exports(&uri)
+            .iter()
+            .map(|symbol| symbol.qualified_name())
+            .collect();
+        assert_eq!(
+            vec!["baz.mojom.Foo", "baz.mojom.Bar", "baz.mojom.Baz"],
+            names
+        );
+
+        let uri = Url::parse("file:///local.mojom").unwrap();
+        let exports = table.exports(&uri);
+        assert_eq!(1, exports.len());
+        assert_eq!("Local", exports[0].qualified_name());
+
+        let uri = Url::parse("file:///unknown.mojom").unwrap();
+        assert!(table.exports(&uri).is_empty());
+    }
+}
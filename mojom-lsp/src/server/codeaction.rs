@@ -0,0 +1,167 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use lsp_types::{CodeAction, Diagnostic, Range, TextEdit, Url as Uri};
+
+/// A fix for a diagnostic.
+pub(crate) struct Fix {
+    pub(crate) title: String,
+    pub(crate) edits: Vec<TextEdit>,
+}
+
+// Identifies a diagnostic by its code and range.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct DiagnosticKey {
+    code: Option<String>,
+    start: (u64, u64),
+    end: (u64, u64),
+}
+
+impl DiagnosticKey {
+    fn new(diagnostic: &Diagnostic) -> DiagnosticKey {
+        let code = diagnostic.code.as_ref().map(|code| match code {
+            lsp_types::NumberOrString::Number(code) => code.to_string(),
+            lsp_types::NumberOrString::String(code) => code.clone(),
+        });
+        let range = &diagnostic.range;
+        DiagnosticKey {
+            code: code,
+            start: (range.start.line, range.start.character),
+            end: (range.end.line, range.end.character),
+        }
+    }
+}
+
+/// Fixes which are available for diagnostics of a document. This is built
+/// while validating the document so that code action requests don't need to
+/// analyze the document again.
+#[derive(Default)]
+pub(crate) struct CodeActionIndex {
+    fixes: HashMap<DiagnosticKey, (Diagnostic, Vec<Fix>)>,
+    // Refactors which aren't associated with diagnostics, with the ranges
+    // where they are available.
+    refactors: Vec<(Range, Fix)>,
+}
+
+fn create_code_action(uri: &Uri, fix: &Fix, kind: lsp_types::CodeActionKind) -> CodeAction {
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), fix.edits.clone());
+    let edit = lsp_types::WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+    };
+    CodeAction {
+        title: fix.title.clone(),
+        kind: Some(kind),
+        diagnostics: None,
+        edit: Some(edit),
+        command: None,
+        is_preferred: None,
+    }
+}
+
+fn intersects(a: &Range, b: &Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+impl CodeActionIndex {
+    pub(crate) fn new() -> CodeActionIndex {
+        Default::default()
+    }
+
+    pub(crate) fn add(&mut self, diagnostic: &Diagnostic, fix: Fix) {
+        let key = DiagnosticKey::new(diagnostic);
+        self.fixes
+            .entry(key)
+            .or_insert_with(|| (diagnostic.clone(), Vec::new()))
+            .1
+            .push(fix);
+    }
+
+    /// Adds a refactor which is available in `range`.
+    pub(crate) fn add_refactor(&mut self, range: Range, fix: Fix) {
+        self.refactors.push((range, fix));
+    }
+
+    /// Returns quick fixes for diagnostics which intersect with `range`,
+    /// followed by refactors available in `range`.
+    pub(crate) fn code_actions(&self, uri: &Uri, range: &Range) -> Vec<CodeAction> {
+        let mut entries = self
+            .fixes
+            .values()
+            .filter(|(diagnostic, _)| intersects(&diagnostic.range, range))
+            .collect::<Vec<_>>();
+        // Make the order stable.
+        entries.sort_by_key(|(diagnostic, _)| diagnostic.range.start);
+
+        let mut actions = Vec::new();
+        for (diagnostic, fixes) in entries {
+            for fix in fixes {
+                let mut action = create_code_action(uri, fix, lsp_types::CodeActionKind::QUICKFIX);
+                action.diagnostics = Some(vec![diagnostic.clone()]);
+                action.is_preferred = Some(true);
+                actions.push(action);
+            }
+        }
+
+        for (refactor_range, fix) in &self.refactors {
+            if intersects(refactor_range, range) {
+                let kind = lsp_types::CodeActionKind::REFACTOR_EXTRACT;
+                actions.push(create_code_action(uri, fix, kind));
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Position;
+
+    fn create_diagnostic(line: u64) -> Diagnostic {
+        let range = Range::new(Position::new(line, 0), Position::new(line, 1));
+        super::super::diagnostic::create_diagnostic(range, "test".to_owned())
+    }
+
+    fn create_fix(line: u64) -> Fix {
+        let range = Range::new(Position::new(line, 0), Position::new(line, 1));
+        Fix {
+            title: format!("Fix line {}", line),
+            edits: vec![TextEdit::new(range, "  ".to_owned())],
+        }
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+        let mut index = CodeActionIndex::new();
+        index.add(&create_diagnostic(3), create_fix(3));
+        index.add(&create_diagnostic(1), create_fix(1));
+        index.add(&create_diagnostic(5), create_fix(5));
+
+        let range = Range::new(Position::new(0, 0), Position::new(4, 0));
+        let actions = index.code_actions(&uri, &range);
+        assert_eq!(2, actions.len());
+        assert_eq!("Fix line 1", actions[0].title);
+        assert_eq!("Fix line 3", actions[1].title);
+        assert_eq!(1, actions[0].diagnostics.as_ref().unwrap().len());
+
+        let range = Range::new(Position::new(6, 0), Position::new(7, 0));
+        assert!(index.code_actions(&uri, &range).is_empty());
+    }
+}
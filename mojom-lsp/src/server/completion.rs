@@ -0,0 +1,1197 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use lsp_types::{CompletionItem, CompletionItemKind, CompletionList, InsertTextFormat, Position};
+use serde_json::Value;
+
+use crate::model::{SymbolKind, SymbolRef, SymbolTable};
+use crate::syntax::{self, is_identifier_char, LexemeKind};
+
+use super::document::position_to_offset;
+use super::mojomast::MojomAst;
+use super::semantic_tokens::BUILTIN_TYPES;
+
+/// Characters which trigger completion in addition to identifier characters.
+pub(crate) const TRIGGER_CHARACTERS: &[&str] = &[".", "\"", "/"];
+
+const TOP_LEVEL_KEYWORDS: &[&str] = &[
+    "module",
+    "import",
+    "interface",
+    "struct",
+    "union",
+    "enum",
+    "const",
+];
+
+const BODY_KEYWORDS: &[&str] = &["enum", "const"];
+
+// Characters which can follow a type name, e.g. `Foo;`, `Foo?` or
+// `array<Foo>`. Typing one of them accepts the selected type.
+const TYPE_COMMIT_CHARACTERS: &[&str] = &[";", ",", "?", ">"];
+
+// Item fields which can be moved to `itemDefaults` of a list. Items don't
+// have `editRange` or `data` when they are sent.
+const ITEM_DEFAULT_FIELDS: &[&str] = &["commitCharacters", "insertTextFormat", "insertTextMode"];
+
+/// Records that a completion item was accepted. Clients execute it when an
+/// item which has it is selected. The argument is the name of the symbol.
+pub(crate) const COMPLETION_ACCEPTED_COMMAND: &str = "mojom.completionAccepted";
+
+// The maximum number of symbols whose usage counts are kept. The least used
+// symbol is forgotten when a new one is used.
+const MAX_TRACKED_USAGES: usize = 256;
+
+// Snippets for keywords which start declarations. Clients which support
+// `adjustIndentation` indent the body relative to the line of the keyword.
+const DECLARATION_SNIPPETS: &[(&str, &str)] = &[
+    ("interface", "interface ${1:Name} {\n  $0\n};"),
+    ("struct", "struct ${1:Name} {\n  $0\n};"),
+    ("union", "union ${1:Name} {\n  $0\n};"),
+    ("enum", "enum ${1:Name} {\n  $0\n};"),
+];
+
+// The maximum number of items returned at once. When there are more
+// candidates, they are filtered by the typed prefix and the list is marked
+// incomplete so that the client asks again as the prefix grows.
+const MAX_COMPLETION_ITEMS: usize = 100;
+
+// The response arrow which is offered after the parameter list of a method.
+// Snippets place the cursor inside the parentheses.
+const RESPONSE_ARROW: &str = "=> ()";
+
+// `InsertTextMode.adjustIndentation`, which was introduced in LSP 3.16.
+// lsp-types doesn't define it yet.
+const INSERT_TEXT_MODE_ADJUST_INDENTATION: u64 = 2;
+
+fn create_item(label: &str, kind: CompletionItemKind, detail: Option<String>) -> CompletionItem {
+    CompletionItem {
+        label: label.to_owned(),
+        kind: Some(kind),
+        detail: detail,
+        ..Default::default()
+    }
+}
+
+fn symbol_item_kind(kind: SymbolKind) -> CompletionItemKind {
+    match kind {
+        SymbolKind::Interface => CompletionItemKind::Interface,
+        SymbolKind::Struct | SymbolKind::Union => CompletionItemKind::Struct,
+        SymbolKind::Enum => CompletionItemKind::Enum,
+        SymbolKind::Const => CompletionItemKind::Constant,
+    }
+}
+
+// Creates an item for a declaration. lsp-types doesn't define
+// `labelDetails`, so the module is kept in `data` until to_json() turns it
+// into `labelDetails`.
+fn create_symbol_item(name: &str, symbol: &SymbolRef) -> CompletionItem {
+    let kind = symbol_item_kind(symbol.symbol.kind);
+    let mut item = create_item(name, kind, Some(symbol.qualified_name()));
+    item.data = symbol
+        .module
+        .map(|module| serde_json::json!({ "module": module }));
+    item
+}
+
+// Returns the partial path when `line` ends inside the path of an import
+// statement, e.g. `foo/b` for `import "foo/b`.
+fn partial_import_path(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("import")?;
+    let path = rest.trim_start().strip_prefix('"')?;
+    if path.contains('"') {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+// Returns directories and mojom files under the directory part of `partial`.
+// The root path is searched first, then `include_dirs`.
+fn complete_import_path(
+    root_path: &Path,
+    include_dirs: &[PathBuf],
+    partial: &str,
+) -> Vec<CompletionItem> {
+    let dir = match partial.rfind('/') {
+        Some(i) => &partial[..i],
+        None => "",
+    };
+    // Sort by name and remove duplicates found in multiple directories.
+    let mut entries = BTreeMap::new();
+    let bases = std::iter::once(root_path.to_owned()).chain(
+        include_dirs
+            .iter()
+            .map(|include_dir| root_path.join(include_dir)),
+    );
+    for base in bases {
+        let read_dir = match std::fs::read_dir(base.join(dir)) {
+            Ok(read_dir) => read_dir,
+            Err(_) => continue,
+        };
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if !name.starts_with('.') => name.to_owned(),
+                _ => continue,
+            };
+            if path.is_dir() {
+                entries.entry(name).or_insert(CompletionItemKind::Folder);
+            } else if name.ends_with(".mojom") {
+                entries.entry(name).or_insert(CompletionItemKind::File);
+            }
+        }
+    }
+    entries
+        .into_iter()
+        .map(|(name, kind)| create_item(&name, kind, None))
+        .collect()
+}
+
+// A block which is open at the cursor.
+struct OpenBlock<'a> {
+    // The word before the name of the declaration, e.g. `interface`.
+    keyword: &'a str,
+    // The name of the declaration when it's an interface or a struct.
+    name: Option<&'a str>,
+}
+
+// Returns blocks which are open at the end of `text`, outermost first.
+// Comments and string literals are skipped. This doesn't rely on the syntax
+// tree as the text is usually incomplete while typing.
+fn open_blocks(text: &str) -> Vec<OpenBlock> {
+    let mut blocks = Vec::new();
+    // The last two words, e.g. `struct` and `Foo`.
+    let mut words: (&str, &str) = ("", "");
+    for lexeme in syntax::lex(text) {
+        if lexeme.kind != LexemeKind::Code {
+            continue;
+        }
+        let code = &text[lexeme.range.start..lexeme.range.end];
+        let mut chars = code.char_indices().peekable();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '{' => {
+                    let name = match words {
+                        ("interface", name) | ("struct", name) => Some(name),
+                        _ => None,
+                    };
+                    blocks.push(OpenBlock {
+                        keyword: words.0,
+                        name: name,
+                    });
+                    words = ("", "");
+                }
+                '}' => {
+                    blocks.pop();
+                    words = ("", "");
+                }
+                ch if is_identifier_char(ch) => {
+                    while chars.next_if(|&(_, ch)| is_identifier_char(ch)).is_some() {}
+                    let end = chars.peek().map(|&(j, _)| j).unwrap_or(code.len());
+                    words = (words.1, &code[i..end]);
+                }
+                _ => (),
+            }
+        }
+    }
+    blocks
+}
+
+// Returns the part of the response arrow which is already typed when `code`
+// ends right after the parameter list of a method, e.g. `=` for
+// `Foo(int32 a) =`. Returns None after the parameter list of a response.
+fn typed_response_arrow(code: &str) -> Option<&str> {
+    let (code, typed) = match code.strip_suffix('=') {
+        Some(code) => (code, "="),
+        None => (code, ""),
+    };
+    let params_end = code.trim_end().strip_suffix(')')?;
+    // Find the opening parenthesis of the parameter list.
+    let mut depth = 0;
+    let params_start = params_end.rfind(|ch| match ch {
+        ')' => {
+            depth += 1;
+            false
+        }
+        '(' if depth == 0 => true,
+        '(' => {
+            depth -= 1;
+            false
+        }
+        _ => false,
+    })?;
+    // The method name may have an ordinal, e.g. `Foo@1(`.
+    let name = params_end[..params_start].trim_end();
+    let name = name.trim_end_matches(|ch: char| ch.is_ascii_digit());
+    let name = name.strip_suffix('@').unwrap_or(name);
+    if name.ends_with(is_identifier_char) {
+        Some(typed)
+    } else {
+        None
+    }
+}
+
+// Offers the response arrow after the parameter list of a method. Only the
+// part which isn't typed yet is inserted.
+fn complete_response_arrow(text: &str, offset: usize) -> Option<CompletionItem> {
+    let typed = typed_response_arrow(&text[..offset])?;
+    // The method already has a response.
+    if text[offset..].trim_start().starts_with("=>") {
+        return None;
+    }
+    let mut item = create_item(RESPONSE_ARROW, CompletionItemKind::Keyword, None);
+    item.insert_text = Some(RESPONSE_ARROW[typed.len()..].to_owned());
+    Some(item)
+}
+
+// Returns names of symbols as they can be written in `scope`. When
+// `qualifier` is given, returns the rest of the names which are qualified by
+// it, e.g. `Kind` for `Foo.Kind` when `qualifier` is `Foo`.
+fn complete_symbols(
+    symbols: &SymbolTable,
+    scope: &[&str],
+    qualifier: Option<&str>,
+) -> Vec<CompletionItem> {
+    // Inner declarations come first so they shadow outer ones.
+    let mut prefixes: Vec<String> = (0..=scope.len())
+        .rev()
+        .map(|depth| {
+            let mut prefix = scope[..depth].to_vec();
+            prefix.extend(qualifier);
+            prefix.join(".")
+        })
+        .collect();
+    if qualifier.is_some() {
+        prefixes.retain(|prefix| !prefix.is_empty());
+    }
+
+    let mut items = BTreeMap::new();
+    for prefix in &prefixes {
+        for symbol in symbols.symbols() {
+            let name = if prefix.is_empty() {
+                Some(symbol.symbol.name.as_str())
+            } else {
+                symbol
+                    .symbol
+                    .name
+                    .strip_prefix(prefix.as_str())
+                    .and_then(|rest| rest.strip_prefix('.'))
+            };
+            let name = match name {
+                Some(name) if !name.contains('.') => name,
+                _ => continue,
+            };
+            items
+                .entry(name.to_owned())
+                .or_insert_with(|| create_symbol_item(name, &symbol));
+        }
+    }
+    items.into_values().collect()
+}
+
+// Returns top-level declarations in the module `module`, and the next
+// segments of modules nested in it, e.g. `baz` for `foo.bar.baz` when
+// `module` is `foo.bar`.
+fn complete_module_path(symbols: &SymbolTable, module: &str) -> Vec<CompletionItem> {
+    let mut items = BTreeMap::new();
+    for symbol in symbols.symbols() {
+        let symbol_module = match symbol.module {
+            Some(symbol_module) => symbol_module,
+            None => continue,
+        };
+        if symbol_module == module {
+            let name = symbol.symbol.name.as_str();
+            if !name.contains('.') {
+                items
+                    .entry(name.to_owned())
+                    .or_insert_with(|| create_symbol_item(name, &symbol));
+            }
+            continue;
+        }
+        let nested = symbol_module
+            .strip_prefix(module)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.split('.').next());
+        if let Some(segment) = nested {
+            items.entry(segment.to_owned()).or_insert_with(|| {
+                let detail = format!("{}.{}", module, segment);
+                create_item(segment, CompletionItemKind::Module, Some(detail))
+            });
+        }
+    }
+    items.into_values().collect()
+}
+
+// Returns true when the characters of `prefix` appear in `label` in order.
+// Matching is case insensitive like filtering in clients.
+fn matches_prefix(label: &str, prefix: &str) -> bool {
+    let mut chars = label.chars().map(|ch| ch.to_ascii_lowercase());
+    prefix
+        .chars()
+        .all(|ch| chars.any(|c| c == ch.to_ascii_lowercase()))
+}
+
+// Filters `items` by `prefix` when there are too many of them. The result
+// is incomplete whenever it's filtered, as a shorter prefix would match
+// items which were dropped. Items ranked by usage are kept first.
+fn narrow(mut items: Vec<CompletionItem>, prefix: &str) -> CompletionList {
+    if items.len() <= MAX_COMPLETION_ITEMS {
+        return CompletionList {
+            is_incomplete: false,
+            items: items,
+        };
+    }
+    items.retain(|item| matches_prefix(&item.label, prefix));
+    // Only used items have `sort_text`. The sort is stable so the others stay
+    // in order.
+    items.sort_by(|a, b| {
+        (a.sort_text.is_none(), &a.sort_text).cmp(&(b.sort_text.is_none(), &b.sort_text))
+    });
+    items.truncate(MAX_COMPLETION_ITEMS);
+    CompletionList {
+        is_incomplete: true,
+        items: items,
+    }
+}
+
+/// Returns completion candidates at `pos`. `ast` may be a partially parsed
+/// document. `symbols` should contain symbols visible from the document.
+/// When `module_paths` is true, module names followed by a dot are completed
+/// with declarations in the module. Candidates are ranked by `usage`, and
+/// too many of them are narrowed by the identifier before `pos`.
+pub(crate) fn completion(
+    root_path: &Path,
+    include_dirs: &[PathBuf],
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+    module_paths: bool,
+    usage: &CompletionUsage,
+) -> CompletionList {
+    let text = &ast.text;
+    let offset = position_to_offset(text, pos);
+    let prefix_start = text[..offset]
+        .rfind(|ch: char| !is_identifier_char(ch))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let mut items = candidates(root_path, include_dirs, ast, symbols, offset, module_paths);
+    usage.rank(&mut items);
+    narrow(items, &text[prefix_start..offset])
+}
+
+fn candidates(
+    root_path: &Path,
+    include_dirs: &[PathBuf],
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    offset: usize,
+    module_paths: bool,
+) -> Vec<CompletionItem> {
+    let text = &ast.text;
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    // Only paths of imports are completed in comments and string literals.
+    match syntax::lexeme_kind_at(text, offset) {
+        LexemeKind::Code => (),
+        LexemeKind::String => {
+            return match partial_import_path(&text[line_start..offset]) {
+                Some(partial) => complete_import_path(root_path, include_dirs, partial),
+                None => Vec::new(),
+            };
+        }
+        LexemeKind::LineComment | LexemeKind::BlockComment => return Vec::new(),
+    }
+
+    let word_start = text[..offset]
+        .rfind(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let word = &text[word_start..offset];
+    let blocks = open_blocks(&text[..offset]);
+    let scope: Vec<&str> = blocks.iter().filter_map(|block| block.name).collect();
+    if let Some(i) = word.rfind('.') {
+        let qualifier = &word[..i];
+        let mut items = complete_symbols(symbols, &scope, Some(qualifier));
+        if module_paths {
+            for item in complete_module_path(symbols, qualifier) {
+                if !items.iter().any(|existing| existing.label == item.label) {
+                    items.push(item);
+                }
+            }
+        }
+        return items;
+    }
+
+    let in_interface = blocks
+        .last()
+        .is_some_and(|block| block.keyword == "interface");
+    if in_interface && word.is_empty() {
+        if let Some(item) = complete_response_arrow(text, offset) {
+            return vec![item];
+        }
+    }
+
+    if blocks.is_empty() {
+        return TOP_LEVEL_KEYWORDS
+            .iter()
+            .map(|keyword| create_item(keyword, CompletionItemKind::Keyword, None))
+            .collect();
+    }
+
+    let mut items: Vec<_> = BODY_KEYWORDS
+        .iter()
+        .chain(BUILTIN_TYPES)
+        .map(|keyword| create_item(keyword, CompletionItemKind::Keyword, None))
+        .collect();
+    items.extend(complete_symbols(symbols, &scope, None));
+    items
+}
+
+/// Turns keywords which start declarations and the response arrow into
+/// snippets. Only for clients which support snippets.
+pub(crate) fn expand_snippets(items: &mut [CompletionItem]) {
+    for item in items {
+        if item.kind != Some(CompletionItemKind::Keyword) {
+            continue;
+        }
+        if item.label == RESPONSE_ARROW {
+            let text = item.insert_text.take().unwrap_or_default();
+            item.insert_text = Some(text.replace("()", "($0)"));
+            item.insert_text_format = Some(InsertTextFormat::Snippet);
+            continue;
+        }
+        let snippet = DECLARATION_SNIPPETS
+            .iter()
+            .find(|(keyword, _)| *keyword == item.label);
+        if let Some((_, snippet)) = snippet {
+            item.insert_text = Some((*snippet).to_owned());
+            item.insert_text_format = Some(InsertTextFormat::Snippet);
+        }
+    }
+}
+
+/// Completion features which lsp-types doesn't know. These are read from the
+/// JSON of client capabilities.
+#[derive(Debug, Default)]
+pub(crate) struct ClientSupport {
+    /// Multi-line items can be inserted with the indentation of the current
+    /// line instead of column zero.
+    pub(crate) adjust_indentation: bool,
+    /// Items can have `labelDetails`.
+    pub(crate) label_details: bool,
+    /// Item fields which the list can provide in `itemDefaults`.
+    pub(crate) item_defaults: Vec<String>,
+}
+
+impl ClientSupport {
+    pub(crate) fn from_capabilities(capabilities: &Value) -> ClientSupport {
+        let item = &capabilities["textDocument"]["completion"]["completionItem"];
+        let modes = &item["insertTextModeSupport"]["valueSet"];
+        let adjust_indentation = modes.as_array().is_some_and(|modes| {
+            modes
+                .iter()
+                .any(|mode| mode.as_u64() == Some(INSERT_TEXT_MODE_ADJUST_INDENTATION))
+        });
+        let list = &capabilities["textDocument"]["completion"]["completionList"];
+        let item_defaults = list["itemDefaults"]
+            .as_array()
+            .map(|fields| {
+                fields
+                    .iter()
+                    .filter_map(|field| field.as_str().map(ToOwned::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default();
+        ClientSupport {
+            adjust_indentation: adjust_indentation,
+            label_details: item["labelDetailsSupport"].as_bool().unwrap_or(false),
+            item_defaults: item_defaults,
+        }
+    }
+}
+
+fn is_type_item(item: &CompletionItem) -> bool {
+    match item.kind {
+        Some(CompletionItemKind::Interface)
+        | Some(CompletionItemKind::Struct)
+        | Some(CompletionItemKind::Enum) => true,
+        Some(CompletionItemKind::Keyword) => BUILTIN_TYPES.contains(&item.label.as_str()),
+        _ => false,
+    }
+}
+
+/// Lets type names be accepted by typing a character which follows a type.
+/// Only for clients which support commit characters.
+pub(crate) fn add_commit_characters(items: &mut [CompletionItem]) {
+    for item in items {
+        if is_type_item(item) {
+            let characters = TYPE_COMMIT_CHARACTERS.iter().map(|ch| (*ch).to_owned());
+            item.commit_characters = Some(characters.collect());
+        }
+    }
+}
+
+// Returns the name which usages of the symbol of `item` are counted by. This
+// is the qualified name for declarations.
+fn usage_key(item: &CompletionItem) -> Option<String> {
+    match item.kind {
+        Some(CompletionItemKind::Constant) => item.detail.clone(),
+        _ if is_type_item(item) => item.detail.clone().or_else(|| Some(item.label.clone())),
+        _ => None,
+    }
+}
+
+/// Counts how many times symbols were completed in the session so that
+/// frequently used symbols are ranked first.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CompletionUsage {
+    counts: HashMap<String, u32>,
+}
+
+impl CompletionUsage {
+    pub(crate) fn record(&mut self, key: String) {
+        if !self.counts.contains_key(&key) && self.counts.len() >= MAX_TRACKED_USAGES {
+            let least_used = self
+                .counts
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(key, _)| key.clone());
+            if let Some(least_used) = least_used {
+                self.counts.remove(&least_used);
+            }
+        }
+        let count = self.counts.entry(key).or_insert(0);
+        *count = count.saturating_add(1);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counts.clear();
+    }
+
+    /// Attaches the command which records acceptance to symbol items, and
+    /// ranks used symbols before the others by their counts.
+    pub(crate) fn rank(&self, items: &mut [CompletionItem]) {
+        for item in items {
+            let key = match usage_key(item) {
+                Some(key) => key,
+                None => continue,
+            };
+            // Clients sort items without `sortText` by labels, which are
+            // identifiers and come after digits.
+            if let Some(count) = self.counts.get(&key) {
+                item.sort_text = Some(format!("0{:010}{}", u32::MAX - count, item.label));
+            }
+            item.command = Some(lsp_types::Command {
+                title: String::new(),
+                command: COMPLETION_ACCEPTED_COMMAND.to_owned(),
+                arguments: Some(vec![key.into()]),
+            });
+        }
+    }
+}
+
+/// Serializes `list` with features which `support` allows.
+pub(crate) fn to_json(list: CompletionList, support: &ClientSupport) -> Value {
+    let mut json = serde_json::to_value(&list).unwrap();
+    let json_items = json["items"].as_array_mut().unwrap();
+    for (item, json_item) in list.items.iter().zip(json_items) {
+        // `data` only carries the module for `labelDetails`.
+        let data = json_item
+            .as_object_mut()
+            .and_then(|item| item.remove("data"));
+        if let (Some(data), true) = (data, support.label_details) {
+            json_item["labelDetails"] = serde_json::json!({ "description": data["module"] });
+        }
+        let multi_line = item
+            .insert_text
+            .as_ref()
+            .is_some_and(|text| text.contains('\n'));
+        if multi_line && support.adjust_indentation {
+            json_item["insertTextMode"] = INSERT_TEXT_MODE_ADJUST_INDENTATION.into();
+        }
+    }
+    for field in ITEM_DEFAULT_FIELDS {
+        if support
+            .item_defaults
+            .iter()
+            .any(|supported| supported == field)
+        {
+            move_to_item_defaults(&mut json, field);
+        }
+    }
+    json
+}
+
+// The value an item needs so that the default of `field` doesn't apply to it
+// when it didn't have the field.
+fn item_default_fallback(field: &str) -> Value {
+    match field {
+        "commitCharacters" => Value::Array(Vec::new()),
+        // PlainText for `insertTextFormat` and asIs for `insertTextMode`.
+        _ => 1.into(),
+    }
+}
+
+// Moves the most common value of `field` to `itemDefaults` when it makes the
+// list smaller, i.e. more items drop the field than need a fallback.
+fn move_to_item_defaults(json: &mut Value, field: &str) {
+    let items = json["items"].as_array_mut().unwrap();
+    let mut counts: Vec<(&Value, usize)> = Vec::new();
+    for value in items.iter().filter_map(|item| item.get(field)) {
+        match counts.iter_mut().find(|(counted, _)| *counted == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    let (value, count) = match counts.into_iter().max_by_key(|(_, count)| *count) {
+        Some((value, count)) => (value.clone(), count),
+        None => return,
+    };
+    let missing = items
+        .iter()
+        .filter(|item| item.get(field).is_none())
+        .count();
+    if count <= missing {
+        return;
+    }
+    for item in items.iter_mut().filter_map(|item| item.as_object_mut()) {
+        match item.get(field) {
+            Some(item_value) if *item_value == value => {
+                item.remove(field);
+            }
+            Some(_) => (),
+            None => {
+                item.insert(field.to_owned(), item_default_fallback(field));
+            }
+        }
+    }
+    if !json["itemDefaults"].is_object() {
+        json["itemDefaults"] = serde_json::json!({});
+    }
+    json["itemDefaults"][field] = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::FileSymbols;
+    use crate::syntax;
+
+    fn complete(text: &str, pos: Position) -> Vec<CompletionItem> {
+        complete_list(text, pos).items
+    }
+
+    fn complete_list(text: &str, pos: Position) -> CompletionList {
+        complete_list_with_usage(text, pos, &CompletionUsage::default())
+    }
+
+    fn complete_list_with_usage(
+        text: &str,
+        pos: Position,
+        usage: &CompletionUsage,
+    ) -> CompletionList {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = super::super::document_symbol::parse_recoverable(text);
+        let ast = MojomAst::from_mojom(uri.clone(), text.to_owned(), mojom);
+        let imported = syntax::parse("module bar;\nstruct Imported {};").unwrap();
+        let imported = FileSymbols::from_mojom(
+            lsp_types::Url::parse("file:///imported.mojom").unwrap(),
+            "module bar;\nstruct Imported {};",
+            &imported,
+        );
+        let file = FileSymbols::from_mojom(uri, &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, vec![imported]);
+        completion(
+            Path::new("/nonexistent"),
+            &[],
+            &ast,
+            &symbols,
+            &pos,
+            true,
+            usage,
+        )
+    }
+
+    fn labels(items: &[CompletionItem]) -> Vec<&str> {
+        items.iter().map(|item| item.label.as_str()).collect()
+    }
+
+    fn find<'a>(items: &'a [CompletionItem], label: &str) -> Option<&'a CompletionItem> {
+        items.iter().find(|item| item.label == label)
+    }
+
+    #[test]
+    fn test_top_level_keywords() {
+        let items = complete("module foo;\n\n", Position::new(1, 0));
+        assert_eq!(TOP_LEVEL_KEYWORDS.to_vec(), labels(&items));
+        assert!(items
+            .iter()
+            .all(|item| item.kind == Some(CompletionItemKind::Keyword)));
+
+        // Braces in comments don't matter.
+        let items = complete("// }\n/* { */\nin", Position::new(2, 2));
+        assert_eq!(TOP_LEVEL_KEYWORDS.to_vec(), labels(&items));
+    }
+
+    #[test]
+    fn test_field_type() {
+        let text = "module foo;
+struct Foo { enum Kind { kA }; };
+interface Bar {};
+struct Baz {
+  F
+";
+        let items = complete(text, Position::new(4, 3));
+        let item = find(&items, "Foo").unwrap();
+        assert_eq!(Some(CompletionItemKind::Struct), item.kind);
+        assert_eq!(Some("foo.Foo"), item.detail.as_deref());
+        let item = find(&items, "Bar").unwrap();
+        assert_eq!(Some(CompletionItemKind::Interface), item.kind);
+        let item = find(&items, "Imported").unwrap();
+        assert_eq!(Some("bar.Imported"), item.detail.as_deref());
+        let item = find(&items, "int32").unwrap();
+        assert_eq!(Some(CompletionItemKind::Keyword), item.kind);
+        // Nested declarations are only visible with their qualifier.
+        assert!(find(&items, "Kind").is_none());
+        assert!(find(&items, "interface").is_none());
+
+        // Nested declarations are visible in the enclosing declaration.
+        let text = "struct Foo {\n  enum Kind { kA };\n  Kind kind;\n};";
+        let items = complete(text, Position::new(2, 3));
+        let item = find(&items, "Kind").unwrap();
+        assert_eq!(Some(CompletionItemKind::Enum), item.kind);
+    }
+
+    #[test]
+    fn test_scoped_names() {
+        let text = "struct Foo { enum Kind { kA }; const int32 kB = 1; };
+struct Bar {
+  Foo.
+";
+        let items = complete(text, Position::new(2, 6));
+        assert_eq!(vec!["Kind", "kB"], labels(&items));
+        assert_eq!(Some(CompletionItemKind::Constant), items[1].kind);
+    }
+
+    #[test]
+    fn test_module_paths() {
+        let imports = [
+            (
+                "file:///bar.mojom",
+                "module foo.bar;\nstruct Point { enum Kind {}; };\nenum Color {};",
+            ),
+            ("file:///baz.mojom", "module foo.bar.baz;\nstruct Deep {};"),
+            ("file:///qux.mojom", "module foo.qux;\ninterface Qux {};"),
+        ];
+        let imports: Vec<_> = imports
+            .iter()
+            .map(|(uri, text)| {
+                let mojom = syntax::parse(text).unwrap();
+                FileSymbols::from_mojom(lsp_types::Url::parse(uri).unwrap(), text, &mojom)
+            })
+            .collect();
+        let text = "module app;\nstruct App {\n  foo.bar.\n  foo.\n};";
+        let uri = lsp_types::Url::parse("file:///app.mojom").unwrap();
+        let mojom = super::super::document_symbol::parse_recoverable(text);
+        let ast = MojomAst::from_mojom(uri.clone(), text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(uri, &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, imports);
+        let complete = |pos: Position, module_paths: bool| {
+            completion(
+                Path::new("/nonexistent"),
+                &[],
+                &ast,
+                &symbols,
+                &pos,
+                module_paths,
+                &CompletionUsage::default(),
+            )
+            .items
+        };
+
+        let items = complete(Position::new(2, 10), true);
+        assert_eq!(vec!["Color", "Point", "baz"], labels(&items));
+        let item = find(&items, "Point").unwrap();
+        assert_eq!(Some(CompletionItemKind::Struct), item.kind);
+        assert_eq!(Some("foo.bar.Point"), item.detail.as_deref());
+        let item = find(&items, "baz").unwrap();
+        assert_eq!(Some(CompletionItemKind::Module), item.kind);
+        assert_eq!(Some("foo.bar.baz"), item.detail.as_deref());
+
+        let items = complete(Position::new(3, 6), true);
+        assert_eq!(vec!["bar", "qux"], labels(&items));
+
+        assert!(complete(Position::new(2, 10), false).is_empty());
+    }
+
+    #[test]
+    fn test_comments_and_strings() {
+        let text =
+            "module foo;\n// i\nstruct Foo {\n  /* i */ int32 a;\n  [Bar=\"i\"] int32 b;\n};";
+        // In the line comment.
+        assert!(complete(text, Position::new(1, 4)).is_empty());
+        // In the block comment.
+        assert!(complete(text, Position::new(3, 6)).is_empty());
+        // In the string literal.
+        assert!(complete(text, Position::new(4, 9)).is_empty());
+        // Right after the block comment.
+        assert!(!complete(text, Position::new(3, 9)).is_empty());
+    }
+
+    #[test]
+    fn test_import_path() {
+        let dir =
+            std::env::temp_dir().join(format!("mojom-lsp-test-completion-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("foo/bar")).unwrap();
+        std::fs::write(dir.join("foo/a.mojom"), "").unwrap();
+        std::fs::write(dir.join("foo/a.txt"), "").unwrap();
+
+        let uri = lsp_types::Url::from_file_path(dir.join("test.mojom")).unwrap();
+        let text = "import \"foo/";
+        let ast = MojomAst::from_mojom(
+            uri.clone(),
+            text.to_owned(),
+            syntax::MojomFile { stmts: Vec::new() },
+        );
+        let file = FileSymbols::from_mojom(uri, &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        let items = completion(
+            &dir,
+            &[],
+            &ast,
+            &symbols,
+            &Position::new(0, 12),
+            true,
+            &CompletionUsage::default(),
+        )
+        .items;
+        assert_eq!(vec!["a.mojom", "bar"], labels(&items));
+        assert_eq!(Some(CompletionItemKind::File), items[0].kind);
+        assert_eq!(Some(CompletionItemKind::Folder), items[1].kind);
+
+        // Not inside the path.
+        let text = "import \"foo/a.mojom\";";
+        let ast = MojomAst::from_mojom(ast.uri.clone(), text.to_owned(), ast.mojom);
+        let items = completion(
+            &dir,
+            &[],
+            &ast,
+            &symbols,
+            &Position::new(0, 21),
+            true,
+            &CompletionUsage::default(),
+        )
+        .items;
+        assert_eq!(TOP_LEVEL_KEYWORDS.to_vec(), labels(&items));
+    }
+
+    #[test]
+    fn test_response_arrow() {
+        let complete_at = |text: &str| {
+            let offset = text.find('|').unwrap();
+            let text = text.replace('|', "");
+            let line = text[..offset].matches('\n').count() as u64;
+            let col = offset - text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            complete(&text, Position::new(line, col as u64))
+        };
+
+        let mut items = complete_at("interface Foo {\n  Bar(int32 a, array<int32> b) |\n};");
+        assert_eq!(vec![RESPONSE_ARROW], labels(&items));
+        assert_eq!(Some("=> ()"), items[0].insert_text.as_deref());
+        expand_snippets(&mut items);
+        assert_eq!(Some("=> ($0)"), items[0].insert_text.as_deref());
+        assert_eq!(Some(InsertTextFormat::Snippet), items[0].insert_text_format);
+
+        // The typed part isn't inserted again.
+        let mut items = complete_at("interface Foo {\n  Bar@1(Baz.Kind k)=|\n};");
+        assert_eq!(Some("> ()"), items[0].insert_text.as_deref());
+        expand_snippets(&mut items);
+        assert_eq!(Some("> ($0)"), items[0].insert_text.as_deref());
+
+        // Not after a response, in a struct or when there's a response.
+        let items = complete_at("interface Foo {\n  Bar() => (bool ok) |\n};");
+        assert!(find(&items, RESPONSE_ARROW).is_none());
+        let items = complete_at("interface Foo {\n  Bar()| => (bool ok);\n};");
+        assert!(find(&items, RESPONSE_ARROW).is_none());
+        let items = complete_at("struct Foo {\n  int32 a |\n};");
+        assert!(find(&items, RESPONSE_ARROW).is_none());
+        let items = complete_at("interface Foo {\n  |\n};");
+        assert!(find(&items, RESPONSE_ARROW).is_none());
+        assert!(find(&items, "int32").is_some());
+    }
+
+    #[test]
+    fn test_commit_characters() {
+        let text = "module foo;\nstruct Foo { const int32 kA = 1; };\nstruct Baz {\n  \n";
+        let mut items = complete(text, Position::new(3, 2));
+        add_commit_characters(&mut items);
+        let expected = Some(vec![
+            ";".to_owned(),
+            ",".to_owned(),
+            "?".to_owned(),
+            ">".to_owned(),
+        ]);
+        assert_eq!(expected, find(&items, "Foo").unwrap().commit_characters);
+        assert_eq!(
+            expected,
+            find(&items, "Imported").unwrap().commit_characters
+        );
+        assert_eq!(expected, find(&items, "int32").unwrap().commit_characters);
+        // Not types.
+        assert_eq!(None, find(&items, "const").unwrap().commit_characters);
+
+        let mut items = complete(
+            text.replace("  \n", "  Foo.\n").as_str(),
+            Position::new(3, 6),
+        );
+        add_commit_characters(&mut items);
+        assert_eq!(vec!["kA"], labels(&items));
+        assert_eq!(None, items[0].commit_characters);
+    }
+
+    #[test]
+    fn test_completion_usage() {
+        let text = "module foo;\nstruct Alpha {};\nstruct Beta {};\nstruct Baz {\n  \n";
+        let pos = Position::new(4, 2);
+        let sort_key =
+            |item: &CompletionItem| item.sort_text.clone().unwrap_or_else(|| item.label.clone());
+        let mut usage = CompletionUsage::default();
+        let items = complete_list_with_usage(text, pos, &usage).items;
+        let alpha = find(&items, "Alpha").unwrap();
+        let beta = find(&items, "Beta").unwrap();
+        assert!(sort_key(alpha) < sort_key(beta));
+        let command = beta.command.as_ref().unwrap();
+        assert_eq!(COMPLETION_ACCEPTED_COMMAND, command.command);
+        assert_eq!(Some(vec![Value::from("foo.Beta")]), command.arguments);
+        let int32 = find(&items, "int32").unwrap();
+        let arguments = int32.command.as_ref().unwrap().arguments.clone();
+        assert_eq!(Some(vec![Value::from("int32")]), arguments);
+        assert!(find(&items, "const").unwrap().command.is_none());
+
+        // A used symbol outranks an unused one.
+        usage.record("foo.Beta".to_owned());
+        let items = complete_list_with_usage(text, pos, &usage).items;
+        let alpha = find(&items, "Alpha").unwrap();
+        let beta = find(&items, "Beta").unwrap();
+        assert!(sort_key(beta) < sort_key(alpha));
+        assert!(sort_key(beta) < sort_key(find(&items, "array").unwrap()));
+
+        // A more used symbol outranks a less used one.
+        usage.record("foo.Alpha".to_owned());
+        usage.record("foo.Alpha".to_owned());
+        let items = complete_list_with_usage(text, pos, &usage).items;
+        let alpha = find(&items, "Alpha").unwrap();
+        let beta = find(&items, "Beta").unwrap();
+        assert!(sort_key(alpha) < sort_key(beta));
+
+        usage.clear();
+        let items = complete_list_with_usage(text, pos, &usage).items;
+        assert!(items.iter().all(|item| item.sort_text.is_none()));
+    }
+
+    #[test]
+    fn test_completion_usage_bounded() {
+        let mut usage = CompletionUsage::default();
+        usage.record("used".to_owned());
+        usage.record("used".to_owned());
+        for i in 0..MAX_TRACKED_USAGES {
+            usage.record(format!("symbol{}", i));
+        }
+        assert_eq!(MAX_TRACKED_USAGES, usage.counts.len());
+        assert_eq!(Some(&2), usage.counts.get("used"));
+        let last = format!("symbol{}", MAX_TRACKED_USAGES - 1);
+        assert_eq!(Some(&1), usage.counts.get(&last));
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let mut text = (0..150)
+            .map(|i| format!("struct Item{} {{}};\n", i))
+            .collect::<String>();
+        text.push_str("struct Foo {\n  ");
+        let complete_after = |typed: &str| {
+            let text = format!("{}{}", text, typed);
+            complete_list(&text, Position::new(151, 2 + typed.len() as u64))
+        };
+
+        let list = complete_after("");
+        assert!(list.is_incomplete);
+        assert_eq!(MAX_COMPLETION_ITEMS, list.items.len());
+
+        // Typing more narrows the list.
+        let list = complete_after("Item1");
+        assert!(list.is_incomplete);
+        assert!(list.items.len() < MAX_COMPLETION_ITEMS);
+        assert!(find(&list.items, "Item149").is_some());
+        assert!(find(&list.items, "Item23").is_none());
+        let narrowed = complete_after("Item14");
+        assert!(narrowed.items.len() < list.items.len());
+        assert!(narrowed
+            .items
+            .iter()
+            .all(|item| matches_prefix(&item.label, "Item14")));
+        assert_eq!(vec!["Item149"], labels(&complete_after("Item149").items));
+
+        // Used items aren't dropped from the narrowed list.
+        let mut usage = CompletionUsage::default();
+        usage.record("foo.Item149".to_owned());
+        let text = format!("module foo;\n{}", text);
+        let list = complete_list_with_usage(&text, Position::new(152, 2), &usage);
+        assert!(list.is_incomplete);
+        assert_eq!(MAX_COMPLETION_ITEMS, list.items.len());
+        assert_eq!("Item149", list.items[0].label);
+
+        // Few candidates are left to the client.
+        let list = complete_list("struct Foo {\n  I", Position::new(1, 3));
+        assert!(!list.is_incomplete);
+        assert!(find(&list.items, "int32").is_some());
+    }
+
+    #[test]
+    fn test_label_details() {
+        let text = "module foo;\nstruct Foo {};\nstruct Baz {\n  F\n";
+        let capabilities = serde_json::json!({
+            "textDocument": {
+                "completion": { "completionItem": { "labelDetailsSupport": true } },
+            },
+        });
+        let support = ClientSupport::from_capabilities(&capabilities);
+        assert!(support.label_details);
+        let json = to_json(complete_list(text, Position::new(3, 3)), &support);
+        let find_json = |json: &Value, label: &str| {
+            json["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|item| item["label"] == label)
+                .cloned()
+                .unwrap()
+        };
+        // Types are described with their modules.
+        let item = find_json(&json, "Foo");
+        assert_eq!("foo", item["labelDetails"]["description"]);
+        let item = find_json(&json, "Imported");
+        assert_eq!("bar", item["labelDetails"]["description"]);
+        assert!(item.get("data").is_none());
+        assert!(find_json(&json, "int32").get("labelDetails").is_none());
+
+        let json = to_json(
+            complete_list(text, Position::new(3, 3)),
+            &ClientSupport::default(),
+        );
+        let item = find_json(&json, "Foo");
+        assert!(item.get("labelDetails").is_none());
+        assert!(item.get("data").is_none());
+    }
+
+    #[test]
+    fn test_item_defaults() {
+        let text = "module foo;\nstruct Foo {};\nstruct Baz {\n  \n";
+        let capabilities = serde_json::json!({
+            "textDocument": {
+                "completion": {
+                    "completionList": { "itemDefaults": ["commitCharacters", "editRange"] },
+                },
+            },
+        });
+        let support = ClientSupport::from_capabilities(&capabilities);
+        assert_eq!(vec!["commitCharacters", "editRange"], support.item_defaults);
+        let list = || {
+            let mut list = complete_list(text, Position::new(3, 2));
+            add_commit_characters(&mut list.items);
+            list
+        };
+        let find_json = |json: &Value, label: &str| {
+            json["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|item| item["label"] == label)
+                .cloned()
+                .unwrap()
+        };
+
+        // Types share commit characters in the defaults. Other items opt out.
+        let json = to_json(list(), &support);
+        let expected = serde_json::json!([";", ",", "?", ">"]);
+        assert_eq!(expected, json["itemDefaults"]["commitCharacters"]);
+        assert!(json["itemDefaults"].get("editRange").is_none());
+        assert!(find_json(&json, "Foo").get("commitCharacters").is_none());
+        assert!(find_json(&json, "int32").get("commitCharacters").is_none());
+        assert_eq!(
+            serde_json::json!([]),
+            find_json(&json, "const")["commitCharacters"]
+        );
+
+        // Without support, items have the fields inline.
+        let json = to_json(list(), &ClientSupport::default());
+        assert!(json.get("itemDefaults").is_none());
+        assert_eq!(expected, find_json(&json, "Foo")["commitCharacters"]);
+        assert!(find_json(&json, "const").get("commitCharacters").is_none());
+    }
+
+    #[test]
+    fn test_adjust_indentation() {
+        let text = "interface Foo {\n  e\n};";
+        let mut items = complete(text, Position::new(1, 3));
+        expand_snippets(&mut items);
+        let item = find(&items, "enum").unwrap();
+        assert_eq!(Some(InsertTextFormat::Snippet), item.insert_text_format);
+
+        let capabilities = serde_json::json!({
+            "textDocument": {
+                "completion": {
+                    "completionItem": { "insertTextModeSupport": { "valueSet": [1, 2] } },
+                },
+            },
+        });
+        let support = ClientSupport::from_capabilities(&capabilities);
+        assert!(support.adjust_indentation);
+        let no_support = ClientSupport::from_capabilities(&serde_json::json!({}));
+        assert!(!no_support.adjust_indentation);
+
+        let list = CompletionList {
+            is_incomplete: false,
+            items: items,
+        };
+        let json = to_json(list, &support);
+        let json_items = json["items"].as_array().unwrap();
+        let enum_item = json_items
+            .iter()
+            .find(|item| item["label"] == "enum")
+            .unwrap();
+        assert_eq!(
+            serde_json::json!(INSERT_TEXT_MODE_ADJUST_INDENTATION),
+            enum_item["insertTextMode"]
+        );
+        assert_eq!("enum ${1:Name} {\n  $0\n};", enum_item["insertText"]);
+        // Single-line items are inserted as they are.
+        let int32_item = json_items
+            .iter()
+            .find(|item| item["label"] == "int32")
+            .unwrap();
+        assert!(int32_item.get("insertTextMode").is_none());
+
+        let json = to_json(complete_list(text, Position::new(1, 3)), &no_support);
+        assert!(json["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|item| item.get("insertTextMode").is_none()));
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Project-local configuration files. A config file is a JSON object which has
+// the same keys as `initializationOptions`. It is discovered by walking up
+// from the directory of an opened document.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+pub(crate) const CONFIG_FILE_NAME: &str = "mojom-ls.json";
+
+/// Finds the nearest config file for `document`.
+pub(crate) fn find_config_file(document: &Path) -> Option<PathBuf> {
+    document
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+fn load_config(path: &Path) -> anyhow::Result<Value> {
+    let text = std::fs::read_to_string(path)?;
+    let mut config: Value = serde_json::from_str(&text)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let object = config
+        .as_object_mut()
+        .ok_or(anyhow!("Config must be a JSON object"))?;
+    // Relative include dirs are relative to the config file.
+    if let Some(Value::Array(dirs)) = object.get_mut("includeDirs") {
+        for entry in dirs.iter_mut() {
+            if let Value::String(dir_path) = entry {
+                let resolved = dir.join(&dir_path);
+                *entry = Value::String(resolved.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(config)
+}
+
+// Used to detect modifications. The length is included because modification
+// times can be coarse.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Loaded config files. A config file is read again when it's modified.
+#[derive(Default)]
+pub(crate) struct ConfigCache {
+    configs: HashMap<PathBuf, (Stamp, Value)>,
+}
+
+impl ConfigCache {
+    pub(crate) fn new() -> ConfigCache {
+        Default::default()
+    }
+
+    /// Returns the config which applies to `document`, if any.
+    pub(crate) fn config_for(&mut self, document: &Path) -> Option<Value> {
+        let path = find_config_file(document)?;
+        let stamp = stamp(&path);
+        if let Some((cached_stamp, config)) = self.configs.get(&path) {
+            if stamp.is_some() && *cached_stamp == stamp {
+                return Some(config.clone());
+            }
+        }
+
+        match load_config(&path) {
+            Ok(config) => {
+                self.configs.insert(path, (stamp, config.clone()));
+                Some(config)
+            }
+            Err(err) => {
+                log::warn!("Failed to load {}: {}", path.display(), err);
+                self.configs.remove(&path);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_for() {
+        let root = std::env::temp_dir().join(format!(
+            "mojom-lsp-test-config-cache-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        let document = root.join("a/b/foo.mojom");
+
+        let mut cache = ConfigCache::new();
+        assert!(cache.config_for(&document).is_none());
+
+        let config_path = root.join("a").join(CONFIG_FILE_NAME);
+        std::fs::write(&config_path, r#"{ "includeDirs": ["deps", "/abs"] }"#).unwrap();
+        let config = cache.config_for(&document).unwrap();
+        let expected = serde_json::json!({
+            "includeDirs": [root.join("a/deps").to_string_lossy(), "/abs"],
+        });
+        assert_eq!(expected, config);
+
+        // The config is read again after modification.
+        std::fs::write(&config_path, "[]").unwrap();
+        assert!(cache.config_for(&document).is_none());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -12,21 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use std::sync::mpsc::{channel, Sender};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 
 use lsp_types::Url as Uri;
 
+use crate::model::{enclosing_scope, FileSymbols, SymbolTable};
 use crate::syntax;
 
-use super::imported_files::{check_imports, ImportedFiles};
-use super::messagesender::MessageSender;
+use super::codeaction::CodeActionIndex;
+use super::completion::CompletionUsage;
+use super::definition::create_lsp_range;
+use super::document::{identifier_range_at, position_to_offset};
+use super::imported_files::{check_imports, normalize_path, ImportedFiles};
+use super::messagesender::{start_message_sender_thread, MessageSender};
 use super::mojomast::MojomAst;
 use super::protocol::NotificationMessage;
+use super::references::{unresolved_types, ReferenceGroup};
+use super::server::panic_message;
+use super::settings::Settings;
 
 pub(crate) fn create_diagnostic(range: lsp_types::Range, message: String) -> lsp_types::Diagnostic {
     lsp_types::Diagnostic {
@@ -40,15 +51,84 @@ pub(crate) fn create_diagnostic(range: lsp_types::Range, message: String) -> lsp
     }
 }
 
+/// Diagnostics which were last published for each opened document. Clones
+/// share the same state, so a handle can be kept while the server runs.
+#[derive(Debug, Clone, Default)]
+pub struct PublishedDiagnostics {
+    diagnostics: Arc<Mutex<HashMap<Uri, Vec<lsp_types::Diagnostic>>>>,
+}
+
+impl PublishedDiagnostics {
+    /// Returns the diagnostics which were last published for `uri`. Empty
+    /// when nothing was published or the document was closed.
+    pub fn diagnostics_for(&self, uri: &Uri) -> Vec<lsp_types::Diagnostic> {
+        let diagnostics = self.diagnostics.lock().unwrap();
+        diagnostics.get(uri).cloned().unwrap_or_default()
+    }
+
+    fn update(&self, uri: Uri, diagnostics: Vec<lsp_types::Diagnostic>) {
+        self.diagnostics.lock().unwrap().insert(uri, diagnostics);
+    }
+
+    fn remove(&self, uri: &Uri) {
+        self.diagnostics.lock().unwrap().remove(uri);
+    }
+}
+
 enum DiagnosticMessage {
-    CheckSyntax((Uri, String)),
+    CheckSyntax((Uri, String, Settings, bool /* publish */)),
+    Diagnostics((Uri, String, Settings, Sender<Vec<lsp_types::Diagnostic>>)),
     GotoDefinition(
         (
             Uri,
             lsp_types::Position,
-            Sender<Option<lsp_types::Location>>,
+            Sender<Option<lsp_types::LocationLink>>,
+        ),
+    ),
+    Hover((Uri, lsp_types::Position, Sender<Option<lsp_types::Hover>>)),
+    References(
+        (
+            Uri,
+            lsp_types::Position,
+            bool, /* include_declaration */
+            Sender<Vec<lsp_types::Location>>,
+        ),
+    ),
+    ReferencesByKind((Uri, lsp_types::Position, Sender<Vec<ReferenceGroup>>)),
+    DocumentHighlights(
+        (
+            Uri,
+            lsp_types::Position,
+            Sender<Vec<lsp_types::DocumentHighlight>>,
+        ),
+    ),
+    PrepareRename((Uri, lsp_types::Position, Sender<Option<lsp_types::Range>>)),
+    Rename(
+        (
+            Uri,
+            lsp_types::Position,
+            String, /* new_name */
+            Sender<Result<Option<lsp_types::WorkspaceEdit>, String>>,
+        ),
+    ),
+    Completion(
+        (
+            Uri,
+            String,
+            Settings,
+            lsp_types::Position,
+            CompletionUsage,
+            Sender<lsp_types::CompletionList>,
         ),
     ),
+    CodeActions((Uri, lsp_types::Range, Sender<Vec<lsp_types::CodeAction>>)),
+    UpdateWorkspaceFolders((PathBuf, Vec<PathBuf>)),
+    Close(Uri),
+    Clear(Uri),
+    #[cfg(test)]
+    IndexedSymbols(Sender<Vec<String>>),
+    #[cfg(test)]
+    PanicForTest(Sender<Vec<String>>),
 }
 
 pub(crate) struct DiagnosticsThread {
@@ -57,36 +137,238 @@ pub(crate) struct DiagnosticsThread {
 }
 
 impl DiagnosticsThread {
-    #[allow(unused)]
+    /// Waits until the thread handles all sent messages.
     pub(crate) fn join(self) {
+        // The thread stops when the channel is closed.
+        drop(self.sender);
         self.handle.join().unwrap();
     }
 
-    pub(crate) fn check(&self, uri: Uri, text: String) {
+    // Checks `text` with `settings`, which are the settings for `uri`. Later
+    // requests for `uri` use the same settings.
+    pub(crate) fn check(&self, uri: Uri, text: String, settings: Settings) {
+        self.sender
+            .send(DiagnosticMessage::CheckSyntax((uri, text, settings, true)))
+            .unwrap();
+    }
+
+    // Same as check() but doesn't publish diagnostics.
+    pub(crate) fn update(&self, uri: Uri, text: String, settings: Settings) {
+        self.sender
+            .send(DiagnosticMessage::CheckSyntax((uri, text, settings, false)))
+            .unwrap();
+    }
+
+    // Same as update() but returns diagnostics instead of publishing them.
+    pub(crate) fn diagnostics(
+        &self,
+        uri: Uri,
+        text: String,
+        settings: Settings,
+    ) -> Vec<lsp_types::Diagnostic> {
+        let (diagnostics_sender, diagnostics_receiver) = channel::<Vec<lsp_types::Diagnostic>>();
         self.sender
-            .send(DiagnosticMessage::CheckSyntax((uri, text)))
+            .send(DiagnosticMessage::Diagnostics((
+                uri,
+                text,
+                settings,
+                diagnostics_sender,
+            )))
             .unwrap();
+        diagnostics_receiver.recv().unwrap_or_default()
     }
 
     pub(crate) fn goto_definition(
         &self,
         uri: Uri,
         pos: lsp_types::Position,
-    ) -> Option<lsp_types::Location> {
-        let (loc_sender, loc_receiver) = channel::<Option<lsp_types::Location>>();
+    ) -> Option<lsp_types::LocationLink> {
+        let (loc_sender, loc_receiver) = channel::<Option<lsp_types::LocationLink>>();
         self.sender
             .send(DiagnosticMessage::GotoDefinition((uri, pos, loc_sender)))
             .unwrap();
-        let loc = loc_receiver.recv().unwrap();
-        loc
+        loc_receiver.recv().unwrap_or_default()
+    }
+
+    pub(crate) fn hover(&self, uri: Uri, pos: lsp_types::Position) -> Option<lsp_types::Hover> {
+        let (hover_sender, hover_receiver) = channel::<Option<lsp_types::Hover>>();
+        self.sender
+            .send(DiagnosticMessage::Hover((uri, pos, hover_sender)))
+            .unwrap();
+        hover_receiver.recv().unwrap_or_default()
+    }
+
+    /// Returns a receiver which yields references per file as soon as each
+    /// file is scanned. Dropping the receiver stops the scan.
+    pub(crate) fn stream_references(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        include_declaration: bool,
+    ) -> Receiver<Vec<lsp_types::Location>> {
+        let (references_sender, references_receiver) = channel::<Vec<lsp_types::Location>>();
+        self.sender
+            .send(DiagnosticMessage::References((
+                uri,
+                pos,
+                include_declaration,
+                references_sender,
+            )))
+            .unwrap();
+        references_receiver
+    }
+
+    pub(crate) fn references_by_kind(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Vec<ReferenceGroup> {
+        let (groups_sender, groups_receiver) = channel::<Vec<ReferenceGroup>>();
+        self.sender
+            .send(DiagnosticMessage::ReferencesByKind((
+                uri,
+                pos,
+                groups_sender,
+            )))
+            .unwrap();
+        groups_receiver.recv().unwrap_or_default()
+    }
+
+    pub(crate) fn document_highlights(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Vec<lsp_types::DocumentHighlight> {
+        let (highlights_sender, highlights_receiver) =
+            channel::<Vec<lsp_types::DocumentHighlight>>();
+        self.sender
+            .send(DiagnosticMessage::DocumentHighlights((
+                uri,
+                pos,
+                highlights_sender,
+            )))
+            .unwrap();
+        highlights_receiver.recv().unwrap_or_default()
+    }
+
+    pub(crate) fn prepare_rename(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Option<lsp_types::Range> {
+        let (range_sender, range_receiver) = channel::<Option<lsp_types::Range>>();
+        self.sender
+            .send(DiagnosticMessage::PrepareRename((uri, pos, range_sender)))
+            .unwrap();
+        range_receiver.recv().unwrap_or_default()
+    }
+
+    // Returns an error message when `new_name` isn't a valid identifier.
+    pub(crate) fn rename(
+        &self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        new_name: String,
+    ) -> Result<Option<lsp_types::WorkspaceEdit>, String> {
+        let (edit_sender, edit_receiver) =
+            channel::<Result<Option<lsp_types::WorkspaceEdit>, String>>();
+        self.sender
+            .send(DiagnosticMessage::Rename((uri, pos, new_name, edit_sender)))
+            .unwrap();
+        edit_receiver.recv().unwrap_or(Ok(None))
+    }
+
+    // Completion works on the given text, which may not be parsable yet.
+    // Items are ranked by `usage`.
+    pub(crate) fn completion(
+        &self,
+        uri: Uri,
+        text: String,
+        settings: Settings,
+        pos: lsp_types::Position,
+        usage: CompletionUsage,
+    ) -> lsp_types::CompletionList {
+        let (items_sender, items_receiver) = channel::<lsp_types::CompletionList>();
+        self.sender
+            .send(DiagnosticMessage::Completion((
+                uri,
+                text,
+                settings,
+                pos,
+                usage,
+                items_sender,
+            )))
+            .unwrap();
+        items_receiver
+            .recv()
+            .unwrap_or_else(|_| lsp_types::CompletionList {
+                is_incomplete: false,
+                items: Vec::new(),
+            })
+    }
+
+    pub(crate) fn code_actions(
+        &self,
+        uri: Uri,
+        range: lsp_types::Range,
+    ) -> Vec<lsp_types::CodeAction> {
+        let (actions_sender, actions_receiver) = channel::<Vec<lsp_types::CodeAction>>();
+        self.sender
+            .send(DiagnosticMessage::CodeActions((uri, range, actions_sender)))
+            .unwrap();
+        actions_receiver.recv().unwrap_or_default()
+    }
+
+    // Sets the root path and the other workspace folders. Imports are resolved
+    // against all of them.
+    pub(crate) fn update_workspace_folders(&self, root_path: PathBuf, folders: Vec<PathBuf>) {
+        self.sender
+            .send(DiagnosticMessage::UpdateWorkspaceFolders((
+                root_path, folders,
+            )))
+            .unwrap();
+    }
+
+    // Discards states which are associated with `uri`.
+    pub(crate) fn close(&self, uri: Uri) {
+        self.sender.send(DiagnosticMessage::Close(uri)).unwrap();
+    }
+
+    // Publishes empty diagnostics for `uri`, which is no longer analyzed.
+    pub(crate) fn clear(&self, uri: Uri) {
+        self.sender.send(DiagnosticMessage::Clear(uri)).unwrap();
+    }
+
+    // Returns qualified names of top-level symbols in the last analyzed
+    // document.
+    #[cfg(test)]
+    pub(crate) fn indexed_symbols(&self) -> Vec<String> {
+        let (symbols_sender, symbols_receiver) = channel::<Vec<String>>();
+        self.sender
+            .send(DiagnosticMessage::IndexedSymbols(symbols_sender))
+            .unwrap();
+        symbols_receiver.recv().unwrap()
+    }
+
+    // Makes the handler of a message panic. Returns the result which
+    // requesters get in that case.
+    #[cfg(test)]
+    pub(crate) fn panic_for_test(&self) -> Vec<String> {
+        let (sender, receiver) = channel::<Vec<String>>();
+        self.sender
+            .send(DiagnosticMessage::PanicForTest(sender))
+            .unwrap();
+        receiver.recv().unwrap_or_default()
     }
 }
 
 pub(crate) fn start_diagnostics_thread(
     root_path: PathBuf,
+    settings: Settings,
     msg_sender: MessageSender,
+    published: PublishedDiagnostics,
 ) -> DiagnosticsThread {
-    let mut diag = Diagnostic::new(root_path, msg_sender);
+    let mut diag = Diagnostic::new(root_path, settings, msg_sender, published);
     let (sender, receiver) = channel::<DiagnosticMessage>();
     let handle = thread::spawn(move || loop {
         let msg = match receiver.recv() {
@@ -94,14 +376,18 @@ pub(crate) fn start_diagnostics_thread(
             Err(_) => break,
         };
 
-        match msg {
-            DiagnosticMessage::CheckSyntax((uri, text)) => {
-                diag.check(uri, text);
-            }
-            DiagnosticMessage::GotoDefinition((uri, pos, loc_sender)) => {
-                let loc = diag.find_definition(uri, pos);
-                loc_sender.send(loc).unwrap();
-            }
+        // A bug in a handler shouldn't stop the thread. The reply channel of
+        // the message is dropped while unwinding, so the requester gets an
+        // empty result.
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| diag.handle_message(msg))) {
+            log::error!(
+                "Diagnostics handler panicked: {}",
+                panic_message(payload.as_ref())
+            );
+            // States of the current document may be inconsistent.
+            diag.ast = None;
+            diag.imported_files = None;
+            diag.symbols = None;
         }
     });
 
@@ -111,53 +397,447 @@ pub(crate) fn start_diagnostics_thread(
     }
 }
 
+// A document opened by the client.
+struct OpenDocument {
+    text: String,
+    settings: Settings,
+    // True when diagnostics of the document have been published.
+    published: bool,
+}
+
 struct Diagnostic {
     // Workspace root path.
     root_path: PathBuf,
+    // Workspace folders other than the root path.
+    workspace_folders: Vec<PathBuf>,
+    // Settings for the current document. Used to configure lints.
+    settings: Settings,
     // A message sender. It is used in the diagnostics thread to send
     // notifications.
     msg_sender: MessageSender,
+    // Diagnostics which were published last time. Shared with the owner of
+    // the server.
+    published: PublishedDiagnostics,
     // Current parsed syntax tree with the original text.
     ast: Option<MojomAst>,
     // Parsed mojom files that are imported from the current document.
     imported_files: Option<ImportedFiles>,
+    // Symbols which are visible from the current document.
+    symbols: Option<SymbolTable>,
+    // Available fixes for diagnostics of each document.
+    code_actions: HashMap<Uri, CodeActionIndex>,
+    // Documents opened by the client. Imports are read from these rather
+    // than files on disk.
+    open_documents: HashMap<Uri, OpenDocument>,
+    // Open documents which import each file directly or indirectly, keyed by
+    // normalized path.
+    importers: HashMap<PathBuf, HashSet<Uri>>,
 }
 
 impl Diagnostic {
-    fn new(root_path: PathBuf, msg_sender: MessageSender) -> Self {
+    fn handle_message(&mut self, msg: DiagnosticMessage) {
+        match msg {
+            DiagnosticMessage::CheckSyntax((uri, text, settings, publish)) => {
+                let published = publish
+                    || self
+                        .open_documents
+                        .get(&uri)
+                        .is_some_and(|document| document.published);
+                let document = OpenDocument {
+                    text: text.clone(),
+                    settings: settings.clone(),
+                    published: published,
+                };
+                self.open_documents.insert(uri.clone(), document);
+                self.settings = settings;
+                self.check(uri.clone(), text, publish);
+                if publish {
+                    self.refresh_importers(&uri);
+                }
+            }
+            DiagnosticMessage::Diagnostics((uri, text, settings, diagnostics_sender)) => {
+                self.settings = settings;
+                let diagnostics = self.check(uri, text, false);
+                diagnostics_sender.send(diagnostics).unwrap();
+            }
+            DiagnosticMessage::GotoDefinition((uri, pos, loc_sender)) => {
+                let loc = self.find_definition(uri, pos);
+                loc_sender.send(loc).unwrap();
+            }
+            DiagnosticMessage::Hover((uri, pos, hover_sender)) => {
+                let hover = self.hover(uri, pos);
+                hover_sender.send(hover).unwrap();
+            }
+            DiagnosticMessage::References((uri, pos, include_declaration, references_sender)) => {
+                self.find_references(uri, pos, include_declaration, |references| {
+                    references_sender.send(references).is_ok()
+                });
+            }
+            DiagnosticMessage::ReferencesByKind((uri, pos, groups_sender)) => {
+                let groups = self.find_references_by_kind(uri, pos);
+                groups_sender.send(groups).unwrap();
+            }
+            DiagnosticMessage::DocumentHighlights((uri, pos, highlights_sender)) => {
+                let highlights = self.document_highlights(uri, pos);
+                highlights_sender.send(highlights).unwrap();
+            }
+            DiagnosticMessage::PrepareRename((uri, pos, range_sender)) => {
+                let range = self.prepare_rename(uri, pos);
+                range_sender.send(range).unwrap();
+            }
+            DiagnosticMessage::Rename((uri, pos, new_name, edit_sender)) => {
+                let edit = self.rename(uri, pos, &new_name);
+                edit_sender.send(edit).unwrap();
+            }
+            DiagnosticMessage::Completion((uri, text, settings, pos, usage, items_sender)) => {
+                self.settings = settings;
+                let items = self.completion(uri, text, pos, &usage);
+                items_sender.send(items).unwrap();
+            }
+            DiagnosticMessage::CodeActions((uri, range, actions_sender)) => {
+                let actions = self.code_actions(&uri, &range);
+                actions_sender.send(actions).unwrap();
+            }
+            DiagnosticMessage::UpdateWorkspaceFolders((root_path, folders)) => {
+                self.root_path = root_path;
+                self.workspace_folders = folders;
+                // Imports of the current document may resolve differently.
+                self.check_imported_files();
+                self.build_symbol_table();
+            }
+            DiagnosticMessage::Close(uri) => {
+                self.code_actions.remove(&uri);
+                self.published.remove(&uri);
+                self.open_documents.remove(&uri);
+                self.remove_importer(&uri);
+                // Importers read the file from disk from now on.
+                self.refresh_importers(&uri);
+            }
+            DiagnosticMessage::Clear(uri) => {
+                self.code_actions.remove(&uri);
+                self.published.update(uri.clone(), Vec::new());
+                let params = lsp_types::PublishDiagnosticsParams {
+                    uri: uri,
+                    diagnostics: Vec::new(),
+                    version: None,
+                };
+                publish_diagnostics(&self.msg_sender, params);
+            }
+            #[cfg(test)]
+            DiagnosticMessage::PanicForTest(_sender) => panic!("Deliberate panic"),
+            #[cfg(test)]
+            DiagnosticMessage::IndexedSymbols(symbols_sender) => {
+                let symbols = match (&self.ast, &self.symbols) {
+                    (Some(ast), Some(symbols)) => symbols
+                        .exports(&ast.uri)
+                        .iter()
+                        .map(|symbol| symbol.qualified_name())
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                symbols_sender.send(symbols).unwrap();
+            }
+        }
+    }
+
+    fn new(
+        root_path: PathBuf,
+        settings: Settings,
+        msg_sender: MessageSender,
+        published: PublishedDiagnostics,
+    ) -> Self {
         Diagnostic {
             root_path: root_path,
+            workspace_folders: Vec::new(),
+            settings: settings,
             msg_sender: msg_sender,
+            published: published,
             ast: None,
             imported_files: None,
+            symbols: None,
+            code_actions: HashMap::new(),
+            open_documents: HashMap::new(),
+            importers: HashMap::new(),
         }
     }
 
-    fn check(&mut self, uri: Uri, text: String) {
-        self.check_syntax(uri.clone(), text);
+    fn check(&mut self, uri: Uri, text: String, publish: bool) -> Vec<lsp_types::Diagnostic> {
+        let mut diagnostics = self.check_syntax(uri.clone(), text);
         self.check_imported_files();
+        self.build_symbol_table();
+        if self.open_documents.contains_key(&uri) {
+            self.update_importers(&uri);
+        }
+        if let (Some(ast), Some(imported_files)) = (&self.ast, &self.imported_files) {
+            diagnostics.extend(imported_files.diagnostics(ast));
+            // Types may be declared in imports which failed to parse.
+            if let (true, Some(symbols)) = (imported_files.is_complete(), &self.symbols) {
+                diagnostics.extend(unresolved_types(ast, symbols));
+            }
+        }
+        dedup_diagnostics(&mut diagnostics);
+
+        if publish {
+            self.published.update(uri.clone(), diagnostics.clone());
+            let params = lsp_types::PublishDiagnosticsParams {
+                uri: uri,
+                diagnostics: diagnostics.clone(),
+                // TODO: Support version
+                version: None,
+            };
+            publish_diagnostics(&self.msg_sender, params);
+        }
+        diagnostics
+    }
+
+    // Records files which the current document `uri` imports.
+    fn update_importers(&mut self, uri: &Uri) {
+        self.remove_importer(uri);
+        if !self.is_same_uri(uri) {
+            return;
+        }
+        if let Some(imported_files) = &self.imported_files {
+            for path in imported_files.paths() {
+                self.importers.entry(path).or_default().insert(uri.clone());
+            }
+        }
+    }
+
+    fn remove_importer(&mut self, uri: &Uri) {
+        for importers in self.importers.values_mut() {
+            importers.remove(uri);
+        }
+        self.importers.retain(|_, importers| !importers.is_empty());
+    }
+
+    // Checks and publishes diagnostics of open documents which import `uri`
+    // again. Types they refer to may have been declared or removed.
+    fn refresh_importers(&mut self, uri: &Uri) {
+        let path = match uri.to_file_path() {
+            Ok(path) => normalize_path(&path),
+            Err(_) => return,
+        };
+        let importers: Vec<Uri> = match self.importers.get(&path) {
+            Some(importers) => importers
+                .iter()
+                .filter(|importer| *importer != uri)
+                .cloned()
+                .collect(),
+            None => return,
+        };
+        for importer in importers {
+            let (text, settings) = match self.open_documents.get(&importer) {
+                Some(document) if document.published => {
+                    (document.text.clone(), document.settings.clone())
+                }
+                _ => continue,
+            };
+            self.settings = settings;
+            self.check(importer, text, true);
+        }
+    }
+
+    // Texts of open documents keyed by normalized path.
+    fn open_texts(&self) -> HashMap<PathBuf, &str> {
+        self.open_documents
+            .iter()
+            .filter_map(|(uri, document)| {
+                let path = uri.to_file_path().ok()?;
+                Some((normalize_path(&path), document.text.as_str()))
+            })
+            .collect()
+    }
+
+    fn find_references<F>(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        include_declaration: bool,
+        report: F,
+    ) where
+        F: FnMut(Vec<lsp_types::Location>) -> bool,
+    {
+        if !self.is_same_uri(&uri) {
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return;
+            }
+        }
+
+        if let (Some(ast), Some(symbols)) = (&self.ast, &self.symbols) {
+            super::references::stream_references(ast, symbols, &pos, include_declaration, report);
+        }
+    }
+
+    fn find_references_by_kind(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Vec<ReferenceGroup> {
+        if !self.is_same_uri(&uri) {
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return Vec::new();
+            }
+        }
+
+        match (&self.ast, &self.symbols) {
+            (Some(ast), Some(symbols)) => {
+                super::references::find_references_by_kind(ast, symbols, &pos)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn document_highlights(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+    ) -> Vec<lsp_types::DocumentHighlight> {
+        if !self.is_same_uri(&uri) {
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return Vec::new();
+            }
+        }
+
+        match (&self.ast, &self.symbols) {
+            (Some(ast), Some(symbols)) => {
+                super::references::document_highlights(ast, symbols, &pos)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn prepare_rename(&mut self, uri: Uri, pos: lsp_types::Position) -> Option<lsp_types::Range> {
+        if !self.is_same_uri(&uri) {
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return None;
+            }
+        }
+
+        match (&self.ast, &self.symbols) {
+            (Some(ast), Some(symbols)) => super::rename::prepare_rename(ast, symbols, &pos),
+            _ => None,
+        }
+    }
+
+    fn rename(
+        &mut self,
+        uri: Uri,
+        pos: lsp_types::Position,
+        new_name: &str,
+    ) -> Result<Option<lsp_types::WorkspaceEdit>, String> {
+        if !self.is_same_uri(&uri) {
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return Ok(None);
+            }
+        }
+
+        match (&self.ast, &self.symbols) {
+            (Some(ast), Some(symbols)) => super::rename::rename(ast, symbols, &pos, new_name),
+            _ => Ok(None),
+        }
     }
 
     fn find_definition(
         &mut self,
         uri: Uri,
         pos: lsp_types::Position,
-    ) -> Option<lsp_types::Location> {
+    ) -> Option<lsp_types::LocationLink> {
         if !self.is_same_uri(&uri) {
-            // TODO: Don't use unwrap().
-            self.open(uri).unwrap();
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return None;
+            }
         }
 
-        if let Some(ast) = &self.ast {
-            let ident = get_identifier(&ast.text, &pos);
-            let loc = find_definition_in_doc(ast, &ident).or(find_definition_in_imported_files(
-                &self.imported_files,
-                &ident,
-            ));
-            loc
-        } else {
-            None
+        // `ast` is None when the document has syntax errors.
+        let ast = self.ast.as_ref()?;
+        let symbols = self.symbols.as_ref()?;
+        let ident_range = identifier_range_at(&ast.text, &pos)?;
+        let ident = &ast.text[ident_range.clone()];
+        let offset = position_to_offset(&ast.text, &pos);
+        let scope = enclosing_scope(&ast.text, &ast.mojom, offset);
+        let symbol = symbols.lookup(ident, &scope)?;
+        let origin = syntax::Range {
+            start: ident_range.start,
+            end: ident_range.end,
+        };
+        // We don't keep ranges of whole declarations. Use the declared name
+        // for both target ranges.
+        Some(lsp_types::LocationLink {
+            origin_selection_range: Some(create_lsp_range(ast, &origin)),
+            target_uri: symbol.uri.clone(),
+            target_range: symbol.symbol.range,
+            target_selection_range: symbol.symbol.range,
+        })
+    }
+
+    fn hover(&mut self, uri: Uri, pos: lsp_types::Position) -> Option<lsp_types::Hover> {
+        if !self.is_same_uri(&uri) {
+            if let Err(err) = self.open(uri) {
+                log::warn!("Failed to open document: {}", err);
+                return None;
+            }
         }
+
+        let ast = self.ast.as_ref()?;
+        super::hover::hover(
+            &self.root_path,
+            &self.include_dirs(),
+            ast,
+            self.imported_files.as_ref(),
+            self.symbols.as_ref(),
+            &pos,
+        )
+    }
+
+    fn completion(
+        &self,
+        uri: Uri,
+        text: String,
+        pos: lsp_types::Position,
+        usage: &CompletionUsage,
+    ) -> lsp_types::CompletionList {
+        // Don't replace the current `ast`. The text is likely to be incomplete
+        // while typing.
+        let mojom = super::document_symbol::parse_recoverable(&text);
+        let ast = MojomAst::from_mojom(uri.clone(), text, mojom);
+        let imported_files = check_imports(
+            &self.root_path,
+            &self.include_dirs(),
+            &ast,
+            &self.open_texts(),
+        );
+        let file = FileSymbols::from_mojom(uri, &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, imported_files.symbols().cloned());
+        super::completion::completion(
+            &self.root_path,
+            &self.include_dirs(),
+            &ast,
+            &symbols,
+            &pos,
+            self.settings.complete_module_paths,
+            usage,
+        )
+    }
+
+    fn code_actions(&self, uri: &Uri, range: &lsp_types::Range) -> Vec<lsp_types::CodeAction> {
+        self.code_actions
+            .get(uri)
+            .map(|index| index.code_actions(uri, range))
+            .unwrap_or_default()
+    }
+
+    // Directories to resolve imports against in addition to the root path.
+    // Workspace folders are absolute, so they are searched as is.
+    fn include_dirs(&self) -> Vec<PathBuf> {
+        let mut include_dirs = self.settings.include_dirs.clone();
+        include_dirs.extend(self.workspace_folders.iter().cloned());
+        include_dirs
     }
 
     fn is_same_uri(&self, uri: &Uri) -> bool {
@@ -169,25 +849,36 @@ impl Diagnostic {
     }
 
     fn open(&mut self, uri: Uri) -> std::io::Result<()> {
-        let path = uri.to_file_path().unwrap();
+        // Another document may have been checked after an open document.
+        if let Some(document) = self.open_documents.get(&uri) {
+            self.settings = document.settings.clone();
+            let text = document.text.clone();
+            self.check(uri, text, false);
+            return Ok(());
+        }
+        let path = uri
+            .to_file_path()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a file URI"))?;
         let mut text = String::new();
         File::open(path).and_then(|mut f| f.read_to_string(&mut text))?;
-        self.check(uri, text);
+        // The document isn't opened by the client. Don't publish diagnostics.
+        self.check(uri, text, false);
         Ok(())
     }
 
-    fn check_syntax(&mut self, uri: Uri, text: String) {
+    fn check_syntax(&mut self, uri: Uri, text: String) -> Vec<lsp_types::Diagnostic> {
         let mojom = syntax::parse(&text);
-        let diagnostics = match mojom {
+        let mut code_actions = CodeActionIndex::new();
+        let text_lints = super::lint::check_text_lints(&text, &self.settings, &mut code_actions);
+        let mut diagnostics = match mojom {
             Ok(mojom) => {
-                let analytics = super::semantic::check_semantics(&text, &mojom);
+                let mut analytics = super::semantic::check_semantics(&uri, &text, &mojom);
+                let lints =
+                    super::lint::check_lints(&text, &mojom, &self.settings, &mut code_actions);
+                analytics.diagnostics.extend(lints);
+                super::refactor::add_refactors(&text, &mojom, &mut code_actions);
                 // TODO: Don't store ast when semantics check fails?
-                self.ast = Some(MojomAst::from_mojom(
-                    uri.clone(),
-                    text,
-                    mojom,
-                    analytics.module,
-                ));
+                self.ast = Some(MojomAst::from_mojom(uri.clone(), text, mojom));
                 analytics.diagnostics
             }
             Err(err) => {
@@ -198,24 +889,185 @@ impl Diagnostic {
                 vec![diagnostic]
             }
         };
+        diagnostics.extend(text_lints);
 
-        let params = lsp_types::PublishDiagnosticsParams {
-            uri: uri,
-            diagnostics: diagnostics,
-            // TODO: Support version
-            version: None,
-        };
-        publish_diagnostics(&self.msg_sender, params);
+        self.code_actions.insert(uri, code_actions);
+        diagnostics
+    }
+
+    fn build_symbol_table(&mut self) {
+        self.symbols = self.ast.as_ref().map(|ast| {
+            let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+            let imports = self
+                .imported_files
+                .iter()
+                .flat_map(|imported_files| imported_files.symbols().cloned());
+            SymbolTable::new(file, imports)
+        });
     }
 
     fn check_imported_files(&mut self) {
         if let Some(ast) = &self.ast {
-            let imported_files = check_imports(&self.root_path, ast);
+            let imported_files = check_imports(
+                &self.root_path,
+                &self.include_dirs(),
+                ast,
+                &self.open_texts(),
+            );
             self.imported_files = Some(imported_files);
         }
     }
 }
 
+/// Checks every mojom file under `roots` and returns diagnostics for each
+/// file, keyed by canonical path. Files are analyzed the same way as opened
+/// documents; imports are resolved against the root the file was found in.
+pub fn analyze_workspace(
+    roots: &[PathBuf],
+    settings: &Settings,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    // Diagnostics are never published, but `Diagnostic` needs a sender.
+    let msg_sender_thread = start_message_sender_thread(std::io::sink());
+    let mut results = HashMap::new();
+    for root in roots {
+        let root = match root.canonicalize() {
+            Ok(root) => root,
+            Err(err) => {
+                log::warn!("Failed to open workspace root {}: {}", root.display(), err);
+                continue;
+            }
+        };
+        let mut diag = Diagnostic::new(
+            root.clone(),
+            settings.clone(),
+            msg_sender_thread.get_sender(),
+            PublishedDiagnostics::default(),
+        );
+        for path in find_mojom_files(&root, &settings.exclude_globs) {
+            if results.contains_key(&path) {
+                continue;
+            }
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) => {
+                    log::warn!("Failed to read {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+            let uri = Uri::from_file_path(&path).unwrap();
+            let diagnostics = diag.check(uri, text, false);
+            results.insert(path, diagnostics);
+        }
+    }
+    results
+}
+
+// Returns true when `name` matches `pattern`, which may contain `*` (any
+// characters) and `?` (one character).
+fn matches_glob_segment(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => name
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(name.len()))
+            .any(|i| matches_glob_segment(&pattern[1..], &name[i..])),
+        Some('?') => name
+            .chars()
+            .next()
+            .is_some_and(|ch| matches_glob_segment(&pattern[1..], &name[ch.len_utf8()..])),
+        Some(ch) => {
+            name.starts_with(ch)
+                && matches_glob_segment(&pattern[ch.len_utf8()..], &name[ch.len_utf8()..])
+        }
+    }
+}
+
+fn matches_glob_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| matches_glob_segments(rest, &path[i..])),
+        Some((segment, rest)) => path.split_first().is_some_and(|(name, path)| {
+            matches_glob_segment(segment, name) && matches_glob_segments(rest, path)
+        }),
+    }
+}
+
+/// Returns true when `path`, a `/`-separated path relative to a workspace
+/// root, matches `pattern`. `**` in `pattern` matches any number of
+/// directories.
+pub(crate) fn matches_glob(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<_> = pattern.trim_end_matches('/').split('/').collect();
+    let path: Vec<_> = path.split('/').collect();
+    matches_glob_segments(&pattern, &path)
+}
+
+/// Collects mojom files under `root` recursively. Hidden directories (e.g.
+/// `.git`), symbolic links to directories, the build output directory `out`
+/// of `root`, and paths matching `exclude_globs` are skipped.
+pub(crate) fn find_mojom_files(root: &Path, exclude_globs: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    walk_mojom_files(root, exclude_globs, &mut |path| paths.push(path));
+    paths
+}
+
+/// Same as find_mojom_files() but calls `found` with each file as soon as it
+/// is found.
+pub(crate) fn walk_mojom_files(
+    root: &Path,
+    exclude_globs: &[String],
+    found: &mut dyn FnMut(PathBuf),
+) {
+    collect_mojom_files(root, "", exclude_globs, found);
+}
+
+fn collect_mojom_files(
+    dir: &Path,
+    relative: &str,
+    exclude_globs: &[String],
+    found: &mut dyn FnMut(PathBuf),
+) {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return,
+    };
+    let mut entries = read_dir.filter_map(|entry| entry.ok()).collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        if exclude_globs
+            .iter()
+            .any(|pattern| matches_glob(pattern, &relative))
+        {
+            continue;
+        }
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if is_dir {
+            if !name.starts_with('.') && relative != "out" {
+                collect_mojom_files(&path, &relative, exclude_globs, found);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "mojom") && path.is_file() {
+            found(path);
+        }
+    }
+}
+
+// Removes diagnostics which have the same range, code and message as an
+// earlier one. Different passes may report the same issue.
+fn dedup_diagnostics(diagnostics: &mut Vec<lsp_types::Diagnostic>) {
+    let mut seen = HashSet::new();
+    diagnostics.retain(|diagnostic| {
+        let key = (&diagnostic.range, &diagnostic.code, &diagnostic.message);
+        seen.insert(serde_json::to_string(&key).unwrap())
+    });
+}
+
 pub(crate) fn into_lsp_range(start: &syntax::LineCol, end: &syntax::LineCol) -> lsp_types::Range {
     lsp_types::Range {
         start: lsp_types::Position::new(start.line as u64, start.col as u64),
@@ -232,54 +1084,375 @@ fn publish_diagnostics(msg_sender: &MessageSender, params: lsp_types::PublishDia
     msg_sender.send_notification(msg);
 }
 
-fn get_offset_from_position(text: &str, pos: &lsp_types::Position) -> usize {
-    let pos_line = pos.line as usize;
-    let pos_col = pos.character as usize;
-    let mut offset = 0;
-    for (i, line) in text.lines().enumerate() {
-        if i == pos_line {
-            break;
-        }
-        offset += line.len() + 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::references::UsageKind;
+
+    use std::path::Path;
+
+    use pipe::pipe;
+
+    use super::super::messagesender::start_message_sender_thread;
+
+    fn create_uri<P: AsRef<Path>>(path: P) -> Uri {
+        let path = path.as_ref().canonicalize().unwrap();
+        Uri::from_file_path(path).unwrap()
     }
-    offset + pos_col
-}
 
-#[inline(always)]
-fn is_identifier_char(ch: char) -> bool {
-    ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'
-}
+    fn create_diagnostic_for_test() -> Diagnostic {
+        let (_, w) = pipe();
+        let msg_sender_thread = start_message_sender_thread(w);
+        Diagnostic::new(
+            PathBuf::from("testdata"),
+            Settings::default(),
+            msg_sender_thread.get_sender(),
+            PublishedDiagnostics::default(),
+        )
+    }
+
+    #[test]
+    fn test_analyze_workspace() {
+        let root = Path::new("testdata/workspace").canonicalize().unwrap();
+        let results = analyze_workspace(std::slice::from_ref(&root), &Settings::default());
+
+        let mut paths = results.keys().cloned().collect::<Vec<_>>();
+        paths.sort();
+        let expected = vec![
+            root.join("broken.mojom"),
+            root.join("missing_import.mojom"),
+            root.join("point.mojom"),
+            root.join("sub/canvas.mojom"),
+        ];
+        assert_eq!(expected, paths);
+
+        let messages = |name: &str| -> Vec<String> {
+            results[&root.join(name)]
+                .iter()
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect()
+        };
+        assert!(messages("point.mojom").is_empty());
+        assert!(messages("sub/canvas.mojom").is_empty());
+        let message = format!("Import not found: {}", root.join("missing.mojom").display());
+        assert_eq!(vec![message], messages("missing_import.mojom"));
+        let broken = &results[&root.join("broken.mojom")];
+        assert_eq!(1, broken.len());
+        assert_eq!(4, broken[0].range.start.line);
+    }
+
+    #[test]
+    fn test_find_mojom_files() {
+        let root = Path::new("testdata/exclude");
+        let relative = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| {
+                    let path = path.strip_prefix(root).unwrap();
+                    path.to_string_lossy().replace('\\', "/")
+                })
+                .collect()
+        };
+        assert_eq!(
+            vec!["src/a.mojom", "src/out/b.mojom", "third_party/c.mojom"],
+            relative(find_mojom_files(root, &[]))
+        );
+        let exclude_globs = vec!["third_party/**".to_owned(), "**/b.mojom".to_owned()];
+        assert_eq!(
+            vec!["src/a.mojom"],
+            relative(find_mojom_files(root, &exclude_globs))
+        );
+    }
+
+    #[test]
+    fn test_matches_glob() {
+        assert!(matches_glob("out", "out"));
+        assert!(matches_glob("out/", "out"));
+        assert!(!matches_glob("out", "src/out"));
+        assert!(matches_glob("**/out", "src/out"));
+        assert!(matches_glob("**/out", "out"));
+        assert!(matches_glob("src/*_test.mojom", "src/foo_test.mojom"));
+        assert!(!matches_glob("src/*_test.mojom", "src/a/foo_test.mojom"));
+        assert!(matches_glob("src/**/*.mojom", "src/a/b/foo.mojom"));
+        assert!(matches_glob("fo?.mojom", "foo.mojom"));
+        assert!(!matches_glob("fo?.mojom", "fo.mojom"));
+    }
+
+    #[test]
+    fn test_analyze_workspace_overlapping_roots() {
+        let roots = vec![
+            PathBuf::from("testdata/workspace"),
+            PathBuf::from("testdata/workspace/sub"),
+            PathBuf::from("testdata/nonexistent"),
+        ];
+        let results = analyze_workspace(&roots, &Settings::default());
+        assert_eq!(4, results.len());
+    }
+
+    #[test]
+    fn test_dedup_diagnostics() {
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(0, 0),
+            lsp_types::Position::new(0, 3),
+        );
+        let first_pass = vec![create_diagnostic(range, "Bad ordinal".to_owned())];
+        let mut second_pass = first_pass.clone();
+        // A different severity doesn't make a different issue.
+        second_pass[0].severity = Some(lsp_types::DiagnosticSeverity::Warning);
+        let mut other_code = create_diagnostic(range, "Bad ordinal".to_owned());
+        other_code.code = Some(lsp_types::NumberOrString::String("lint".to_owned()));
+        second_pass.push(other_code);
+
+        let mut diagnostics: Vec<_> = first_pass.into_iter().chain(second_pass).collect();
+        dedup_diagnostics(&mut diagnostics);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Error),
+            diagnostics[0].severity
+        );
+        assert_eq!(
+            Some(lsp_types::NumberOrString::String("lint".to_owned())),
+            diagnostics[1].code
+        );
+    }
+
+    #[test]
+    fn test_text_lints_broken_input() {
+        let mut diag = create_diagnostic_for_test();
+        diag.settings.max_line_length = Some(10);
+        let uri = Uri::parse("file:///broken.mojom").unwrap();
+        let text = "struct S {\n\tint32 a\n};\n// 0123456789";
+        let diagnostics = diag.check(uri.clone(), text.to_owned(), false);
+        assert_eq!(3, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Error),
+            diagnostics[0].severity
+        );
+        assert_eq!(
+            "Use spaces instead of tabs for indentation",
+            diagnostics[1].message
+        );
+        let actions = diag.code_actions(&uri, &diagnostics[1].range);
+        assert_eq!(1, actions.len());
+        assert_eq!(
+            "Line is 13 columns long (more than 10)",
+            diagnostics[2].message
+        );
+    }
 
-fn get_identifier<'a>(text: &'a str, pos: &lsp_types::Position) -> &'a str {
-    // TODO: The current implementation isn't accurate.
+    #[test]
+    fn test_find_definition_broken_input() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = Uri::parse("file:///broken.mojom").unwrap();
 
-    let offset = get_offset_from_position(text, pos);
-    let mut s = offset;
-    for ch in text[..offset].chars().rev() {
-        if !is_identifier_char(ch) {
-            break;
+        let positions = [
+            lsp_types::Position::new(0, 0),
+            lsp_types::Position::new(0, 100),
+            lsp_types::Position::new(100, 0),
+        ];
+        let inputs = [
+            "",
+            "struct",
+            "struct Foo {",
+            "interface I { Foo(int32 a",
+            "struct Föö { int32 a; };",
+        ];
+        for input in inputs.iter() {
+            diag.check(uri.clone(), input.to_string(), false);
+            for pos in positions.iter() {
+                assert!(diag.find_definition(uri.clone(), *pos).is_none());
+            }
         }
-        s -= 1;
     }
-    let mut e = offset;
-    for ch in text[offset..].chars() {
-        if !is_identifier_char(ch) {
-            break;
+
+    #[test]
+    fn test_find_definition_local() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = Uri::parse("file:///local.mojom").unwrap();
+        let text = "struct Foo {};\nstruct Bar {\n  Foo foo;\n  array<Foo> foos;\n  string name;\n  uint32 id;\n};";
+        diag.check(uri.clone(), text.to_owned(), false);
+
+        // `Foo` of the field type.
+        let link = diag
+            .find_definition(uri.clone(), lsp_types::Position::new(2, 3))
+            .unwrap();
+        assert_eq!(uri, link.target_uri);
+        let expected = lsp_types::Range::new(
+            lsp_types::Position::new(0, 7),
+            lsp_types::Position::new(0, 10),
+        );
+        assert_eq!(expected, link.target_selection_range);
+        let origin = lsp_types::Range::new(
+            lsp_types::Position::new(2, 2),
+            lsp_types::Position::new(2, 5),
+        );
+        assert_eq!(Some(origin), link.origin_selection_range);
+
+        // `Foo` in `array<Foo>`.
+        let link = diag
+            .find_definition(uri.clone(), lsp_types::Position::new(3, 9))
+            .unwrap();
+        assert_eq!(expected, link.target_selection_range);
+
+        // Builtin types have no definition.
+        assert!(diag
+            .find_definition(uri.clone(), lsp_types::Position::new(4, 3))
+            .is_none());
+        assert!(diag
+            .find_definition(uri.clone(), lsp_types::Position::new(5, 3))
+            .is_none());
+    }
+
+    #[test]
+    fn test_find_references_imported() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = create_uri("testdata/my_interface.mojom");
+        // Position on the declaration of `MyInterface`.
+        let pos = lsp_types::Position::new(3, 12);
+        let mut batches = Vec::new();
+        diag.find_references(uri.clone(), pos, true, |references| {
+            batches.push(references);
+            true
+        });
+        // One batch per file.
+        assert_eq!(2, batches.len());
+        let references = batches.concat();
+        assert_eq!(2, references.len());
+        assert_eq!(uri, references[0].uri);
+        assert_eq!(create_uri("testdata/my_service.mojom"), references[1].uri);
+        assert_eq!(lsp_types::Position::new(4, 25), references[1].range.start);
+    }
+
+    #[test]
+    fn test_rename_across_files() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = create_uri("testdata/my_interface.mojom");
+        // Position on the declaration of `MyInterface`. my_service.mojom
+        // isn't opened and is read from the disk.
+        let pos = lsp_types::Position::new(3, 12);
+        let edit = diag.rename(uri.clone(), pos, "Renamed").unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+        assert_eq!(2, changes.len());
+        let service_edits = &changes[&create_uri("testdata/my_service.mojom")];
+        assert_eq!(1, service_edits.len());
+        assert_eq!(
+            lsp_types::Position::new(4, 25),
+            service_edits[0].range.start
+        );
+        for edits in changes.values() {
+            for pair in edits.windows(2) {
+                assert!(pair[0].range.end <= pair[1].range.start);
+            }
         }
-        e += 1;
     }
-    &text[s..e]
-}
 
-fn find_definition_in_doc(ast: &MojomAst, ident: &str) -> Option<lsp_types::Location> {
-    super::definition::find_definition_preorder(ident, ast)
-}
+    #[test]
+    fn test_refresh_importers() {
+        let root =
+            std::env::temp_dir().join(format!("mojom-lsp-test-importers-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let root = root.canonicalize().unwrap();
+        let a_text = "module test;\nimport \"b.mojom\";\nstruct A {\n  Foo foo;\n};\n";
+        let b_text = "module test;\nstruct Bar {};\n";
+        std::fs::write(root.join("a.mojom"), a_text).unwrap();
+        std::fs::write(root.join("b.mojom"), b_text).unwrap();
+        let a_uri = create_uri(root.join("a.mojom"));
+        let b_uri = create_uri(root.join("b.mojom"));
+
+        let mut diag = create_diagnostic_for_test();
+        diag.root_path = root.clone();
+        let check = |diag: &mut Diagnostic, uri: &Uri, text: &str, publish: bool| {
+            let settings = Settings::default();
+            let msg =
+                DiagnosticMessage::CheckSyntax((uri.clone(), text.to_owned(), settings, publish));
+            diag.handle_message(msg);
+        };
+        let messages = |diag: &Diagnostic| -> Vec<String> {
+            diag.published
+                .diagnostics_for(&a_uri)
+                .into_iter()
+                .map(|diagnostic| diagnostic.message)
+                .collect()
+        };
+        check(&mut diag, &a_uri, a_text, true);
+        assert_eq!(vec!["Unknown type `Foo`"], messages(&diag));
+        check(&mut diag, &b_uri, b_text, true);
+        assert_eq!(vec!["Unknown type `Foo`"], messages(&diag));
+
+        // Changes which aren't published don't refresh importers.
+        let fixed_text = "module test;\nstruct Foo {};\n";
+        check(&mut diag, &b_uri, fixed_text, false);
+        assert_eq!(vec!["Unknown type `Foo`"], messages(&diag));
+
+        // Fixing the type in b.mojom clears the diagnostic in a.mojom, though
+        // b.mojom isn't saved.
+        check(&mut diag, &b_uri, fixed_text, true);
+        assert!(messages(&diag).is_empty());
+        // Requests for b.mojom use its open text.
+        let pos = lsp_types::Position::new(1, 8);
+        assert!(diag.hover(b_uri.clone(), pos).is_some());
 
-fn find_definition_in_imported_files(
-    imported_files: &Option<ImportedFiles>,
-    ident: &str,
-) -> Option<lsp_types::Location> {
-    imported_files
-        .as_ref()
-        .and_then(|ref imported_files| imported_files.find_definition(ident))
+        // Closing b.mojom brings back the text on disk.
+        diag.handle_message(DiagnosticMessage::Close(b_uri));
+        assert_eq!(vec!["Unknown type `Foo`"], messages(&diag));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_references_by_kind_imported() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = create_uri("testdata/my_interface.mojom");
+        let service_uri = create_uri("testdata/my_service.mojom");
+        let pos = lsp_types::Position::new(3, 12);
+        let groups: Vec<_> = diag
+            .find_references_by_kind(uri.clone(), pos)
+            .into_iter()
+            .map(|group| {
+                let locations: Vec<_> = group
+                    .locations
+                    .into_iter()
+                    .map(|location| (location.uri, location.range.start))
+                    .collect();
+                (group.kind, locations)
+            })
+            .collect();
+        let expected = vec![
+            (
+                UsageKind::Declaration,
+                vec![(uri, lsp_types::Position::new(3, 10))],
+            ),
+            (
+                UsageKind::ReturnType,
+                vec![(service_uri.clone(), lsp_types::Position::new(4, 25))],
+            ),
+            (
+                UsageKind::Import,
+                vec![(service_uri, lsp_types::Position::new(0, 7))],
+            ),
+        ];
+        assert_eq!(expected, groups);
+    }
+
+    #[test]
+    fn test_find_definition_unknown_document() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = Uri::parse("file:///nonexistent/foo.mojom").unwrap();
+        let pos = lsp_types::Position::new(0, 0);
+        assert!(diag.find_definition(uri, pos).is_none());
+
+        let uri = Uri::parse("https://example.com/foo.mojom").unwrap();
+        assert!(diag.find_definition(uri, pos).is_none());
+    }
+
+    #[test]
+    fn test_find_definition_partial_result() {
+        let mut diag = create_diagnostic_for_test();
+        let uri = create_uri("testdata/my_service.mojom");
+        // Position on `MyInterface` in the response parameters.
+        let pos = lsp_types::Position::new(4, 28);
+        let link = diag.find_definition(uri, pos).unwrap();
+        assert_eq!(create_uri("testdata/my_interface.mojom"), link.target_uri);
+    }
 }
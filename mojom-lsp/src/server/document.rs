@@ -0,0 +1,168 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{Position, TextDocumentContentChangeEvent};
+
+use crate::syntax;
+
+/// An opened document.
+#[derive(Debug, Default)]
+pub(crate) struct Document {
+    /// The version sent by the client.
+    pub(crate) version: i64,
+    pub(crate) text: String,
+}
+
+/// Converts `pos` into a byte offset in `text`. `pos.character` is counted in
+/// UTF-16 code units as defined in the spec. Positions beyond the end of a
+/// line or the text are clamped.
+pub(crate) fn position_to_offset(text: &str, pos: &Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..pos.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut offset = line_start;
+    let mut col = 0;
+    for ch in text[line_start..].chars() {
+        if ch == '\n' || col >= pos.character as usize {
+            break;
+        }
+        col += ch.len_utf16();
+        offset += ch.len_utf8();
+    }
+    offset
+}
+
+// Dotted identifiers are treated as a single word.
+fn is_dotted_identifier_char(ch: char) -> bool {
+    syntax::is_identifier_char(ch) || ch == '.'
+}
+
+/// Returns the byte range of the (possibly dotted) identifier at `pos`, if
+/// any. Out-of-range positions are clamped, so this is safe to call with any
+/// position sent by clients.
+pub(crate) fn identifier_range_at(text: &str, pos: &Position) -> Option<std::ops::Range<usize>> {
+    let offset = position_to_offset(text, pos);
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .find(|&(_, ch)| !is_dotted_identifier_char(ch))
+        .map(|(i, ch)| i + ch.len_utf8())
+        .unwrap_or(0);
+    let end = text[offset..]
+        .find(|ch| !is_dotted_identifier_char(ch))
+        .map(|i| offset + i)
+        .unwrap_or(text.len());
+    if start == end {
+        None
+    } else {
+        Some(start..end)
+    }
+}
+
+/// Applies `changes` to `text` in order. Each change is applied against the
+/// text produced by the previous change.
+pub(crate) fn apply_content_changes(text: &mut String, changes: &[TextDocumentContentChangeEvent]) {
+    for change in changes {
+        match change.range {
+            Some(ref range) => {
+                let start = position_to_offset(text, &range.start);
+                let end = position_to_offset(text, &range.end);
+                let end = std::cmp::max(start, end);
+                text.replace_range(start..end, &change.text);
+            }
+            None => *text = change.text.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Range;
+
+    fn create_change(
+        start: (u64, u64),
+        end: (u64, u64),
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_position_to_offset() {
+        let text = "module a;\nstruct Foo {};\n";
+        assert_eq!(0, position_to_offset(text, &Position::new(0, 0)));
+        assert_eq!(10, position_to_offset(text, &Position::new(1, 0)));
+        assert_eq!(17, position_to_offset(text, &Position::new(1, 7)));
+        // Clamped to the end of the line.
+        assert_eq!(24, position_to_offset(text, &Position::new(1, 100)));
+        assert_eq!(text.len(), position_to_offset(text, &Position::new(5, 0)));
+    }
+
+    fn identifier_at<'a>(text: &'a str, pos: &Position) -> Option<&'a str> {
+        identifier_range_at(text, pos).map(|range| &text[range])
+    }
+
+    #[test]
+    fn test_identifier_range_at() {
+        let text = "struct Foo { a.b.Bar bar; };\nconst string kÄ = \"x\";";
+        assert_eq!(Some(7..10), identifier_range_at(text, &Position::new(0, 8)));
+        assert_eq!(Some("Foo"), identifier_at(text, &Position::new(0, 10)));
+        assert_eq!(Some("a.b.Bar"), identifier_at(text, &Position::new(0, 15)));
+        assert_eq!(None, identifier_at(text, &Position::new(0, 11)));
+        assert_eq!(Some("k"), identifier_at(text, &Position::new(1, 13)));
+        // Stops at non-ASCII characters.
+        assert_eq!(Some("k"), identifier_at(text, &Position::new(1, 14)));
+        assert_eq!(None, identifier_at(text, &Position::new(1, 15)));
+        assert_eq!(None, identifier_at(text, &Position::new(100, 100)));
+        assert_eq!(None, identifier_at("", &Position::new(0, 1)));
+    }
+
+    #[test]
+    fn test_apply_content_changes() {
+        let mut text = "module a;\nstruct Foo {};\n".to_owned();
+        let changes = vec![
+            // Insert a field.
+            create_change((1, 12), (1, 12), " int32 bar; "),
+            // Rename the struct.
+            create_change((1, 7), (1, 10), "FooBar"),
+            // Replace the module statement.
+            create_change((0, 0), (0, 9), "module b.c;"),
+        ];
+        apply_content_changes(&mut text, &changes);
+        assert_eq!("module b.c;\nstruct FooBar { int32 bar; };\n", text);
+
+        let changes = vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "module b.c;\nstruct FooBar { int32 bar; };\n".to_owned(),
+        }];
+        let mut full_text = String::new();
+        apply_content_changes(&mut full_text, &changes);
+        assert_eq!(full_text, text);
+    }
+}
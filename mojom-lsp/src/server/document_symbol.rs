@@ -0,0 +1,398 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{DocumentSymbol, SymbolInformation, SymbolKind};
+
+use crate::syntax::{self, InterfaceMember, MojomFile, Statement, StructBody};
+
+use super::definition::create_lsp_range;
+use super::mojomast::MojomAst;
+
+// Returns a range which starts at `first` and ends at `last`.
+fn join_ranges(first: &syntax::Range, last: &syntax::Range) -> syntax::Range {
+    syntax::Range {
+        start: first.start,
+        end: last.end,
+    }
+}
+
+// Returns `Some(true)` for declarations marked `[Deprecated]`.
+fn deprecated(ast: &MojomAst, attributes: &[syntax::Attribute]) -> Option<bool> {
+    if syntax::has_attribute(&ast.text, attributes, "Deprecated") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+fn create_symbol(
+    ast: &MojomAst,
+    kind: SymbolKind,
+    attributes: &[syntax::Attribute],
+    name: &syntax::Range,
+    range: &syntax::Range,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: ast.text(name).to_owned(),
+        detail: None,
+        kind: kind,
+        deprecated: deprecated(ast, attributes),
+        range: create_lsp_range(ast, range),
+        selection_range: create_lsp_range(ast, name),
+        children: if children.is_empty() {
+            None
+        } else {
+            Some(children)
+        },
+    }
+}
+
+fn const_symbol(ast: &MojomAst, node: &syntax::Const) -> DocumentSymbol {
+    let range = join_ranges(&node.typ, &node.value);
+    create_symbol(
+        ast,
+        SymbolKind::Constant,
+        &node.attributes,
+        &node.name,
+        &range,
+        Vec::new(),
+    )
+}
+
+fn enum_symbol(ast: &MojomAst, node: &syntax::Enum) -> DocumentSymbol {
+    let values = node
+        .values
+        .iter()
+        .map(|value| {
+            let last = value.value.as_ref().or(value.ordinal.as_ref());
+            let range = join_ranges(&value.name, last.unwrap_or(&value.name));
+            create_symbol(
+                ast,
+                SymbolKind::EnumMember,
+                &value.attributes,
+                &value.name,
+                &range,
+                Vec::new(),
+            )
+        })
+        .collect();
+    create_symbol(
+        ast,
+        SymbolKind::Enum,
+        &node.attributes,
+        &node.name,
+        &node.range,
+        values,
+    )
+}
+
+fn interface_symbol(ast: &MojomAst, node: &syntax::Interface) -> DocumentSymbol {
+    let members = node
+        .members
+        .iter()
+        .map(|member| match member {
+            InterfaceMember::Const(node) => const_symbol(ast, node),
+            InterfaceMember::Enum(node) => enum_symbol(ast, node),
+            InterfaceMember::Method(node) => create_symbol(
+                ast,
+                SymbolKind::Method,
+                &node.attributes,
+                &node.name,
+                &node.range,
+                Vec::new(),
+            ),
+        })
+        .collect();
+    create_symbol(
+        ast,
+        SymbolKind::Interface,
+        &node.attributes,
+        &node.name,
+        &node.range,
+        members,
+    )
+}
+
+fn struct_symbol(ast: &MojomAst, node: &syntax::Struct) -> DocumentSymbol {
+    let members = node
+        .members
+        .iter()
+        .map(|member| match member {
+            StructBody::Const(node) => const_symbol(ast, node),
+            StructBody::Enum(node) => enum_symbol(ast, node),
+            StructBody::Field(node) => {
+                let last = node.default.as_ref().or(node.ordinal.as_ref());
+                let range = join_ranges(&node.typ, last.unwrap_or(&node.name));
+                create_symbol(
+                    ast,
+                    SymbolKind::Field,
+                    &node.attributes,
+                    &node.name,
+                    &range,
+                    Vec::new(),
+                )
+            }
+        })
+        .collect();
+    create_symbol(
+        ast,
+        SymbolKind::Struct,
+        &node.attributes,
+        &node.name,
+        &node.range,
+        members,
+    )
+}
+
+fn union_symbol(ast: &MojomAst, node: &syntax::Union) -> DocumentSymbol {
+    let fields = node
+        .fields
+        .iter()
+        .map(|field| {
+            let range = join_ranges(&field.typ, field.ordinal.as_ref().unwrap_or(&field.name));
+            create_symbol(
+                ast,
+                SymbolKind::Field,
+                &field.attributes,
+                &field.name,
+                &range,
+                Vec::new(),
+            )
+        })
+        .collect();
+    // LSP has no symbol kind for unions.
+    create_symbol(
+        ast,
+        SymbolKind::Struct,
+        &node.attributes,
+        &node.name,
+        &node.range,
+        fields,
+    )
+}
+
+fn collect_symbols(ast: &MojomAst) -> Vec<DocumentSymbol> {
+    ast.mojom
+        .stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Module(node) => Some(create_symbol(
+                ast,
+                SymbolKind::Module,
+                &[],
+                &node.name,
+                &node.name,
+                Vec::new(),
+            )),
+            Statement::Import(_) => None,
+            Statement::Interface(node) => Some(interface_symbol(ast, node)),
+            Statement::Struct(node) => Some(struct_symbol(ast, node)),
+            Statement::Union(node) => Some(union_symbol(ast, node)),
+            Statement::Enum(node) => Some(enum_symbol(ast, node)),
+            Statement::Const(node) => Some(const_symbol(ast, node)),
+        })
+        .collect()
+}
+
+// Returns offsets just after `;`s outside of braces, before `limit`. These
+// are candidates for the end of the last complete top-level statement.
+// Braces in comments and strings aren't taken into account, so callers should
+// try every candidate.
+fn statement_ends(text: &str, limit: usize) -> Vec<usize> {
+    let mut depth = 0;
+    let mut ends = Vec::new();
+    for (i, ch) in text[..limit].char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ';' if depth <= 0 => ends.push(i + 1),
+            _ => (),
+        }
+    }
+    ends
+}
+
+// Parses `text`. When `text` has syntax errors, parses the longest prefix
+// which consists of complete top-level statements instead.
+pub(crate) fn parse_recoverable(text: &str) -> MojomFile {
+    let offset = match syntax::parse(text) {
+        Ok(mojom) => return mojom,
+        Err(err) => err.offset(),
+    };
+    for end in statement_ends(text, offset).into_iter().rev() {
+        if let Ok(mojom) = syntax::parse(&text[..end]) {
+            return mojom;
+        }
+    }
+    MojomFile { stmts: Vec::new() }
+}
+
+/// Returns the outline of a document. Declarations after a syntax error are
+/// omitted.
+pub(crate) fn document_symbols(uri: lsp_types::Url, text: String) -> Vec<DocumentSymbol> {
+    let mojom = parse_recoverable(&text);
+    let ast = MojomAst::from_mojom(uri, text, mojom);
+    collect_symbols(&ast)
+}
+
+/// Flattens `symbols` for clients which don't support hierarchical document
+/// symbols.
+pub(crate) fn flatten_symbols(
+    uri: &lsp_types::Url,
+    symbols: Vec<DocumentSymbol>,
+) -> Vec<SymbolInformation> {
+    fn flatten(
+        uri: &lsp_types::Url,
+        symbols: Vec<DocumentSymbol>,
+        container_name: Option<&str>,
+        flattened: &mut Vec<SymbolInformation>,
+    ) {
+        for symbol in symbols {
+            flattened.push(SymbolInformation {
+                name: symbol.name.clone(),
+                kind: symbol.kind,
+                deprecated: symbol.deprecated,
+                location: lsp_types::Location::new(uri.clone(), symbol.range),
+                container_name: container_name.map(|name| name.to_owned()),
+            });
+            if let Some(children) = symbol.children {
+                flatten(uri, children, Some(&symbol.name), flattened);
+            }
+        }
+    }
+
+    let mut flattened = Vec::new();
+    flatten(uri, symbols, None, &mut flattened);
+    flattened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::{Position, Range};
+
+    fn symbols(text: &str) -> Vec<DocumentSymbol> {
+        let uri = lsp_types::Url::parse("file:///foo.mojom").unwrap();
+        document_symbols(uri, text.to_owned())
+    }
+
+    fn names(symbols: &[DocumentSymbol]) -> Vec<&str> {
+        symbols.iter().map(|symbol| symbol.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let text = r#"module foo.mojom;
+import "bar.mojom";
+interface Foo {
+  const int32 kLimit = 10;
+  Bar(int32 a) => (bool ok);
+  Baz@1();
+};
+struct S {
+  enum Kind { kA, kB = 2 };
+  int32 x@0 = 1;
+};
+union U { int32 a; string b; };
+"#;
+        let symbols = symbols(text);
+        assert_eq!(vec!["foo.mojom", "Foo", "S", "U"], names(&symbols));
+        assert_eq!(SymbolKind::Module, symbols[0].kind);
+
+        let interface = &symbols[1];
+        assert_eq!(SymbolKind::Interface, interface.kind);
+        assert_eq!(
+            Range::new(Position::new(2, 0), Position::new(6, 2)),
+            interface.range
+        );
+        assert_eq!(
+            Range::new(Position::new(2, 10), Position::new(2, 13)),
+            interface.selection_range
+        );
+        let members = interface.children.as_ref().unwrap();
+        assert_eq!(vec!["kLimit", "Bar", "Baz"], names(members));
+        assert_eq!(SymbolKind::Constant, members[0].kind);
+        assert_eq!(SymbolKind::Method, members[1].kind);
+        assert_eq!(
+            Range::new(Position::new(4, 2), Position::new(4, 28)),
+            members[1].range
+        );
+        assert_eq!(
+            Range::new(Position::new(4, 2), Position::new(4, 5)),
+            members[1].selection_range
+        );
+        assert_eq!(SymbolKind::Method, members[2].kind);
+        assert!(members[2].children.is_none());
+
+        let members = symbols[2].children.as_ref().unwrap();
+        assert_eq!(vec!["Kind", "x"], names(members));
+        assert_eq!(SymbolKind::Enum, members[0].kind);
+        let values = members[0].children.as_ref().unwrap();
+        assert_eq!(vec!["kA", "kB"], names(values));
+        assert_eq!(SymbolKind::EnumMember, values[1].kind);
+        assert_eq!(SymbolKind::Field, members[1].kind);
+        assert_eq!(
+            Range::new(Position::new(9, 2), Position::new(9, 15)),
+            members[1].range
+        );
+
+        let fields = symbols[3].children.as_ref().unwrap();
+        assert_eq!(vec!["a", "b"], names(fields));
+    }
+
+    #[test]
+    fn test_document_symbols_broken_input() {
+        let text = "struct A { int32 a; };\ninterface B { Foo(); };\nstruct C { int32 };";
+        assert_eq!(vec!["A", "B"], names(&symbols(text)));
+
+        let text = "interface A {\n  Foo(\n";
+        assert!(symbols(text).is_empty());
+    }
+
+    #[test]
+    fn test_deprecated_symbols() {
+        let text = r#"[Deprecated] interface Old { [Deprecated] Foo(); Bar(); };
+interface New {};
+struct S { [Deprecated] int32 a; int32 b; };
+"#;
+        let symbols = symbols(text);
+        assert_eq!(vec!["Old", "New", "S"], names(&symbols));
+        assert_eq!(Some(true), symbols[0].deprecated);
+        assert_eq!(None, symbols[1].deprecated);
+        let methods = symbols[0].children.as_ref().unwrap();
+        assert_eq!(Some(true), methods[0].deprecated);
+        assert_eq!(None, methods[1].deprecated);
+        let fields = symbols[2].children.as_ref().unwrap();
+        assert_eq!(Some(true), fields[0].deprecated);
+        assert_eq!(None, fields[1].deprecated);
+
+        let uri = lsp_types::Url::parse("file:///foo.mojom").unwrap();
+        let flattened = flatten_symbols(&uri, symbols);
+        assert_eq!(Some(true), flattened[0].deprecated);
+    }
+
+    #[test]
+    fn test_flatten_symbols() {
+        let uri = lsp_types::Url::parse("file:///foo.mojom").unwrap();
+        let symbols = symbols("interface Foo { Bar(); };");
+        let flattened = flatten_symbols(&uri, symbols);
+        assert_eq!(2, flattened.len());
+        assert_eq!("Foo", flattened[0].name);
+        assert_eq!(None, flattened[0].container_name);
+        assert_eq!("Bar", flattened[1].name);
+        assert_eq!(Some("Foo".to_owned()), flattened[1].container_name);
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::syntax::{self, preorder, LexemeKind, Traversal};
+
+use super::document_symbol::parse_recoverable;
+
+fn line_of(text: &str, offset: usize) -> u64 {
+    syntax::line_col(text, offset).line as u64
+}
+
+// Creates a folding range from `start` to `end` offsets. Characters are
+// omitted when the client only supports folding whole lines. Returns None
+// when the range doesn't span multiple lines.
+fn create_folding_range(
+    text: &str,
+    start: usize,
+    end: usize,
+    kind: Option<FoldingRangeKind>,
+    line_folding_only: bool,
+) -> Option<FoldingRange> {
+    let start = syntax::line_col(text, start);
+    let end = syntax::line_col(text, end);
+    if end.line <= start.line {
+        return None;
+    }
+    let character = |col: usize| {
+        if line_folding_only {
+            None
+        } else {
+            Some(col as u64)
+        }
+    };
+    Some(FoldingRange {
+        start_line: start.line as u64,
+        start_character: character(start.col),
+        end_line: end.line as u64,
+        end_character: character(end.col),
+        kind: kind,
+    })
+}
+
+// Folds the body of a declaration between the braces. When only lines can be
+// folded, the line of the closing brace stays visible.
+fn block_range(
+    text: &str,
+    name: &syntax::Range,
+    range: &syntax::Range,
+    line_folding_only: bool,
+) -> Option<FoldingRange> {
+    let open = name.end + text[name.end..range.end].find('{')?;
+    let close = range.start + text[range.start..range.end].rfind('}')?;
+    let end = if line_folding_only {
+        // The line break before the line of the closing brace.
+        text[..close].rfind('\n')?
+    } else {
+        close
+    };
+    create_folding_range(text, open + 1, end, None, line_folding_only)
+}
+
+// Returns folding ranges of block comments and runs of line comments. Line
+// comments which follow code aren't folded.
+fn comment_ranges(text: &str, line_folding_only: bool) -> Vec<FoldingRange> {
+    let comment_range = |start, end| {
+        create_folding_range(
+            text,
+            start,
+            end,
+            Some(FoldingRangeKind::Comment),
+            line_folding_only,
+        )
+    };
+    let mut ranges = Vec::new();
+    // The start of the first comment, the end of the last comment and its
+    // line in the current run of line comments.
+    let mut run: Option<(usize, usize, u64)> = None;
+    for lexeme in syntax::lex(text) {
+        let syntax::Range { start, end } = lexeme.range;
+        match lexeme.kind {
+            LexemeKind::LineComment => {
+                let line_start = text[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+                if !text[line_start..start].trim().is_empty() {
+                    continue;
+                }
+                let line = line_of(text, start);
+                run = match run {
+                    Some((first, _, last)) if last + 1 == line => Some((first, end, line)),
+                    _ => {
+                        if let Some((first, last, _)) = run {
+                            ranges.extend(comment_range(first, last));
+                        }
+                        Some((start, end, line))
+                    }
+                };
+            }
+            LexemeKind::BlockComment => {
+                // Unterminated comments may end with a line break.
+                let end = start + text[start..end].trim_end().len();
+                ranges.extend(comment_range(start, end));
+            }
+            LexemeKind::String | LexemeKind::Code => (),
+        }
+    }
+    if let Some((first, last, _)) = run {
+        ranges.extend(comment_range(first, last));
+    }
+    ranges
+}
+
+// Returns folding ranges of consecutive import statements.
+fn import_ranges(
+    text: &str,
+    mojom: &syntax::MojomFile,
+    line_folding_only: bool,
+) -> Vec<FoldingRange> {
+    let import_range = |start, end| {
+        create_folding_range(
+            text,
+            start,
+            end,
+            Some(FoldingRangeKind::Imports),
+            line_folding_only,
+        )
+    };
+    let mut ranges = Vec::new();
+    // The start of the first path and the end of the last path.
+    let mut group: Option<(usize, usize)> = None;
+    for stmt in &mojom.stmts {
+        group = match (stmt, group) {
+            (syntax::Statement::Import(stmt), Some((first, _))) => Some((first, stmt.path.end)),
+            (syntax::Statement::Import(stmt), None) => Some((stmt.path.start, stmt.path.end)),
+            (_, Some((first, last))) => {
+                ranges.extend(import_range(first, last));
+                None
+            }
+            (_, None) => None,
+        };
+    }
+    if let Some((first, last)) = group {
+        ranges.extend(import_range(first, last));
+    }
+    ranges
+}
+
+/// Returns folding ranges of declaration bodies, comments and import
+/// statements in `text`. Declarations after a syntax error aren't folded.
+/// Ranges have characters unless `line_folding_only` is set.
+pub(crate) fn folding_ranges(text: &str, line_folding_only: bool) -> Vec<FoldingRange> {
+    let mojom = parse_recoverable(text);
+    let mut ranges = Vec::new();
+    for traversal in preorder(&mojom) {
+        let (name, range) = match traversal {
+            Traversal::EnterInterface(node) => (&node.name, &node.range),
+            Traversal::EnterStruct(node) => (&node.name, &node.range),
+            Traversal::Union(node) => (&node.name, &node.range),
+            Traversal::Enum(node) => (&node.name, &node.range),
+            _ => continue,
+        };
+        ranges.extend(block_range(text, name, range, line_folding_only));
+    }
+    ranges.extend(comment_ranges(text, line_folding_only));
+    ranges.extend(import_ranges(text, &mojom, line_folding_only));
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folds(text: &str) -> Vec<(u64, u64, Option<FoldingRangeKind>)> {
+        folding_ranges(text, true)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let text = r#"// Copyright
+// License
+module foo;
+
+import "a.mojom";
+import "b.mojom";
+
+/* Block
+   comment */
+interface Foo {
+  enum Kind {
+    kA,
+    kB,
+  };
+  Get() => (Kind kind);  // Trailing
+  Set(Kind kind);        // comments
+  Reset();
+};
+struct Empty {};
+"#;
+        let expected = vec![
+            (0, 1, Some(FoldingRangeKind::Comment)),
+            (4, 5, Some(FoldingRangeKind::Imports)),
+            (7, 8, Some(FoldingRangeKind::Comment)),
+            (9, 16, None),
+            (10, 12, None),
+        ];
+        assert_eq!(expected, folds(text));
+    }
+
+    #[test]
+    fn test_folding_ranges_syntax_error() {
+        let text = "struct Foo {\n  int32 a;\n};\nstruct Bar {\n  int32";
+        assert_eq!(vec![(0, 1, None)], folds(text));
+    }
+
+    #[test]
+    fn test_folding_ranges_with_characters() {
+        let text =
+            "import \"a.mojom\";\nimport \"b.mojom\";\n/* a\n b */\nstruct Foo {\n  int32 a;\n};";
+        let ranges: Vec<_> = folding_ranges(text, false)
+            .into_iter()
+            .map(|range| {
+                (
+                    range.start_line,
+                    range.start_character,
+                    range.end_line,
+                    range.end_character,
+                )
+            })
+            .collect();
+        let expected = vec![
+            (0, Some(7), 1, Some(16)),
+            (2, Some(0), 3, Some(5)),
+            // Between the braces.
+            (4, Some(12), 6, Some(0)),
+        ];
+        assert_eq!(expected, ranges);
+
+        // Only lines when the client requires them.
+        for range in folding_ranges(text, true) {
+            assert_eq!(None, range.start_character);
+            assert_eq!(None, range.end_character);
+        }
+        assert_eq!(
+            vec![(0, 1), (2, 3), (4, 5)],
+            folds(text)
+                .into_iter()
+                .map(|(s, e, _)| (s, e))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,775 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use lsp_types::{FormattingOptions, Position, Range, TextDocumentContentChangeEvent, TextEdit};
+
+use crate::syntax::{self, LexemeKind};
+
+use super::document::apply_content_changes;
+use super::semantic::ordinal_value;
+use super::settings::{FormatMode, Settings};
+
+// Returns the indentation unit for `options`.
+fn indent_unit(options: &FormattingOptions) -> String {
+    if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_owned()
+    }
+}
+
+// Nesting at the start of a line. Comments and string literals are skipped.
+#[derive(Default, Clone)]
+struct Nesting {
+    // Depth of braces.
+    braces: usize,
+    // Depth of parentheses, brackets and angle brackets which aren't closed
+    // yet. Lines inside them are often aligned by hand.
+    others: usize,
+    in_block_comment: bool,
+}
+
+// Returns the nesting at the start of each line of `text`.
+fn line_nestings(text: &str) -> Vec<Nesting> {
+    let mut nesting = Nesting::default();
+    let mut nestings = vec![nesting.clone()];
+    for lexeme in syntax::lex(text) {
+        let lexeme_text = &text[lexeme.range.start..lexeme.range.end];
+        match lexeme.kind {
+            LexemeKind::Code => {
+                for ch in lexeme_text.chars() {
+                    match ch {
+                        '\n' => nestings.push(nesting.clone()),
+                        '{' => nesting.braces += 1,
+                        '}' => nesting.braces = nesting.braces.saturating_sub(1),
+                        '(' | '[' => nesting.others += 1,
+                        ')' | ']' => nesting.others = nesting.others.saturating_sub(1),
+                        _ => (),
+                    }
+                }
+            }
+            LexemeKind::BlockComment => {
+                for _ in lexeme_text.matches('\n') {
+                    nestings.push(Nesting {
+                        in_block_comment: true,
+                        ..nesting.clone()
+                    });
+                }
+            }
+            // They don't contain line breaks.
+            LexemeKind::LineComment | LexemeKind::String => (),
+        }
+    }
+    nestings
+}
+
+// Returns the UTF-16 length of `text`.
+fn utf16_len(text: &str) -> u64 {
+    text.encode_utf16().count() as u64
+}
+
+/// Normalizes indentation of lines in braces and removes trailing
+/// whitespaces. Declarations and line breaks are left untouched, and only
+/// changed whitespaces are edited. Lines in comments, parentheses and
+/// brackets keep their indentation since they are often aligned by hand.
+pub(crate) fn format_whitespace(text: &str, options: &FormattingOptions) -> Vec<TextEdit> {
+    let unit = indent_unit(options);
+    let nestings = line_nestings(text);
+    let mut edits = Vec::new();
+    for ((i, line), nesting) in text.split('\n').enumerate().zip(nestings) {
+        let line_no = i as u64;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let content = line.trim_matches([' ', '\t']);
+        if content.is_empty() {
+            if !line.is_empty() {
+                let range = Range::new(
+                    Position::new(line_no, 0),
+                    Position::new(line_no, utf16_len(line)),
+                );
+                edits.push(TextEdit::new(range, String::new()));
+            }
+            continue;
+        }
+
+        // Indentation consists of ASCII characters only so byte lengths are
+        // also UTF-16 lengths.
+        let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        if !nesting.in_block_comment && nesting.others == 0 {
+            let closing = content.chars().take_while(|&ch| ch == '}').count();
+            let level = nesting.braces.saturating_sub(closing);
+            let indent = unit.repeat(level);
+            if line[..indent_len] != indent {
+                let range = Range::new(
+                    Position::new(line_no, 0),
+                    Position::new(line_no, indent_len as u64),
+                );
+                edits.push(TextEdit::new(range, indent));
+            }
+        }
+
+        let content_end = indent_len + content.len();
+        if content_end < line.len() {
+            let range = Range::new(
+                Position::new(line_no, utf16_len(&line[..content_end])),
+                Position::new(line_no, utf16_len(line)),
+            );
+            edits.push(TextEdit::new(range, String::new()));
+        }
+    }
+    edits
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    LineComment,
+    BlockComment,
+    // Identifiers, keywords, literals and ordinals.
+    Word,
+    Punct,
+}
+
+#[derive(Debug)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    // The number of line breaks between this token and the previous one.
+    newlines_before: usize,
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_' || ch == '.'
+}
+
+// Splits `text` into tokens. Whitespaces are dropped but line breaks are
+// remembered so that comments and blank lines can be preserved.
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut newlines = 0;
+    for lexeme in syntax::lex(text) {
+        let kind = match lexeme.kind {
+            LexemeKind::LineComment => TokenKind::LineComment,
+            LexemeKind::BlockComment => TokenKind::BlockComment,
+            LexemeKind::String => TokenKind::Word,
+            LexemeKind::Code => {
+                tokenize_code(text, &lexeme.range, &mut newlines, &mut tokens);
+                continue;
+            }
+        };
+        tokens.push(Token {
+            kind: kind,
+            text: text[lexeme.range.start..lexeme.range.end].trim_end(),
+            newlines_before: newlines,
+        });
+        newlines = 0;
+    }
+    tokens
+}
+
+// Splits code between comments and string literals into tokens.
+fn tokenize_code<'a>(
+    text: &'a str,
+    range: &syntax::Range,
+    newlines: &mut usize,
+    tokens: &mut Vec<Token<'a>>,
+) {
+    let code = &text[range.start..range.end];
+    let mut chars = code.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        let next = chars.peek().map(|&(_, ch)| ch);
+        let kind = match ch {
+            '\n' => {
+                *newlines += 1;
+                continue;
+            }
+            ch if ch.is_whitespace() => continue,
+            '=' if next == Some('>') => {
+                chars.next();
+                TokenKind::Punct
+            }
+            // Mojom has no arithmetic so a sign always belongs to a number.
+            '+' | '-' if next.is_some_and(|ch| ch.is_ascii_digit()) => {
+                while chars.next_if(|&(_, ch)| is_word_char(ch)).is_some() {}
+                TokenKind::Word
+            }
+            ch if is_word_char(ch) || ch == '@' => {
+                while chars.next_if(|&(_, ch)| is_word_char(ch)).is_some() {}
+                TokenKind::Word
+            }
+            _ => TokenKind::Punct,
+        };
+        let end = chars.peek().map(|&(i, _)| i).unwrap_or(code.len());
+        tokens.push(Token {
+            kind: kind,
+            text: &code[start..end],
+            newlines_before: *newlines,
+        });
+        *newlines = 0;
+    }
+}
+
+// An open brace, parenthesis, bracket or angle bracket.
+struct Open {
+    ch: char,
+    // True for attribute sections of top-level declarations. A line break
+    // follows them.
+    breaks_after_close: bool,
+}
+
+fn is_closing(token: &Token) -> bool {
+    token.kind == TokenKind::Punct && ["}", ")", "]", ">"].contains(&token.text)
+}
+
+fn is_comment(token: &Token) -> bool {
+    token.kind == TokenKind::LineComment || token.kind == TokenKind::BlockComment
+}
+
+// Whether `prev` and `next` are separated by a space on the same line.
+fn needs_space(prev: &Token, next: &Token, in_attribute: bool) -> bool {
+    if in_attribute && (prev.text == "=" || next.text == "=") {
+        return false;
+    }
+    if prev.text == "{" && next.text == "}" {
+        return false;
+    }
+    if next.kind == TokenKind::Punct
+        && [",", ";", ")", ">", "]", "?", "&", "<"].contains(&next.text)
+    {
+        return false;
+    }
+    if next.text.starts_with('@') {
+        return false;
+    }
+    if prev.kind == TokenKind::Punct && ["(", "<", "["].contains(&prev.text) {
+        return false;
+    }
+    if next.text == "(" {
+        return prev.text == "=>";
+    }
+    true
+}
+
+// Returns the range of the lines of `field`, including comments on the lines
+// right above it and at the end of its line so that they move with the
+// field. Returns None when the field shares a line with other code.
+fn field_lines(text: &str, field: &syntax::StructField) -> Option<syntax::Range> {
+    let line_start = text[..field.range.start].rfind('\n').map_or(0, |i| i + 1);
+    if !text[line_start..field.range.start].trim().is_empty() {
+        return None;
+    }
+    let line_end = text[field.range.end..]
+        .find('\n')
+        .map_or(text.len(), |i| field.range.end + i);
+    let rest = text[field.range.end..line_end].trim();
+    if !rest.is_empty() && !rest.starts_with("//") {
+        return None;
+    }
+
+    let mut start = line_start;
+    while start > 0 {
+        let prev_start = text[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        if !text[prev_start..start].trim_start().starts_with("//") {
+            break;
+        }
+        start = prev_start;
+    }
+    Some(syntax::Range {
+        start: start,
+        end: line_end,
+    })
+}
+
+// Returns the replacements which reorder fields of `stmt` by their ordinals.
+// Fields are serialized in the ordinal order, not in the source order. When
+// some ordinals are implicit, the order can't be changed without changing
+// the wire format, so the struct is left untouched.
+fn sort_fields(text: &str, stmt: &syntax::Struct) -> Vec<(syntax::Range, String)> {
+    let fields: Vec<&syntax::StructField> = stmt
+        .members
+        .iter()
+        .filter_map(|member| match member {
+            syntax::StructBody::Field(field) => Some(field),
+            _ => None,
+        })
+        .collect();
+    let ordinals: Option<Vec<u64>> = fields
+        .iter()
+        .map(|field| {
+            field
+                .ordinal
+                .as_ref()
+                .and_then(|ordinal| ordinal_value(text, ordinal))
+        })
+        .collect();
+    let ordinals = match ordinals {
+        Some(ordinals) => ordinals,
+        None => return Vec::new(),
+    };
+    if ordinals.windows(2).all(|pair| pair[0] < pair[1]) {
+        return Vec::new();
+    }
+    let mut unique = ordinals.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.len() != ordinals.len() {
+        return Vec::new();
+    }
+    let lines: Option<Vec<syntax::Range>> = fields
+        .iter()
+        .map(|field| field_lines(text, field))
+        .collect();
+    let lines = match lines {
+        Some(lines) => lines,
+        None => return Vec::new(),
+    };
+
+    let mut order: Vec<usize> = (0..fields.len()).collect();
+    order.sort_by_key(|&i| ordinals[i]);
+    lines
+        .iter()
+        .zip(order)
+        .map(|(slot, i)| {
+            (
+                slot.clone(),
+                syntax::partial_text(text, &lines[i]).to_owned(),
+            )
+        })
+        .collect()
+}
+
+// Reorders fields of each struct in `text` by their ordinals.
+fn sort_struct_fields(text: &str, mojom: &syntax::MojomFile) -> String {
+    let mut replacements = Vec::new();
+    for stmt in &mojom.stmts {
+        if let syntax::Statement::Struct(stmt) = stmt {
+            replacements.extend(sort_fields(text, stmt));
+        }
+    }
+    let mut sorted = text.to_owned();
+    // Replace from the end so that earlier ranges stay valid.
+    for (range, replacement) in replacements.iter().rev() {
+        sorted.replace_range(range.start..range.end, replacement);
+    }
+    sorted
+}
+
+/// Formats `text` in the canonical Mojom style: two space indentation (or
+/// as `options` specifies), one statement per line, attribute sections of
+/// declarations on their own lines and single spaces between tokens.
+/// Top-level declarations are separated by blank lines as `settings`
+/// specify, while imports stay grouped. Elsewhere comments and single blank
+/// lines are preserved. Returns None when `text` has syntax errors.
+pub(crate) fn format_document(
+    text: &str,
+    options: &FormattingOptions,
+    settings: &Settings,
+) -> Option<String> {
+    let mojom = syntax::parse(text).ok()?;
+    let sorted;
+    let text = if settings.sort_struct_fields_by_ordinal {
+        sorted = sort_struct_fields(text, &mojom);
+        &sorted
+    } else {
+        text
+    };
+
+    let unit = indent_unit(options);
+    let tokens = tokenize(text);
+    let mut out = String::new();
+    let mut stack: Vec<Open> = Vec::new();
+    let mut break_next = false;
+    // Whether the current top-level statement is an import. None before the
+    // first token of a statement.
+    let mut in_import: Option<bool> = None;
+    // Set at the end of a top-level statement, to whether it was an import.
+    let mut statement_end: Option<bool> = None;
+    for (i, token) in tokens.iter().enumerate() {
+        let prev = if i > 0 { Some(&tokens[i - 1]) } else { None };
+        let breaks = break_next
+            || match prev {
+                None => false,
+                Some(prev) => match (prev.kind, prev.text) {
+                    (TokenKind::LineComment, _) => true,
+                    (TokenKind::BlockComment, _) => token.newlines_before > 0,
+                    // Trailing comments stay on the same line.
+                    _ if token.newlines_before == 0
+                        && (token.kind == TokenKind::LineComment
+                            || token.kind == TokenKind::BlockComment) =>
+                    {
+                        false
+                    }
+                    // Comments on their own lines stay on their own lines.
+                    _ if token.newlines_before > 0
+                        && (token.kind == TokenKind::LineComment
+                            || token.kind == TokenKind::BlockComment) =>
+                    {
+                        true
+                    }
+                    (TokenKind::Punct, "{") => token.text != "}",
+                    (TokenKind::Punct, ";") => true,
+                    (TokenKind::Punct, ",") => stack.last().is_some_and(|open| open.ch == '{'),
+                    _ if token.text == "}" => true,
+                    _ => false,
+                },
+            };
+        break_next = false;
+        if breaks {
+            // Keep a blank line, but not at the beginning or the end of
+            // blocks.
+            let blank = token.newlines_before > 1
+                && prev.is_some_and(|prev| prev.text != "{")
+                && token.text != "}";
+            let blank_lines = match statement_end.take() {
+                Some(after_import) => match tokens[i..].iter().find(|next| !is_comment(next)) {
+                    Some(next) if after_import && next.text == "import" => usize::from(blank),
+                    Some(_) => settings.blank_lines_between_declarations,
+                    // Only comments follow.
+                    None => usize::from(blank),
+                },
+                None => usize::from(blank),
+            };
+            out.push_str(&"\n".repeat(blank_lines + 1));
+            let depth = stack.len() - if is_closing(token) { 1 } else { 0 };
+            out.push_str(&unit.repeat(depth));
+        } else if let Some(prev) = prev {
+            if token.kind == TokenKind::LineComment || token.kind == TokenKind::BlockComment {
+                out.push_str(
+                    if prev.text == ";" || prev.text == "," || prev.text == "{" {
+                        "  "
+                    } else {
+                        " "
+                    },
+                );
+            } else if prev.kind == TokenKind::BlockComment
+                || needs_space(prev, token, stack.last().is_some_and(|open| open.ch == '['))
+            {
+                out.push(' ');
+            }
+        }
+        out.push_str(token.text);
+
+        if stack.is_empty() && !is_comment(token) {
+            let is_import = *in_import.get_or_insert(token.text == "import");
+            if token.text == ";" {
+                statement_end = Some(is_import);
+                in_import = None;
+            }
+        }
+
+        if token.kind != TokenKind::Punct {
+            continue;
+        }
+        match token.text {
+            "{" | "(" | "[" | "<" => stack.push(Open {
+                ch: token.text.chars().next().unwrap(),
+                // Attributes of members stay on the same line.
+                breaks_after_close: token.text == "[" && stack.is_empty(),
+            }),
+            "}" | ")" | "]" | ">" => {
+                break_next = stack.pop().is_some_and(|open| open.breaks_after_close);
+            }
+            _ => (),
+        }
+    }
+    out.push('\n');
+    Some(out)
+}
+
+// Returns the position of the end of `text`.
+fn end_position(text: &str) -> Position {
+    let line = text.matches('\n').count() as u64;
+    let last_line = &text[text.rfind('\n').map(|i| i + 1).unwrap_or(0)..];
+    Position::new(line, utf16_len(last_line))
+}
+
+/// Returns an edit which replaces the whole document with the formatted
+/// text. Returns no edits when the document is already formatted or has
+/// syntax errors.
+pub(crate) fn format_canonical(
+    text: &str,
+    options: &FormattingOptions,
+    settings: &Settings,
+) -> Vec<TextEdit> {
+    match format_document(text, options, settings) {
+        Some(formatted) if formatted != text => {
+            let range = Range::new(Position::new(0, 0), end_position(text));
+            vec![TextEdit::new(range, formatted)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the formatting options which are used when the client doesn't
+/// specify them. These follow the Chromium style.
+pub(crate) fn default_options() -> FormattingOptions {
+    FormattingOptions {
+        tab_size: 2,
+        insert_spaces: true,
+        ..Default::default()
+    }
+}
+
+// Applies `edits`, which don't overlap and are sorted by position, to `text`.
+fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    // Edits are based on the original text. Apply them from the end.
+    let changes: Vec<_> = edits
+        .iter()
+        .rev()
+        .map(|edit| TextDocumentContentChangeEvent {
+            range: Some(edit.range),
+            range_length: None,
+            text: edit.new_text.clone(),
+        })
+        .collect();
+    let mut text = text.to_owned();
+    apply_content_changes(&mut text, &changes);
+    text
+}
+
+/// Formats `text` the same way as the server does on save. Returns None when
+/// `text` is already formatted, or can't be formatted because of syntax
+/// errors. Useful to check files in presubmits.
+pub fn format_check(text: &str, settings: &Settings) -> Option<String> {
+    let options = default_options();
+    let formatted = match settings.format_mode {
+        FormatMode::Canonical => format_document(text, &options, settings)?,
+        FormatMode::WhitespaceOnly => apply_edits(text, &format_whitespace(text, &options)),
+    };
+    if formatted == text {
+        None
+    } else {
+        Some(formatted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(tab_size: u32, insert_spaces: bool) -> FormattingOptions {
+        FormattingOptions {
+            tab_size: tab_size as _,
+            insert_spaces: insert_spaces,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_format_whitespace() {
+        let text = "module foo;
+
+interface Foo {
+      // Comment.
+Bar(int32 a,
+    string b) => ();
+    };
+
+struct Baz {   \n\tint32 a; // ü  \n  /* Block
+       comment */
+    bool b;
+};
+";
+        let expected = "module foo;
+
+interface Foo {
+  // Comment.
+  Bar(int32 a,
+    string b) => ();
+};
+
+struct Baz {
+  int32 a; // ü
+  /* Block
+       comment */
+  bool b;
+};
+";
+        let edits = format_whitespace(text, &options(2, true));
+        assert_eq!(expected, apply_edits(text, &edits));
+        // Only changed whitespaces are edited.
+        assert_eq!(7, edits.len());
+        assert_eq!(
+            Range::new(Position::new(9, 14), Position::new(9, 16)),
+            edits[5].range
+        );
+
+        // Already formatted.
+        assert!(format_whitespace(expected, &options(2, true)).is_empty());
+    }
+
+    #[test]
+    fn test_format_whitespace_options() {
+        let text = "struct Foo {\r\n  int32 a;\r\n};\r\n";
+        let edits = format_whitespace(text, &options(4, true));
+        assert_eq!(
+            "struct Foo {\r\n    int32 a;\r\n};\r\n",
+            apply_edits(text, &edits)
+        );
+
+        let edits = format_whitespace(text, &options(4, false));
+        assert_eq!(
+            "struct Foo {\r\n\tint32 a;\r\n};\r\n",
+            apply_edits(text, &edits)
+        );
+    }
+
+    #[test]
+    fn test_format_document() {
+        let text = std::fs::read_to_string("testdata/format/unformatted.mojom").unwrap();
+        let expected = std::fs::read_to_string("testdata/format/formatted.mojom").unwrap();
+        let formatted = format_document(&text, &options(2, true), &Settings::default()).unwrap();
+        assert_eq!(expected, formatted);
+        // Formatting is idempotent.
+        assert_eq!(
+            Some(expected.clone()),
+            format_document(&expected, &options(2, true), &Settings::default())
+        );
+
+        let edits = format_canonical(&text, &options(2, true), &Settings::default());
+        assert_eq!(1, edits.len());
+        assert_eq!(expected, apply_edits(&text, &edits));
+        assert!(format_canonical(&expected, &options(2, true), &Settings::default()).is_empty());
+    }
+
+    #[test]
+    fn test_format_document_options() {
+        let text = "interface Foo { Bar(); };";
+        assert_eq!(
+            Some("interface Foo {\n    Bar();\n};\n".to_owned()),
+            format_document(text, &options(4, true), &Settings::default())
+        );
+        assert_eq!(
+            Some("interface Foo {\n\tBar();\n};\n".to_owned()),
+            format_document(text, &options(4, false), &Settings::default())
+        );
+    }
+
+    #[test]
+    fn test_format_document_syntax_error() {
+        let text = "interface Foo {\nBar()\n};";
+        assert_eq!(
+            None,
+            format_document(text, &options(2, true), &Settings::default())
+        );
+        assert!(format_canonical(text, &options(2, true), &Settings::default()).is_empty());
+    }
+
+    #[test]
+    fn test_format_check() {
+        let text = std::fs::read_to_string("testdata/format/unformatted.mojom").unwrap();
+        let expected = std::fs::read_to_string("testdata/format/formatted.mojom").unwrap();
+        let settings = Settings::default();
+        assert_eq!(Some(expected.clone()), format_check(&text, &settings));
+        assert_eq!(None, format_check(&expected, &settings));
+
+        let mut settings = Settings::default();
+        settings.format_mode = FormatMode::WhitespaceOnly;
+        assert_eq!(
+            Some("struct Foo {\n  int32 a;\n};\n".to_owned()),
+            format_check("struct Foo {\nint32 a;  \n};\n", &settings)
+        );
+        assert_eq!(
+            None,
+            format_check("struct Foo {\n  int32 a;\n};\n", &settings)
+        );
+    }
+
+    #[test]
+    fn test_sort_struct_fields() {
+        let mut settings = Settings::default();
+        settings.sort_struct_fields_by_ordinal = true;
+        let text = "struct Foo {
+  int32 c@2;
+  int32 b@1;  // Trailing comment of b.
+  // Comment of a.
+  int32 a@0;
+};
+";
+        let expected = "struct Foo {
+  // Comment of a.
+  int32 a@0;
+  int32 b@1;  // Trailing comment of b.
+  int32 c@2;
+};
+";
+        assert_eq!(
+            Some(expected.to_owned()),
+            format_document(text, &options(2, true), &settings)
+        );
+        // Off by default.
+        assert_eq!(
+            Some(text.to_owned()),
+            format_document(text, &options(2, true), &Settings::default())
+        );
+
+        // Implicit ordinals are left untouched.
+        let text = "struct Foo {
+  int32 b@1;
+  int32 a;
+};
+";
+        assert_eq!(
+            Some(text.to_owned()),
+            format_document(text, &options(2, true), &settings)
+        );
+    }
+
+    #[test]
+    fn test_blank_lines_between_declarations() {
+        let text = "module foo;
+import \"a.mojom\";
+
+import \"b.mojom\";
+
+
+
+// Comment of Foo.
+struct Foo {};  // Trailing comment.
+enum Bar { kBaz };
+// Comment at the end.
+";
+        let expected = "module foo;
+
+import \"a.mojom\";
+
+import \"b.mojom\";
+
+// Comment of Foo.
+struct Foo {};  // Trailing comment.
+
+enum Bar {
+  kBaz
+};
+// Comment at the end.
+";
+        let formatted = format_document(text, &options(2, true), &Settings::default()).unwrap();
+        assert_eq!(expected, formatted);
+        // Formatting is idempotent.
+        assert_eq!(
+            Some(formatted.clone()),
+            format_document(&formatted, &options(2, true), &Settings::default())
+        );
+
+        let mut settings = Settings::default();
+        settings.blank_lines_between_declarations = 2;
+        let text = "module foo;\nimport \"a.mojom\";\nimport \"b.mojom\";\nstruct Foo {};\n";
+        let expected =
+            "module foo;\n\n\nimport \"a.mojom\";\nimport \"b.mojom\";\n\n\nstruct Foo {};\n";
+        let formatted = format_document(text, &options(2, true), &settings).unwrap();
+        assert_eq!(expected, formatted);
+        assert_eq!(
+            Some(formatted.clone()),
+            format_document(&formatted, &options(2, true), &settings)
+        );
+    }
+}
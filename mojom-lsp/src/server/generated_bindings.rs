@@ -0,0 +1,194 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lsp_types::{Location, Position, Range, Url};
+
+use crate::syntax::is_identifier_char;
+
+use super::document::position_to_offset;
+
+pub(crate) const OPEN_GENERATED_BINDING_COMMAND: &str = "mojom.openGeneratedBinding";
+
+// Suffixes which are appended to a mojom file name by the bindings generator,
+// e.g. `foo.mojom` -> `foo.mojom.h`. C++ bindings come first.
+const GENERATED_BINDING_SUFFIXES: &[&str] = &[".h", "-webui.js", "-lite.js"];
+
+/// Returns candidate paths of the generated bindings for `mojom_path`, which
+/// should be relative to `root_path`.
+pub(crate) fn generated_binding_paths(
+    root_path: &Path,
+    mojom_path: &Path,
+    generated_dirs: &[PathBuf],
+) -> Vec<PathBuf> {
+    let file_name = match mojom_path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => return Vec::new(),
+    };
+    let mut paths = Vec::new();
+    for generated_dir in generated_dirs {
+        let dir = root_path.join(generated_dir).join(mojom_path);
+        for suffix in GENERATED_BINDING_SUFFIXES {
+            paths.push(dir.with_file_name(format!("{}{}", file_name, suffix)));
+        }
+    }
+    paths
+}
+
+// Returns the column of the first occurrence of `word` in `line` which isn't a
+// part of another identifier.
+fn find_word(line: &str, word: &str) -> Option<usize> {
+    let mut start = 0;
+    while let Some(pos) = line[start..].find(word) {
+        let begin = start + pos;
+        let end = begin + word.len();
+        let is_word_start = line[..begin]
+            .chars()
+            .next_back()
+            .map_or(true, |ch| !is_identifier_char(ch));
+        let is_word_end = line[end..]
+            .chars()
+            .next()
+            .map_or(true, |ch| !is_identifier_char(ch));
+        if is_word_start && is_word_end {
+            return Some(begin);
+        }
+        start = end;
+    }
+    None
+}
+
+// Returns the position of the first line which declares `name` as a class,
+// or the first line which mentions `name`.
+fn find_symbol_position(text: &str, name: &str) -> Option<Position> {
+    let mut mentioned = None;
+    for (i, line) in text.lines().enumerate() {
+        let col = match find_word(line, name) {
+            Some(col) => col,
+            None => continue,
+        };
+        let pos = Position::new(i as u64, col as u64);
+        if line[..col].ends_with("class ") {
+            return Some(pos);
+        }
+        if mentioned.is_none() {
+            mentioned = Some(pos);
+        }
+    }
+    mentioned
+}
+
+fn symbol_name(text: &str, range: &Range) -> Option<String> {
+    // Characters of the range are UTF-16 code units.
+    let start = position_to_offset(text, &range.start);
+    let end = position_to_offset(text, &range.end);
+    text.get(start..end).map(|name| name.to_owned())
+}
+
+/// Returns the location of the generated binding of the symbol declared at
+/// `definition`.
+pub(crate) fn find_generated_binding(
+    root_path: &Path,
+    generated_dirs: &[PathBuf],
+    definition: &Location,
+) -> Option<Location> {
+    let path = definition.uri.to_file_path().ok()?;
+    let mut text = String::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .ok()?;
+    let name = symbol_name(&text, &definition.range)?;
+
+    let mojom_path = path.strip_prefix(root_path).ok()?;
+    for path in generated_binding_paths(root_path, mojom_path, generated_dirs) {
+        let mut text = String::new();
+        if File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .is_err()
+        {
+            continue;
+        }
+        let pos = find_symbol_position(&text, &name).unwrap_or(Position::new(0, 0));
+        let uri = match Url::from_file_path(&path) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        return Some(Location::new(uri, Range::new(pos, pos)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_binding_paths() {
+        let root_path = Path::new("/chromium/src");
+        let mojom_path = Path::new("foo/bar/my_service.mojom");
+        let generated_dirs = vec![PathBuf::from("out/Default/gen")];
+        let paths = generated_binding_paths(root_path, mojom_path, &generated_dirs);
+        assert_eq!(
+            PathBuf::from("/chromium/src/out/Default/gen/foo/bar/my_service.mojom.h"),
+            paths[0]
+        );
+        assert_eq!(
+            PathBuf::from("/chromium/src/out/Default/gen/foo/bar/my_service.mojom-webui.js"),
+            paths[1]
+        );
+    }
+
+    #[test]
+    fn test_symbol_name() {
+        let text = "module foo;\n/* \u{e9}\u{1f600} */ interface MyService {};\n";
+        let range = Range::new(Position::new(1, 20), Position::new(1, 29));
+        assert_eq!(Some("MyService".to_owned()), symbol_name(text, &range));
+    }
+
+    #[test]
+    fn test_find_generated_binding() {
+        let root_path = std::env::temp_dir().join(format!(
+            "mojom-lsp-test-generated-bindings-{}",
+            std::process::id()
+        ));
+        let mojom_path = root_path.join("foo/my_service.mojom");
+        let header_path = root_path.join("out/Default/gen/foo/my_service.mojom.h");
+        std::fs::create_dir_all(mojom_path.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(header_path.parent().unwrap()).unwrap();
+        std::fs::write(&mojom_path, "module foo;\ninterface MyService {};\n").unwrap();
+        std::fs::write(
+            &header_path,
+            "namespace foo {\nclass MyServiceProxy;\nclass MyService : public MyServiceInterfaceBase {\n",
+        )
+        .unwrap();
+
+        let definition = Location::new(
+            Url::from_file_path(&mojom_path).unwrap(),
+            Range::new(Position::new(1, 10), Position::new(1, 19)),
+        );
+        let generated_dirs = vec![PathBuf::from("out/Default/gen")];
+        let loc = find_generated_binding(&root_path, &generated_dirs, &definition).unwrap();
+        assert_eq!(Url::from_file_path(&header_path).unwrap(), loc.uri);
+        assert_eq!(Position::new(2, 6), loc.range.start);
+
+        let generated_dirs = vec![PathBuf::from("out/Release/gen")];
+        let loc = find_generated_binding(&root_path, &generated_dirs, &definition);
+        assert!(loc.is_none());
+
+        std::fs::remove_dir_all(&root_path).unwrap();
+    }
+}
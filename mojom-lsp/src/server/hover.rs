@@ -0,0 +1,497 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position};
+
+use crate::model::{
+    enclosing_scope, evaluate_enum, FileSymbols, SymbolKind, SymbolRef, SymbolTable,
+};
+use crate::syntax::{self, preorder, Traversal};
+
+use super::definition::create_lsp_range;
+use super::document::{identifier_range_at, position_to_offset};
+use super::imported_files::{resolve_import, ImportedFiles};
+use super::mojomast::MojomAst;
+
+fn create_hover(ast: &MojomAst, range: &syntax::Range, value: String) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: value,
+        }),
+        range: Some(create_lsp_range(ast, range)),
+    }
+}
+
+fn hover_import(
+    root_path: &Path,
+    include_dirs: &[PathBuf],
+    ast: &MojomAst,
+    imported_files: Option<&ImportedFiles>,
+    offset: usize,
+) -> Option<Hover> {
+    let stmt = ast.mojom.stmts.iter().find_map(|stmt| match stmt {
+        syntax::Statement::Import(stmt) if stmt.path.start <= offset && offset < stmt.path.end => {
+            Some(stmt)
+        }
+        _ => None,
+    })?;
+    let path = resolve_import(root_path, include_dirs, ast, stmt);
+    let summary = imported_files
+        .and_then(|imported_files| imported_files.summary(&path))
+        .unwrap_or_else(|| format!("Import not found: `{}`", path.display()));
+    Some(create_hover(ast, &stmt.path, summary))
+}
+
+// Lists members of the enum declared at `name` in `ast`.
+fn enum_summary(ast: &MojomAst, qualified_name: &str, name: &lsp_types::Range) -> Option<String> {
+    let stmt = preorder(&ast.mojom).find_map(|traversal| match traversal {
+        Traversal::Enum(stmt) if create_lsp_range(ast, &stmt.name) == *name => Some(stmt),
+        _ => None,
+    })?;
+    let mut summary = format!(
+        "enum `{}`\n\n| Member | Value |\n| --- | --- |\n",
+        qualified_name
+    );
+    for member in evaluate_enum(&ast.text, stmt) {
+        let value = match member.value {
+            Some(value) => value.to_string(),
+            None => "?".to_owned(),
+        };
+        summary.push_str(&format!("| `{}` | {} |\n", member.name, value));
+    }
+    Some(summary)
+}
+
+fn code_block(signature: String) -> String {
+    format!("```mojom\n{}\n```", signature)
+}
+
+// Returns e.g. `[MinVersion=1] `, or an empty string when there is no
+// attribute.
+fn attributes_text(ast: &MojomAst, attributes: &[syntax::Attribute]) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+    let attributes: Vec<String> = attributes
+        .iter()
+        .map(|attribute| match attribute.value {
+            Some(ref value) => format!("{}={}", ast.text(&attribute.name), ast.text(value)),
+            None => ast.text(&attribute.name).to_owned(),
+        })
+        .collect();
+    format!("[{}] ", attributes.join(", "))
+}
+
+fn ordinal_text(ast: &MojomAst, ordinal: &Option<syntax::Range>) -> String {
+    ordinal
+        .as_ref()
+        .map(|ordinal| ast.text(ordinal).to_owned())
+        .unwrap_or_default()
+}
+
+fn parameters_text(ast: &MojomAst, params: &[syntax::Parameter]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| {
+            format!(
+                "{} {}{}",
+                ast.text(&param.typ),
+                ast.text(&param.name),
+                ordinal_text(ast, &param.ordinal)
+            )
+        })
+        .collect();
+    params.join(", ")
+}
+
+fn method_signature(ast: &MojomAst, method: &syntax::Method) -> String {
+    let mut signature = format!(
+        "{}{}({})",
+        ast.text(&method.name),
+        ordinal_text(ast, &method.ordinal),
+        parameters_text(ast, &method.params)
+    );
+    if let Some(ref response) = method.response {
+        signature.push_str(&format!(" => ({})", parameters_text(ast, &response.params)));
+    }
+    signature.push(';');
+    signature
+}
+
+fn const_signature(ast: &MojomAst, stmt: &syntax::Const) -> String {
+    format!(
+        "const {} {} = {};",
+        ast.text(&stmt.typ),
+        ast.text(&stmt.name),
+        ast.text(&stmt.value)
+    )
+}
+
+fn struct_field_signature(ast: &MojomAst, field: &syntax::StructField) -> String {
+    let mut signature = format!(
+        "{}{} {}{}",
+        attributes_text(ast, &field.attributes),
+        ast.text(&field.typ),
+        ast.text(&field.name),
+        ordinal_text(ast, &field.ordinal)
+    );
+    if let Some(ref default) = field.default {
+        signature.push_str(&format!(" = {}", ast.text(default)));
+    }
+    signature.push(';');
+    signature
+}
+
+// Shows the declaration of the member or constant whose name is at `offset`.
+fn hover_declaration(ast: &MojomAst, offset: usize) -> Option<Hover> {
+    let contains = |range: &syntax::Range| range.start <= offset && offset <= range.end;
+    let (name, signature) = preorder(&ast.mojom).find_map(|traversal| match traversal {
+        Traversal::Method(node) if contains(&node.name) => {
+            Some((&node.name, method_signature(ast, node)))
+        }
+        Traversal::StructField(node) if contains(&node.name) => {
+            Some((&node.name, struct_field_signature(ast, node)))
+        }
+        Traversal::Const(node) if contains(&node.name) => {
+            Some((&node.name, const_signature(ast, node)))
+        }
+        Traversal::Union(node) => {
+            node.fields
+                .iter()
+                .find(|field| contains(&field.name))
+                .map(|field| {
+                    let signature = format!(
+                        "{} {}{};",
+                        ast.text(&field.typ),
+                        ast.text(&field.name),
+                        ordinal_text(ast, &field.ordinal)
+                    );
+                    (&field.name, signature)
+                })
+        }
+        Traversal::Enum(node) => {
+            let index = node.values.iter().position(|value| contains(&value.name))?;
+            let value = &node.values[index];
+            let member = &evaluate_enum(&ast.text, node)[index];
+            // Implicit values are listed in the hover of the enum.
+            let signature = match (&value.value, member.value) {
+                (Some(_), Some(evaluated)) => format!("{} = {}", member.name, evaluated),
+                (Some(expr), None) => format!("{} = {}", member.name, ast.text(expr)),
+                (None, _) => member.name.to_owned(),
+            };
+            let signature = format!("{}.{}", ast.text(&node.name), signature);
+            Some((&value.name, signature))
+        }
+        _ => None,
+    })?;
+    Some(create_hover(ast, name, code_block(signature)))
+}
+
+// Shows the symbol referred at `pos`. Enums are summarized with their
+// members.
+fn hover_symbol(ast: &MojomAst, symbols: Option<&SymbolTable>, pos: &Position) -> Option<Hover> {
+    let range = identifier_range_at(&ast.text, pos)?;
+    let scope = enclosing_scope(&ast.text, &ast.mojom, range.start);
+    let symbols = symbols?;
+    let symbol = symbols.lookup(&ast.text[range.clone()], &scope)?;
+    let range = syntax::Range {
+        start: range.start,
+        end: range.end,
+    };
+    let keyword = match symbol.symbol.kind {
+        SymbolKind::Interface => "interface",
+        SymbolKind::Struct => "struct",
+        SymbolKind::Union => "union",
+        SymbolKind::Const => match hover_const(ast, symbols, &symbol, &range) {
+            Some(hover) => return Some(hover),
+            None => "const",
+        },
+        SymbolKind::Enum => return hover_enum(ast, symbol, &range),
+    };
+    let signature = format!("{} {}", keyword, symbol.qualified_name());
+    Some(create_hover(ast, &range, code_block(signature)))
+}
+
+// Imported files are parsed again as we only keep their symbols.
+fn read_declaring_file(uri: &lsp_types::Url) -> Option<MojomAst> {
+    let path = uri.to_file_path().ok()?;
+    let text = std::fs::read_to_string(path).ok()?;
+    let mojom = syntax::parse(&text).ok()?;
+    Some(MojomAst::from_mojom(uri.clone(), text, mojom))
+}
+
+fn hover_enum(ast: &MojomAst, symbol: SymbolRef, range: &syntax::Range) -> Option<Hover> {
+    let name = &symbol.symbol.name;
+    let summary = if symbol.uri == &ast.uri {
+        enum_summary(ast, name, &symbol.symbol.range)
+    } else {
+        let declaring = read_declaring_file(symbol.uri)?;
+        enum_summary(&declaring, name, &symbol.symbol.range)
+    }?;
+    Some(create_hover(ast, range, summary))
+}
+
+// The maximum number of constants which are followed to evaluate a constant.
+// This also stops cyclic references.
+const MAX_CONST_REFERENCES: usize = 16;
+
+fn find_const<'a>(ast: &'a MojomAst, name: &lsp_types::Range) -> Option<&'a syntax::Const> {
+    preorder(&ast.mojom).find_map(|traversal| match traversal {
+        Traversal::Const(stmt) if create_lsp_range(ast, &stmt.name) == *name => Some(stmt),
+        _ => None,
+    })
+}
+
+// Calls `f` with the declaration of the constant `symbol`, the file which
+// declares it and symbols to resolve names in the file.
+fn with_const<T, F>(ast: &MojomAst, symbols: &SymbolTable, symbol: &SymbolRef, f: F) -> Option<T>
+where
+    F: FnOnce(&MojomAst, &SymbolTable, &syntax::Const) -> Option<T>,
+{
+    if symbol.uri == &ast.uri {
+        f(ast, symbols, find_const(ast, &symbol.symbol.range)?)
+    } else {
+        // Constants imported by the declaring file can't be resolved.
+        let declaring = read_declaring_file(symbol.uri)?;
+        let file =
+            FileSymbols::from_mojom(declaring.uri.clone(), &declaring.text, &declaring.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        f(
+            &declaring,
+            &symbols,
+            find_const(&declaring, &symbol.symbol.range)?,
+        )
+    }
+}
+
+// Evaluates the value of `stmt`. A value which refers to another constant is
+// replaced with the value of that constant. Returns None when the value
+// can't be evaluated, e.g. it refers to an enum member.
+fn evaluate_const(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    stmt: &syntax::Const,
+    followed: usize,
+) -> Option<String> {
+    let value = ast.text(&stmt.value);
+    let is_reference = value.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+        && !matches!(value, "true" | "false" | "default");
+    if !is_reference {
+        return Some(value.to_owned());
+    }
+    if followed >= MAX_CONST_REFERENCES {
+        return None;
+    }
+    let scope = enclosing_scope(&ast.text, &ast.mojom, stmt.value.start);
+    let symbol = symbols.lookup(value, &scope)?;
+    if !matches!(symbol.symbol.kind, SymbolKind::Const) {
+        return None;
+    }
+    with_const(ast, symbols, &symbol, |ast, symbols, stmt| {
+        evaluate_const(ast, symbols, stmt, followed + 1)
+    })
+}
+
+// Shows the declaration of the constant `symbol` with its evaluated value.
+fn hover_const(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    symbol: &SymbolRef,
+    range: &syntax::Range,
+) -> Option<Hover> {
+    let summary = with_const(ast, symbols, symbol, |declaring, symbols, stmt| {
+        let mut summary = code_block(const_signature(declaring, stmt));
+        if let Some(value) = evaluate_const(declaring, symbols, stmt, 0) {
+            summary.push_str(&format!("\n\nValue: `{}`", value));
+        }
+        Some(summary)
+    })?;
+    Some(create_hover(ast, range, summary))
+}
+
+pub(crate) fn hover(
+    root_path: &Path,
+    include_dirs: &[PathBuf],
+    ast: &MojomAst,
+    imported_files: Option<&ImportedFiles>,
+    symbols: Option<&SymbolTable>,
+    pos: &Position,
+) -> Option<Hover> {
+    let offset = position_to_offset(&ast.text, pos);
+    hover_import(root_path, include_dirs, ast, imported_files, offset)
+        .or_else(|| hover_declaration(ast, offset))
+        .or_else(|| hover_symbol(ast, symbols, pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::FileSymbols;
+
+    use super::super::imported_files::check_imports;
+
+    fn hover_text(input: &str, pos: Position) -> Option<String> {
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(input).unwrap();
+        let ast = MojomAst::from_mojom(uri, input.to_owned(), mojom);
+        let root_path = Path::new("testdata");
+        let imported_files = check_imports(root_path, &[], &ast, &Default::default());
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, imported_files.symbols().cloned());
+        let hover = hover(
+            root_path,
+            &[],
+            &ast,
+            Some(&imported_files),
+            Some(&symbols),
+            &pos,
+        );
+        hover.map(|hover| match hover.contents {
+            HoverContents::Markup(content) => content.value,
+            _ => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn test_hover_import() {
+        let input = "import \"my_interface.mojom\";\nimport \"nonexistent.mojom\";";
+
+        let value = hover_text(input, Position::new(0, 10)).unwrap();
+        let path = Path::new("testdata/my_interface.mojom")
+            .canonicalize()
+            .unwrap();
+        assert!(value.starts_with(&format!("`{}`", path.display())));
+        assert!(value.contains("Defines `MyInterface`"));
+        // Nested symbols aren't listed.
+        assert!(!value.contains("MyInnerEnum"));
+
+        let value = hover_text(input, Position::new(1, 10)).unwrap();
+        assert!(value.starts_with("Import not found"));
+
+        assert!(hover_text(input, Position::new(0, 2)).is_none());
+    }
+
+    #[test]
+    fn test_hover_enum() {
+        let input = "enum Color { kRed, kGreen = 5, kBlue };\nstruct S { Color c; };";
+
+        let value = hover_text(input, Position::new(1, 13)).unwrap();
+        assert!(value.starts_with("enum `Color`"));
+        assert!(value.contains("| `kRed` | 0 |"));
+        assert!(value.contains("| `kGreen` | 5 |"));
+        assert!(value.contains("| `kBlue` | 6 |"));
+
+        // On the declaration.
+        let value = hover_text(input, Position::new(0, 6)).unwrap();
+        assert!(value.starts_with("enum `Color`"));
+
+        // Not an enum.
+        let value = hover_text(input, Position::new(1, 8)).unwrap();
+        assert_eq!("```mojom\nstruct S\n```", value);
+    }
+
+    #[test]
+    fn test_hover_declaration() {
+        let input = "module foo;
+interface Foo {
+  Bar@1(string name, int32 count@2) => (bool ok);
+  Baz();
+};
+struct S {
+  [MinVersion=1] int32 x@3 = 5;
+  Foo? foo;
+};
+enum Color { kRed, kGreen = 5, kBlue, kCrimson = kRed };
+";
+        let value = hover_text(input, Position::new(2, 3)).unwrap();
+        assert_eq!(
+            "```mojom\nBar@1(string name, int32 count@2) => (bool ok);\n```",
+            value
+        );
+        let value = hover_text(input, Position::new(3, 2)).unwrap();
+        assert_eq!("```mojom\nBaz();\n```", value);
+
+        let value = hover_text(input, Position::new(6, 23)).unwrap();
+        assert_eq!("```mojom\n[MinVersion=1] int32 x@3 = 5;\n```", value);
+
+        let value = hover_text(input, Position::new(9, 20)).unwrap();
+        assert_eq!("```mojom\nColor.kGreen = 5\n```", value);
+        let value = hover_text(input, Position::new(9, 40)).unwrap();
+        assert_eq!("```mojom\nColor.kCrimson = 0\n```", value);
+
+        // A reference to an interface.
+        let value = hover_text(input, Position::new(7, 3)).unwrap();
+        assert_eq!("```mojom\ninterface foo.Foo\n```", value);
+    }
+
+    #[test]
+    fn test_hover_const() {
+        let input = "const int32 kMax = 100;
+const int32 kLimit = kMax;
+interface Foo {
+  const int32 kCount = kLimit;
+  const int32 kOther = Bar.kValue;
+};
+struct S {
+  int32 max = kMax;
+  int32 count = Foo.kCount;
+  int32 other = Foo.kOther;
+};
+";
+        let value = hover_text(input, Position::new(7, 15)).unwrap();
+        assert_eq!(
+            "```mojom\nconst int32 kMax = 100;\n```\n\nValue: `100`",
+            value
+        );
+        let value = hover_text(input, Position::new(1, 23)).unwrap();
+        assert_eq!(
+            "```mojom\nconst int32 kMax = 100;\n```\n\nValue: `100`",
+            value
+        );
+        // References are followed.
+        let value = hover_text(input, Position::new(8, 20)).unwrap();
+        assert_eq!(
+            "```mojom\nconst int32 kCount = kLimit;\n```\n\nValue: `100`",
+            value
+        );
+        // Unresolved references have no value.
+        let value = hover_text(input, Position::new(9, 20)).unwrap();
+        assert_eq!("```mojom\nconst int32 kOther = Bar.kValue;\n```", value);
+    }
+
+    #[test]
+    fn test_hover_declaration_range() {
+        let input = "interface Foo {\n  Bar(int32 a);\n};";
+        let uri = lsp_types::Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(input).unwrap();
+        let ast = MojomAst::from_mojom(uri, input.to_owned(), mojom);
+        let hover = hover_declaration(&ast, input.find("Bar").unwrap() + 1).unwrap();
+        let expected = lsp_types::Range::new(Position::new(1, 2), Position::new(1, 5));
+        assert_eq!(Some(expected), hover.range);
+    }
+
+    #[test]
+    fn test_hover_imported_enum() {
+        let input = "import \"my_interface.mojom\";\n\
+                     struct S { MyInterface.MyInnerEnum e; };";
+        let value = hover_text(input, Position::new(1, 25)).unwrap();
+        assert!(value.starts_with("enum `MyInterface.MyInnerEnum`"));
+        assert!(value.contains("| `kOne` | 0 |"));
+        assert!(value.contains("| `KThree` | 2 |"));
+    }
+}
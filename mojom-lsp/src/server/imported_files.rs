@@ -12,30 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use lsp_types::{Location, Range, Url};
+use lsp_types::Url;
 
-use crate::syntax::{self, preorder, Traversal};
+use crate::model::FileSymbols;
+use crate::syntax;
 
 use super::definition::create_lsp_range;
+use super::diagnostic::create_diagnostic;
 use super::mojomast::MojomAst;
-use super::semantic;
-
-#[derive(Debug)]
-struct ImportDefinition {
-    pub ident: String,
-    pub range: Range,
-}
-
-#[derive(Debug)]
-struct Import {
-    uri: Url,
-    module_name: Option<String>,
-    definitions: Vec<ImportDefinition>,
-}
 
 #[derive(Debug)]
 enum ImportError {
@@ -53,121 +42,247 @@ impl From<std::io::Error> for ImportError {
     }
 }
 
-type ImportResult = std::result::Result<Import, ImportError>;
+type ImportResult = std::result::Result<FileSymbols, ImportError>;
 
 #[derive(Debug)]
 pub(crate) struct ImportedFiles {
-    parsed_imports: Vec<ImportResult>,
+    // Pairs of a resolved path and its parse result. Direct imports come
+    // first in the order of import statements, followed by files which are
+    // imported indirectly.
+    parsed_imports: Vec<(PathBuf, ImportResult)>,
+    // Normalized directories which imports are resolved against. Empty when
+    // there is no workspace root.
+    include_roots: Vec<PathBuf>,
 }
 
+// The maximum number of symbols shown in import summaries.
+const MAX_SUMMARY_SYMBOLS: usize = 10;
+
 impl ImportedFiles {
-    pub(crate) fn find_definition(&self, ident: &str) -> Option<Location> {
-        let valid_imports = self.parsed_imports.iter().filter_map(|i| i.as_ref().ok());
-        for imported in valid_imports {
-            for definition in &imported.definitions {
-                if definition.ident == ident {
-                    let loc = Location::new(imported.uri.clone(), definition.range.clone());
-                    return Some(loc);
-                }
+    /// Returns a markdown summary of the imported file at `path`, or None
+    /// when `path` isn't imported.
+    pub(crate) fn summary(&self, path: &Path) -> Option<String> {
+        let (path, imported) = self.parsed_imports.iter().find(|(p, _)| p == path)?;
+        let imported = match imported {
+            Ok(imported) => imported,
+            Err(ImportError::NotFound(_)) => {
+                return Some(format!("Import not found: `{}`", path.display()));
+            }
+            Err(ImportError::IoError(err)) => {
+                return Some(format!("`{}`\n\nFailed to read: {}", path.display(), err));
+            }
+            Err(ImportError::SyntaxError(err)) => {
+                return Some(format!("`{}`\n\nFailed to parse: {}", path.display(), err));
+            }
+        };
 
-                if let Some(module_name) = &imported.module_name {
-                    let canonocal_name = format!("{}.{}", module_name, definition.ident);
-                    if canonocal_name == ident {
-                        let loc = Location::new(imported.uri.clone(), definition.range.clone());
-                        return Some(loc);
-                    }
-                }
+        let resolved = imported
+            .uri
+            .to_file_path()
+            .unwrap_or_else(|_| path.to_owned());
+        let mut summary = format!("`{}`", resolved.display());
+        // Only top-level symbols.
+        let symbols = imported
+            .symbols
+            .iter()
+            .filter(|symbol| !symbol.name.contains('.'))
+            .map(|symbol| format!("`{}`", symbol.name))
+            .collect::<Vec<_>>();
+        if !symbols.is_empty() {
+            summary.push_str("\n\nDefines ");
+            let num_shown = std::cmp::min(symbols.len(), MAX_SUMMARY_SYMBOLS);
+            summary.push_str(&symbols[..num_shown].join(", "));
+            if symbols.len() > num_shown {
+                summary.push_str(&format!(" and {} more", symbols.len() - num_shown));
             }
         }
-        None
+        Some(summary)
+    }
+
+    /// Returns diagnostics for import statements in `ast` whose targets can't
+    /// be found, or are outside include roots. `ast` should be the document
+    /// these imports are checked for.
+    pub(crate) fn diagnostics(&self, ast: &MojomAst) -> Vec<lsp_types::Diagnostic> {
+        let stmts = ast.mojom.stmts.iter().filter_map(|stmt| match stmt {
+            syntax::Statement::Import(stmt) => Some(stmt),
+            _ => None,
+        });
+        stmts
+            .zip(self.parsed_imports.iter())
+            .filter_map(|(stmt, (path, imported))| match imported {
+                Err(ImportError::NotFound(_)) => {
+                    let range = create_lsp_range(ast, &stmt.path);
+                    let message = format!("Import not found: {}", path.display());
+                    Some(create_diagnostic(range, message))
+                }
+                _ => {
+                    let resolved = normalize_path(path);
+                    if self.is_in_include_roots(&resolved) {
+                        return None;
+                    }
+                    // The build only resolves imports against include roots.
+                    let range = create_lsp_range(ast, &stmt.path);
+                    let message = format!(
+                        "Import resolves outside include roots: {}",
+                        resolved.display()
+                    );
+                    let mut diagnostic = create_diagnostic(range, message);
+                    diagnostic.severity = Some(lsp_types::DiagnosticSeverity::Warning);
+                    Some(diagnostic)
+                }
+            })
+            .collect()
+    }
+
+    fn is_in_include_roots(&self, path: &Path) -> bool {
+        self.include_roots.is_empty()
+            || self.include_roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Returns true when all imported files, including indirectly imported
+    /// ones, were parsed successfully.
+    pub(crate) fn is_complete(&self) -> bool {
+        self.parsed_imports
+            .iter()
+            .all(|(_, imported)| imported.is_ok())
+    }
+
+    /// Returns normalized paths of imported files, including indirectly
+    /// imported ones.
+    pub(crate) fn paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.parsed_imports
+            .iter()
+            .map(|(path, _)| normalize_path(path))
+    }
+
+    /// Returns symbols of imported files, including indirectly imported
+    /// ones, which were parsed successfully.
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = &FileSymbols> {
+        self.parsed_imports
+            .iter()
+            .filter_map(|(_, imported)| imported.as_ref().ok())
     }
 }
 
-pub(crate) fn check_imports<P: AsRef<Path>>(root_path: P, ast: &MojomAst) -> ImportedFiles {
+/// Resolves the path of an imported file. The root path is searched first,
+/// then `include_dirs`.
+pub(crate) fn resolve_import(
+    root_path: &Path,
+    include_dirs: &[PathBuf],
+    ast: &MojomAst,
+    stmt: &syntax::Import,
+) -> PathBuf {
+    resolve_import_path(root_path, include_dirs, ast.text(&stmt.path))
+}
+
+// Same as resolve_import() but takes the quoted path of an import statement.
+fn resolve_import_path(root_path: &Path, include_dirs: &[PathBuf], path: &str) -> PathBuf {
+    // Strip quotes.
+    let path = path.get(1..path.len().saturating_sub(1)).unwrap_or("");
+    std::iter::once(root_path.to_owned())
+        .chain(include_dirs.iter().map(|dir| root_path.join(dir)))
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| root_path.join(path))
+}
+
+/// Returns a path which identifies a file regardless of how it is referred.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Parses files imported from `ast`, and files imported from them. Each file
+/// is parsed at most once so cyclic imports are fine. Files in `open_texts`,
+/// keyed by normalized path, are parsed from the text instead of the disk.
+pub(crate) fn check_imports<P: AsRef<Path>>(
+    root_path: P,
+    include_dirs: &[PathBuf],
+    ast: &MojomAst,
+    open_texts: &HashMap<PathBuf, &str>,
+) -> ImportedFiles {
     let root_path = root_path.as_ref();
+    let mut visited = HashSet::new();
+    if let Ok(path) = ast.uri.to_file_path() {
+        visited.insert(normalize_path(&path));
+    }
+
     let mut parsed_imports = Vec::new();
+    let mut pending = Vec::new();
     for stmt in &ast.mojom.stmts {
         match stmt {
             syntax::Statement::Import(stmt) => {
-                let path = ast.text(&stmt.path);
-                let path = root_path.join(&path[1..path.len() - 1]);
-                let imported = parse_imported(&path);
-                parsed_imports.push(imported);
+                let path = resolve_import(root_path, include_dirs, ast, stmt);
+                visited.insert(normalize_path(&path));
+                let imported = parse_imported(&path, open_texts).map(|(symbols, imports)| {
+                    pending.extend(imports);
+                    symbols
+                });
+                parsed_imports.push((path, imported));
             }
             _ => (),
         }
     }
 
+    // Indirect imports.
+    while let Some(import) = pending.pop() {
+        let path = resolve_import_path(root_path, include_dirs, &import);
+        if !visited.insert(normalize_path(&path)) {
+            continue;
+        }
+        let imported = parse_imported(&path, open_texts).map(|(symbols, imports)| {
+            pending.extend(imports);
+            symbols
+        });
+        parsed_imports.push((path, imported));
+    }
+
+    let include_roots = if root_path.as_os_str().is_empty() {
+        Vec::new()
+    } else {
+        std::iter::once(root_path.to_owned())
+            .chain(include_dirs.iter().map(|dir| root_path.join(dir)))
+            .map(|dir| normalize_path(&dir))
+            .collect()
+    };
+
     ImportedFiles {
         parsed_imports: parsed_imports,
+        include_roots: include_roots,
     }
 }
 
-fn add_definition<'a, 'b, 'c>(
-    field: &'a syntax::Range,
-    ast: &'b MojomAst,
-    path: &'c mut Vec<&'b str>,
-    definitions: &'c mut Vec<ImportDefinition>,
-) {
-    let name = ast.text(field);
-    path.push(name);
-    let ident = path.join(".");
-    path.pop();
-    let range = create_lsp_range(&ast, field);
-    definitions.push(ImportDefinition {
-        ident: ident,
-        range: range,
-    });
-}
-
-fn parse_imported<P: AsRef<Path>>(path: P) -> ImportResult {
-    let mut text = String::new();
-    File::open(path.as_ref())?.read_to_string(&mut text)?;
+// Returns symbols of the file at `path` and quoted paths of its imports.
+fn parse_imported<P: AsRef<Path>>(
+    path: P,
+    open_texts: &HashMap<PathBuf, &str>,
+) -> std::result::Result<(FileSymbols, Vec<String>), ImportError> {
+    let path = normalize_path(path.as_ref());
+    let text = match open_texts.get(&path) {
+        Some(text) => (*text).to_owned(),
+        None => {
+            let mut text = String::new();
+            File::open(&path)?.read_to_string(&mut text)?;
+            text
+        }
+    };
 
     let mojom = syntax::parse(&text).map_err(|err| ImportError::SyntaxError(err.to_string()))?;
 
-    // Unwrap shoud be safe because we opened file already.
-    let path = path.as_ref().canonicalize().unwrap();
+    // Unwrap shoud be safe because the file exists or it was opened from a
+    // file URI, so the path is absolute.
     let uri = Url::from_file_path(&path).unwrap();
 
-    // TODO: Maybe store semantics errors.
-    let analysis = semantic::check_semantics(&text, &mojom);
-    let ast = MojomAst::from_mojom(uri, text, mojom, analysis.module);
-
-    let mut path = Vec::new();
-    let mut definitions: Vec<ImportDefinition> = Vec::new();
-    for traversal in preorder(&ast.mojom) {
-        match traversal {
-            Traversal::EnterInterface(node) => {
-                add_definition(&node.name, &ast, &mut path, &mut definitions);
-                let name = ast.text(&node.name);
-                path.push(name);
-            }
-            Traversal::LeaveInterface(_) => {
-                path.pop();
-            }
-            Traversal::EnterStruct(node) => {
-                add_definition(&node.name, &ast, &mut path, &mut definitions);
-                let name = ast.text(&node.name);
-                path.push(name);
-            }
-            Traversal::LeaveStruct(_) => {
-                path.pop();
+    let imports = mojom
+        .stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            syntax::Statement::Import(stmt) => {
+                Some(text[stmt.path.start..stmt.path.end].to_owned())
             }
-            Traversal::Union(node) => add_definition(&node.name, &ast, &mut path, &mut definitions),
-            Traversal::Enum(node) => add_definition(&node.name, &ast, &mut path, &mut definitions),
-            Traversal::Const(node) => add_definition(&node.name, &ast, &mut path, &mut definitions),
-            _ => (),
-        }
-    }
-
-    let module_name = ast.module_name().map(|name| name.to_owned());
-
-    Ok(Import {
-        uri: ast.uri.clone(),
-        module_name: module_name,
-        definitions: definitions,
-    })
+            _ => None,
+        })
+        .collect();
+    Ok((FileSymbols::from_mojom(uri, &text, &mojom), imports))
 }
 
 #[cfg(test)]
@@ -181,8 +296,10 @@ mod tests {
 
     #[test]
     fn test_parse_imported() {
-        let res = parse_imported("testdata/my_interface.mojom");
-        assert!(res.is_ok());
+        let (symbols, imports) =
+            parse_imported("testdata/my_interface.mojom", &HashMap::new()).unwrap();
+        assert!(symbols.find("MyInterface").is_some());
+        assert_eq!(vec!["\"my_service.mojom\""], imports);
     }
 
     #[test]
@@ -196,12 +313,113 @@ mod tests {
             .unwrap();
         let uri = create_uri(&file_path);
         let mojom = syntax::parse(&text).unwrap();
-        let analytics = semantic::check_semantics(&text, &mojom);
-        let ast = MojomAst::from_mojom(uri, text, mojom, analytics.module);
+        let ast = MojomAst::from_mojom(uri, text, mojom);
 
-        let imports = check_imports(&root_path, &ast);
+        let imports = check_imports(&root_path, &[], &ast, &HashMap::new());
 
-        let res = imports.find_definition("FooStruct.FooEnum");
+        let res = imports
+            .symbols()
+            .find_map(|file| file.find("FooStruct.FooEnum"));
         assert!(res.is_some());
     }
+
+    fn check_imports_of(path: &str, text: &str) -> (MojomAst, ImportedFiles) {
+        let uri = create_uri(path);
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let imports = check_imports("testdata", &[], &ast, &HashMap::new());
+        (ast, imports)
+    }
+
+    #[test]
+    fn test_check_imports_cyclic() {
+        // my_interface.mojom imports my_service.mojom, which imports
+        // my_interface.mojom and foo_module/foo.mojom.
+        let text = std::fs::read_to_string("testdata/my_interface.mojom").unwrap();
+        let (_, imports) = check_imports_of("testdata/my_interface.mojom", &text);
+        let mut uris: Vec<_> = imports
+            .symbols()
+            .map(|file| file.uri.path().rsplit('/').next().unwrap().to_owned())
+            .collect();
+        uris.sort();
+        assert_eq!(vec!["foo.mojom", "my_service.mojom"], uris);
+
+        // Symbols of indirectly imported files are available.
+        let res = imports.symbols().find_map(|file| file.find("FooStruct"));
+        assert!(res.is_some());
+    }
+
+    #[test]
+    fn test_check_imports_open_texts() {
+        let text = "import \"my_interface.mojom\";";
+        let uri = create_uri("testdata/my_service.mojom");
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let path = Path::new("testdata/my_interface.mojom")
+            .canonicalize()
+            .unwrap();
+        let mut open_texts = HashMap::new();
+        open_texts.insert(path.clone(), "struct Edited {};");
+        let imports = check_imports("testdata", &[], &ast, &open_texts);
+        assert_eq!(vec![path], imports.paths().collect::<Vec<_>>());
+        let symbols = imports.symbols().next().unwrap();
+        assert!(symbols.find("Edited").is_some());
+        assert!(symbols.find("MyInterface").is_none());
+    }
+
+    #[test]
+    fn test_import_diagnostics() {
+        let text = "import \"my_interface.mojom\";\nimport \"nonexistent.mojom\";";
+        let (ast, imports) = check_imports_of("testdata/my_service.mojom", text);
+        let diagnostics = imports.diagnostics(&ast);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Error),
+            diagnostics[0].severity
+        );
+        let expected = lsp_types::Range::new(
+            lsp_types::Position::new(1, 7),
+            lsp_types::Position::new(1, 26),
+        );
+        assert_eq!(expected, diagnostics[0].range);
+        assert!(diagnostics[0].message.starts_with("Import not found"));
+    }
+
+    #[test]
+    fn test_import_outside_include_roots() {
+        let text = "import \"foo_module/foo.mojom\";";
+        let (ast, imports) = check_imports_of("testdata/my_service.mojom", text);
+        assert!(imports.diagnostics(&ast).is_empty());
+
+        let text = "import \"../my_service.mojom\";";
+        let uri = create_uri("testdata/foo_module/foo.mojom");
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let imports = check_imports("testdata/foo_module", &[], &ast, &HashMap::new());
+        let diagnostics = imports.diagnostics(&ast);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Warning),
+            diagnostics[0].severity
+        );
+        let resolved = Path::new("testdata/my_service.mojom")
+            .canonicalize()
+            .unwrap();
+        assert_eq!(
+            format!(
+                "Import resolves outside include roots: {}",
+                resolved.display()
+            ),
+            diagnostics[0].message
+        );
+
+        // Include directories are include roots too.
+        let imports = check_imports(
+            "testdata/foo_module",
+            &[PathBuf::from("..")],
+            &ast,
+            &HashMap::new(),
+        );
+        assert!(imports.diagnostics(&ast).is_empty());
+    }
 }
@@ -15,97 +15,403 @@
 use std::io::{BufRead, Write};
 
 use anyhow::anyhow;
+use serde_json::Value;
 
-use super::protocol::{read_message, write_success_result, Message};
+use super::completion;
+use super::generated_bindings::OPEN_GENERATED_BINDING_COMMAND;
+use super::protocol::{read_message, write_success_result, Message, RequestId};
+use super::pull_diagnostics;
+use super::references::REFERENCES_BY_KIND_COMMAND;
+use super::semantic_tokens;
+
+fn create_text_document_sync(
+    client_capabilities: &lsp_types::ClientCapabilities,
+) -> lsp_types::TextDocumentSyncCapability {
+    let synchronization = client_capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.synchronization.as_ref());
+    let did_save = synchronization
+        .and_then(|synchronization| synchronization.did_save)
+        .unwrap_or(false);
+
+    // Clients send only changed ranges, which are applied to the documents we
+    // keep. Ask clients that can send `didSave` to include the text so that we
+    // can still analyze documents on save even if the client doesn't send
+    // `didOpen`/`didChange` as we expect.
+    let save = if did_save {
+        Some(lsp_types::SaveOptions {
+            include_text: Some(true),
+        })
+    } else {
+        None
+    };
 
-fn create_server_capabilities() -> lsp_types::ServerCapabilities {
     let options = lsp_types::TextDocumentSyncOptions {
         open_close: Some(true),
-        change: Some(lsp_types::TextDocumentSyncKind::Full),
+        change: Some(lsp_types::TextDocumentSyncKind::Incremental),
         will_save: None,
-        will_save_wait_until: None,
-        save: None,
+        // Formats documents on save when `formatOnSave` is enabled.
+        will_save_wait_until: Some(true),
+        save: save,
     };
+    lsp_types::TextDocumentSyncCapability::Options(options)
+}
 
-    let text_document_sync = lsp_types::TextDocumentSyncCapability::Options(options);
+pub(crate) fn create_server_capabilities(
+    client_capabilities: &lsp_types::ClientCapabilities,
+) -> lsp_types::ServerCapabilities {
+    let text_document_sync = create_text_document_sync(client_capabilities);
 
     // TODO: Understand each field and avoid using None if applicable.
     lsp_types::ServerCapabilities {
         text_document_sync: Some(text_document_sync),
         selection_range_provider: None,
-        hover_provider: None,
-        completion_provider: None,
+        hover_provider: Some(true),
+        completion_provider: Some(lsp_types::CompletionOptions {
+            resolve_provider: None,
+            trigger_characters: Some(
+                completion::TRIGGER_CHARACTERS
+                    .iter()
+                    .map(|ch| (*ch).to_owned())
+                    .collect(),
+            ),
+            work_done_progress_options: Default::default(),
+        }),
         signature_help_provider: None,
         definition_provider: Some(true),
         type_definition_provider: None,
         implementation_provider: None,
-        references_provider: None,
-        document_highlight_provider: None,
-        document_symbol_provider: None,
-        workspace_symbol_provider: None,
-        code_action_provider: None,
+        references_provider: Some(true),
+        document_highlight_provider: Some(true),
+        document_symbol_provider: Some(true),
+        workspace_symbol_provider: Some(true),
+        code_action_provider: Some(lsp_types::CodeActionProviderCapability::Simple(true)),
         code_lens_provider: None,
-        document_formatting_provider: None,
+        document_formatting_provider: Some(true),
         document_range_formatting_provider: None,
         document_on_type_formatting_provider: None,
-        rename_provider: None,
+        rename_provider: Some(lsp_types::RenameProviderCapability::Options(
+            lsp_types::RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: Default::default(),
+            },
+        )),
         document_link_provider: None,
         color_provider: None,
-        folding_range_provider: None,
+        folding_range_provider: Some(lsp_types::FoldingRangeProviderCapability::Simple(true)),
         declaration_provider: Some(false),
-        execute_command_provider: None,
-        workspace: None,
+        execute_command_provider: Some(lsp_types::ExecuteCommandOptions {
+            commands: vec![
+                OPEN_GENERATED_BINDING_COMMAND.to_owned(),
+                REFERENCES_BY_KIND_COMMAND.to_owned(),
+                completion::COMPLETION_ACCEPTED_COMMAND.to_owned(),
+            ],
+            work_done_progress_options: Default::default(),
+        }),
+        workspace: Some(lsp_types::WorkspaceCapability {
+            workspace_folders: Some(lsp_types::WorkspaceFolderCapability {
+                supported: Some(true),
+                change_notifications: Some(
+                    lsp_types::WorkspaceFolderCapabilityChangeNotifications::Bool(true),
+                ),
+            }),
+        }),
         experimental: None,
     }
 }
 
+/// The outcome of the initialize handshake.
+#[derive(Debug)]
+pub(crate) enum Initialization {
+    /// Contains the initialize params, the client capabilities as JSON since
+    /// lsp-types doesn't know newer capabilities, and the server capabilities.
+    Initialized(
+        lsp_types::InitializeParams,
+        Value,
+        lsp_types::ServerCapabilities,
+    ),
+    /// The client sent `exit` before the handshake completed. Contains the
+    /// exit code.
+    Exited(i32),
+}
+
+// https://microsoft.github.io/language-server-protocol/specification#exit
+fn exit_code(shutdown_requested: bool) -> i32 {
+    if shutdown_requested {
+        0
+    } else {
+        1
+    }
+}
+
+fn respond_to_shutdown(writer: &mut impl Write, id: RequestId) -> anyhow::Result<()> {
+    write_success_result(writer, id, Value::Null)
+}
+
+/// Performs the initialize handshake. Clients may send `shutdown` and `exit`
+/// before the handshake completes.
 pub(crate) fn initialize(
     reader: &mut impl BufRead,
     writer: &mut impl Write,
-) -> anyhow::Result<lsp_types::InitializeParams> {
+) -> anyhow::Result<Initialization> {
     use lsp_types::notification::Notification;
     use lsp_types::request::Request;
 
-    let message = read_message(reader)?;
-    let (id, params) = match message {
-        Message::Request(req) => {
-            if req.method != lsp_types::request::Initialize::METHOD {
-                let error_message = anyhow!("Expected initialize message but got {:?}", req.method);
+    let mut shutdown_requested = false;
+    let (id, params, client_capabilities_json) = loop {
+        let message = read_message(reader)?;
+        match message {
+            Message::Request(req) if req.method == lsp_types::request::Shutdown::METHOD => {
+                shutdown_requested = true;
+                respond_to_shutdown(writer, req.id)?;
+            }
+            Message::Notofication(notif)
+                if notif.method == lsp_types::notification::Exit::METHOD =>
+            {
+                return Ok(Initialization::Exited(exit_code(shutdown_requested)));
+            }
+            Message::Request(req) => {
+                if req.method != lsp_types::request::Initialize::METHOD {
+                    let error_message =
+                        anyhow!("Expected initialize message but got {:?}", req.method);
+                    return Err(error_message);
+                }
+                let client_capabilities_json = req.params["capabilities"].clone();
+                let params = serde_json::from_value::<lsp_types::InitializeParams>(req.params)?;
+                break (req.id, params, client_capabilities_json);
+            }
+            _ => {
+                let error_message = anyhow!("Expected initialize message but got {:?}", message);
                 return Err(error_message);
             }
-            let params = serde_json::from_value::<lsp_types::InitializeParams>(req.params)?;
-            (req.id, params)
-        }
-        _ => {
-            let error_message = anyhow!("Expected initialize message but got {:?}", message);
-            return Err(error_message);
         }
     };
 
-    let capabilities = create_server_capabilities();
+    let capabilities = create_server_capabilities(&params.capabilities);
     let res = lsp_types::InitializeResult {
-        capabilities: capabilities,
+        capabilities: capabilities.clone(),
         server_info: Some(lsp_types::ServerInfo {
             name: "mojom-lsp".to_string(),
             version: Some("0.1.0".to_string()),
         }),
     };
+    let mut res = serde_json::to_value(&res)?;
+    // lsp-types doesn't know pull diagnostics and semantic tokens.
+    res["capabilities"]["diagnosticProvider"] = pull_diagnostics::diagnostic_provider();
+    res["capabilities"]["semanticTokensProvider"] = semantic_tokens::semantic_tokens_provider();
     write_success_result(writer, id, res)?;
 
-    let message = read_message(reader)?;
-    match message {
-        Message::Notofication(notif) => {
-            if notif.method != lsp_types::notification::Initialized::METHOD {
-                let error_message =
-                    anyhow!("Expected initialized message but got {:?}", notif.method);
+    loop {
+        let message = read_message(reader)?;
+        match message {
+            Message::Notofication(notif)
+                if notif.method == lsp_types::notification::Exit::METHOD =>
+            {
+                return Ok(Initialization::Exited(exit_code(shutdown_requested)));
+            }
+            Message::Request(req) if req.method == lsp_types::request::Shutdown::METHOD => {
+                shutdown_requested = true;
+                respond_to_shutdown(writer, req.id)?;
+            }
+            Message::Notofication(notif) => {
+                if notif.method != lsp_types::notification::Initialized::METHOD {
+                    let error_message =
+                        anyhow!("Expected initialized message but got {:?}", notif.method);
+                    return Err(error_message);
+                }
+                break;
+            }
+            _ => {
+                let error_message = anyhow!("Expected initialized message but got {:?}", message);
                 return Err(error_message);
             }
         }
-        _ => {
-            let error_message = anyhow!("Expected initialized message but got {:?}", message);
-            return Err(error_message);
+    }
+
+    Ok(Initialization::Initialized(
+        params,
+        client_capabilities_json,
+        capabilities,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_sync_options(
+        capabilities: &lsp_types::ServerCapabilities,
+    ) -> &lsp_types::TextDocumentSyncOptions {
+        match capabilities.text_document_sync {
+            Some(lsp_types::TextDocumentSyncCapability::Options(ref options)) => options,
+            _ => panic!("Expected text document sync options"),
+        }
+    }
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn test_initialized_without_params() {
+        let input = frame(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"processId":null,"rootUri":null,"capabilities":{}}}"#,
+        ) + &frame(r#"{"jsonrpc":"2.0","method":"initialized"}"#);
+        let mut reader = std::io::BufReader::new(input.as_bytes());
+        let mut writer = Vec::new();
+        let res = initialize(&mut reader, &mut writer);
+        assert!(res.is_ok());
+
+        let output = String::from_utf8(writer).unwrap();
+        let body = &output[output.find("\r\n\r\n").unwrap() + 4..];
+        let res: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            serde_json::json!(true),
+            res["result"]["capabilities"]["diagnosticProvider"]["interFileDependencies"]
+        );
+        assert_eq!(
+            serde_json::json!(true),
+            res["result"]["capabilities"]["semanticTokensProvider"]["full"]
+        );
+    }
+
+    fn responses(writer: Vec<u8>) -> Vec<serde_json::Value> {
+        let output = String::from_utf8(writer).unwrap();
+        output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|message| {
+                let body = &message[message.find("\r\n\r\n").unwrap() + 4..];
+                serde_json::from_str(body).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exit_before_initialize() {
+        let input = frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+        let mut reader = std::io::BufReader::new(input.as_bytes());
+        let mut writer = Vec::new();
+        match initialize(&mut reader, &mut writer).unwrap() {
+            Initialization::Exited(exit_code) => assert_eq!(1, exit_code),
+            res => panic!("Unexpected result: {:?}", res),
         }
-    };
+        assert!(writer.is_empty());
 
-    Ok(params)
+        let input = frame(r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#)
+            + &frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+        let mut reader = std::io::BufReader::new(input.as_bytes());
+        let mut writer = Vec::new();
+        match initialize(&mut reader, &mut writer).unwrap() {
+            Initialization::Exited(exit_code) => assert_eq!(0, exit_code),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let responses = responses(writer);
+        assert_eq!(1, responses.len());
+        assert_eq!(serde_json::json!(1), responses[0]["id"]);
+        assert_eq!(serde_json::Value::Null, responses[0]["result"]);
+    }
+
+    #[test]
+    fn test_exit_before_initialized() {
+        let input = frame(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"processId":null,"rootUri":null,"capabilities":{}}}"#,
+        ) + &frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+        let mut reader = std::io::BufReader::new(input.as_bytes());
+        let mut writer = Vec::new();
+        match initialize(&mut reader, &mut writer).unwrap() {
+            Initialization::Exited(exit_code) => assert_eq!(1, exit_code),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_unexpected_first_message() {
+        let input = frame(
+            r#"{"jsonrpc":"2.0","method":"textDocument/didClose","params":{"textDocument":{"uri":"file:///foo.mojom"}}}"#,
+        );
+        let mut reader = std::io::BufReader::new(input.as_bytes());
+        let mut writer = Vec::new();
+        assert!(initialize(&mut reader, &mut writer).is_err());
+    }
+
+    // Guards against silent changes of the capabilities JSON, e.g. when
+    // updating lsp-types.
+    #[test]
+    fn test_server_capabilities_json() {
+        let client_capabilities: lsp_types::ClientCapabilities =
+            serde_json::from_value(serde_json::json!({})).unwrap();
+        let capabilities = create_server_capabilities(&client_capabilities);
+        let expected = serde_json::json!({
+            "textDocumentSync": {
+                "openClose": true,
+                "change": 2,
+                "willSaveWaitUntil": true,
+            },
+            "hoverProvider": true,
+            "completionProvider": {
+                "triggerCharacters": [".", "\"", "/"],
+            },
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "documentHighlightProvider": true,
+            "documentSymbolProvider": true,
+            "workspaceSymbolProvider": true,
+            "codeActionProvider": true,
+            "documentFormattingProvider": true,
+            "renameProvider": { "prepareProvider": true },
+            "foldingRangeProvider": true,
+            "declarationProvider": false,
+            "executeCommandProvider": {
+                "commands": [
+                    "mojom.openGeneratedBinding",
+                    "mojom.referencesByKind",
+                    "mojom.completionAccepted",
+                ],
+            },
+            "workspace": {
+                "workspaceFolders": { "supported": true, "changeNotifications": true },
+            },
+        });
+        assert_eq!(expected, serde_json::to_value(&capabilities).unwrap());
+    }
+
+    #[test]
+    fn test_sync_capabilities_without_client_sync() {
+        let client_capabilities: lsp_types::ClientCapabilities =
+            serde_json::from_value(serde_json::json!({})).unwrap();
+        let capabilities = create_server_capabilities(&client_capabilities);
+        let options = get_sync_options(&capabilities);
+        assert_eq!(Some(true), options.open_close);
+        assert_eq!(
+            Some(lsp_types::TextDocumentSyncKind::Incremental),
+            options.change
+        );
+        assert!(options.save.is_none());
+    }
+
+    #[test]
+    fn test_sync_capabilities_full_sync_client() {
+        let client_capabilities: lsp_types::ClientCapabilities =
+            serde_json::from_value(serde_json::json!({
+                "textDocument": {
+                    "synchronization": {
+                        "dynamicRegistration": false,
+                        "willSave": false,
+                        "willSaveWaitUntil": false,
+                        "didSave": true
+                    }
+                }
+            }))
+            .unwrap();
+        let capabilities = create_server_capabilities(&client_capabilities);
+        let options = get_sync_options(&capabilities);
+        assert_eq!(
+            Some(lsp_types::TextDocumentSyncKind::Incremental),
+            options.change
+        );
+        let save = options.save.as_ref().unwrap();
+        assert_eq!(Some(true), save.include_text);
+    }
 }
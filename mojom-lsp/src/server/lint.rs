@@ -0,0 +1,552 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Lints are optional checks which don't make a mojom file invalid but
+// usually indicate a problem worth looking at during review.
+
+use crate::syntax::{self, preorder, MojomFile, Traversal};
+
+use super::codeaction::{CodeActionIndex, Fix};
+use super::diagnostic;
+use super::semantic::{field_ordinals, method_ordinals, ordinal_value};
+use super::settings::{NamingConvention, Settings};
+
+fn create_lint(
+    text: &str,
+    range: &syntax::Range,
+    severity: lsp_types::DiagnosticSeverity,
+    message: String,
+) -> lsp_types::Diagnostic {
+    let start = syntax::line_col(text, range.start);
+    let end = syntax::line_col(text, range.end);
+    let range = diagnostic::into_lsp_range(&start, &end);
+    let mut diagnostic = diagnostic::create_diagnostic(range, message);
+    diagnostic.severity = Some(severity);
+    diagnostic
+}
+
+fn create_warning(text: &str, range: &syntax::Range, message: String) -> lsp_types::Diagnostic {
+    create_lint(text, range, lsp_types::DiagnosticSeverity::Warning, message)
+}
+
+// Returns the range of `{ ... }` of a declaration, if it has a body.
+fn body_range(text: &str, name: &syntax::Range, decl: &syntax::Range) -> Option<syntax::Range> {
+    let body = text.get(name.end..decl.end)?;
+    let start = name.end + body.find('{')?;
+    let end = name.end + body.rfind('}')? + 1;
+    Some(syntax::Range {
+        start: start,
+        end: end,
+    })
+}
+
+fn check_empty_body(
+    text: &str,
+    kind: &str,
+    name: &syntax::Range,
+    decl: &syntax::Range,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    if let Some(body) = body_range(text, name, decl) {
+        let message = format!("Empty {} {}", kind, &text[name.start..name.end]);
+        let severity = lsp_types::DiagnosticSeverity::Hint;
+        diagnostics.push(create_lint(text, &body, severity, message));
+    }
+}
+
+fn check_method_limits(
+    text: &str,
+    method: &syntax::Method,
+    settings: &Settings,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let name = &text[method.name.start..method.name.end];
+
+    let num_params = method.params.len();
+    if num_params > settings.max_method_params {
+        let message = format!(
+            "Method {} has {} parameters (more than {}). Consider grouping them into a struct.",
+            name, num_params, settings.max_method_params
+        );
+        diagnostics.push(create_warning(text, &method.name, message));
+    }
+
+    if let Some(ref ordinal) = method.ordinal {
+        // Skip the leading `@`.
+        let value = text[ordinal.start + 1..ordinal.end].parse::<u64>();
+        let too_large = match value {
+            Ok(value) => value > settings.max_method_ordinal as u64,
+            Err(_) => true,
+        };
+        if too_large {
+            let message = format!(
+                "Method ordinal {} exceeds {}",
+                &text[ordinal.start..ordinal.end],
+                settings.max_method_ordinal
+            );
+            diagnostics.push(create_warning(text, ordinal, message));
+        }
+    }
+}
+
+fn is_stable(text: &str, attributes: &[syntax::Attribute]) -> bool {
+    syntax::has_attribute(text, attributes, "Stable")
+}
+
+// Members of `[Stable]` declarations should have explicit ordinals so that
+// reordering members doesn't break compatibility. `members` are pairs of a
+// member name and its ordinal, in declaration order.
+fn check_stable_ordinals(
+    text: &str,
+    kind: &str,
+    name: &syntax::Range,
+    members: &[(&syntax::Range, &Option<syntax::Range>)],
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+    code_actions: &mut CodeActionIndex,
+) {
+    let decl_name = &text[name.start..name.end];
+    // The ordinal which would be assigned implicitly to the next member.
+    let mut next_ordinal = Some(0);
+    for (member_name, ordinal) in members {
+        if let Some(ordinal) = ordinal {
+            next_ordinal = ordinal_value(text, ordinal).map(|value| value + 1);
+            continue;
+        }
+
+        let message = format!(
+            "{} in [Stable] {} {} should have an explicit ordinal",
+            &text[member_name.start..member_name.end],
+            kind,
+            decl_name
+        );
+        let diagnostic = create_warning(text, member_name, message);
+        if let Some(value) = next_ordinal {
+            let pos = syntax::line_col(text, member_name.end);
+            let range = diagnostic::into_lsp_range(&pos, &pos);
+            let fix = Fix {
+                title: format!("Insert ordinal @{}", value),
+                edits: vec![lsp_types::TextEdit::new(range, format!("@{}", value))],
+            };
+            code_actions.add(&diagnostic, fix);
+        }
+        diagnostics.push(diagnostic);
+        next_ordinal = next_ordinal.map(|value| value + 1);
+    }
+}
+
+fn check_response_naming(
+    text: &str,
+    method: &syntax::Method,
+    convention: NamingConvention,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+    code_actions: &mut CodeActionIndex,
+) {
+    let params = match method.response {
+        Some(ref response) => &response.params,
+        None => return,
+    };
+    for param in params {
+        let name = &text[param.name.start..param.name.end];
+        let expected = convention.apply(name);
+        if name == expected {
+            continue;
+        }
+        let message = format!(
+            "Response parameter {} of {} should be named {}",
+            name,
+            &text[method.name.start..method.name.end],
+            expected
+        );
+        let severity = lsp_types::DiagnosticSeverity::Hint;
+        let diagnostic = create_lint(text, &param.name, severity, message);
+        let fix = Fix {
+            title: format!("Rename to {}", expected),
+            edits: vec![lsp_types::TextEdit::new(diagnostic.range, expected)],
+        };
+        code_actions.add(&diagnostic, fix);
+        diagnostics.push(diagnostic);
+    }
+}
+
+// Chromium mojom files are indented with two spaces.
+const INDENT: &str = "  ";
+
+// A line whose indentation contains tabs, and the indentation which should
+// replace it.
+struct IndentationFix {
+    range: lsp_types::Range,
+    new_text: String,
+}
+
+fn find_tab_indentations(text: &str) -> Vec<IndentationFix> {
+    let mut fixes = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        // Indentation consists of ASCII characters only so byte lengths are
+        // also UTF-16 lengths.
+        let len = line.len() - line.trim_start_matches(|ch| ch == ' ' || ch == '\t').len();
+        let indentation = &line[..len];
+        if !indentation.contains('\t') {
+            continue;
+        }
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(i as u64, 0),
+            lsp_types::Position::new(i as u64, len as u64),
+        );
+        fixes.push(IndentationFix {
+            range: range,
+            new_text: indentation.replace('\t', INDENT),
+        });
+    }
+    fixes
+}
+
+fn check_indentation(
+    text: &str,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+    code_actions: &mut CodeActionIndex,
+) {
+    for fix in find_tab_indentations(text) {
+        let message = "Use spaces instead of tabs for indentation".to_owned();
+        let mut diagnostic = diagnostic::create_diagnostic(fix.range, message);
+        diagnostic.severity = Some(lsp_types::DiagnosticSeverity::Hint);
+        let fix = Fix {
+            title: "Convert indentation to spaces".to_owned(),
+            edits: vec![lsp_types::TextEdit::new(fix.range, fix.new_text)],
+        };
+        code_actions.add(&diagnostic, fix);
+        diagnostics.push(diagnostic);
+    }
+}
+
+// Hints the part of each line which exceeds `max_line_length`. Columns are
+// counted in UTF-16 code units, the same as LSP positions.
+fn check_line_length(
+    text: &str,
+    max_line_length: usize,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    for (i, line) in text.lines().enumerate() {
+        let len = line.encode_utf16().count();
+        if len <= max_line_length {
+            continue;
+        }
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(i as u64, max_line_length as u64),
+            lsp_types::Position::new(i as u64, len as u64),
+        );
+        let message = format!(
+            "Line is {} columns long (more than {})",
+            len, max_line_length
+        );
+        let mut diagnostic = diagnostic::create_diagnostic(range, message);
+        diagnostic.severity = Some(lsp_types::DiagnosticSeverity::Hint);
+        diagnostics.push(diagnostic);
+    }
+}
+
+/// Checks lints which only look at the text. These work even when the text
+/// can't be parsed.
+pub(crate) fn check_text_lints(
+    text: &str,
+    settings: &Settings,
+    code_actions: &mut CodeActionIndex,
+) -> Vec<lsp_types::Diagnostic> {
+    let mut diagnostics = Vec::new();
+    check_indentation(text, &mut diagnostics, code_actions);
+    if let Some(max_line_length) = settings.max_line_length {
+        check_line_length(text, max_line_length, &mut diagnostics);
+    }
+    diagnostics
+}
+
+pub(crate) fn check_lints(
+    text: &str,
+    mojom: &MojomFile,
+    settings: &Settings,
+    code_actions: &mut CodeActionIndex,
+) -> Vec<lsp_types::Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::Method(method) => {
+                check_method_limits(text, method, settings, &mut diagnostics);
+                if let Some(convention) = settings.response_param_naming {
+                    check_response_naming(text, method, convention, &mut diagnostics, code_actions);
+                }
+            }
+            Traversal::EnterInterface(node)
+                if settings.lint_empty_declarations && node.members.is_empty() =>
+            {
+                check_empty_body(text, "interface", &node.name, &node.range, &mut diagnostics)
+            }
+            Traversal::EnterStruct(node)
+                if settings.lint_empty_declarations && node.members.is_empty() =>
+            {
+                check_empty_body(text, "struct", &node.name, &node.range, &mut diagnostics)
+            }
+            _ => (),
+        }
+
+        match traversal {
+            Traversal::EnterInterface(node) if is_stable(text, &node.attributes) => {
+                let members = method_ordinals(node);
+                check_stable_ordinals(
+                    text,
+                    "interface",
+                    &node.name,
+                    &members,
+                    &mut diagnostics,
+                    code_actions,
+                );
+            }
+            Traversal::EnterStruct(node) if is_stable(text, &node.attributes) => {
+                let members = field_ordinals(node);
+                // When some fields have ordinals, the others are already
+                // reported as errors by semantic checks.
+                if members.iter().all(|(_, ordinal)| ordinal.is_none()) {
+                    check_stable_ordinals(
+                        text,
+                        "struct",
+                        &node.name,
+                        &members,
+                        &mut diagnostics,
+                        code_actions,
+                    );
+                }
+            }
+            Traversal::Union(node) if is_stable(text, &node.attributes) => {
+                let members: Vec<_> = node
+                    .fields
+                    .iter()
+                    .map(|field| (&field.name, &field.ordinal))
+                    .collect();
+                check_stable_ordinals(
+                    text,
+                    "union",
+                    &node.name,
+                    &members,
+                    &mut diagnostics,
+                    code_actions,
+                );
+            }
+            _ => (),
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str, settings: &Settings) -> Vec<lsp_types::Diagnostic> {
+        let mojom = syntax::parse(input).unwrap();
+        let mut code_actions = CodeActionIndex::new();
+        let mut diagnostics = check_text_lints(input, settings, &mut code_actions);
+        diagnostics.extend(check_lints(input, &mojom, settings, &mut code_actions));
+        diagnostics
+    }
+
+    #[test]
+    fn test_method_params_limit() {
+        let mut settings = Settings::default();
+        settings.max_method_params = 2;
+
+        let input = "interface I { Foo(int32 a, int32 b, int32 c); };";
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Warning),
+            diagnostics[0].severity
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 14), range.start);
+        assert_eq!(lsp_types::Position::new(0, 17), range.end);
+
+        let input = "interface I { Foo(int32 a, int32 b); };";
+        let diagnostics = check(input, &settings);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_empty_declarations() {
+        let mut settings = Settings::default();
+        let input = "struct S {};\ninterface I {\n};\n[Native] struct N;";
+        assert!(check(input, &settings).is_empty());
+
+        settings.lint_empty_declarations = true;
+        let diagnostics = check(input, &settings);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Hint),
+            diagnostics[0].severity
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 9), range.start);
+        assert_eq!(lsp_types::Position::new(0, 11), range.end);
+        let range = diagnostics[1].range;
+        assert_eq!(lsp_types::Position::new(1, 12), range.start);
+        assert_eq!(lsp_types::Position::new(2, 1), range.end);
+
+        let input = "struct S { int32 a; };\ninterface I { Foo(); };";
+        assert!(check(input, &settings).is_empty());
+    }
+
+    #[test]
+    fn test_line_length() {
+        let mut settings = Settings::default();
+        let input = "// 0123456789\nstruct S {};";
+        assert!(check(input, &settings).is_empty());
+
+        settings.max_line_length = Some(12);
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Hint),
+            diagnostics[0].severity
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 12), range.start);
+        assert_eq!(lsp_types::Position::new(0, 13), range.end);
+
+        // Exactly at the limit.
+        settings.max_line_length = Some(13);
+        assert!(check(input, &settings).is_empty());
+
+        // "Ä" is two bytes in UTF-8 but one UTF-16 code unit. "😀" is two
+        // UTF-16 code units.
+        let input = "// ÄÄÄÄÄÄÄÄÄ\n// 😀😀😀😀😀\nstruct S {};";
+        settings.max_line_length = Some(12);
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(1, 12), range.start);
+        assert_eq!(lsp_types::Position::new(1, 13), range.end);
+    }
+
+    #[test]
+    fn test_tab_indentation() {
+        let settings = Settings::default();
+
+        let input = "struct S {\n\t \tint32 a;\n};";
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Hint),
+            diagnostics[0].severity
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(1, 0), range.start);
+        assert_eq!(lsp_types::Position::new(1, 3), range.end);
+
+        let mut code_actions = CodeActionIndex::new();
+        check_text_lints(input, &settings, &mut code_actions);
+        let uri = lsp_types::Url::parse("file:///foo.mojom").unwrap();
+        let actions = code_actions.code_actions(&uri, &range);
+        assert_eq!(1, actions.len());
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(range, edits[0].range);
+        assert_eq!("     ", edits[0].new_text);
+
+        let input = "struct S {\n  int32 a;\t// comment\n};";
+        let diagnostics = check(input, &settings);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_method_ordinal_limit() {
+        let mut settings = Settings::default();
+        settings.max_method_ordinal = 100;
+
+        let input = "interface I { Foo@101(); Bar@100(); };";
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 17), range.start);
+        assert_eq!(lsp_types::Position::new(0, 21), range.end);
+    }
+
+    #[test]
+    fn test_response_naming() {
+        let mut settings = Settings::default();
+        let input = "interface I {\n  Foo() => (bool success, int32 errorCode);\n};";
+        assert!(check(input, &settings).is_empty());
+
+        settings.response_param_naming = Some(NamingConvention::SnakeCase);
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Hint),
+            diagnostics[0].severity
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(1, 32), range.start);
+        assert_eq!(lsp_types::Position::new(1, 41), range.end);
+
+        let mojom = syntax::parse(input).unwrap();
+        let mut code_actions = CodeActionIndex::new();
+        check_lints(input, &mojom, &settings, &mut code_actions);
+        let uri = lsp_types::Url::parse("file:///foo.mojom").unwrap();
+        let actions = code_actions.code_actions(&uri, &range);
+        assert_eq!(1, actions.len());
+        assert_eq!("Rename to error_code", actions[0].title);
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(range, edits[0].range);
+        assert_eq!("error_code", edits[0].new_text);
+
+        // Request parameters aren't checked.
+        let input = "interface I { Foo(int32 fooBar) => (bool success); Bar(); };";
+        assert!(check(input, &settings).is_empty());
+    }
+
+    #[test]
+    fn test_stable_ordinals() {
+        let settings = Settings::default();
+
+        let input = "[Stable] interface I { Foo@0(); Bar@1(int32 a); };\n\
+                     [Stable] struct S { int32 a@0; };\n\
+                     [Stable] union U { int32 a@0; string b@1; };\n\
+                     interface J { Foo(); };";
+        assert!(check(input, &settings).is_empty());
+
+        let input = "[Stable]\ninterface I {\n  Foo@3();\n  Bar();\n};";
+        let diagnostics = check(input, &settings);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Warning),
+            diagnostics[0].severity
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(3, 2), range.start);
+        assert_eq!(lsp_types::Position::new(3, 5), range.end);
+
+        let mojom = syntax::parse(input).unwrap();
+        let mut code_actions = CodeActionIndex::new();
+        check_lints(input, &mojom, &settings, &mut code_actions);
+        let uri = lsp_types::Url::parse("file:///foo.mojom").unwrap();
+        let actions = code_actions.code_actions(&uri, &range);
+        assert_eq!(1, actions.len());
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(lsp_types::Position::new(3, 5), edits[0].range.start);
+        assert_eq!("@4", edits[0].new_text);
+
+        let input = "[Stable] struct S { int32 a; int32 b; };";
+        let diagnostics = check(input, &settings);
+        assert_eq!(2, diagnostics.len());
+
+        // `b` is reported by semantic checks instead.
+        let input = "[Stable] struct S { int32 a@0; int32 b; };";
+        assert!(check(input, &settings).is_empty());
+    }
+}
@@ -18,22 +18,23 @@ use std::thread;
 
 use serde_json::Value;
 
-use super::protocol::{self, NotificationMessage, ResponseError};
+use super::protocol::{self, NotificationMessage, RequestId, RequestMessage, ResponseError};
 
 #[derive(Debug)]
 struct SuccessResponse {
-    id: u64,
+    id: RequestId,
     result: Value,
 }
 
 #[derive(Debug)]
 struct ErrorResponse {
-    id: u64,
+    id: RequestId,
     err: ResponseError,
 }
 
 #[derive(Debug)]
 enum SendingMessage {
+    Request(RequestMessage),
     SuccessResponse(SuccessResponse),
     ErrorResponse(ErrorResponse),
     Notification(NotificationMessage),
@@ -45,7 +46,13 @@ pub(crate) struct MessageSender {
 }
 
 impl MessageSender {
-    pub(crate) fn send_success_response(&self, id: u64, res: Value) {
+    pub(crate) fn send_request(&self, req: RequestMessage) {
+        log::debug!("[send] Request: id = {}, method = {}", req.id, req.method);
+        let msg = SendingMessage::Request(req);
+        self.send(msg);
+    }
+
+    pub(crate) fn send_success_response(&self, id: RequestId, res: Value) {
         log::debug!("[send] Success: id = {}", id);
         let msg = SendingMessage::SuccessResponse(SuccessResponse {
             id: id,
@@ -54,7 +61,7 @@ impl MessageSender {
         self.send(msg);
     }
 
-    pub(crate) fn send_error_response(&self, id: u64, err: ResponseError) {
+    pub(crate) fn send_error_response(&self, id: RequestId, err: ResponseError) {
         log::debug!("[send] Error: message = '{}'", err.message);
         let msg = SendingMessage::ErrorResponse(ErrorResponse { id: id, err: err });
         self.send(msg);
@@ -78,8 +85,10 @@ pub(crate) struct MessageSenderThread {
 }
 
 impl MessageSenderThread {
-    #[allow(unused)]
+    /// Waits until all messages are written. Senders returned by
+    /// `get_sender()` must be dropped beforehand.
     pub(crate) fn join(self) {
+        drop(self.sender);
         self.handle.join().unwrap();
     }
 
@@ -100,6 +109,9 @@ pub(crate) fn start_message_sender_thread<W: Write + Send + 'static>(
         let msg = if let Ok(msg) = msg { msg } else { break };
 
         match msg {
+            SendingMessage::Request(req) => {
+                protocol::write_request(&mut writer, req.id, &req.method, req.params).unwrap();
+            }
             SendingMessage::SuccessResponse(res) => {
                 protocol::write_success_response(&mut writer, res.id, res.result).unwrap();
             }
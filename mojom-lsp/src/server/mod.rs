@@ -12,14 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod codeaction;
+mod completion;
+mod config;
 mod definition;
 mod diagnostic;
+mod document;
+mod document_symbol;
+mod folding_range;
+mod formatting;
+mod generated_bindings;
+mod hover;
 mod imported_files;
 mod initialization;
+mod lint;
 mod messagesender;
 mod mojomast;
 mod protocol;
+mod pull_diagnostics;
+mod refactor;
+mod references;
+mod rename;
 mod semantic;
+mod semantic_tokens;
 mod server;
+mod settings;
+mod watchdog;
+mod workspace_symbol;
 
-pub use server::start;
+pub use diagnostic::{analyze_workspace, PublishedDiagnostics};
+pub use formatting::format_check;
+pub use server::{start, start_tcp, start_with, Server};
+pub use settings::{DiagnosticsMode, FormatMode, NamingConvention, Settings};
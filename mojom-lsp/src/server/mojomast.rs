@@ -12,45 +12,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::syntax::{self, Module, MojomFile};
+use crate::syntax::{self, MojomFile};
 
 #[derive(Debug)]
 pub(crate) struct MojomAst {
     pub(crate) uri: lsp_types::Url,
     pub(crate) text: String,
     pub(crate) mojom: MojomFile,
-
-    module: Option<Module>,
 }
 
 impl MojomAst {
-    pub(crate) fn from_mojom(
-        uri: lsp_types::Url,
-        text: String,
-        mojom: MojomFile,
-        module: Option<Module>,
-    ) -> MojomAst {
+    pub(crate) fn from_mojom(uri: lsp_types::Url, text: String, mojom: MojomFile) -> MojomAst {
         MojomAst {
             uri: uri,
             text: text,
             mojom: mojom,
-            module: module,
         }
     }
 
+    // Returns an empty string when `field` is out of range.
     pub(crate) fn text(&self, field: &syntax::Range) -> &str {
-        // Can panic.
-        &self.text[field.start..field.end]
+        self.text.get(field.start..field.end).unwrap_or("")
     }
 
+    // Returns the end of the text when `offset` is out of range.
     pub(crate) fn line_col(&self, offset: usize) -> syntax::LineCol {
-        // Can panic.
-        syntax::line_col(&self.text, offset).unwrap()
-    }
-
-    pub(crate) fn module_name(&self) -> Option<&str> {
-        self.module
-            .as_ref()
-            .map(|ref module| self.text(&module.name))
+        syntax::line_col(&self.text, offset)
     }
 }
@@ -16,7 +16,74 @@ use std::io::{self, Write};
 
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
-use serde_json::{from_slice, Value};
+use serde_json::{Deserializer, Value};
+
+/// Errors which are specific to the base protocol. Other errors are reported
+/// as is.
+#[derive(Debug)]
+pub(crate) enum ProtocolError {
+    /// The stream ended in the middle of a message body. This usually means
+    /// that the client disconnected.
+    Eof,
+    /// `Content-Type` declared a charset other than UTF-8.
+    UnsupportedCharset(String),
+    /// `Content-Length` exceeded the maximum. The body is discarded.
+    ContentTooLarge {
+        content_length: usize,
+        max_content_length: usize,
+    },
+    /// `Content-Length` was zero. JSON-RPC messages can't be empty.
+    EmptyMessage,
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Eof => write!(f, "Unexpected EOF in message body"),
+            ProtocolError::UnsupportedCharset(charset) => {
+                write!(f, "Unsupported charset: {}", charset)
+            }
+            ProtocolError::ContentTooLarge {
+                content_length,
+                max_content_length,
+            } => write!(
+                f,
+                "Content length {} exceeds the maximum of {} bytes",
+                content_length, max_content_length
+            ),
+            ProtocolError::EmptyMessage => write!(f, "Empty message body"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Returns true when `err` is about a single message and the stream is still
+/// in sync, so the next message can be read.
+pub(crate) fn is_skippable(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<ProtocolError>() {
+        Some(ProtocolError::ContentTooLarge { .. }) | Some(ProtocolError::EmptyMessage) => true,
+        _ => false,
+    }
+}
+
+/// A request ID. JSON-RPC allows both numbers and strings. IDs are echoed back
+/// as is in responses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestId::Number(id) => write!(f, "{}", id),
+            RequestId::String(id) => write!(f, "{:?}", id),
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -27,21 +94,39 @@ pub(crate) enum Message {
 }
 
 impl Message {
-    fn from_slice(buf: &[u8]) -> serde_json::Result<Message> {
-        from_slice::<Message>(buf)
+    fn from_slice(buf: &[u8]) -> anyhow::Result<Message> {
+        let mut stream = Deserializer::from_slice(buf).into_iter::<Message>();
+        let message = match stream.next() {
+            Some(Ok(message)) => message,
+            _ => return Err(anyhow!("Failed to parse message")),
+        };
+
+        // Trailing content usually means that the client has a framing bug.
+        // The body has been consumed as a whole, so the stream is still in
+        // sync. Keep the message.
+        let offset = stream.byte_offset();
+        if buf[offset..].iter().any(|b| !b.is_ascii_whitespace()) {
+            log::warn!(
+                "Skipped {} bytes of trailing content after message",
+                buf.len() - offset
+            );
+        }
+        Ok(message)
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct RequestMessage {
-    pub id: u64,
+    pub id: RequestId,
     pub method: String,
+    // `params` is optional. Null when omitted.
+    #[serde(default)]
     pub params: Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct ResponseMessage {
-    pub id: u64,
+    pub id: RequestId,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -51,15 +136,14 @@ pub(crate) struct ResponseMessage {
 #[derive(Serialize)]
 pub(crate) struct JsonRpcRequestMessage<'a> {
     jsonrpc: &'a str,
-    id: u64,
+    id: RequestId,
     method: &'a str,
     params: Value,
 }
 
-#[allow(unused)]
 pub(crate) fn write_request(
     writer: &mut impl Write,
-    id: u64,
+    id: RequestId,
     method: &str,
     params: Value,
 ) -> anyhow::Result<()> {
@@ -131,6 +215,8 @@ impl From<ErrorCodes> for i32 {
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct NotificationMessage {
     pub method: String,
+    // `params` is optional. Null when omitted.
+    #[serde(default)]
     pub params: Value,
 }
 
@@ -138,61 +224,204 @@ pub(crate) struct NotificationMessage {
 #[derive(Debug)]
 struct Header {
     pub content_length: usize,
+    /// The media type of `Content-Type`, without parameters.
+    pub content_type: Option<String>,
+    /// The `charset` parameter of `Content-Type`.
+    pub charset: Option<String>,
 }
 
-fn read_header(reader: &mut impl io::BufRead) -> io::Result<Header> {
-    let mut content_length = None;
-    loop {
-        let mut line = String::new();
-        let n = reader.read_line(&mut line)?;
-        if n == 0 {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "No header"));
-        }
-        if line == "\r\n" {
-            break;
+// Splits a `Content-Type` value into the media type and the charset.
+fn parse_content_type(value: &str) -> (String, Option<String>) {
+    let mut params = value.split(';');
+    let media_type = params.next().unwrap_or("").trim().to_owned();
+    let charset = params.find_map(|param| {
+        let mut kv = param.splitn(2, '=');
+        let key = kv.next()?.trim();
+        let value = kv.next()?.trim().trim_matches('"');
+        if key.eq_ignore_ascii_case("charset") {
+            Some(value.to_owned())
+        } else {
+            None
         }
+    });
+    (media_type, charset)
+}
 
-        let header_fields = line.trim().split(": ").collect::<Vec<_>>();
-        if header_fields.len() != 2 {
+// The maximum size of a header part. Real headers are less than 100 bytes, so
+// anything larger is garbage which would otherwise be buffered without bound.
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+
+// Reads bytes up to and including the blank line which terminates a header
+// part. Both CRLF and LF line endings are accepted.
+fn read_header_block(reader: &mut impl io::BufRead) -> io::Result<Vec<u8>> {
+    let mut block = Vec::new();
+    loop {
+        let (consumed, done) = {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "No header"));
+            }
+            let mut consumed = 0;
+            let mut done = false;
+            for &b in buf {
+                block.push(b);
+                consumed += 1;
+                if block.ends_with(b"\r\n\r\n") || block.ends_with(b"\n\n") {
+                    done = true;
+                    break;
+                }
+            }
+            (consumed, done)
+        };
+        reader.consume(consumed);
+        if done {
+            return Ok(block);
+        }
+        if block.len() > MAX_HEADER_SIZE {
             return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid header",
+                io::ErrorKind::InvalidData,
+                format!("Header exceeds {} bytes", MAX_HEADER_SIZE),
             ));
         }
-        let name = header_fields[0].to_ascii_lowercase();
-        let value = header_fields[1];
+    }
+}
 
-        if name == "content-length" {
-            let value = match value.parse::<usize>() {
+fn find_ignore_ascii_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+fn read_header(reader: &mut impl io::BufRead) -> io::Result<Header> {
+    let block = loop {
+        let block = read_header_block(reader)?;
+        // Skip stray blank lines between messages.
+        if block.iter().all(|b| b.is_ascii_whitespace()) {
+            continue;
+        }
+        break block;
+    };
+
+    // Resynchronize when a misbehaving client sent garbage before the header.
+    let start = [&b"content-length"[..], &b"content-type"[..]]
+        .iter()
+        .filter_map(|name| find_ignore_ascii_case(&block, name))
+        .min()
+        .unwrap_or(0);
+
+    let mut content_length = None;
+    let mut content_type = None;
+    let mut charset = None;
+    for line in block[start..].split(|&b| b == b'\n') {
+        let line = match line.split_last() {
+            Some((b'\r', line)) => line,
+            _ => line,
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        let sep = match line.iter().position(|&b| b == b':') {
+            Some(sep) => sep,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid header",
+                ))
+            }
+        };
+        let name = String::from_utf8_lossy(&line[..sep]);
+        let value = String::from_utf8_lossy(&line[sep + 1..]);
+
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            // Rejects negative values too.
+            let value = match value.trim().parse::<usize>() {
                 Ok(n) => n,
-                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid content length: {}", value.trim()),
+                    ))
+                }
             };
             content_length = Some(value);
+        } else if name.trim().eq_ignore_ascii_case("content-type") {
+            let (media_type, value) = parse_content_type(&value);
+            content_type = Some(media_type);
+            charset = value;
         }
     }
 
     content_length
-        .map(|n| Header { content_length: n })
+        .map(|n| Header {
+            content_length: n,
+            content_type: content_type,
+            charset: charset,
+        })
         .ok_or(io::Error::new(
             io::ErrorKind::InvalidInput,
             "No content length",
         ))
 }
 
+/// The maximum size of a message body which `read_message()` accepts. Large
+/// enough for any sane document.
+pub(crate) const MAX_CONTENT_LENGTH: usize = 32 * 1024 * 1024;
+
 pub(crate) fn read_message(reader: &mut impl io::BufRead) -> anyhow::Result<Message> {
+    read_message_with_limit(reader, MAX_CONTENT_LENGTH)
+}
+
+/// Same as `read_message()` but takes the maximum size of a message body.
+/// Bodies are allocated up front, so a bogus `Content-Length` must not be
+/// trusted. Larger bodies are discarded without being allocated.
+pub(crate) fn read_message_with_limit(
+    reader: &mut impl io::BufRead,
+    max_content_length: usize,
+) -> anyhow::Result<Message> {
     let header = read_header(reader)?;
+    if header.content_length > max_content_length {
+        let len = header.content_length as u64;
+        if io::copy(&mut io::Read::take(reader, len), &mut io::sink())? < len {
+            return Err(anyhow::Error::new(ProtocolError::Eof));
+        }
+        return Err(anyhow::Error::new(ProtocolError::ContentTooLarge {
+            content_length: header.content_length,
+            max_content_length: max_content_length,
+        }));
+    }
+    if header.content_length == 0 {
+        return Err(anyhow::Error::new(ProtocolError::EmptyMessage));
+    }
     let mut buf = vec![0; header.content_length];
-    reader.read_exact(&mut buf)?;
-    match Message::from_slice(&buf) {
-        Ok(message) => Ok(message),
-        Err(_) => Err(anyhow!("Failed to parse message")),
+    reader
+        .read_exact(&mut buf)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => anyhow::Error::new(ProtocolError::Eof),
+            _ => err.into(),
+        })?;
+    // The body is consumed above so that the stream stays in sync.
+    if let Some(content_type) = header.content_type {
+        // The spec only defines this type. Be lenient about others.
+        if !content_type.eq_ignore_ascii_case("application/vscode-jsonrpc") {
+            log::debug!("Unexpected content type: {}", content_type);
+        }
+    }
+    if let Some(charset) = header.charset {
+        let lower = charset.to_ascii_lowercase();
+        if lower != "utf-8" && lower != "utf8" {
+            return Err(anyhow::Error::new(ProtocolError::UnsupportedCharset(
+                charset,
+            )));
+        }
     }
+    Message::from_slice(&buf)
 }
 
 #[derive(Serialize)]
 struct JsonRpcResponseMessage<'a> {
     jsonrpc: &'a str,
-    id: u64,
+    id: RequestId,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -211,7 +440,7 @@ fn write_message<M: Serialize>(writer: &mut impl Write, message: M) -> anyhow::R
 
 pub(crate) fn write_success_result<R>(
     writer: &mut impl Write,
-    id: u64,
+    id: RequestId,
     res: R,
 ) -> anyhow::Result<()>
 where
@@ -223,7 +452,7 @@ where
 
 pub(crate) fn write_success_response(
     writer: &mut impl Write,
-    id: u64,
+    id: RequestId,
     result: Value,
 ) -> anyhow::Result<()> {
     let message = JsonRpcResponseMessage {
@@ -237,7 +466,7 @@ pub(crate) fn write_success_response(
 
 pub(crate) fn write_error_response(
     writer: &mut impl Write,
-    id: u64,
+    id: RequestId,
     error: ResponseError,
 ) -> anyhow::Result<()> {
     let message = JsonRpcResponseMessage {
@@ -273,6 +502,35 @@ pub(crate) fn write_notification(
 mod tests {
     use super::*;
 
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn test_request_id_round_trip() {
+        let inputs = [
+            (r#"{"jsonrpc":"2.0","id":42,"method":"shutdown"}"#, "42"),
+            (
+                r#"{"jsonrpc":"2.0","id":"abc-1","method":"shutdown"}"#,
+                r#""abc-1""#,
+            ),
+        ];
+        for (input, expected_id) in inputs.iter() {
+            let input = frame(input);
+            let mut reader = io::BufReader::new(input.as_bytes());
+            let id = match read_message(&mut reader).unwrap() {
+                Message::Request(req) => req.id,
+                msg => panic!("Expected a request but got {:?}", msg),
+            };
+
+            let mut output = Vec::new();
+            write_success_response(&mut output, id, Value::Null).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            let expected = format!(r#"{{"jsonrpc":"2.0","id":{},"result":null}}"#, expected_id);
+            assert_eq!(frame(&expected), output);
+        }
+    }
+
     #[test]
     fn test_read_header() {
         let input = b"content-length: 208\r\n\r\n";
@@ -280,4 +538,218 @@ mod tests {
         let header = read_header(&mut reader).unwrap();
         assert_eq!(208, header.content_length);
     }
+
+    #[test]
+    fn test_read_header_content_type() {
+        let input =
+            b"Content-Length: 2\r\nContent-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!(2, header.content_length);
+        assert_eq!(
+            Some("application/vscode-jsonrpc"),
+            header.content_type.as_deref()
+        );
+        assert_eq!(Some("utf-8"), header.charset.as_deref());
+
+        let input = b"Content-Length: 2\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!(2, header.content_length);
+        assert_eq!(None, header.content_type);
+        assert_eq!(None, header.charset);
+
+        let input = b"Content-Type: application/vscode-jsonrpc\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        let err = read_header(&mut reader).unwrap_err();
+        assert_eq!("No content length", err.to_string());
+    }
+
+    #[test]
+    fn test_read_message_charset() {
+        let body = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
+        let input = format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc; charset=\"UTF8\"\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert!(read_message(&mut reader).is_ok());
+
+        let input = format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc; charset=latin1\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_message(&mut reader).unwrap_err();
+        match err.downcast_ref::<ProtocolError>() {
+            Some(ProtocolError::UnsupportedCharset(charset)) => assert_eq!("latin1", charset),
+            _ => panic!("Expected unsupported charset error but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_read_header_lf_only() {
+        let input = b"Content-Length: 2\n\n{}";
+        let mut reader = io::BufReader::new(&input[..]);
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!(2, header.content_length);
+        // The body shouldn't be consumed.
+        let mut body = String::new();
+        io::Read::read_to_string(&mut reader, &mut body).unwrap();
+        assert_eq!("{}", body);
+    }
+
+    #[test]
+    fn test_read_header_stray_bytes() {
+        let input = b"\r\n\xff\x00 garbageContent-Length: 52\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!(52, header.content_length);
+
+        let input = b"\x00Content-Type: application/vscode-jsonrpc\r\nContent-Length: 2\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        let header = read_header(&mut reader).unwrap();
+        assert_eq!(2, header.content_length);
+
+        let input = b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\n";
+        let mut reader = io::BufReader::new(&input[..]);
+        assert!(read_header(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_read_header_too_large() {
+        let input = "X".repeat(MAX_HEADER_SIZE * 2);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_header(&mut reader).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+        // A header which fits is fine even after some garbage.
+        let input = format!("{}\r\nContent-Length: 2\r\n\r\n{{}}", "X".repeat(100));
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert_eq!(2, read_header(&mut reader).unwrap().content_length);
+    }
+
+    #[test]
+    fn test_read_message_without_params() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        match read_message(&mut reader).unwrap() {
+            Message::Request(req) => {
+                assert_eq!("shutdown", req.method);
+                assert_eq!(Value::Null, req.params);
+            }
+            msg => panic!("Expected a request but got {:?}", msg),
+        }
+
+        let body = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        match read_message(&mut reader).unwrap() {
+            Message::Notofication(notif) => assert_eq!("exit", notif.method),
+            msg => panic!("Expected a notification but got {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_read_message_truncated_body() {
+        let input = b"Content-Length: 52\r\n\r\n{\"jsonrpc\":\"2.0\",";
+        let mut reader = io::BufReader::new(&input[..]);
+        let err = read_message(&mut reader).unwrap_err();
+        match err.downcast_ref::<ProtocolError>() {
+            Some(ProtocolError::Eof) => (),
+            _ => panic!("Expected EOF error but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_read_message_trailing_content() {
+        let body = r#"{"jsonrpc":"2.0","method":"initialized","params":{}} junk"#;
+        let input = format!(
+            "Content-Length: {}\r\n\r\n{}{}",
+            body.len(),
+            body,
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#)
+        );
+        let mut reader = io::BufReader::new(input.as_bytes());
+        match read_message(&mut reader).unwrap() {
+            Message::Notofication(notif) => assert_eq!("initialized", notif.method),
+            msg => panic!("Expected a notification but got {:?}", msg),
+        }
+        // The next message is read as usual.
+        match read_message(&mut reader).unwrap() {
+            Message::Notofication(notif) => assert_eq!("exit", notif.method),
+            msg => panic!("Expected a notification but got {:?}", msg),
+        }
+
+        let body = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}  "#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert!(read_message(&mut reader).is_ok());
+    }
+
+    #[test]
+    fn test_read_message_content_length_limit() {
+        let input = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_message(&mut reader).unwrap_err();
+        // The body is missing.
+        match err.downcast_ref::<ProtocolError>() {
+            Some(ProtocolError::Eof) => (),
+            _ => panic!("Expected EOF error but got {:?}", err),
+        }
+
+        // The large body is skipped and the next message is read.
+        let large = r#"{"jsonrpc":"2.0","method":"initialized","params":{"a":1}}"#;
+        let body = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let input = format!("{}{}", frame(large), frame(body));
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_message_with_limit(&mut reader, body.len()).unwrap_err();
+        assert!(is_skippable(&err));
+        match err.downcast_ref::<ProtocolError>() {
+            Some(ProtocolError::ContentTooLarge {
+                content_length,
+                max_content_length,
+            }) => {
+                assert_eq!(large.len(), *content_length);
+                assert_eq!(body.len(), *max_content_length);
+            }
+            _ => panic!("Expected content too large error but got {:?}", err),
+        }
+        match read_message_with_limit(&mut reader, body.len()).unwrap() {
+            Message::Notofication(notif) => assert_eq!("exit", notif.method),
+            msg => panic!("Expected a notification but got {:?}", msg),
+        }
+
+        let body = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
+        let input = frame(body);
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert!(read_message_with_limit(&mut reader, body.len()).is_ok());
+        let mut reader = io::BufReader::new(input.as_bytes());
+        assert!(read_message_with_limit(&mut reader, body.len() - 1).is_err());
+    }
+
+    #[test]
+    fn test_read_message_invalid_content_length() {
+        let input = b"Content-Length: -1\r\n\r\n{}";
+        let mut reader = io::BufReader::new(&input[..]);
+        let err = read_message(&mut reader).unwrap_err();
+        assert_eq!("Invalid content length: -1", err.to_string());
+
+        // The body which follows an empty message is skipped as garbage.
+        let body = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let input = format!("Content-Length: 0\r\n\r\n{{}}{}", frame(body));
+        let mut reader = io::BufReader::new(input.as_bytes());
+        let err = read_message(&mut reader).unwrap_err();
+        match err.downcast_ref::<ProtocolError>() {
+            Some(ProtocolError::EmptyMessage) => (),
+            _ => panic!("Expected empty message error but got {:?}", err),
+        }
+        match read_message(&mut reader).unwrap() {
+            Message::Notofication(notif) => assert_eq!("exit", notif.method),
+            msg => panic!("Expected a notification but got {:?}", msg),
+        }
+    }
 }
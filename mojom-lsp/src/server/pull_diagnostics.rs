@@ -0,0 +1,82 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Pull diagnostics (`textDocument/diagnostic`) which were introduced in LSP
+// 3.17. lsp-types doesn't define them yet.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub(crate) const DOCUMENT_DIAGNOSTIC_METHOD: &str = "textDocument/diagnostic";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DocumentDiagnosticParams {
+    pub(crate) text_document: lsp_types::TextDocumentIdentifier,
+    pub(crate) previous_result_id: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum DocumentDiagnosticReport {
+    #[serde(rename_all = "camelCase")]
+    Full {
+        result_id: String,
+        items: Vec<lsp_types::Diagnostic>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Unchanged { result_id: String },
+}
+
+/// The `diagnosticProvider` server capability.
+pub(crate) fn diagnostic_provider() -> Value {
+    serde_json::json!({
+        // Diagnostics depend on imported files.
+        "interFileDependencies": true,
+        "workspaceDiagnostics": false,
+    })
+}
+
+/// Returns true when the client declares `textDocument.diagnostic`.
+pub(crate) fn supports_pull_diagnostics(capabilities: &Value) -> bool {
+    capabilities["textDocument"]["diagnostic"].is_object()
+}
+
+// Result IDs are derived from diagnostics so that a report is unchanged as
+// long as the diagnostics are the same.
+fn result_id(items: &[lsp_types::Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(items).unwrap().hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+pub(crate) fn create_report(
+    items: Vec<lsp_types::Diagnostic>,
+    previous_result_id: Option<&str>,
+) -> DocumentDiagnosticReport {
+    let result_id = result_id(&items);
+    if previous_result_id == Some(result_id.as_str()) {
+        DocumentDiagnosticReport::Unchanged {
+            result_id: result_id,
+        }
+    } else {
+        DocumentDiagnosticReport::Full {
+            result_id: result_id,
+            items: items,
+        }
+    }
+}
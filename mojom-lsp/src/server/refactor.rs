@@ -0,0 +1,227 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Refactors which are offered as code actions.
+
+use std::collections::HashSet;
+
+use lsp_types::{Position, Range, TextEdit};
+
+use crate::syntax::{self, partial_text, preorder, MojomFile, Traversal};
+
+use super::codeaction::{CodeActionIndex, Fix};
+
+// Methods need at least this number of parameters to offer extracting them
+// into a struct.
+const MIN_PARAMS_TO_EXTRACT: usize = 2;
+
+fn position(text: &str, offset: usize) -> Position {
+    let pos = syntax::line_col(text, offset);
+    Position::new(pos.line as u64, pos.col as u64)
+}
+
+fn lsp_range(text: &str, start: usize, end: usize) -> Range {
+    Range::new(position(text, start), position(text, end))
+}
+
+fn declared_names<'a>(text: &'a str, mojom: &MojomFile) -> HashSet<&'a str> {
+    let mut names = HashSet::new();
+    for traversal in preorder(mojom) {
+        let name = match traversal {
+            Traversal::EnterInterface(node) => &node.name,
+            Traversal::EnterStruct(node) => &node.name,
+            Traversal::Union(node) => &node.name,
+            Traversal::Enum(node) => &node.name,
+            Traversal::Const(node) => &node.name,
+            _ => continue,
+        };
+        names.insert(partial_text(text, name));
+    }
+    names
+}
+
+// Returns attributes written as an attribute section followed by a space, e.g.
+// `[MinVersion=1] `, or an empty string when there are no attributes.
+fn attribute_section(text: &str, attributes: &[syntax::Attribute]) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+    let attributes: Vec<_> = attributes
+        .iter()
+        .map(|attribute| match &attribute.value {
+            Some(value) => format!(
+                "{}={}",
+                partial_text(text, &attribute.name),
+                partial_text(text, value)
+            ),
+            None => partial_text(text, &attribute.name).to_owned(),
+        })
+        .collect();
+    format!("[{}] ", attributes.join(", "))
+}
+
+fn extract_params(
+    text: &str,
+    interface: &syntax::Interface,
+    method: &syntax::Method,
+    declared_names: &HashSet<&str>,
+) -> Option<(Range, Fix)> {
+    if method.params.len() < MIN_PARAMS_TO_EXTRACT {
+        return None;
+    }
+    let method_name = partial_text(text, &method.name);
+    let struct_name = format!("{}Params", method_name);
+    if declared_names.contains(struct_name.as_str()) {
+        return None;
+    }
+
+    let mut fields = String::new();
+    for param in &method.params {
+        let typ = partial_text(text, &param.typ);
+        let ordinal = param
+            .ordinal
+            .as_ref()
+            .map(|ordinal| partial_text(text, ordinal))
+            .unwrap_or("");
+        let name = partial_text(text, &param.name);
+        fields.push_str(&format!(
+            "  {}{} {}{};\n",
+            attribute_section(text, &param.attributes),
+            typ,
+            name,
+            ordinal
+        ));
+    }
+
+    let first = method.params.first()?;
+    let last = method.params.last()?;
+    let params_start = first
+        .attributes
+        .first()
+        .map(|attribute| attribute.name.start)
+        .and_then(|start| text[..start].rfind('['))
+        .unwrap_or(first.typ.start);
+    let params_end = last.ordinal.as_ref().unwrap_or(&last.name).end;
+
+    // Insert the new struct right before the interface.
+    let insert_pos = position(text, interface.range.start);
+    let new_struct = format!("struct {} {{\n{}}};\n\n", struct_name, fields);
+    let edits = vec![
+        TextEdit::new(Range::new(insert_pos, insert_pos), new_struct),
+        TextEdit::new(
+            lsp_range(text, params_start, params_end),
+            format!("{} params", struct_name),
+        ),
+    ];
+    let fix = Fix {
+        title: format!("Extract parameters into struct {}", struct_name),
+        edits: edits,
+    };
+    let range = lsp_range(text, method.name.start, params_end);
+    Some((range, fix))
+}
+
+pub(crate) fn add_refactors(text: &str, mojom: &MojomFile, code_actions: &mut CodeActionIndex) {
+    let declared_names = declared_names(text, mojom);
+    for stmt in &mojom.stmts {
+        let interface = match stmt {
+            syntax::Statement::Interface(interface) => interface,
+            _ => continue,
+        };
+        for member in &interface.members {
+            if let syntax::InterfaceMember::Method(method) = member {
+                if let Some((range, fix)) = extract_params(text, interface, method, &declared_names)
+                {
+                    code_actions.add_refactor(range, fix);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::{CodeAction, Url};
+
+    fn code_actions(input: &str, range: Range) -> Vec<CodeAction> {
+        let mojom = syntax::parse(input).unwrap();
+        let mut code_actions = CodeActionIndex::new();
+        add_refactors(input, &mojom, &mut code_actions);
+        let uri = Url::parse("file:///foo.mojom").unwrap();
+        code_actions.code_actions(&uri, &range)
+    }
+
+    fn apply_edits(input: &str, edits: &[TextEdit]) -> String {
+        let mut edits = edits.to_vec();
+        // Apply from the end so that earlier positions stay valid.
+        edits.sort_by_key(|edit| edit.range.start);
+        let mut text = input.to_owned();
+        for edit in edits.iter().rev() {
+            let change = lsp_types::TextDocumentContentChangeEvent {
+                range: Some(edit.range),
+                range_length: None,
+                text: edit.new_text.clone(),
+            };
+            super::super::document::apply_content_changes(&mut text, &[change]);
+        }
+        text
+    }
+
+    #[test]
+    fn test_extract_params() {
+        let input =
+            "module foo;\n\ninterface I {\n  Foo([MinVersion=1] int32 a, string? b@1) => ();\n};\n";
+        let range = Range::new(Position::new(3, 3), Position::new(3, 3));
+        let actions = code_actions(input, range);
+        assert_eq!(1, actions.len());
+        assert_eq!(
+            Some(lsp_types::CodeActionKind::REFACTOR_EXTRACT),
+            actions[0].kind
+        );
+        let uri = Url::parse("file:///foo.mojom").unwrap();
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        let expected = "module foo;
+
+struct FooParams {
+  [MinVersion=1] int32 a;
+  string? b@1;
+};
+
+interface I {
+  Foo(FooParams params) => ();
+};
+";
+        assert_eq!(expected, apply_edits(input, edits));
+    }
+
+    #[test]
+    fn test_extract_params_not_offered() {
+        let range = Range::new(Position::new(0, 0), Position::new(10, 0));
+
+        // Too few parameters.
+        let input = "interface I { Foo(int32 a); };";
+        assert!(code_actions(input, range).is_empty());
+
+        // The struct name is already taken.
+        let input = "struct FooParams {};\ninterface I { Foo(int32 a, int32 b); };";
+        assert!(code_actions(input, range).is_empty());
+
+        // Outside of the method.
+        let input = "interface I {\n  Foo(int32 a, int32 b);\n};";
+        let range = Range::new(Position::new(0, 0), Position::new(0, 5));
+        assert!(code_actions(input, range).is_empty());
+    }
+}
@@ -0,0 +1,559 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, Location, Position, Url};
+use serde::Serialize;
+
+use crate::model::{enclosing_scope, FileSymbols, SymbolTable};
+use crate::syntax::{self, preorder, Traversal};
+
+use super::definition::create_lsp_range;
+use super::diagnostic::create_diagnostic;
+use super::document::identifier_range_at;
+use super::mojomast::MojomAst;
+use super::semantic_tokens::type_names;
+
+/// Returns references of the symbol at the given position, grouped by
+/// `UsageKind`.
+pub(crate) const REFERENCES_BY_KIND_COMMAND: &str = "mojom.referencesByKind";
+
+/// How a location refers to a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum UsageKind {
+    Declaration,
+    /// The type of a field or a constant.
+    Type,
+    /// The type of a method parameter.
+    Parameter,
+    /// The type of a method response parameter.
+    ReturnType,
+    /// A constant value or a default value.
+    Value,
+    /// An import of the file which declares the symbol.
+    Import,
+}
+
+/// Locations which refer to a symbol in the same way.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct ReferenceGroup {
+    pub(crate) kind: UsageKind,
+    pub(crate) locations: Vec<Location>,
+}
+
+// Identifies a declaration by its file and the range of its name.
+#[derive(PartialEq)]
+struct Target {
+    uri: Url,
+    range: lsp_types::Range,
+}
+
+// Returns ranges of (possibly dotted) identifiers in `range` of `text`. String
+// literals don't contain identifiers.
+fn identifiers(text: &str, range: &syntax::Range) -> Vec<syntax::Range> {
+    let source = &text[range.start..range.end];
+    if source.starts_with('"') {
+        return Vec::new();
+    }
+    let mut identifiers = Vec::new();
+    let mut start = None;
+    for (i, ch) in source
+        .char_indices()
+        .chain(std::iter::once((source.len(), ' ')))
+    {
+        match start {
+            None if ch.is_ascii_alphabetic() || ch == '_' => start = Some(i),
+            Some(_) if ch.is_ascii_alphanumeric() || ch == '_' || ch == '.' => (),
+            Some(begin) => {
+                identifiers.push(syntax::Range {
+                    start: range.start + begin,
+                    end: range.start + i,
+                });
+                start = None;
+            }
+            None => (),
+        }
+    }
+    identifiers
+}
+
+// Returns ranges of types and values in `ast` which may refer to other
+// declarations, with how they refer to them.
+fn referring_ranges(ast: &MojomAst) -> Vec<(&syntax::Range, UsageKind)> {
+    let mut ranges = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        match traversal {
+            Traversal::StructField(node) => {
+                ranges.push((&node.typ, UsageKind::Type));
+                if let Some(ref default) = node.default {
+                    ranges.push((default, UsageKind::Value));
+                }
+            }
+            Traversal::Method(node) => {
+                for param in &node.params {
+                    ranges.push((&param.typ, UsageKind::Parameter));
+                }
+                for param in node.response.iter().flat_map(|response| &response.params) {
+                    ranges.push((&param.typ, UsageKind::ReturnType));
+                }
+            }
+            Traversal::Union(node) => {
+                for field in &node.fields {
+                    ranges.push((&field.typ, UsageKind::Type));
+                }
+            }
+            Traversal::Const(node) => {
+                ranges.push((&node.typ, UsageKind::Type));
+                ranges.push((&node.value, UsageKind::Value));
+            }
+            Traversal::Enum(node) => {
+                for value in node.values.iter().filter_map(|value| value.value.as_ref()) {
+                    ranges.push((value, UsageKind::Value));
+                }
+            }
+            _ => (),
+        }
+    }
+    ranges
+}
+
+// Appends locations in `ast` which refer to `target`.
+fn collect_references(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    target: &Target,
+    references: &mut Vec<(Location, UsageKind)>,
+) {
+    let is_target = |name: &str, scope: &[&str]| match symbols.lookup(name, scope) {
+        Some(symbol) => symbol.uri == &target.uri && symbol.symbol.range == target.range,
+        None => false,
+    };
+    for (range, kind) in referring_ranges(ast) {
+        for ident in identifiers(&ast.text, range) {
+            let name = ast.text(&ident);
+            let scope = enclosing_scope(&ast.text, &ast.mojom, ident.start);
+            let reference = if is_target(name, &scope) {
+                Some(ident)
+            } else if kind == UsageKind::Value {
+                // Enum values, e.g. `MyEnum.kValue`, refer to the enum.
+                name.rfind('.')
+                    .filter(|&dot| is_target(&name[..dot], &scope))
+                    .map(|dot| syntax::Range {
+                        start: ident.start,
+                        end: ident.start + dot,
+                    })
+            } else {
+                None
+            };
+            if let Some(reference) = reference {
+                let range = create_lsp_range(ast, &reference);
+                references.push((Location::new(ast.uri.clone(), range), kind));
+            }
+        }
+    }
+}
+
+// Appends imports in `ast` of the file which declares `target`. Import paths
+// are matched against the end of the file path, so files with the same
+// relative path under different include directories can't be told apart.
+fn collect_imports(ast: &MojomAst, target: &Target, references: &mut Vec<(Location, UsageKind)>) {
+    let target_path = match target.uri.to_file_path() {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+    for stmt in &ast.mojom.stmts {
+        if let syntax::Statement::Import(stmt) = stmt {
+            let path = ast.text(&stmt.path).trim_matches('"');
+            if !path.is_empty() && target_path.ends_with(path) {
+                let range = create_lsp_range(ast, &stmt.path);
+                references.push((Location::new(ast.uri.clone(), range), UsageKind::Import));
+            }
+        }
+    }
+}
+
+// Parses a file in the symbol table from the disk.
+fn read_file(uri: &Url) -> Option<MojomAst> {
+    let path = uri.to_file_path().ok()?;
+    let text = std::fs::read_to_string(path).ok()?;
+    let mojom = syntax::parse(&text).ok()?;
+    Some(MojomAst::from_mojom(uri.clone(), text, mojom))
+}
+
+// Returns the declaration of the symbol at `pos` in `ast`.
+fn find_target(ast: &MojomAst, symbols: &SymbolTable, pos: &Position) -> Option<Target> {
+    let range = identifier_range_at(&ast.text, pos)?;
+    let scope = enclosing_scope(&ast.text, &ast.mojom, range.start);
+    let symbol = symbols.lookup(&ast.text[range], &scope)?;
+    Some(Target {
+        uri: symbol.uri.clone(),
+        range: symbol.symbol.range,
+    })
+}
+
+// Calls `report` with locations in each file which refer to `target`,
+// except for the declaration. Files in `symbols` other than `ast` are read
+// from the disk. Stops scanning when `report` returns false.
+fn scan_usages<F>(ast: &MojomAst, symbols: &SymbolTable, target: &Target, mut report: F)
+where
+    F: FnMut(Vec<(Location, UsageKind)>) -> bool,
+{
+    let mut usages = Vec::new();
+    collect_references(ast, symbols, target, &mut usages);
+    collect_imports(ast, target, &mut usages);
+    if !report(usages) {
+        return;
+    }
+    for uri in symbols.uris().filter(|uri| **uri != ast.uri) {
+        if let Some(other) = read_file(uri) {
+            let file = FileSymbols::from_mojom(uri.clone(), &other.text, &other.mojom);
+            let table = symbols.with_file(file);
+            let mut usages = Vec::new();
+            collect_references(&other, &table, target, &mut usages);
+            collect_imports(&other, target, &mut usages);
+            if !report(usages) {
+                return;
+            }
+        }
+    }
+}
+
+/// Same as find_references() but calls `report` with locations as soon as
+/// each file is scanned. Files without references aren't reported. Stops
+/// scanning when `report` returns false.
+pub(crate) fn stream_references<F>(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+    include_declaration: bool,
+    mut report: F,
+) where
+    F: FnMut(Vec<Location>) -> bool,
+{
+    let target = match find_target(ast, symbols, pos) {
+        Some(target) => target,
+        None => return,
+    };
+
+    let mut declaration = if include_declaration {
+        Some(Location::new(target.uri.clone(), target.range))
+    } else {
+        None
+    };
+    scan_usages(ast, symbols, &target, |usages| {
+        let mut locations: Vec<Location> = declaration.take().into_iter().collect();
+        // Imports refer to the file rather than the symbol.
+        locations.extend(
+            usages
+                .into_iter()
+                .filter(|(_, kind)| *kind != UsageKind::Import)
+                .map(|(location, _)| location),
+        );
+        locations.is_empty() || report(locations)
+    });
+}
+
+/// Returns locations which refer to the symbol at `pos` in `ast`. Files in
+/// `symbols` other than `ast` are read from the disk.
+pub(crate) fn find_references(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+    include_declaration: bool,
+) -> Vec<Location> {
+    let mut references = Vec::new();
+    stream_references(ast, symbols, pos, include_declaration, |locations| {
+        references.extend(locations);
+        true
+    });
+    references
+}
+
+/// Same as find_references() but groups locations by how they refer to the
+/// symbol. Includes the declaration and imports of the file which declares
+/// the symbol. Empty groups are omitted.
+pub(crate) fn find_references_by_kind(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+) -> Vec<ReferenceGroup> {
+    let target = match find_target(ast, symbols, pos) {
+        Some(target) => target,
+        None => return Vec::new(),
+    };
+
+    let mut groups = BTreeMap::new();
+    groups.insert(
+        UsageKind::Declaration,
+        vec![Location::new(target.uri.clone(), target.range)],
+    );
+    scan_usages(ast, symbols, &target, |usages| {
+        for (location, kind) in usages {
+            groups.entry(kind).or_insert_with(Vec::new).push(location);
+        }
+        true
+    });
+    groups
+        .into_iter()
+        .map(|(kind, locations)| ReferenceGroup {
+            kind: kind,
+            locations: locations,
+        })
+        .collect()
+}
+
+/// Same as find_references() but only looks into `ast`. The declaration is
+/// marked as `Write` and usages are marked as `Read`.
+pub(crate) fn document_highlights(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+) -> Vec<DocumentHighlight> {
+    let target = match find_target(ast, symbols, pos) {
+        Some(target) => target,
+        None => return Vec::new(),
+    };
+
+    let mut highlights = Vec::new();
+    if target.uri == ast.uri {
+        highlights.push(DocumentHighlight {
+            range: target.range,
+            kind: Some(DocumentHighlightKind::Write),
+        });
+    }
+    let mut references = Vec::new();
+    collect_references(ast, symbols, &target, &mut references);
+    highlights.extend(
+        references
+            .into_iter()
+            .map(|(location, _)| DocumentHighlight {
+                range: location.range,
+                kind: Some(DocumentHighlightKind::Read),
+            }),
+    );
+    // The cursor may be on a name in a comment or a string.
+    let on_occurrence = highlights
+        .iter()
+        .any(|highlight| highlight.range.start <= *pos && *pos <= highlight.range.end);
+    if !on_occurrence {
+        highlights.clear();
+    }
+    highlights
+}
+
+/// Returns diagnostics for type names in `ast` which are neither builtin
+/// types nor declared in `symbols`.
+pub(crate) fn unresolved_types(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+) -> Vec<lsp_types::Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (range, kind) in referring_ranges(ast) {
+        if kind == UsageKind::Value {
+            continue;
+        }
+        for name in type_names(&ast.text, range) {
+            let scope = enclosing_scope(&ast.text, &ast.mojom, name.start);
+            if symbols.lookup(ast.text(&name), &scope).is_none() {
+                let range = create_lsp_range(ast, &name);
+                let message = format!("Unknown type `{}`", ast.text(&name));
+                diagnostics.push(create_diagnostic(range, message));
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Range;
+
+    fn references(text: &str, pos: Position, include_declaration: bool) -> Vec<Range> {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        find_references(&ast, &symbols, &pos, include_declaration)
+            .into_iter()
+            .map(|location| location.range)
+            .collect()
+    }
+
+    fn references_by_kind(text: &str, pos: Position) -> Vec<(UsageKind, Vec<Range>)> {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        find_references_by_kind(&ast, &symbols, &pos)
+            .into_iter()
+            .map(|group| {
+                let ranges = group
+                    .locations
+                    .into_iter()
+                    .map(|location| location.range)
+                    .collect();
+                (group.kind, ranges)
+            })
+            .collect()
+    }
+
+    fn highlights(text: &str, pos: Position) -> Vec<DocumentHighlight> {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        document_highlights(&ast, &symbols, &pos)
+    }
+
+    fn range(line: u64, start: u64, end: u64) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn test_find_references() {
+        let text = r#"struct Foo {};
+// Foo in a comment.
+struct Bar {
+  Foo foo;
+  array<Foo>? foos;
+  string name = "Foo";
+};
+interface Baz {
+  Get(Foo foo);
+};"#;
+        // On the declaration.
+        let expected = vec![
+            range(0, 7, 10),
+            range(3, 2, 5),
+            range(4, 8, 11),
+            range(8, 6, 9),
+        ];
+        assert_eq!(expected, references(text, Position::new(0, 8), true));
+        // On a usage.
+        assert_eq!(expected, references(text, Position::new(4, 9), true));
+        assert_eq!(
+            expected[1..].to_vec(),
+            references(text, Position::new(3, 3), false)
+        );
+
+        // Builtin types aren't symbols.
+        assert!(references(text, Position::new(5, 4), true).is_empty());
+    }
+
+    fn unresolved(text: &str) -> Vec<(Range, String)> {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        unresolved_types(&ast, &symbols)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.range, diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_unresolved_types() {
+        let text = r#"struct Point {
+  int32 x;
+};
+struct Shape {
+  array<Point> points;
+  map<string, Point?> named_points;
+  handle<message_pipe> pipe;
+  Color color;
+};
+interface Canvas {
+  enum Mode { kFill, kStroke };
+  Draw(array<Shape> shapes, Mode mode) => (Brush? brush);
+};
+const uint32 kMax = 1;"#;
+        let expected = vec![
+            (range(7, 2, 7), "Unknown type `Color`".to_owned()),
+            (range(11, 43, 48), "Unknown type `Brush`".to_owned()),
+        ];
+        assert_eq!(expected, unresolved(text));
+    }
+
+    #[test]
+    fn test_find_references_values() {
+        let text = "enum Color { kRed, kBlue = kRed };
+interface Foo { Bar(pending_remote<Foo> foo); };
+const Color kDefault = Color.kRed;
+struct S { Color c = Color.kBlue; };";
+        let expected = vec![
+            range(2, 6, 11),
+            range(2, 23, 28),
+            range(3, 11, 16),
+            range(3, 21, 26),
+        ];
+        assert_eq!(expected, references(text, Position::new(0, 6), false));
+
+        let expected = vec![range(1, 35, 38)];
+        assert_eq!(expected, references(text, Position::new(1, 11), false));
+    }
+
+    #[test]
+    fn test_find_references_by_kind() {
+        let text = "enum Color { kRed };
+struct Bar { Color color = Color.kRed; };
+interface Baz {
+  Get(Color color) => (Color result);
+};";
+        let expected = vec![
+            (UsageKind::Declaration, vec![range(0, 5, 10)]),
+            (UsageKind::Type, vec![range(1, 13, 18)]),
+            (UsageKind::Parameter, vec![range(3, 6, 11)]),
+            (UsageKind::ReturnType, vec![range(3, 23, 28)]),
+            (UsageKind::Value, vec![range(1, 27, 32)]),
+        ];
+        assert_eq!(expected, references_by_kind(text, Position::new(3, 7)));
+        // The standard result doesn't change.
+        assert_eq!(5, references(text, Position::new(3, 7), true).len());
+
+        assert!(references_by_kind(text, Position::new(2, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_document_highlights() {
+        let text = r#"struct Foo {};
+/* Foo */
+struct Bar {
+  Foo foo;
+  string name = "Foo";
+};
+interface Baz {
+  Get(Foo foo);
+};"#;
+        let expected = vec![
+            (range(0, 7, 10), DocumentHighlightKind::Write),
+            (range(3, 2, 5), DocumentHighlightKind::Read),
+            (range(7, 6, 9), DocumentHighlightKind::Read),
+        ];
+        let actual: Vec<_> = highlights(text, Position::new(3, 3))
+            .into_iter()
+            .map(|highlight| (highlight.range, highlight.kind.unwrap()))
+            .collect();
+        assert_eq!(expected, actual);
+
+        assert!(highlights(text, Position::new(1, 4)).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use lsp_types::{Position, Range, TextEdit, Url, WorkspaceEdit};
+
+use crate::model::SymbolTable;
+use crate::syntax::{self, preorder, Traversal};
+
+use super::definition::create_lsp_range;
+use super::document::position_to_offset;
+use super::mojomast::MojomAst;
+use super::references::{document_highlights, find_references};
+use super::semantic_tokens::{BUILTIN_TYPES, KEYWORDS};
+
+/// Returns true when `name` can be used as the name of a declaration.
+pub(crate) fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_with_letter = chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_');
+    starts_with_letter
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && !KEYWORDS.contains(&name)
+        && !BUILTIN_TYPES.contains(&name)
+}
+
+// Returns the name range of the field, method or parameter declared at `pos`.
+// These can't be referred from other declarations.
+fn member_name_at(ast: &MojomAst, pos: &Position) -> Option<syntax::Range> {
+    let mut names = Vec::new();
+    for traversal in preorder(&ast.mojom) {
+        match traversal {
+            Traversal::StructField(node) => names.push(&node.name),
+            Traversal::Union(node) => names.extend(node.fields.iter().map(|field| &field.name)),
+            Traversal::Method(node) => {
+                names.push(&node.name);
+                let response = node.response.iter().flat_map(|response| &response.params);
+                names.extend(node.params.iter().chain(response).map(|param| &param.name));
+            }
+            _ => (),
+        }
+    }
+    names
+        .into_iter()
+        .find(|name| {
+            let range = create_lsp_range(ast, name);
+            range.start <= *pos && *pos <= range.end
+        })
+        .cloned()
+}
+
+/// Returns the range of the name which `rename()` would rename at `pos`, or
+/// None when there is nothing to rename, e.g. on a keyword, a builtin type or
+/// in a comment.
+pub(crate) fn prepare_rename(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+) -> Option<Range> {
+    if let Some(name) = member_name_at(ast, pos) {
+        return Some(create_lsp_range(ast, &name));
+    }
+
+    let highlight = document_highlights(ast, symbols, pos)
+        .into_iter()
+        .find(|highlight| highlight.range.start <= *pos && *pos <= highlight.range.end)?;
+    // Only the last component of a qualified name is renamed.
+    let mut range = highlight.range;
+    let start = position_to_offset(&ast.text, &range.start);
+    let end = position_to_offset(&ast.text, &range.end);
+    let name = ast.text[start..end].rsplit('.').next().unwrap_or("");
+    range.start.character = range.end.character - name.len() as u64;
+    Some(range)
+}
+
+/// Returns edits which rename the declaration at `pos` and its references
+/// to `new_name`. Returns `Ok(None)` when there is nothing to rename at
+/// `pos`. Files in `symbols` other than `ast` are read from the disk.
+pub(crate) fn rename(
+    ast: &MojomAst,
+    symbols: &SymbolTable,
+    pos: &Position,
+    new_name: &str,
+) -> Result<Option<WorkspaceEdit>, String> {
+    if !is_valid_identifier(new_name) {
+        return Err(format!("Invalid identifier: {}", new_name));
+    }
+
+    if let Some(name) = member_name_at(ast, pos) {
+        let edit = TextEdit::new(create_lsp_range(ast, &name), new_name.to_owned());
+        let mut changes = HashMap::new();
+        changes.insert(ast.uri.clone(), vec![edit]);
+        return Ok(Some(WorkspaceEdit::new(changes)));
+    }
+
+    let locations = find_references(ast, symbols, pos, true);
+    // The cursor may be on a name in a comment or a string.
+    let on_occurrence = locations.iter().any(|location| {
+        location.uri == ast.uri && location.range.start <= *pos && *pos <= location.range.end
+    });
+    if !on_occurrence {
+        return Ok(None);
+    }
+
+    // References may be qualified, e.g. `MyInterface.MyEnum`. Only the last
+    // component is the name of the declaration. The declaration comes first.
+    let old_name_len = locations[0].range.end.character - locations[0].range.start.character;
+    let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+    for location in locations {
+        let mut range = location.range;
+        range.start.character = range.end.character.saturating_sub(old_name_len);
+        changes
+            .entry(location.uri)
+            .or_default()
+            .push(TextEdit::new(range, new_name.to_owned()));
+    }
+    for (uri, edits) in changes.iter_mut() {
+        sort_edits(uri, edits)?;
+    }
+    Ok(Some(WorkspaceEdit::new(changes)))
+}
+
+// Sorts `edits` in a file and removes duplicates. Clients reject edits which
+// overlap, so they are reported as an error rather than applied partially.
+fn sort_edits(uri: &Url, edits: &mut Vec<TextEdit>) -> Result<(), String> {
+    edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+    edits.dedup_by(|a, b| a.range == b.range);
+    match edits
+        .windows(2)
+        .find(|pair| pair[1].range.start < pair[0].range.end)
+    {
+        Some(pair) => Err(format!(
+            "Overlapping edits in {}: {:?} and {:?}",
+            uri, pair[0].range, pair[1].range
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_types::Range;
+
+    use crate::model::FileSymbols;
+
+    fn rename_in(text: &str, pos: Position, new_name: &str) -> Result<Option<Vec<Range>>, String> {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri.clone(), text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        let edit = rename(&ast, &symbols, &pos, new_name)?;
+        Ok(edit.map(|edit| {
+            let changes = edit.changes.unwrap();
+            assert_eq!(1, changes.len());
+            changes[&uri]
+                .iter()
+                .map(|edit| {
+                    assert_eq!(new_name, edit.new_text);
+                    edit.range
+                })
+                .collect()
+        }))
+    }
+
+    fn range(line: u64, start: u64, end: u64) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn test_rename_struct() {
+        let text = r#"struct Foo {};
+// Foo in a comment.
+struct Bar {
+  Foo foo;
+  array<Foo>? foos;
+  string name = "Foo";
+};
+interface Baz {
+  Get(Foo foo);
+};"#;
+        let expected = vec![
+            range(0, 7, 10),
+            range(3, 2, 5),
+            range(4, 8, 11),
+            range(8, 6, 9),
+        ];
+        let edits = rename_in(text, Position::new(3, 3), "Qux").unwrap();
+        assert_eq!(Some(expected), edits);
+
+        // Not on an occurrence.
+        assert_eq!(Ok(None), rename_in(text, Position::new(1, 4), "Qux"));
+    }
+
+    #[test]
+    fn test_rename_nested() {
+        let text = "interface Foo { enum Kind { kA }; Get(Foo.Kind kind); };";
+        let edits = rename_in(text, Position::new(0, 22), "Type").unwrap();
+        assert_eq!(Some(vec![range(0, 21, 25), range(0, 42, 46)]), edits);
+    }
+
+    #[test]
+    fn test_rename_members() {
+        let text = "struct Foo { int32 bar; };\ninterface Baz { Get(int32 id) => (bool ok); };";
+        let edits = rename_in(text, Position::new(0, 20), "qux").unwrap();
+        assert_eq!(Some(vec![range(0, 19, 22)]), edits);
+        let edits = rename_in(text, Position::new(1, 16), "Fetch").unwrap();
+        assert_eq!(Some(vec![range(1, 16, 19)]), edits);
+        let edits = rename_in(text, Position::new(1, 27), "key").unwrap();
+        assert_eq!(Some(vec![range(1, 26, 28)]), edits);
+    }
+
+    #[test]
+    fn test_sort_edits() {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let edit = |line, start, end| TextEdit::new(range(line, start, end), "Foo".to_owned());
+        let mut edits = vec![edit(2, 0, 3), edit(0, 4, 7), edit(2, 0, 3), edit(0, 0, 3)];
+        assert!(sort_edits(&uri, &mut edits).is_ok());
+        assert_eq!(vec![edit(0, 0, 3), edit(0, 4, 7), edit(2, 0, 3)], edits);
+
+        let mut edits = vec![edit(0, 4, 7), edit(0, 2, 5)];
+        assert!(sort_edits(&uri, &mut edits).is_err());
+    }
+
+    #[test]
+    fn test_rename_invalid_identifier() {
+        let text = "struct Foo {};";
+        for name in &["", "1Foo", "Foo.Bar", "Foo-Bar", "struct", "int32"] {
+            assert!(rename_in(text, Position::new(0, 8), name).is_err());
+        }
+        assert!(rename_in(text, Position::new(0, 8), "_Foo2").is_ok());
+    }
+
+    fn prepare_rename_in(text: &str, pos: Position) -> Option<Range> {
+        let uri = Url::parse("file:///test.mojom").unwrap();
+        let mojom = syntax::parse(text).unwrap();
+        let ast = MojomAst::from_mojom(uri, text.to_owned(), mojom);
+        let file = FileSymbols::from_mojom(ast.uri.clone(), &ast.text, &ast.mojom);
+        let symbols = SymbolTable::new(file, Vec::new());
+        prepare_rename(&ast, &symbols, &pos)
+    }
+
+    #[test]
+    fn test_prepare_rename() {
+        let text = r#"// Foo
+struct Foo { uint32 id; };
+interface Bar {
+  enum Kind { kA };
+  Get(Bar.Kind kind) => (Foo foo);
+};"#;
+        // Declarations.
+        assert_eq!(
+            Some(range(1, 7, 10)),
+            prepare_rename_in(text, Position::new(1, 8))
+        );
+        assert_eq!(
+            Some(range(1, 20, 22)),
+            prepare_rename_in(text, Position::new(1, 21))
+        );
+        // References.
+        assert_eq!(
+            Some(range(4, 10, 14)),
+            prepare_rename_in(text, Position::new(4, 11))
+        );
+        assert_eq!(
+            Some(range(4, 25, 28)),
+            prepare_rename_in(text, Position::new(4, 26))
+        );
+
+        // Builtin type.
+        assert_eq!(None, prepare_rename_in(text, Position::new(1, 15)));
+        // Keyword.
+        assert_eq!(None, prepare_rename_in(text, Position::new(1, 2)));
+        // Punctuation.
+        assert_eq!(None, prepare_rename_in(text, Position::new(1, 24)));
+        // Comment.
+        assert_eq!(None, prepare_rename_in(text, Position::new(0, 4)));
+    }
+}
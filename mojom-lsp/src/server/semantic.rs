@@ -12,24 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::syntax::{self, Module, MojomFile};
+use std::collections::HashMap;
+
+use lsp_types::Url as Uri;
+
+use crate::model::{enclosing_scope, FileSymbols, SymbolKind, SymbolTable};
+use crate::syntax::{self, has_attribute, partial_text, preorder, Module, MojomFile, Traversal};
 
 use super::diagnostic;
 
 pub(crate) struct Analysis {
-    pub(crate) module: Option<Module>,
     pub(crate) diagnostics: Vec<lsp_types::Diagnostic>,
 }
 
-fn partial_text<'a>(text: &'a str, range: &syntax::Range) -> &'a str {
-    &text[range.start..range.end]
+fn create_diagnostic(text: &str, range: &syntax::Range, message: String) -> lsp_types::Diagnostic {
+    let start = syntax::line_col(text, range.start);
+    let end = syntax::line_col(text, range.end);
+    let range = diagnostic::into_lsp_range(&start, &end);
+    diagnostic::create_diagnostic(range, message)
 }
 
-fn find_module(
-    text: &str,
-    mojom: &MojomFile,
-    diagnostics: &mut Vec<lsp_types::Diagnostic>,
-) -> Option<Module> {
+fn check_module(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<lsp_types::Diagnostic>) {
     let mut module: Option<Module> = None;
     for stmt in &mojom.stmts {
         match stmt {
@@ -40,8 +43,8 @@ fn find_module(
                         partial_text(&text, &module.name),
                         partial_text(&text, &stmt.name)
                     );
-                    let start = syntax::line_col(text, stmt.name.start).unwrap();
-                    let end = syntax::line_col(text, stmt.name.end).unwrap();
+                    let start = syntax::line_col(text, stmt.name.start);
+                    let end = syntax::line_col(text, stmt.name.end);
                     let range = diagnostic::into_lsp_range(&start, &end);
                     let diagnostic = diagnostic::create_diagnostic(range, message);
                     diagnostics.push(diagnostic);
@@ -52,14 +55,848 @@ fn find_module(
             _ => (),
         }
     }
-    module
 }
 
-pub(crate) fn check_semantics(text: &str, mojom: &MojomFile) -> Analysis {
+fn is_valid_fixed_array_size(size: &str) -> bool {
+    // Fixed array sizes must be positive decimal integers that fit in uint32.
+    if !size.chars().all(|ch| ch.is_ascii_digit()) {
+        return false;
+    }
+    match size.parse::<u32>() {
+        Ok(size) => size > 0,
+        Err(_) => false,
+    }
+}
+
+fn check_fixed_array_sizes(
+    text: &str,
+    typ: &syntax::Range,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let sizes = match syntax::fixed_array_sizes(partial_text(text, typ)) {
+        Ok(sizes) => sizes,
+        Err(_) => return,
+    };
+    for size in sizes {
+        let range = syntax::Range {
+            start: typ.start + size.start,
+            end: typ.start + size.end,
+        };
+        let size = partial_text(text, &range);
+        if !is_valid_fixed_array_size(size) {
+            let message = format!("Fixed array size must be a positive integer: {}", size);
+            diagnostics.push(create_diagnostic(text, &range, message));
+        }
+    }
+}
+
+fn check_enum_ordinals(
+    text: &str,
+    stmt: &syntax::Enum,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    for value in &stmt.values {
+        if let Some(ref ordinal) = value.ordinal {
+            let message = format!(
+                "Ordinals are not allowed on enum values: {}",
+                partial_text(text, &value.name)
+            );
+            diagnostics.push(create_diagnostic(text, ordinal, message));
+        }
+    }
+}
+
+// Unknown values of an `[Extensible]` enum are mapped to its `[Default]`
+// member when deserialized, so an extensible enum needs exactly one of it.
+// Non-extensible enums reject unknown values and can't have one.
+fn check_enum_default(
+    uri: &Uri,
+    text: &str,
+    stmt: &syntax::Enum,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let enum_name = partial_text(text, &stmt.name);
+    let defaults: Vec<_> = stmt
+        .values
+        .iter()
+        .filter(|value| has_attribute(text, &value.attributes, "Default"))
+        .collect();
+    let extensible = has_attribute(text, &stmt.attributes, "Extensible");
+
+    if !extensible {
+        for value in defaults {
+            let message = format!(
+                "[Default] is only allowed in [Extensible] enums: {}",
+                partial_text(text, &value.name)
+            );
+            diagnostics.push(create_diagnostic(text, &value.name, message));
+        }
+        return;
+    }
+
+    // A declaration like `[Extensible] enum E;` has no members to check.
+    if stmt.values.is_empty() {
+        return;
+    }
+    match defaults.split_first() {
+        None => {
+            let message = format!(
+                "[Extensible] enum {} must have a [Default] member for unknown values",
+                enum_name
+            );
+            diagnostics.push(create_diagnostic(text, &stmt.name, message));
+        }
+        Some((first, rest)) => {
+            for value in rest {
+                let message = format!(
+                    "Enum {} has more than one [Default] member: {}",
+                    enum_name,
+                    partial_text(text, &value.name)
+                );
+                let mut diagnostic = create_diagnostic(text, &value.name, message);
+                let related = create_related_information(
+                    uri,
+                    text,
+                    &first.name,
+                    "First [Default] member".to_owned(),
+                );
+                diagnostic.related_information = Some(vec![related]);
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+}
+
+fn check_enums(
+    uri: &Uri,
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::Enum(stmt) => {
+                check_enum_ordinals(text, stmt, diagnostics);
+                check_enum_default(uri, text, stmt, diagnostics);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn create_related_information(
+    uri: &Uri,
+    text: &str,
+    range: &syntax::Range,
+    message: String,
+) -> lsp_types::DiagnosticRelatedInformation {
+    let start = syntax::line_col(text, range.start);
+    let end = syntax::line_col(text, range.end);
+    let range = diagnostic::into_lsp_range(&start, &end);
+    lsp_types::DiagnosticRelatedInformation {
+        location: lsp_types::Location::new(uri.clone(), range),
+        message: message,
+    }
+}
+
+// Types are compared textually, ignoring whitespaces.
+fn normalize_type(typ: &str) -> String {
+    typ.chars().filter(|ch| !ch.is_whitespace()).collect()
+}
+
+fn check_union_variants(
+    uri: &Uri,
+    text: &str,
+    union: &syntax::Union,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    for (i, field) in union.fields.iter().enumerate() {
+        let previous_fields = &union.fields[..i];
+
+        let name = partial_text(text, &field.name);
+        let same_name = previous_fields
+            .iter()
+            .find(|prev| partial_text(text, &prev.name) == name);
+        if let Some(prev) = same_name {
+            let message = format!("Duplicate union variant name: {}", name);
+            let mut diagnostic = create_diagnostic(text, &field.name, message);
+            let related = create_related_information(
+                uri,
+                text,
+                &prev.name,
+                format!("{} is first declared here", name),
+            );
+            diagnostic.related_information = Some(vec![related]);
+            diagnostics.push(diagnostic);
+        }
+
+        let typ = normalize_type(partial_text(text, &field.typ));
+        let same_type = previous_fields
+            .iter()
+            .find(|prev| normalize_type(partial_text(text, &prev.typ)) == typ);
+        if let Some(prev) = same_type {
+            let message = format!("Union variants must have distinct types: {}", typ);
+            let mut diagnostic = create_diagnostic(text, &field.typ, message);
+            let related = create_related_information(
+                uri,
+                text,
+                &prev.typ,
+                format!("{} is also used by {}", typ, partial_text(text, &prev.name)),
+            );
+            diagnostic.related_information = Some(vec![related]);
+            diagnostics.push(diagnostic);
+        }
+    }
+}
+
+fn check_unions(
+    uri: &Uri,
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::Union(union) => check_union_variants(uri, text, union, diagnostics),
+            _ => (),
+        }
+    }
+}
+
+fn check_types(text: &str, mojom: &MojomFile, diagnostics: &mut Vec<lsp_types::Diagnostic>) {
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::StructField(field) => check_fixed_array_sizes(text, &field.typ, diagnostics),
+            Traversal::Union(union) => {
+                for field in &union.fields {
+                    check_fixed_array_sizes(text, &field.typ, diagnostics);
+                }
+            }
+            Traversal::Method(method) => {
+                let response_params = method.response.iter().flat_map(|res| res.params.iter());
+                for param in method.params.iter().chain(response_params) {
+                    check_fixed_array_sizes(text, &param.typ, diagnostics);
+                }
+            }
+            Traversal::Const(stmt) => check_fixed_array_sizes(text, &stmt.typ, diagnostics),
+            _ => (),
+        }
+    }
+}
+
+// Kinds of default values of struct fields.
+#[derive(Clone, Copy, PartialEq)]
+enum DefaultKind {
+    Bool,
+    Integer,
+    Float,
+    String,
+    // The `default` keyword.
+    Default,
+    // Constants or enum values.
+    Identifier,
+}
+
+fn default_kind(value: &str) -> DefaultKind {
+    match value {
+        "true" | "false" => DefaultKind::Bool,
+        "default" => DefaultKind::Default,
+        _ if value.starts_with('"') => DefaultKind::String,
+        _ if value.starts_with(|ch: char| ch.is_ascii_digit() || "+-.".contains(ch)) => {
+            let digits = value.trim_start_matches(['+', '-']);
+            let is_hex = digits.starts_with("0x") || digits.starts_with("0X");
+            if is_hex || !digits.contains(['.', 'e', 'E']) {
+                DefaultKind::Integer
+            } else {
+                DefaultKind::Float
+            }
+        }
+        _ => DefaultKind::Identifier,
+    }
+}
+
+// Returns the last component of a dotted name.
+fn last_component(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+// Checks the default value of a struct field against the field type. User
+// types are resolved in `scope`, the declarations which enclose the field.
+// Types declared in other files can't be checked.
+fn check_field_default(
+    text: &str,
+    field: &syntax::StructField,
+    symbols: &SymbolTable,
+    scope: &[&str],
+    enums: &HashMap<String, Vec<&str>>,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let default = match field.default {
+        Some(ref default) => default,
+        None => return,
+    };
+    let typ = partial_text(text, &field.typ);
+    let type_spec = match syntax::typespec(typ) {
+        Ok(type_spec) => type_spec,
+        Err(_) => return,
+    };
+    let value = partial_text(text, default);
+    let kind = default_kind(value);
+    let disallowed = |diagnostics: &mut Vec<lsp_types::Diagnostic>| {
+        let message = format!("Default values aren't allowed for type: {}", typ);
+        diagnostics.push(create_diagnostic(text, default, message));
+    };
+    let name = match type_spec.type_name {
+        syntax::TypeName::BasicTypeName(ref name) if !name.starts_with("pending_") => name.as_str(),
+        _ => {
+            disallowed(diagnostics);
+            return;
+        }
+    };
+    let is_valid = match name {
+        "bool" => kind == DefaultKind::Bool || kind == DefaultKind::Identifier,
+        "int8" | "uint8" | "int16" | "uint16" | "int32" | "uint32" | "int64" | "uint64" => {
+            kind == DefaultKind::Integer || kind == DefaultKind::Identifier
+        }
+        "float" | "double" => {
+            kind == DefaultKind::Integer
+                || kind == DefaultKind::Float
+                || kind == DefaultKind::Identifier
+        }
+        "string" => kind == DefaultKind::String || kind == DefaultKind::Identifier,
+        _ => match symbols.lookup(name, scope).map(|found| found.symbol) {
+            Some(symbol) if symbol.kind == SymbolKind::Enum => {
+                let values = enums.get(&symbol.name);
+                let is_known =
+                    values.map_or(true, |values| values.contains(&last_component(value)));
+                if kind == DefaultKind::Identifier && !is_known {
+                    let message = format!("Unknown value of enum {}: {}", name, value);
+                    diagnostics.push(create_diagnostic(text, default, message));
+                    return;
+                }
+                kind == DefaultKind::Identifier
+            }
+            // Structs can only be initialized with `default`.
+            Some(symbol) if symbol.kind == SymbolKind::Struct => kind == DefaultKind::Default,
+            Some(symbol) if symbol.kind != SymbolKind::Const => {
+                disallowed(diagnostics);
+                return;
+            }
+            _ => kind == DefaultKind::Default || kind == DefaultKind::Identifier,
+        },
+    };
+    if !is_valid {
+        let message = format!("Default value doesn't match type {}: {}", typ, value);
+        diagnostics.push(create_diagnostic(text, default, message));
+    }
+}
+
+// Returns values of enums declared in `mojom`, keyed by names qualified by
+// enclosing declarations, e.g. `MyStruct.MyEnum`.
+fn enum_values<'a>(text: &'a str, mojom: &MojomFile) -> HashMap<String, Vec<&'a str>> {
+    let mut enums = HashMap::new();
+    let mut path: Vec<&str> = Vec::new();
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterInterface(node) => path.push(partial_text(text, &node.name)),
+            Traversal::EnterStruct(node) => path.push(partial_text(text, &node.name)),
+            Traversal::LeaveInterface(_) | Traversal::LeaveStruct(_) => {
+                path.pop();
+            }
+            Traversal::Enum(node) => {
+                let mut name = path.clone();
+                name.push(partial_text(text, &node.name));
+                let values = node
+                    .values
+                    .iter()
+                    .map(|value| partial_text(text, &value.name))
+                    .collect();
+                enums.insert(name.join("."), values);
+            }
+            _ => (),
+        }
+    }
+    enums
+}
+
+fn check_field_defaults(
+    uri: &Uri,
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let file = FileSymbols::from_mojom(uri.clone(), text, mojom);
+    let symbols = SymbolTable::new(file, Vec::new());
+    let enums = enum_values(text, mojom);
+    for traversal in preorder(mojom) {
+        if let Traversal::StructField(field) = traversal {
+            let scope = enclosing_scope(text, mojom, field.name.start);
+            check_field_default(text, field, &symbols, &scope, &enums, diagnostics);
+        }
+    }
+}
+
+// Ordinals are encoded as uint32.
+const MAX_ORDINAL: u64 = 0xffff_ffff;
+
+/// Returns the ordinal value without the leading `@`. Returns None when the
+/// value doesn't fit in u64.
+pub(crate) fn ordinal_value(text: &str, ordinal: &syntax::Range) -> Option<u64> {
+    text[ordinal.start + 1..ordinal.end].parse::<u64>().ok()
+}
+
+/// Returns pairs of the name and the ordinal of each method of `stmt`.
+pub(crate) fn method_ordinals(
+    stmt: &syntax::Interface,
+) -> Vec<(&syntax::Range, &Option<syntax::Range>)> {
+    stmt.members
+        .iter()
+        .filter_map(|member| match member {
+            syntax::InterfaceMember::Method(method) => Some((&method.name, &method.ordinal)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns pairs of the name and the ordinal of each field of `stmt`.
+pub(crate) fn field_ordinals(
+    stmt: &syntax::Struct,
+) -> Vec<(&syntax::Range, &Option<syntax::Range>)> {
+    stmt.members
+        .iter()
+        .filter_map(|member| match member {
+            syntax::StructBody::Field(field) => Some((&field.name, &field.ordinal)),
+            _ => None,
+        })
+        .collect()
+}
+
+// `members` are pairs of a member name and its ordinal, in declaration order.
+// Members without ordinals are assigned the next ordinal of the previous
+// member, as the bindings generator does.
+fn check_ordinal_collisions(
+    uri: &Uri,
+    text: &str,
+    kind: &str,
+    members: &[(&syntax::Range, &Option<syntax::Range>)],
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let mut assigned: Vec<(u64, &syntax::Range)> = Vec::new();
+    let mut next_ordinal = 0;
+    for &(name, ordinal) in members {
+        let value = match ordinal {
+            Some(ordinal) => match ordinal_value(text, ordinal) {
+                Some(value) if value <= MAX_ORDINAL => value,
+                _ => {
+                    let message = format!(
+                        "Ordinal {} of {} is out of range. Ordinals must be between @0 and @{}",
+                        partial_text(text, ordinal),
+                        partial_text(text, name),
+                        MAX_ORDINAL
+                    );
+                    diagnostics.push(create_diagnostic(text, ordinal, message));
+                    // Following implicit ordinals can't be determined.
+                    return;
+                }
+            },
+            None => next_ordinal,
+        };
+        next_ordinal = value + 1;
+
+        let same_ordinal = assigned.iter().find(|&&(prev, _)| prev == value);
+        if let Some(&(_, prev_name)) = same_ordinal {
+            let message = format!(
+                "{} ordinal @{} of {} collides with {}",
+                kind,
+                value,
+                partial_text(text, name),
+                partial_text(text, prev_name)
+            );
+            let range = ordinal.as_ref().unwrap_or(name);
+            let mut diagnostic = create_diagnostic(text, range, message);
+            let related = create_related_information(
+                uri,
+                text,
+                prev_name,
+                format!(
+                    "@{} is first used by {}",
+                    value,
+                    partial_text(text, prev_name)
+                ),
+            );
+            diagnostic.related_information = Some(vec![related]);
+            diagnostics.push(diagnostic);
+        } else {
+            assigned.push((value, name));
+        }
+    }
+}
+
+// Struct fields must have ordinals either for all fields or for none of them.
+// Explicit ordinals must be contiguous from @0.
+fn check_struct_ordinals(
+    text: &str,
+    stmt: &syntax::Struct,
+    fields: &[(&syntax::Range, &Option<syntax::Range>)],
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    let struct_name = partial_text(text, &stmt.name);
+    if fields.iter().all(|(_, ordinal)| ordinal.is_none()) {
+        return;
+    }
+
+    let mut mixed = false;
+    for (name, _) in fields.iter().filter(|(_, ordinal)| ordinal.is_none()) {
+        let message = format!(
+            "Field {} has no ordinal while other fields of {} have ordinals",
+            partial_text(text, name),
+            struct_name
+        );
+        diagnostics.push(create_diagnostic(text, name, message));
+        mixed = true;
+    }
+    if mixed {
+        return;
+    }
+
+    // Without duplicates, ordinals are contiguous iff all of them are smaller
+    // than the number of fields.
+    for (name, ordinal) in fields {
+        let ordinal = ordinal.as_ref().unwrap();
+        match ordinal_value(text, ordinal) {
+            Some(value) if value >= fields.len() as u64 && value <= MAX_ORDINAL => {
+                let message = format!(
+                    "Ordinal {} of {} leaves a gap. Ordinals of {} must be contiguous from @0",
+                    partial_text(text, ordinal),
+                    partial_text(text, name),
+                    struct_name
+                );
+                diagnostics.push(create_diagnostic(text, ordinal, message));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn check_ordinals(
+    uri: &Uri,
+    text: &str,
+    mojom: &MojomFile,
+    diagnostics: &mut Vec<lsp_types::Diagnostic>,
+) {
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::EnterInterface(stmt) => {
+                let methods = method_ordinals(stmt);
+                check_ordinal_collisions(uri, text, "Method", &methods, diagnostics);
+            }
+            Traversal::EnterStruct(stmt) => {
+                let fields = field_ordinals(stmt);
+                let num_diagnostics = diagnostics.len();
+                check_ordinal_collisions(uri, text, "Field", &fields, diagnostics);
+                // Gaps are meaningless when ordinals collide.
+                if diagnostics.len() == num_diagnostics {
+                    check_struct_ordinals(text, stmt, &fields, diagnostics);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub(crate) fn check_semantics(uri: &Uri, text: &str, mojom: &MojomFile) -> Analysis {
     let mut diagnostics = Vec::new();
-    let module = find_module(text, mojom, &mut diagnostics);
+    check_module(text, mojom, &mut diagnostics);
+    check_types(text, mojom, &mut diagnostics);
+    check_field_defaults(uri, text, mojom, &mut diagnostics);
+    check_enums(uri, text, mojom, &mut diagnostics);
+    check_unions(uri, text, mojom, &mut diagnostics);
+    check_ordinals(uri, text, mojom, &mut diagnostics);
     Analysis {
-        module: module,
         diagnostics: diagnostics,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &str) -> Vec<lsp_types::Diagnostic> {
+        let mojom = syntax::parse(input).unwrap();
+        let uri = Uri::parse("file:///test.mojom").unwrap();
+        check_semantics(&uri, input, &mojom).diagnostics
+    }
+
+    #[test]
+    fn test_fixed_array_size() {
+        let input = "struct S { array<int32, 0> a; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 24), range.start);
+        assert_eq!(lsp_types::Position::new(0, 25), range.end);
+
+        let input = "interface I { Foo(array<int32, -1> a); };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 31), range.start);
+        assert_eq!(lsp_types::Position::new(0, 33), range.end);
+
+        let input = "struct S { array<int32, 4> a; };";
+        let diagnostics = check(input);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_field_defaults() {
+        let input = r#"enum Color { kRed, kBlue };
+struct S {
+  Color a = kRed;
+  Color b = Color.kBlue;
+  bool c = true;
+  int32 d = -0x10;
+  double e = 1.5e3;
+  float f = 2;
+  string g = "g";
+  Other h = default;
+  int64 i = kConstant;
+};"#;
+        assert!(check(input).is_empty());
+
+        let input = r#"enum Color { kRed };
+struct S {
+  int32 a = "a";
+  Color b = kGreen;
+  Color c = 1;
+  bool d = 0;
+  int8 e = 1.0;
+};"#;
+        let diagnostics = check(input);
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        let expected = vec![
+            "Default value doesn't match type int32: \"a\"",
+            "Unknown value of enum Color: kGreen",
+            "Default value doesn't match type Color: 1",
+            "Default value doesn't match type bool: 0",
+            "Default value doesn't match type int8: 1.0",
+        ];
+        assert_eq!(expected, messages);
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(2, 12), range.start);
+        assert_eq!(lsp_types::Position::new(2, 15), range.end);
+
+        // Nested enums with the same name.
+        let input = r#"struct A { enum Kind { kX }; Kind k = kX; };
+struct B { enum Kind { kY }; Kind k = kY; A.Kind a = A.Kind.kX; };"#;
+        assert!(check(input).is_empty());
+        let input = "struct A { enum Kind { kX }; };\nstruct B { enum Kind { kY }; Kind k = kX; };";
+        let messages: Vec<_> = check(input)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(vec!["Unknown value of enum Kind: kX"], messages);
+
+        // Only structs can be initialized with `default`.
+        let input = r#"interface I {};
+union U { int32 a; };
+struct P {};
+struct S {
+  I i = default;
+  U u = kConstant;
+  P p = default;
+  handle<message_pipe> h = default;
+  pending_remote<I> r = default;
+};"#;
+        let messages: Vec<_> = check(input)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        let expected = vec![
+            "Default values aren't allowed for type: I",
+            "Default values aren't allowed for type: U",
+            "Default values aren't allowed for type: handle<message_pipe>",
+            "Default values aren't allowed for type: pending_remote<I>",
+        ];
+        assert_eq!(expected, messages);
+
+        let input = "struct S {\n  array<int32> a = default;\n  handle h = 0;\n};";
+        let diagnostics = check(input);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            "Default values aren't allowed for type: array<int32>",
+            diagnostics[0].message
+        );
+        let range = diagnostics[1].range;
+        assert_eq!(lsp_types::Position::new(2, 13), range.start);
+        assert_eq!(lsp_types::Position::new(2, 14), range.end);
+    }
+
+    #[test]
+    fn test_enum_value_ordinal() {
+        let input = "struct S { int32 a@0; };";
+        let diagnostics = check(input);
+        assert!(diagnostics.is_empty());
+
+        let input = "enum E { kA@0, kB = 1 };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 11), range.start);
+        assert_eq!(lsp_types::Position::new(0, 13), range.end);
+    }
+
+    #[test]
+    fn test_union_variants() {
+        let input = "union U { int32 a; string b; array<uint8> c; };";
+        let diagnostics = check(input);
+        assert!(diagnostics.is_empty());
+
+        let input = "union U { string a; int32 b; string c; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 29), range.start);
+        assert_eq!(lsp_types::Position::new(0, 35), range.end);
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        assert_eq!(1, related.len());
+        assert_eq!(
+            lsp_types::Position::new(0, 10),
+            related[0].location.range.start
+        );
+        assert_eq!(
+            lsp_types::Position::new(0, 16),
+            related[0].location.range.end
+        );
+
+        let input = "union U { int32 a; uint32 a; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 26), range.start);
+        assert_eq!(lsp_types::Position::new(0, 27), range.end);
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        assert_eq!(
+            lsp_types::Position::new(0, 16),
+            related[0].location.range.start
+        );
+    }
+
+    #[test]
+    fn test_method_ordinals() {
+        let input = "interface I { A@0(); B@2(); C(); };";
+        let diagnostics = check(input);
+        assert!(diagnostics.is_empty());
+
+        let input = "interface I { A@1(); B@0(); C(); };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "Method ordinal @1 of C collides with A",
+            diagnostics[0].message
+        );
+        // Implicit ordinals are reported on the name.
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 28), range.start);
+        assert_eq!(lsp_types::Position::new(0, 29), range.end);
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        assert_eq!(
+            lsp_types::Position::new(0, 14),
+            related[0].location.range.start
+        );
+
+        let input = "interface I {\n  A@3();\n  B@3();\n};";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "Method ordinal @3 of B collides with A",
+            diagnostics[0].message
+        );
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(2, 3), range.start);
+        assert_eq!(lsp_types::Position::new(2, 5), range.end);
+    }
+
+    #[test]
+    fn test_ordinal_out_of_range() {
+        let input = "interface I { A@4294967295(); B@4294967296(); };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 31), range.start);
+        assert_eq!(lsp_types::Position::new(0, 42), range.end);
+
+        let input = "struct S { int32 a@99999999999999999999; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert!(diagnostics[0].message.contains("out of range"));
+    }
+
+    #[test]
+    fn test_struct_ordinals() {
+        let input = "struct S { int32 a@1; int32 b@0; };";
+        let diagnostics = check(input);
+        assert!(diagnostics.is_empty());
+
+        let input = "struct S { int32 a@0; int32 b@0; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "Field ordinal @0 of b collides with a",
+            diagnostics[0].message
+        );
+
+        let input = "struct S { int32 a@0; int32 b; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 28), range.start);
+        assert_eq!(lsp_types::Position::new(0, 29), range.end);
+
+        let input = "struct S { int32 a@0; int32 b@2; };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        let range = diagnostics[0].range;
+        assert_eq!(lsp_types::Position::new(0, 29), range.start);
+        assert_eq!(lsp_types::Position::new(0, 31), range.end);
+    }
+
+    #[test]
+    fn test_extensible_enum_default() {
+        let input = "[Extensible] enum E { [Default] kUnknown, kA, kB };";
+        assert!(check(input).is_empty());
+        let input = "enum E { kA, kB };";
+        assert!(check(input).is_empty());
+        let input = "[Extensible] enum E;";
+        assert!(check(input).is_empty());
+
+        let input = "[Extensible] enum E { kA, kB };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(lsp_types::Position::new(0, 18), diagnostics[0].range.start);
+        assert!(diagnostics[0]
+            .message
+            .contains("must have a [Default] member"));
+
+        let input = "[Extensible] enum E { [Default] kA, [Default] kB };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(lsp_types::Position::new(0, 46), diagnostics[0].range.start);
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        assert_eq!(
+            lsp_types::Position::new(0, 32),
+            related[0].location.range.start
+        );
+
+        let input = "enum E { [Default] kA, kB };";
+        let diagnostics = check(input);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(lsp_types::Position::new(0, 19), diagnostics[0].range.start);
+        assert!(diagnostics[0]
+            .message
+            .contains("only allowed in [Extensible] enums"));
+    }
+}
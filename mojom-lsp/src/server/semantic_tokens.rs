@@ -0,0 +1,598 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Semantic tokens (`textDocument/semanticTokens/full`) which were introduced
+// in LSP 3.16. lsp-types doesn't define them without the `proposed` feature.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::{enclosing_scope, FileSymbols, SymbolKind, SymbolTable};
+use crate::syntax::{self, is_identifier_char, preorder, Lexeme, LexemeKind, MojomFile, Traversal};
+
+use super::document_symbol::parse_recoverable;
+
+pub(crate) const SEMANTIC_TOKENS_FULL_METHOD: &str = "textDocument/semanticTokens/full";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SemanticTokensParams {
+    pub(crate) text_document: lsp_types::TextDocumentIdentifier,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SemanticTokens {
+    /// Tokens encoded as `(deltaLine, deltaStart, length, tokenType,
+    /// tokenModifiers)` tuples.
+    pub(crate) data: Vec<u32>,
+}
+
+// The order must match TOKEN_TYPES.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenType {
+    Namespace,
+    Interface,
+    Struct,
+    Enum,
+    Type,
+    Parameter,
+    Property,
+    EnumMember,
+    Method,
+    Variable,
+    Keyword,
+    Number,
+    String,
+    Comment,
+}
+
+const TOKEN_TYPES: &[&str] = &[
+    "namespace",
+    "interface",
+    "struct",
+    "enum",
+    "type",
+    "parameter",
+    "property",
+    "enumMember",
+    "method",
+    "variable",
+    "keyword",
+    "number",
+    "string",
+    "comment",
+];
+
+pub(crate) const KEYWORDS: &[&str] = &[
+    "module",
+    "import",
+    "interface",
+    "struct",
+    "union",
+    "enum",
+    "const",
+    "associated",
+    "true",
+    "false",
+    "default",
+];
+
+pub(crate) const BUILTIN_TYPES: &[&str] = &[
+    "bool",
+    "int8",
+    "uint8",
+    "int16",
+    "uint16",
+    "int32",
+    "uint32",
+    "int64",
+    "uint64",
+    "float",
+    "double",
+    "string",
+    "array",
+    "map",
+    "handle",
+    "pending_remote",
+    "pending_receiver",
+    "pending_associated_remote",
+    "pending_associated_receiver",
+];
+
+// Bits of token modifiers. The order must match TOKEN_MODIFIERS.
+const DECLARATION: u32 = 1 << 0;
+const DEFINITION: u32 = 1 << 1;
+const DEPRECATED: u32 = 1 << 2;
+const READONLY: u32 = 1 << 3;
+
+const TOKEN_MODIFIERS: &[&str] = &["declaration", "definition", "deprecated", "readonly"];
+
+/// The `semanticTokensProvider` server capability.
+pub(crate) fn semantic_tokens_provider() -> Value {
+    serde_json::json!({
+        "legend": {
+            "tokenTypes": TOKEN_TYPES,
+            "tokenModifiers": TOKEN_MODIFIERS,
+        },
+        "full": true,
+    })
+}
+
+// Tokenizes comments, literals, keywords and attributes. Other identifiers
+// are classified by the syntax tree.
+fn lexical_tokens(text: &str, lexemes: &[Lexeme]) -> Vec<(syntax::Range, TokenType)> {
+    let mut tokens = Vec::new();
+    let mut in_attribute = false;
+    let mut after_equal = false;
+    for lexeme in lexemes {
+        let typ = match lexeme.kind {
+            LexemeKind::LineComment | LexemeKind::BlockComment => TokenType::Comment,
+            LexemeKind::String => TokenType::String,
+            LexemeKind::Code => {
+                code_tokens(
+                    text,
+                    &lexeme.range,
+                    &mut in_attribute,
+                    &mut after_equal,
+                    &mut tokens,
+                );
+                continue;
+            }
+        };
+        tokens.push((lexeme.range.clone(), typ));
+    }
+    tokens
+}
+
+// Tokenizes numbers, keywords and attributes in code between comments and
+// string literals.
+fn code_tokens(
+    text: &str,
+    range: &syntax::Range,
+    in_attribute: &mut bool,
+    after_equal: &mut bool,
+    tokens: &mut Vec<(syntax::Range, TokenType)>,
+) {
+    let code = &text[range.start..range.end];
+    let mut chars = code.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        let next = chars.peek().map(|&(_, ch)| ch);
+        let typ = match ch {
+            '@' | '+' | '-' | '.' | '0'..='9'
+                if ch.is_ascii_digit() || next.is_some_and(|ch| ch.is_ascii_digit()) =>
+            {
+                while chars
+                    .next_if(|&(_, ch)| ch.is_ascii_alphanumeric() || ch == '.')
+                    .is_some()
+                {}
+                TokenType::Number
+            }
+            ch if is_identifier_char(ch) => {
+                while chars.next_if(|&(_, ch)| is_identifier_char(ch)).is_some() {}
+                let end = chars.peek().map(|&(i, _)| i).unwrap_or(code.len());
+                let word = &code[start..end];
+                if *in_attribute {
+                    if *after_equal {
+                        TokenType::Variable
+                    } else {
+                        TokenType::Property
+                    }
+                } else if KEYWORDS.contains(&word) {
+                    TokenType::Keyword
+                } else if BUILTIN_TYPES.contains(&word) {
+                    TokenType::Type
+                } else {
+                    continue;
+                }
+            }
+            '[' | ']' => {
+                *in_attribute = ch == '[';
+                *after_equal = false;
+                continue;
+            }
+            ',' => {
+                *after_equal = false;
+                continue;
+            }
+            '=' => {
+                *after_equal = true;
+                continue;
+            }
+            _ => continue,
+        };
+        let end = chars.peek().map(|&(i, _)| i).unwrap_or(code.len());
+        let range = syntax::Range {
+            start: range.start + start,
+            end: range.start + end,
+        };
+        tokens.push((range, typ));
+    }
+}
+
+/// Returns user-defined type names in a type spec, e.g. `foo.Bar` in
+/// `array<foo.Bar>?`.
+pub(crate) fn type_names(text: &str, range: &syntax::Range) -> Vec<syntax::Range> {
+    let source = &text[range.start..range.end];
+    let mut names = Vec::new();
+    let mut start = None;
+    for (i, ch) in source
+        .char_indices()
+        .chain(std::iter::once((source.len(), ' ')))
+    {
+        match start {
+            None if ch.is_ascii_alphabetic() || ch == '_' => start = Some(i),
+            Some(s) if !(is_identifier_char(ch) || ch == '.') => {
+                // Handle types like `handle<message_pipe>` aren't user-defined.
+                let word = &source[s..i];
+                let is_builtin = BUILTIN_TYPES.contains(&word) || KEYWORDS.contains(&word);
+                if !is_builtin && !source[..s].ends_with("handle<") {
+                    names.push(syntax::Range {
+                        start: range.start + s,
+                        end: range.start + i,
+                    });
+                }
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    names
+}
+
+// Returns modifiers of the name of a type declaration. Declarations with a
+// body, e.g. not `[Native] struct Foo;`, are also definitions.
+fn declaration_modifiers(
+    text: &str,
+    attributes: &[syntax::Attribute],
+    name: &syntax::Range,
+    range: &syntax::Range,
+) -> u32 {
+    let modifiers = if text[name.end..range.end].contains('{') {
+        DECLARATION | DEFINITION
+    } else {
+        DECLARATION
+    };
+    modifiers | deprecated(text, attributes)
+}
+
+// Returns `DEPRECATED` for declarations marked `[Deprecated]`.
+fn deprecated(text: &str, attributes: &[syntax::Attribute]) -> u32 {
+    if syntax::has_attribute(text, attributes, "Deprecated") {
+        DEPRECATED
+    } else {
+        0
+    }
+}
+
+// Tokenizes declared names and type references. Each token comes with its
+// modifiers.
+fn syntactic_tokens(text: &str, mojom: &MojomFile) -> Vec<(syntax::Range, TokenType, u32)> {
+    let file = FileSymbols::from_mojom(lsp_types::Url::parse("file:///").unwrap(), text, mojom);
+    let symbols = SymbolTable::new(file, Vec::new());
+    let type_of = |range: &syntax::Range| {
+        let scope = enclosing_scope(text, mojom, range.start);
+        let kind = symbols
+            .lookup(&text[range.start..range.end], &scope)
+            .map(|symbol| symbol.symbol.kind);
+        match kind {
+            Some(SymbolKind::Interface) => TokenType::Interface,
+            Some(SymbolKind::Struct) | Some(SymbolKind::Union) => TokenType::Struct,
+            Some(SymbolKind::Enum) => TokenType::Enum,
+            _ => TokenType::Type,
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let add_type = |tokens: &mut Vec<(syntax::Range, TokenType, u32)>, typ: &syntax::Range| {
+        for name in type_names(text, typ) {
+            let token_type = type_of(&name);
+            tokens.push((name, token_type, 0));
+        }
+    };
+    for traversal in preorder(mojom) {
+        match traversal {
+            Traversal::Module(node) => tokens.push((node.name.clone(), TokenType::Namespace, 0)),
+            Traversal::EnterInterface(node) => {
+                let modifiers =
+                    declaration_modifiers(text, &node.attributes, &node.name, &node.range);
+                tokens.push((node.name.clone(), TokenType::Interface, modifiers))
+            }
+            Traversal::EnterStruct(node) => {
+                let modifiers =
+                    declaration_modifiers(text, &node.attributes, &node.name, &node.range);
+                tokens.push((node.name.clone(), TokenType::Struct, modifiers))
+            }
+            Traversal::Union(node) => {
+                let modifiers =
+                    declaration_modifiers(text, &node.attributes, &node.name, &node.range);
+                tokens.push((node.name.clone(), TokenType::Struct, modifiers));
+                for field in &node.fields {
+                    add_type(&mut tokens, &field.typ);
+                    let modifiers = DECLARATION | deprecated(text, &field.attributes);
+                    tokens.push((field.name.clone(), TokenType::Property, modifiers));
+                }
+            }
+            Traversal::Enum(node) => {
+                let modifiers =
+                    declaration_modifiers(text, &node.attributes, &node.name, &node.range);
+                tokens.push((node.name.clone(), TokenType::Enum, modifiers));
+                for value in &node.values {
+                    let modifiers = DECLARATION | READONLY | deprecated(text, &value.attributes);
+                    tokens.push((value.name.clone(), TokenType::EnumMember, modifiers));
+                }
+            }
+            Traversal::Const(node) => {
+                add_type(&mut tokens, &node.typ);
+                let modifiers =
+                    DECLARATION | DEFINITION | READONLY | deprecated(text, &node.attributes);
+                tokens.push((node.name.clone(), TokenType::Variable, modifiers));
+            }
+            Traversal::StructField(node) => {
+                add_type(&mut tokens, &node.typ);
+                let modifiers = DECLARATION | deprecated(text, &node.attributes);
+                tokens.push((node.name.clone(), TokenType::Property, modifiers));
+            }
+            Traversal::Method(node) => {
+                let modifiers = DECLARATION | deprecated(text, &node.attributes);
+                tokens.push((node.name.clone(), TokenType::Method, modifiers));
+                let response_params = node.response.iter().flat_map(|res| res.params.iter());
+                for param in node.params.iter().chain(response_params) {
+                    add_type(&mut tokens, &param.typ);
+                    let modifiers = DECLARATION | deprecated(text, &param.attributes);
+                    tokens.push((param.name.clone(), TokenType::Parameter, modifiers));
+                }
+            }
+            _ => (),
+        }
+    }
+    tokens
+}
+
+// Converts byte offsets into (line, UTF-16 column) in increasing order.
+struct PositionCursor<'a> {
+    text: &'a str,
+    offset: usize,
+    line: u32,
+    col: u32,
+}
+
+impl<'a> PositionCursor<'a> {
+    fn advance(&mut self, offset: usize) -> (u32, u32) {
+        for ch in self.text[self.offset..offset].chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.col = 0;
+            } else {
+                self.col += ch.len_utf16() as u32;
+            }
+        }
+        self.offset = offset;
+        (self.line, self.col)
+    }
+}
+
+/// Returns semantic tokens of `text` which is split into `lexemes`.
+/// Declarations after a syntax error are only tokenized lexically.
+pub(crate) fn semantic_tokens(text: &str, lexemes: &[Lexeme]) -> SemanticTokens {
+    let mojom = parse_recoverable(text);
+    // Syntactic tokens take precedence over lexical ones.
+    let mut tokens = BTreeMap::new();
+    let lexical = lexical_tokens(text, lexemes)
+        .into_iter()
+        .map(|(range, typ)| (range, typ, 0));
+    for (range, typ, modifiers) in lexical.chain(syntactic_tokens(text, &mojom)) {
+        tokens.insert(range.start, (range.end, typ, modifiers));
+    }
+
+    let mut data = Vec::new();
+    let mut cursor = PositionCursor {
+        text: text,
+        offset: 0,
+        line: 0,
+        col: 0,
+    };
+    let (mut prev_line, mut prev_col) = (0, 0);
+    for (start, (end, typ, modifiers)) in tokens {
+        // Multi-line tokens are split into lines as clients may not support
+        // them.
+        let mut line_start = start;
+        for line in text[start..end].split('\n') {
+            let line = line.trim_end_matches('\r');
+            let (line_no, col) = cursor.advance(line_start);
+            line_start += line.len() + 1;
+            if line.is_empty() {
+                continue;
+            }
+            let length = line.chars().map(|ch| ch.len_utf16() as u32).sum::<u32>();
+            let delta_col = if line_no == prev_line {
+                col - prev_col
+            } else {
+                col
+            };
+            data.extend(&[
+                line_no - prev_line,
+                delta_col,
+                length,
+                typ as u32,
+                modifiers,
+            ]);
+            prev_line = line_no;
+            prev_col = col;
+        }
+    }
+    SemanticTokens { data: data }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decodes tokens into (line, col, length, token type) tuples.
+    fn decode(tokens: &SemanticTokens) -> Vec<(u32, u32, u32, &'static str)> {
+        let mut decoded = Vec::new();
+        let (mut line, mut col) = (0, 0);
+        for chunk in tokens.data.chunks(5) {
+            if chunk[0] > 0 {
+                col = 0;
+            }
+            line += chunk[0];
+            col += chunk[1];
+            decoded.push((line, col, chunk[2], TOKEN_TYPES[chunk[3] as usize]));
+        }
+        decoded
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let text = r#"module foo.mojom;
+/* Multi-line
+   comment */
+[EnableIf=foo]
+struct Foo {
+  pending_remote<Bar>? bar@1; // Trailing.
+};
+interface Bar {
+  Get(Foo foo) => (string value);
+};
+"#;
+        let tokens = semantic_tokens(text, &syntax::lex(text));
+        let expected = vec![
+            (0, 0, 6, "keyword"),
+            (0, 7, 9, "namespace"),
+            (1, 0, 13, "comment"),
+            (2, 0, 13, "comment"),
+            (3, 1, 8, "property"),
+            (3, 10, 3, "variable"),
+            (4, 0, 6, "keyword"),
+            (4, 7, 3, "struct"),
+            (5, 2, 14, "type"),
+            (5, 17, 3, "interface"),
+            (5, 23, 3, "property"),
+            (5, 26, 2, "number"),
+            (5, 30, 12, "comment"),
+            (7, 0, 9, "keyword"),
+            (7, 10, 3, "interface"),
+            (8, 2, 3, "method"),
+            (8, 6, 3, "struct"),
+            (8, 10, 3, "parameter"),
+            (8, 19, 6, "type"),
+            (8, 26, 5, "parameter"),
+        ];
+        assert_eq!(expected, decode(&tokens));
+    }
+
+    #[test]
+    fn test_semantic_tokens_syntax_error() {
+        let text = "struct Foo { int32 a; };\nstruct Bar { Foo";
+        let decoded = decode(&semantic_tokens(text, &syntax::lex(text)));
+        // The incomplete declaration is tokenized lexically.
+        assert_eq!(
+            vec![
+                (0, 0, 6, "keyword"),
+                (0, 7, 3, "struct"),
+                (0, 13, 5, "type"),
+                (0, 19, 1, "property"),
+                (1, 0, 6, "keyword"),
+            ],
+            decoded
+        );
+    }
+
+    // Decodes tokens into (line, col, token type, modifiers) tuples.
+    fn decode_modifiers(
+        tokens: &SemanticTokens,
+    ) -> Vec<(u32, u32, &'static str, Vec<&'static str>)> {
+        let positions = decode(tokens);
+        tokens
+            .data
+            .chunks(5)
+            .zip(positions)
+            .map(|(chunk, (line, col, _, typ))| {
+                let modifiers = TOKEN_MODIFIERS
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| chunk[4] & (1 << i) != 0)
+                    .map(|(_, modifier)| *modifier)
+                    .collect();
+                (line, col, typ, modifiers)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_semantic_token_modifiers() {
+        let text = r#"struct Foo { Foo? next; };
+[Native] struct Bar;
+enum E { kA };
+const int32 kMax = 1;
+interface I { Get(Foo foo); };
+"#;
+        let decoded = decode_modifiers(&semantic_tokens(text, &syntax::lex(text)));
+        let identifiers: Vec<_> = decoded
+            .into_iter()
+            .filter(|(_, _, typ, _)| *typ != "keyword")
+            .collect();
+        let expected = vec![
+            (0, 7, "struct", vec!["declaration", "definition"]),
+            (0, 13, "struct", vec![]),
+            (0, 18, "property", vec!["declaration"]),
+            (1, 1, "property", vec![]),
+            (1, 16, "struct", vec!["declaration"]),
+            (2, 5, "enum", vec!["declaration", "definition"]),
+            (2, 9, "enumMember", vec!["declaration", "readonly"]),
+            (3, 6, "type", vec![]),
+            (
+                3,
+                12,
+                "variable",
+                vec!["declaration", "definition", "readonly"],
+            ),
+            (3, 19, "number", vec![]),
+            (4, 10, "interface", vec!["declaration", "definition"]),
+            (4, 14, "method", vec!["declaration"]),
+            (4, 18, "struct", vec![]),
+            (4, 22, "parameter", vec!["declaration"]),
+        ];
+        assert_eq!(expected, identifiers);
+    }
+
+    #[test]
+    fn test_semantic_token_deprecated() {
+        let text = r#"[Deprecated] struct Old { [Deprecated] int32 a; int32 b; };
+struct New {};
+"#;
+        let decoded = decode_modifiers(&semantic_tokens(text, &syntax::lex(text)));
+        let identifiers: Vec<_> = decoded
+            .into_iter()
+            .filter(|(_, _, typ, _)| *typ != "keyword" && *typ != "type")
+            .collect();
+        let expected = vec![
+            (0, 1, "property", vec![]),
+            (
+                0,
+                20,
+                "struct",
+                vec!["declaration", "definition", "deprecated"],
+            ),
+            (0, 27, "property", vec![]),
+            (0, 45, "property", vec!["declaration", "deprecated"]),
+            (0, 54, "property", vec!["declaration"]),
+            (1, 7, "struct", vec!["declaration", "definition"]),
+        ];
+        assert_eq!(expected, identifiers);
+    }
+}
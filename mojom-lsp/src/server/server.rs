@@ -12,17 +12,45 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
+use lsp_types::Url as Uri;
 use serde_json::Value;
 
+use crate::syntax::LineLexer;
+
 use super::protocol::{
-    read_message, ErrorCodes, Message, NotificationMessage, RequestMessage, ResponseError,
+    self, read_message_with_limit, ErrorCodes, Message, NotificationMessage, RequestId,
+    RequestMessage, ResponseError, ResponseMessage,
 };
 
-use super::diagnostic::{start_diagnostics_thread, DiagnosticsThread};
+use super::completion;
+use super::config::ConfigCache;
+use super::diagnostic::{start_diagnostics_thread, DiagnosticsThread, PublishedDiagnostics};
+use super::document::{apply_content_changes, identifier_range_at, position_to_offset, Document};
+use super::document_symbol::{document_symbols, flatten_symbols};
+use super::folding_range::folding_ranges;
+use super::formatting::{default_options, format_canonical, format_whitespace};
+use super::generated_bindings::{find_generated_binding, OPEN_GENERATED_BINDING_COMMAND};
+use super::initialization::Initialization;
 use super::messagesender::{start_message_sender_thread, MessageSender};
+use super::pull_diagnostics::{self, DocumentDiagnosticParams, DOCUMENT_DIAGNOSTIC_METHOD};
+use super::references::REFERENCES_BY_KIND_COMMAND;
+use super::semantic_tokens::{semantic_tokens, SemanticTokensParams, SEMANTIC_TOKENS_FULL_METHOD};
+use super::settings::{self, DiagnosticsMode, FormatMode, Settings};
+use super::watchdog::{start_parent_watchdog, ProcessProbe, WATCH_INTERVAL};
+use super::workspace_symbol::{ProgressReporter, WorkspaceSymbolIndex};
+
+// Called with the result of a request which was sent to the client.
+type ResponseHandler = Box<dyn FnOnce(&mut ServerContext, Result<Value, ResponseError>)>;
 
 #[derive(PartialEq)]
 enum State {
@@ -30,27 +58,296 @@ enum State {
     ShuttingDown,
 }
 
+// The last hover result. Clients send hover requests repeatedly while the
+// mouse moves over the same token.
+struct HoverCache {
+    uri: Uri,
+    version: i64,
+    // The identifier at the hovered position, or an empty range at the
+    // position when there is no identifier.
+    token: std::ops::Range<usize>,
+    hover: Option<lsp_types::Hover>,
+}
+
 struct ServerContext {
     state: State,
+    // Workspace root path. This is the first workspace folder, or empty when
+    // there is none.
+    root_path: PathBuf,
+    // Workspace folders opened by the client, including `root_path`.
+    workspace_folders: Vec<PathBuf>,
+    // Settings which don't take project config files into account. Use
+    // `settings_for()` to get the settings for a document.
+    base_settings: Settings,
+    // `initializationOptions` sent by the client, updated with the `mojom`
+    // section of `workspace/configuration`. These take precedence over project
+    // config files.
+    initialization_options: Value,
+    // Loaded project config files.
+    configs: ConfigCache,
+    // Capabilities which were sent from the client in the initialize request.
+    client_capabilities: lsp_types::ClientCapabilities,
+    // Same as `client_capabilities` but includes capabilities which
+    // lsp-types doesn't know.
+    client_capabilities_json: Value,
+    // Capabilities which were sent to the client in the initialize response.
+    capabilities: lsp_types::ServerCapabilities,
+    // Opened documents.
+    documents: HashMap<Uri, Document>,
+    // Documents which are too large to analyze.
+    oversized_documents: HashSet<Uri>,
+    // Lexemes of opened documents for semantic tokens.
+    lexers: HashMap<Uri, LineLexer>,
+    // Declarations in the workspace. Built on the first `workspace/symbol`.
+    workspace_symbols: WorkspaceSymbolIndex,
+    // Cleared when any document changes.
+    hover_cache: Option<HoverCache>,
+    // Symbols accepted in completions during the session.
+    completion_usage: completion::CompletionUsage,
+    // The number of hovers computed without the cache.
+    #[cfg(test)]
+    hover_computations: usize,
     // A handler to send messages on the main thread.
     msg_sender: MessageSender,
     // A handler to the diagnostics thread.
     diag: DiagnosticsThread,
-    // Set when `exit` notification is received.
+    // The ID of the next request sent to the client.
+    next_request_id: i64,
+    // Requests sent to the client which are waiting for responses.
+    pending_requests: HashMap<RequestId, ResponseHandler>,
+    // Requests received from the client which haven't been handled yet.
+    queued_requests: HashSet<RequestId>,
+    // Queued requests which the client cancelled.
+    cancelled_requests: HashSet<RequestId>,
+    // Requests which the client cancelled, recorded by the reader thread as
+    // soon as `$/cancelRequest` arrives so that long running requests can
+    // stop midway.
+    cancellations: Arc<Mutex<HashSet<RequestId>>>,
+    // The maximum content length which the reader thread accepts. Follows
+    // `base_settings.max_content_length`.
+    max_content_length: Arc<AtomicUsize>,
+    // Set when `exit` notification is received or the client process is gone.
     exit_code: Option<i32>,
 }
 
 impl ServerContext {
-    fn new(msg_sender: MessageSender, diag: DiagnosticsThread) -> ServerContext {
+    fn new(
+        root_path: PathBuf,
+        workspace_folders: Vec<PathBuf>,
+        settings: Settings,
+        initialization_options: Value,
+        client_capabilities: lsp_types::ClientCapabilities,
+        client_capabilities_json: Value,
+        capabilities: lsp_types::ServerCapabilities,
+        msg_sender: MessageSender,
+        diag: DiagnosticsThread,
+    ) -> ServerContext {
+        let mut workspace_folders = workspace_folders;
+        let root_path = if root_path.as_os_str().is_empty() {
+            workspace_folders.first().cloned().unwrap_or_default()
+        } else {
+            root_path
+        };
+        if !root_path.as_os_str().is_empty() && !workspace_folders.contains(&root_path) {
+            workspace_folders.insert(0, root_path.clone());
+        }
         ServerContext {
             state: State::Initialized,
+            root_path: root_path,
+            workspace_folders: workspace_folders,
+            base_settings: settings.clone(),
+            initialization_options: initialization_options,
+            max_content_length: Arc::new(AtomicUsize::new(settings.max_content_length)),
+            configs: ConfigCache::new(),
+            client_capabilities: client_capabilities,
+            client_capabilities_json: client_capabilities_json,
+            capabilities: capabilities,
+            documents: HashMap::new(),
+            oversized_documents: HashSet::new(),
+            lexers: HashMap::new(),
+            workspace_symbols: WorkspaceSymbolIndex::default(),
+            hover_cache: None,
+            completion_usage: completion::CompletionUsage::default(),
+            #[cfg(test)]
+            hover_computations: 0,
             msg_sender: msg_sender,
             diag: diag,
+            next_request_id: 1,
+            pending_requests: HashMap::new(),
+            queued_requests: HashSet::new(),
+            cancelled_requests: HashSet::new(),
+            cancellations: Arc::new(Mutex::new(HashSet::new())),
             exit_code: None,
         }
     }
 }
 
+// A view of the server state which tests can assert on.
+#[cfg(test)]
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    shutting_down: bool,
+    // Versions of opened documents.
+    documents: std::collections::BTreeMap<String, i64>,
+    oversized_documents: std::collections::BTreeSet<String>,
+    pending_requests: usize,
+    // Qualified names of top-level symbols in the last analyzed document.
+    indexed_symbols: Vec<String>,
+}
+
+#[cfg(test)]
+impl ServerContext {
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            shutting_down: self.state == State::ShuttingDown,
+            documents: self
+                .documents
+                .iter()
+                .map(|(uri, document)| (uri.to_string(), document.version))
+                .collect(),
+            oversized_documents: self
+                .oversized_documents
+                .iter()
+                .map(|uri| uri.to_string())
+                .collect(),
+            pending_requests: self.pending_requests.len(),
+            indexed_symbols: self.diag.indexed_symbols(),
+        }
+    }
+}
+
+// Requests sent to the client
+
+// Sends a request to the client. `handler` is called when the response
+// arrives.
+fn send_request(ctx: &mut ServerContext, method: &str, params: Value, handler: ResponseHandler) {
+    let id = RequestId::Number(ctx.next_request_id);
+    ctx.next_request_id += 1;
+    ctx.pending_requests.insert(id.clone(), handler);
+    let req = RequestMessage {
+        id: id,
+        method: method.to_owned(),
+        params: params,
+    };
+    ctx.msg_sender.send_request(req);
+}
+
+// Returns a reporter which sends scan progress to the client as `$/progress`
+// with `token`.
+fn progress_reporter(msg_sender: &MessageSender, token: Value) -> ProgressReporter {
+    let msg_sender = msg_sender.clone();
+    Box::new(move |progress| {
+        let msg = NotificationMessage {
+            method: "$/progress".to_owned(),
+            params: serde_json::json!({
+                "token": token,
+                "value": progress.to_work_done_progress(),
+            }),
+        };
+        msg_sender.send_notification(msg);
+    })
+}
+
+// Starts scanning the workspace for `workspace/symbol`. The scan is reported
+// with work done progress if the client supports it.
+fn start_workspace_symbol_build(ctx: &mut ServerContext) {
+    let supported = ctx.client_capabilities_json["window"]["workDoneProgress"]
+        .as_bool()
+        .unwrap_or(false);
+    let reporter = if supported {
+        // Request ids are unique, so are tokens made from them.
+        let token = Value::from(format!("mojom-lsp/indexing/{}", ctx.next_request_id));
+        // The client handles this request before the progress which follows.
+        let params = serde_json::json!({ "token": token });
+        let handler: ResponseHandler = Box::new(|_, result| {
+            if let Err(err) = result {
+                log::warn!("Failed to create work done progress: {}", err.message);
+            }
+        });
+        send_request(ctx, "window/workDoneProgress/create", params, handler);
+        Some(progress_reporter(&ctx.msg_sender, token))
+    } else {
+        None
+    };
+    ctx.workspace_symbols.start_build(
+        ctx.workspace_folders.clone(),
+        ctx.base_settings.exclude_globs.clone(),
+        reporter,
+    );
+}
+
+// Asks the client for the `mojom` section of its configuration, if the client
+// supports `workspace/configuration`.
+fn request_configuration(ctx: &mut ServerContext) {
+    use lsp_types::request::{Request, WorkspaceConfiguration};
+    let supported = ctx
+        .client_capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.configuration)
+        .unwrap_or(false);
+    if !supported {
+        return;
+    }
+    let params = serde_json::json!({ "items": [{ "section": "mojom" }] });
+    send_request(
+        ctx,
+        WorkspaceConfiguration::METHOD,
+        params,
+        Box::new(|ctx, res| match res {
+            Ok(Value::Array(mut sections)) if !sections.is_empty() => {
+                apply_configuration(ctx, sections.swap_remove(0))
+            }
+            Ok(res) => log::warn!("Unexpected configuration: {}", res),
+            Err(err) => log::warn!("Failed to get configuration: {}", err.message),
+        }),
+    );
+}
+
+// Applies the `mojom` section of the client configuration on top of the
+// current settings.
+fn apply_configuration(ctx: &mut ServerContext, section: Value) {
+    let section = match section {
+        Value::Object(section) => section,
+        // The client has no configuration for mojom.
+        Value::Null => return,
+        section => {
+            log::warn!("Invalid configuration: {}", section);
+            return;
+        }
+    };
+    ctx.base_settings = settings::with_config(
+        &ctx.base_settings,
+        &Value::Object(section.clone()),
+        &Value::Null,
+    );
+    ctx.max_content_length
+        .store(ctx.base_settings.max_content_length, Ordering::Relaxed);
+    if !ctx.initialization_options.is_object() {
+        ctx.initialization_options = Value::Object(serde_json::Map::new());
+    }
+    if let Some(options) = ctx.initialization_options.as_object_mut() {
+        options.extend(section);
+    }
+}
+
+fn handle_response(ctx: &mut ServerContext, msg: ResponseMessage) {
+    log::debug!("[recv] Response: id = {}", msg.id);
+    let handler = match ctx.pending_requests.remove(&msg.id) {
+        Some(handler) => handler,
+        None => {
+            log::warn!("Ignored a response to an unknown request: id = {}", msg.id);
+            return;
+        }
+    };
+    let res = match msg.error {
+        Some(err) => Err(err),
+        None => Ok(msg.result.unwrap_or(Value::Null)),
+    };
+    handler(ctx, res);
+}
+
 // Requests
 
 fn get_request_params<P: serde::de::DeserializeOwned>(
@@ -60,24 +357,84 @@ fn get_request_params<P: serde::de::DeserializeOwned>(
         .map_err(|err| ResponseError::new(ErrorCodes::InvalidRequest, err.to_string()))
 }
 
+// Returns false when the server didn't advertise the capability which is
+// needed to handle `method`.
+fn is_request_enabled(capabilities: &lsp_types::ServerCapabilities, method: &str) -> bool {
+    use lsp_types::request::*;
+    match method {
+        HoverRequest::METHOD => capabilities.hover_provider.unwrap_or(false),
+        Completion::METHOD => capabilities.completion_provider.is_some(),
+        GotoDefinition::METHOD => capabilities.definition_provider.unwrap_or(false),
+        References::METHOD => capabilities.references_provider.unwrap_or(false),
+        DocumentHighlightRequest::METHOD => {
+            capabilities.document_highlight_provider.unwrap_or(false)
+        }
+        DocumentSymbolRequest::METHOD => capabilities.document_symbol_provider.unwrap_or(false),
+        WorkspaceSymbol::METHOD => capabilities.workspace_symbol_provider.unwrap_or(false),
+        CodeActionRequest::METHOD => capabilities.code_action_provider.is_some(),
+        Formatting::METHOD => capabilities.document_formatting_provider.unwrap_or(false),
+        PrepareRenameRequest::METHOD | Rename::METHOD => capabilities.rename_provider.is_some(),
+        FoldingRangeRequest::METHOD => capabilities.folding_range_provider.is_some(),
+        ExecuteCommand::METHOD => capabilities.execute_command_provider.is_some(),
+        WillSaveWaitUntil::METHOD => match &capabilities.text_document_sync {
+            Some(lsp_types::TextDocumentSyncCapability::Options(options)) => {
+                options.will_save_wait_until.unwrap_or(false)
+            }
+            _ => false,
+        },
+        // Always advertised. lsp-types doesn't know these capabilities.
+        DOCUMENT_DIAGNOSTIC_METHOD | SEMANTIC_TOKENS_FULL_METHOD => true,
+        Initialize::METHOD | Shutdown::METHOD => true,
+        #[cfg(test)]
+        PANIC_FOR_TEST_METHOD => true,
+        // Unknown requests.
+        _ => false,
+    }
+}
+
 fn handle_request(ctx: &mut ServerContext, msg: RequestMessage) -> anyhow::Result<()> {
     let id = msg.id;
     let method = msg.method.as_str();
     log::debug!("[recv] Request: id = {}, method = {}", id, method);
 
+    ctx.queued_requests.remove(&id);
+    if ctx.cancelled_requests.remove(&id) {
+        let message = format!("Cancelled request: id = {} method = {}", id, method);
+        let err = ResponseError::new(ErrorCodes::RequestCancelled, message);
+        ctx.msg_sender.send_error_response(id, err);
+        return Ok(());
+    }
+
     // Workaround for Eglot. It sends "exit" as a request, not as a notification.
     if method == "exit" {
         exit_notification(ctx);
         return Ok(());
     }
 
-    use lsp_types::request::*;
-    let res = match method {
-        Initialize::METHOD => initialize_request(),
-        Shutdown::METHOD => shutdown_request(ctx),
-        GotoDefinition::METHOD => get_request_params(msg.params)
-            .and_then(|params| goto_definition_request(&mut ctx.diag, params)),
-        _ => unimplemented_request(id, method),
+    if !is_request_enabled(&ctx.capabilities, method) {
+        let message = format!("Unsupported request: id = {} method = {}", id, method);
+        let err = ResponseError::new(ErrorCodes::MethodNotFound, message);
+        ctx.msg_sender.send_error_response(id, err);
+        return Ok(());
+    }
+
+    // A bug in a handler shouldn't kill the session. Report it to the client
+    // and keep running.
+    let params = msg.params;
+    let res = match panic::catch_unwind(AssertUnwindSafe(|| {
+        dispatch_request(ctx, &id, method, params)
+    })) {
+        Ok(res) => res,
+        Err(payload) => {
+            log::error!(
+                "Request handler panicked: id = {} method = {}: {}",
+                id,
+                method,
+                panic_message(payload.as_ref())
+            );
+            let message = format!("Internal error while handling {}", method);
+            Err(ResponseError::new(ErrorCodes::InternalError, message))
+        }
     };
     match res {
         Ok(res) => {
@@ -88,14 +445,87 @@ fn handle_request(ctx: &mut ServerContext, msg: RequestMessage) -> anyhow::Resul
     Ok(())
 }
 
+fn dispatch_request(
+    ctx: &mut ServerContext,
+    id: &RequestId,
+    method: &str,
+    params: Value,
+) -> RequestResult {
+    use lsp_types::request::*;
+    match method {
+        Initialize::METHOD => initialize_request(),
+        Shutdown::METHOD => shutdown_request(ctx),
+        GotoDefinition::METHOD => {
+            get_request_params(params).and_then(|params| goto_definition_request(ctx, params))
+        }
+        References::METHOD => references_request(ctx, id, params),
+        DocumentHighlightRequest::METHOD => get_request_params(params)
+            .and_then(|params| document_highlight_request(&mut ctx.diag, params)),
+        PrepareRenameRequest::METHOD => get_request_params(params)
+            .and_then(|params| prepare_rename_request(&mut ctx.diag, params)),
+        Rename::METHOD => {
+            get_request_params(params).and_then(|params| rename_request(&mut ctx.diag, params))
+        }
+        HoverRequest::METHOD => {
+            get_request_params(params).and_then(|params| hover_request(ctx, params))
+        }
+        Completion::METHOD => {
+            get_request_params(params).and_then(|params| completion_request(ctx, params))
+        }
+        DocumentSymbolRequest::METHOD => {
+            get_request_params(params).and_then(|params| document_symbol_request(ctx, params))
+        }
+        WorkspaceSymbol::METHOD => workspace_symbol_request(ctx, params),
+        CodeActionRequest::METHOD => {
+            get_request_params(params).and_then(|params| code_action_request(ctx, params))
+        }
+        ExecuteCommand::METHOD => {
+            get_request_params(params).and_then(|params| execute_command_request(ctx, params))
+        }
+        Formatting::METHOD => {
+            get_request_params(params).and_then(|params| formatting_request(ctx, params))
+        }
+        WillSaveWaitUntil::METHOD => {
+            get_request_params(params).and_then(|params| will_save_wait_until_request(ctx, params))
+        }
+        DOCUMENT_DIAGNOSTIC_METHOD => {
+            get_request_params(params).and_then(|params| document_diagnostic_request(ctx, params))
+        }
+        SEMANTIC_TOKENS_FULL_METHOD => {
+            get_request_params(params).and_then(|params| semantic_tokens_request(ctx, params))
+        }
+        FoldingRangeRequest::METHOD => {
+            get_request_params(params).and_then(|params| folding_range_request(ctx, params))
+        }
+        #[cfg(test)]
+        PANIC_FOR_TEST_METHOD => panic!("Deliberate panic"),
+        _ => unknown_request(id, method),
+    }
+}
+
+// A request or a notification which makes the handler panic.
+#[cfg(test)]
+const PANIC_FOR_TEST_METHOD: &str = "mojom/panicForTest";
+
+// Returns the message of a panic payload. Panics usually have a string
+// message.
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Unknown panic"
+    }
+}
+
 type RequestResult = std::result::Result<Value, ResponseError>;
 
-fn unimplemented_request(id: u64, method_name: &str) -> RequestResult {
-    let msg = format!(
-        "Unimplemented request: id = {} method = {}",
-        id, method_name
-    );
-    let err = ResponseError::new(ErrorCodes::InternalError, msg);
+// The server doesn't know the method. This isn't an internal error; clients
+// may probe methods which the server doesn't support.
+fn unknown_request(id: &RequestId, method_name: &str) -> RequestResult {
+    let msg = format!("Unknown request: id = {} method = {}", id, method_name);
+    let err = ResponseError::new(ErrorCodes::MethodNotFound, msg);
     Err(err)
 }
 
@@ -110,158 +540,2581 @@ fn initialize_request() -> RequestResult {
 
 fn shutdown_request(ctx: &mut ServerContext) -> RequestResult {
     ctx.state = State::ShuttingDown;
+    ctx.completion_usage.clear();
     Ok(Value::Null)
 }
 
 fn goto_definition_request(
-    diag: &mut DiagnosticsThread,
+    ctx: &mut ServerContext,
     params: lsp_types::TextDocumentPositionParams,
 ) -> RequestResult {
-    if let Some(loc) = diag.goto_definition(params.text_document.uri, params.position) {
-        let res = serde_json::to_value(loc).unwrap();
-        return Ok(res);
+    let link = match ctx
+        .diag
+        .goto_definition(params.text_document.uri, params.position)
+    {
+        Some(link) => link,
+        None => return Ok(Value::Null),
+    };
+    let link_support = ctx
+        .client_capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.definition.as_ref())
+        .and_then(|definition| definition.link_support)
+        .unwrap_or(false);
+    let res = if link_support {
+        lsp_types::GotoDefinitionResponse::Link(vec![link])
+    } else {
+        let loc = lsp_types::Location::new(link.target_uri, link.target_selection_range);
+        lsp_types::GotoDefinitionResponse::Scalar(loc)
+    };
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+// Streams references with `$/progress` when the client sent
+// `partialResultToken`. lsp-types doesn't know the token, so it's read from
+// the raw params.
+fn references_request(ctx: &mut ServerContext, id: &RequestId, params: Value) -> RequestResult {
+    let token = params.get("partialResultToken").cloned();
+    let params: lsp_types::ReferenceParams = get_request_params(params)?;
+    let position = params.text_document_position;
+    let batches = ctx.diag.stream_references(
+        position.text_document.uri,
+        position.position,
+        params.context.include_declaration,
+    );
+
+    let mut references = Vec::new();
+    for batch in batches {
+        // Dropping `batches` stops the scan.
+        if ctx.cancellations.lock().unwrap().contains(id) {
+            let message = format!("Cancelled request: id = {}", id);
+            return Err(ResponseError::new(ErrorCodes::RequestCancelled, message));
+        }
+        match &token {
+            Some(token) => {
+                let msg = NotificationMessage {
+                    method: "$/progress".to_owned(),
+                    params: serde_json::json!({ "token": token, "value": batch }),
+                };
+                ctx.msg_sender.send_notification(msg);
+            }
+            None => references.extend(batch),
+        }
     }
-    return Ok(Value::Null);
+    // Empty when all references were reported as partial results.
+    Ok(serde_json::to_value(references).unwrap())
 }
 
-// Notifications
+fn prepare_rename_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::TextDocumentPositionParams,
+) -> RequestResult {
+    let range = diag.prepare_rename(params.text_document.uri, params.position);
+    Ok(serde_json::to_value(range).unwrap())
+}
 
-fn get_params<P: serde::de::DeserializeOwned>(params: Value) -> anyhow::Result<P> {
-    serde_json::from_value::<P>(params).map_err(|err| err.into())
+fn rename_request(diag: &mut DiagnosticsThread, params: lsp_types::RenameParams) -> RequestResult {
+    let position = params.text_document_position;
+    let edit = diag
+        .rename(
+            position.text_document.uri,
+            position.position,
+            params.new_name,
+        )
+        .map_err(|message| ResponseError::new(ErrorCodes::InvalidParams, message))?;
+    Ok(serde_json::to_value(edit).unwrap())
 }
 
-fn handle_notification(ctx: &mut ServerContext, msg: NotificationMessage) -> anyhow::Result<()> {
-    log::debug!("[recv] Notification: method = {}", msg.method);
+fn document_highlight_request(
+    diag: &mut DiagnosticsThread,
+    params: lsp_types::TextDocumentPositionParams,
+) -> RequestResult {
+    let highlights = diag.document_highlights(params.text_document.uri, params.position);
+    Ok(serde_json::to_value(highlights).unwrap())
+}
 
-    use lsp_types::notification::*;
-    match msg.method.as_str() {
-        Exit::METHOD => exit_notification(ctx),
-        DidOpenTextDocument::METHOD => {
-            get_params(msg.params).map(|params| did_open_text_document(ctx, params))?;
+fn hover_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::TextDocumentPositionParams,
+) -> RequestResult {
+    let uri = params.text_document.uri;
+    let position = params.position;
+    // Hovers only depend on the token at the position, so moving within a
+    // token reuses the last result. Documents which aren't opened aren't
+    // cached as their versions are unknown.
+    let key = ctx.documents.get(&uri).map(|document| {
+        let offset = position_to_offset(&document.text, &position);
+        let token = identifier_range_at(&document.text, &position).unwrap_or(offset..offset);
+        (document.version, token)
+    });
+    if let (Some(cache), Some((version, token))) = (&ctx.hover_cache, &key) {
+        if cache.uri == uri && cache.version == *version && cache.token == *token {
+            return Ok(serde_json::to_value(&cache.hover).unwrap());
         }
-        DidChangeTextDocument::METHOD => {
-            get_params(msg.params).map(|params| did_change_text_document(ctx, params))?;
+    }
+
+    #[cfg(test)]
+    {
+        ctx.hover_computations += 1;
+    }
+    let hover = ctx.diag.hover(uri.clone(), position);
+    let res = serde_json::to_value(&hover).unwrap();
+    ctx.hover_cache = key.map(|(version, token)| HoverCache {
+        uri: uri,
+        version: version,
+        token: token,
+        hover: hover,
+    });
+    Ok(res)
+}
+
+fn completion_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::CompletionParams,
+) -> RequestResult {
+    let position = params.text_document_position;
+    let uri = position.text_document.uri;
+    let text = match ctx.documents.get(&uri) {
+        Some(document) => document.text.clone(),
+        None => {
+            let message = format!("Document not opened: {}", uri);
+            return Err(ResponseError::new(ErrorCodes::InvalidParams, message));
+        }
+    };
+    let settings = settings_for(ctx, &uri);
+    let usage = ctx.completion_usage.clone();
+    let mut list = ctx
+        .diag
+        .completion(uri, text, settings, position.position, usage);
+    let snippet_support = ctx
+        .client_capabilities
+        .text_document
+        .as_ref()
+        .and_then(|caps| caps.completion.as_ref())
+        .and_then(|caps| caps.completion_item.as_ref())
+        .and_then(|caps| caps.snippet_support)
+        .unwrap_or(false);
+    if snippet_support {
+        completion::expand_snippets(&mut list.items);
+    }
+    let commit_characters_support = ctx
+        .client_capabilities
+        .text_document
+        .as_ref()
+        .and_then(|caps| caps.completion.as_ref())
+        .and_then(|caps| caps.completion_item.as_ref())
+        .and_then(|caps| caps.commit_characters_support)
+        .unwrap_or(false);
+    if commit_characters_support {
+        completion::add_commit_characters(&mut list.items);
+    }
+    let support = completion::ClientSupport::from_capabilities(&ctx.client_capabilities_json);
+    Ok(completion::to_json(list, &support))
+}
+
+fn document_symbol_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::DocumentSymbolParams,
+) -> RequestResult {
+    let uri = params.text_document.uri;
+    let text = match ctx.documents.get(&uri) {
+        Some(document) => document.text.clone(),
+        None => {
+            let message = format!("Document not opened: {}", uri);
+            return Err(ResponseError::new(ErrorCodes::InvalidParams, message));
+        }
+    };
+    let symbols = document_symbols(uri.clone(), text);
+    let hierarchical = ctx
+        .client_capabilities
+        .text_document
+        .as_ref()
+        .and_then(|caps| caps.document_symbol.as_ref())
+        .and_then(|caps| caps.hierarchical_document_symbol_support)
+        .unwrap_or(false);
+    let res = if hierarchical {
+        lsp_types::DocumentSymbolResponse::Nested(symbols)
+    } else {
+        lsp_types::DocumentSymbolResponse::Flat(flatten_symbols(&uri, symbols))
+    };
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+// When the client sent `workDoneToken`, waits for the scan of the workspace
+// and reports it with the token. lsp-types doesn't know the token, so it's
+// read from the raw params.
+fn workspace_symbol_request(ctx: &mut ServerContext, params: Value) -> RequestResult {
+    let token = params.get("workDoneToken").cloned();
+    let params: lsp_types::WorkspaceSymbolParams = get_request_params(params)?;
+    if let Some(token) = token {
+        let reporter = progress_reporter(&ctx.msg_sender, token);
+        ctx.workspace_symbols.wait_for_build_with_progress(
+            &ctx.workspace_folders,
+            &ctx.base_settings.exclude_globs,
+            reporter,
+        );
+    }
+    let symbols = ctx.workspace_symbols.query(
+        &ctx.workspace_folders,
+        &ctx.base_settings.exclude_globs,
+        &params.query,
+    );
+    Ok(serde_json::to_value(symbols).unwrap())
+}
+
+fn code_action_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::CodeActionParams,
+) -> RequestResult {
+    let actions = ctx
+        .diag
+        .code_actions(params.text_document.uri, params.range);
+    Ok(serde_json::to_value(actions).unwrap())
+}
+
+fn execute_command_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::ExecuteCommandParams,
+) -> RequestResult {
+    match params.command.as_str() {
+        OPEN_GENERATED_BINDING_COMMAND => open_generated_binding_command(ctx, params.arguments),
+        REFERENCES_BY_KIND_COMMAND => references_by_kind_command(ctx, params.arguments),
+        completion::COMPLETION_ACCEPTED_COMMAND => {
+            completion_accepted_command(ctx, params.arguments)
         }
-        // Accept following notifications but do nothing.
-        DidChangeConfiguration::METHOD => (),
-        WillSaveTextDocument::METHOD => (),
-        DidSaveTextDocument::METHOD => (),
         _ => {
-            log::warn!("Received unimplemented notification: {:#?}", msg);
+            let message = format!("Unknown command: {}", params.command);
+            Err(ResponseError::new(ErrorCodes::InvalidParams, message))
         }
     }
-    Ok(())
 }
 
-fn exit_notification(ctx: &mut ServerContext) {
-    // https://microsoft.github.io/language-server-protocol/specification#exit
-    if ctx.state == State::ShuttingDown {
-        ctx.exit_code = Some(0);
+fn document_diagnostic_request(
+    ctx: &mut ServerContext,
+    params: DocumentDiagnosticParams,
+) -> RequestResult {
+    let uri = params.text_document.uri;
+    let text = match ctx.documents.get(&uri) {
+        Some(document) => document.text.clone(),
+        // Clients may pull diagnostics for files which aren't opened. Read
+        // them from disk like imported files.
+        None => match read_file(&uri) {
+            Ok(text) => text,
+            Err(err) => {
+                let message = format!("Failed to read {}: {}", uri, err);
+                return Err(ResponseError::new(ErrorCodes::InvalidParams, message));
+            }
+        },
+    };
+    let settings = settings_for(ctx, &uri);
+    let items = if is_analyzable(ctx, &uri, &text, &settings) {
+        ctx.diag.diagnostics(uri, text, settings)
     } else {
-        ctx.exit_code = Some(1);
-    }
+        Vec::new()
+    };
+    let report = pull_diagnostics::create_report(items, params.previous_result_id.as_deref());
+    Ok(serde_json::to_value(report).unwrap())
 }
 
-fn did_open_text_document(ctx: &mut ServerContext, params: lsp_types::DidOpenTextDocumentParams) {
-    ctx.diag
-        .check(params.text_document.uri, params.text_document.text);
+// Formats the opened document at `uri` according to the settings for it.
+fn format_document_edits(
+    ctx: &mut ServerContext,
+    uri: &Uri,
+    options: &lsp_types::FormattingOptions,
+) -> std::result::Result<Vec<lsp_types::TextEdit>, ResponseError> {
+    let settings = settings_for(ctx, uri);
+    let text = match ctx.documents.get(uri) {
+        Some(document) => &document.text,
+        None => {
+            let message = format!("Document not opened: {}", uri);
+            return Err(ResponseError::new(ErrorCodes::InvalidParams, message));
+        }
+    };
+    let edits = match settings.format_mode {
+        FormatMode::Canonical => format_canonical(text, options, &settings),
+        FormatMode::WhitespaceOnly => format_whitespace(text, options),
+    };
+    Ok(edits)
 }
 
-fn did_change_text_document(
+fn formatting_request(
     ctx: &mut ServerContext,
-    params: lsp_types::DidChangeTextDocumentParams,
-) {
-    let uri = params.text_document.uri.clone();
-    let content = params
-        .content_changes
-        .iter()
-        .map(|i| i.text.to_owned())
-        .collect::<Vec<_>>();
-    let text = content.join("");
-    ctx.diag.check(uri, text);
+    params: lsp_types::DocumentFormattingParams,
+) -> RequestResult {
+    let edits = format_document_edits(ctx, &params.text_document.uri, &params.options)?;
+    Ok(serde_json::to_value(edits).unwrap())
 }
 
-fn is_chromium_src_dir(path: &PathBuf) -> bool {
-    // The root is named `src`.
-    if !path.file_name().map(|name| name == "src").unwrap_or(false) {
-        return false;
+fn will_save_wait_until_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::WillSaveTextDocumentParams,
+) -> RequestResult {
+    let uri = params.text_document.uri;
+    if !settings_for(ctx, &uri).format_on_save {
+        return Ok(Value::Null);
     }
+    // The request doesn't carry formatting options.
+    let edits = format_document_edits(ctx, &uri, &default_options())?;
+    Ok(serde_json::to_value(edits).unwrap())
+}
 
-    // Check if the parent directory contains `.gclient`.
-    match path.parent() {
-        Some(parent) => parent.join(".gclient").is_file(),
-        None => false,
-    }
+fn semantic_tokens_request(ctx: &mut ServerContext, params: SemanticTokensParams) -> RequestResult {
+    let uri = params.text_document.uri;
+    let text = match ctx.documents.get(&uri) {
+        Some(document) => &document.text,
+        None => {
+            let message = format!("Document not opened: {}", uri);
+            return Err(ResponseError::new(ErrorCodes::InvalidParams, message));
+        }
+    };
+    // Only lines which changed since the last request are lexed.
+    let lexer = ctx.lexers.entry(uri).or_default();
+    lexer.update(text);
+    Ok(serde_json::to_value(semantic_tokens(text, &lexer.lexemes())).unwrap())
 }
 
-fn find_chromium_src_dir(mut path: PathBuf) -> PathBuf {
-    if is_chromium_src_dir(&path) {
-        return path;
+fn folding_range_request(
+    ctx: &mut ServerContext,
+    params: lsp_types::FoldingRangeParams,
+) -> RequestResult {
+    let uri = params.text_document.uri;
+    let text = match ctx.documents.get(&uri) {
+        Some(document) => &document.text,
+        None => {
+            let message = format!("Document not opened: {}", uri);
+            return Err(ResponseError::new(ErrorCodes::InvalidParams, message));
+        }
+    };
+    let line_folding_only = ctx
+        .client_capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.folding_range.as_ref())
+        .and_then(|folding_range| folding_range.line_folding_only)
+        .unwrap_or(false);
+    Ok(serde_json::to_value(folding_ranges(text, line_folding_only)).unwrap())
+}
+
+fn references_by_kind_command(ctx: &mut ServerContext, arguments: Vec<Value>) -> RequestResult {
+    // The argument should be a symbol position.
+    let argument = arguments.into_iter().next().unwrap_or(Value::Null);
+    let params = get_request_params::<lsp_types::TextDocumentPositionParams>(argument)?;
+    let groups = ctx
+        .diag
+        .references_by_kind(params.text_document.uri, params.position);
+    Ok(serde_json::to_value(groups).unwrap())
+}
+
+fn completion_accepted_command(ctx: &mut ServerContext, arguments: Vec<Value>) -> RequestResult {
+    // The argument should be the name of the accepted symbol.
+    match arguments.into_iter().next() {
+        Some(Value::String(key)) => {
+            ctx.completion_usage.record(key);
+            Ok(Value::Null)
+        }
+        _ => {
+            let message = "Expected the name of a symbol".to_owned();
+            Err(ResponseError::new(ErrorCodes::InvalidParams, message))
+        }
     }
+}
 
-    let original = path.clone();
-    while path.pop() {
-        if is_chromium_src_dir(&path) {
-            return path;
+fn open_generated_binding_command(ctx: &mut ServerContext, arguments: Vec<Value>) -> RequestResult {
+    // The argument should be a symbol position.
+    let argument = arguments.into_iter().next().unwrap_or(Value::Null);
+    let params = get_request_params::<lsp_types::TextDocumentPositionParams>(argument)?;
+
+    let settings = settings_for(ctx, &params.text_document.uri);
+    let loc = ctx
+        .diag
+        .goto_definition(params.text_document.uri, params.position)
+        .and_then(|link| {
+            let definition = lsp_types::Location::new(link.target_uri, link.target_selection_range);
+            find_generated_binding(&ctx.root_path, &settings.generated_dirs, &definition)
+        });
+    match loc {
+        Some(loc) => Ok(serde_json::to_value(loc).unwrap()),
+        None => {
+            show_message(
+                &ctx.msg_sender,
+                lsp_types::MessageType::Warning,
+                "Generated binding not found".to_owned(),
+            );
+            Ok(Value::Null)
         }
     }
-    original
 }
 
-fn get_root_path(params: &lsp_types::InitializeParams) -> Option<PathBuf> {
-    let uri = match params.root_uri {
-        Some(ref uri) => uri,
-        None => return None,
+fn log_message(msg_sender: &MessageSender, typ: lsp_types::MessageType, message: String) {
+    let params = lsp_types::LogMessageParams {
+        typ: typ,
+        message: message,
     };
-    let path = match uri.to_file_path() {
-        Ok(path) => path,
-        Err(_) => return None,
+    let msg = NotificationMessage {
+        method: "window/logMessage".to_owned(),
+        params: serde_json::to_value(params).unwrap(),
     };
-
-    // Try to find chromium's `src` directory and use it if exists.
-    let path = find_chromium_src_dir(path);
-    Some(path)
+    msg_sender.send_notification(msg);
 }
 
-// Returns exit code.
-pub fn start<R, W>(reader: R, writer: W) -> anyhow::Result<i32>
-where
-    R: Read,
-    W: Write + Send + 'static,
-{
-    let mut reader = BufReader::new(reader);
-    let mut writer = BufWriter::new(writer);
-
-    let params = super::initialization::initialize(&mut reader, &mut writer)?;
+fn show_message(msg_sender: &MessageSender, typ: lsp_types::MessageType, message: String) {
+    let params = lsp_types::ShowMessageParams {
+        typ: typ,
+        message: message,
+    };
+    let msg = NotificationMessage {
+        method: "window/showMessage".to_owned(),
+        params: serde_json::to_value(params).unwrap(),
+    };
+    msg_sender.send_notification(msg);
+}
 
-    let root_path = get_root_path(&params).unwrap_or(PathBuf::new());
+// Notifications
 
-    let msg_sender_thread = start_message_sender_thread(writer);
-    let diag = start_diagnostics_thread(root_path, msg_sender_thread.get_sender());
+fn get_params<P: serde::de::DeserializeOwned>(params: Value) -> anyhow::Result<P> {
+    serde_json::from_value::<P>(params).map_err(|err| err.into())
+}
 
-    let mut ctx = ServerContext::new(msg_sender_thread.get_sender(), diag);
-    loop {
-        let message = read_message(&mut reader)?;
-        match message {
-            Message::Request(request) => handle_request(&mut ctx, request)?,
-            Message::Notofication(notification) => handle_notification(&mut ctx, notification)?,
-            _ => unreachable!(),
-        };
+fn handle_notification(ctx: &mut ServerContext, msg: NotificationMessage) -> anyhow::Result<()> {
+    log::debug!("[recv] Notification: method = {}", msg.method);
 
-        if let Some(exit_code) = ctx.exit_code {
-            return Ok(exit_code);
+    // Like requests, a bug in a handler shouldn't kill the session.
+    // Notifications can't be answered so the panic is only logged.
+    let method = msg.method;
+    let params = msg.params;
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        dispatch_notification(ctx, &method, params)
+    })) {
+        Ok(res) => res,
+        Err(payload) => {
+            log::error!(
+                "Notification handler panicked: method = {}: {}",
+                method,
+                panic_message(payload.as_ref())
+            );
+            Ok(())
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::super::protocol::{self, read_message, write_notification, write_request};
-    use super::*;
-
+fn dispatch_notification(
+    ctx: &mut ServerContext,
+    method: &str,
+    params: Value,
+) -> anyhow::Result<()> {
     use lsp_types::notification::*;
-    use lsp_types::request::*;
-    use pipe::pipe;
+    match method {
+        Exit::METHOD => exit_notification(ctx),
+        Cancel::METHOD => {
+            get_params(params).map(|params| cancel_request_notification(ctx, params))?;
+        }
+        DidOpenTextDocument::METHOD => {
+            get_params(params).map(|params| did_open_text_document(ctx, params))?;
+        }
+        DidChangeTextDocument::METHOD => {
+            get_params(params).map(|params| did_change_text_document(ctx, params))?;
+        }
+        DidCloseTextDocument::METHOD => {
+            get_params(params).map(|params| did_close_text_document(ctx, params))?;
+        }
+        DidSaveTextDocument::METHOD => {
+            get_params(params).map(|params| did_save_text_document(ctx, params))?;
+        }
+        DidChangeWorkspaceFolders::METHOD => {
+            get_params(params).map(|params| did_change_workspace_folders(ctx, params))?;
+        }
+        // Settings are pulled with `workspace/configuration` instead of
+        // reading the params.
+        DidChangeConfiguration::METHOD => request_configuration(ctx),
+        // Accept following notifications but do nothing.
+        WillSaveTextDocument::METHOD => (),
+        #[cfg(test)]
+        PANIC_FOR_TEST_METHOD => panic!("Deliberate panic"),
+        _ => {
+            // Notifications can't be answered. Unknown ones are ignored.
+            log::warn!("Received unimplemented notification: {}", method);
+        }
+    }
+    Ok(())
+}
+
+// Params of `$/cancelRequest`. `lsp_types::CancelParams` isn't used so that
+// ids can be compared with `RequestId`s of incoming requests.
+#[derive(serde::Deserialize)]
+struct CancelParams {
+    id: RequestId,
+}
+
+fn cancel_request_notification(ctx: &mut ServerContext, params: CancelParams) {
+    // The request has already stopped or finished if it was running.
+    ctx.cancellations.lock().unwrap().remove(&params.id);
+    // Otherwise only requests which haven't started yet can be cancelled.
+    if ctx.queued_requests.contains(&params.id) {
+        ctx.cancelled_requests.insert(params.id);
+    } else {
+        log::debug!("Ignored cancellation: id = {}", params.id);
+    }
+}
+
+// Events which wake up the message loop.
+enum LoopEvent {
+    // Messages which were read from the stream at once.
+    Messages(Vec<anyhow::Result<Message>>),
+    // The client process is gone.
+    ParentExited,
+}
+
+// Starts a thread which reads messages from `reader` and sends them to the
+// message loop. Messages which are already buffered are sent together so
+// that a cancellation can take effect before the cancelled request is
+// handled. Cancelled request ids are also recorded in `cancellations`. The
+// thread stops when the stream is broken or the loop is gone.
+fn start_reader_thread<R: Read + Send + 'static>(
+    mut reader: BufReader<R>,
+    max_content_length: Arc<AtomicUsize>,
+    cancellations: Arc<Mutex<HashSet<RequestId>>>,
+    sender: Sender<LoopEvent>,
+) {
+    use lsp_types::notification::{Cancel, Notification};
+    thread::spawn(move || loop {
+        let mut results = Vec::new();
+        let broken = loop {
+            let limit = max_content_length.load(Ordering::Relaxed);
+            let result = read_message_with_limit(&mut reader, limit);
+            let broken = match &result {
+                Ok(Message::Notofication(notification))
+                    if notification.method == Cancel::METHOD =>
+                {
+                    if let Ok(params) =
+                        serde_json::from_value::<CancelParams>(notification.params.clone())
+                    {
+                        cancellations.lock().unwrap().insert(params.id);
+                    }
+                    false
+                }
+                Ok(_) => false,
+                Err(err) => !protocol::is_skippable(err),
+            };
+            results.push(result);
+            if broken || reader.buffer().is_empty() {
+                break broken;
+            }
+        };
+        if sender.send(LoopEvent::Messages(results)).is_err() || broken {
+            break;
+        }
+    });
+}
+
+// Receives all messages which have already arrived so that `$/cancelRequest`
+// can take effect before the cancelled request is handled. Blocks when
+// `queue` is empty. Cancel notifications are handled here; other messages
+// are appended to `queue`. An error which broke the stream is stored in
+// `broken` so that messages received before it are still handled.
+fn read_messages(
+    ctx: &mut ServerContext,
+    events: &Receiver<LoopEvent>,
+    queue: &mut VecDeque<Message>,
+    broken: &mut Option<anyhow::Error>,
+) -> anyhow::Result<()> {
+    use lsp_types::notification::*;
+    let mut event = if queue.is_empty() {
+        Some(events.recv()?)
+    } else {
+        events.try_recv().ok()
+    };
+    while let Some(current) = event {
+        let results = match current {
+            LoopEvent::Messages(results) => results,
+            LoopEvent::ParentExited => {
+                // The client can't receive anything anymore.
+                ctx.exit_code = Some(1);
+                return Ok(());
+            }
+        };
+        for result in results {
+            match result {
+                Ok(Message::Request(request)) => {
+                    ctx.queued_requests.insert(request.id.clone());
+                    queue.push_back(Message::Request(request));
+                }
+                Ok(Message::Notofication(notification))
+                    if notification.method == Cancel::METHOD =>
+                {
+                    handle_notification(ctx, notification)?;
+                }
+                Ok(message) => queue.push_back(message),
+                // The stream is still in sync. Keep serving the client.
+                Err(err) if protocol::is_skippable(&err) => {
+                    log::warn!("Skipped a message: {}", err)
+                }
+                Err(err) => {
+                    *broken = Some(err);
+                    return Ok(());
+                }
+            }
+        }
+        event = events.try_recv().ok();
+    }
+    Ok(())
+}
+
+fn exit_notification(ctx: &mut ServerContext) {
+    // https://microsoft.github.io/language-server-protocol/specification#exit
+    if ctx.state == State::ShuttingDown {
+        ctx.exit_code = Some(0);
+    } else {
+        ctx.exit_code = Some(1);
+    }
+}
+
+// Returns the settings for `uri`, which apply the project config file for it
+// if any.
+fn settings_for(ctx: &mut ServerContext, uri: &Uri) -> Settings {
+    let config = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| ctx.configs.config_for(&path));
+    match config {
+        Some(config) => {
+            settings::with_config(&ctx.base_settings, &config, &ctx.initialization_options)
+        }
+        None => ctx.base_settings.clone(),
+    }
+}
+
+// Returns false when `text` is larger than `maxFileSizeBytes`. Logs a message
+// the first time a document exceeds the limit.
+fn is_analyzable(ctx: &mut ServerContext, uri: &Uri, text: &str, settings: &Settings) -> bool {
+    let max_size = match settings.max_file_size_bytes {
+        Some(max_size) => max_size,
+        None => return true,
+    };
+    if (text.len() as u64) <= max_size {
+        ctx.oversized_documents.remove(uri);
+        return true;
+    }
+    if ctx.oversized_documents.insert(uri.clone()) {
+        let message = format!(
+            "Skipped analyzing {} because it is larger than {} bytes",
+            uri, max_size
+        );
+        log_message(&ctx.msg_sender, lsp_types::MessageType::Info, message);
+        // Diagnostics published before the document grew are stale.
+        if diagnostics_mode(ctx, settings) != DiagnosticsMode::Off {
+            ctx.diag.clear(uri.clone());
+        }
+    }
+    false
+}
+
+fn read_file(uri: &Uri) -> std::io::Result<String> {
+    let path = uri
+        .to_file_path()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a file URI"))?;
+    std::fs::read_to_string(path)
+}
+
+// Returns when diagnostics are pushed. Clients which pull diagnostics with
+// `textDocument/diagnostic` don't need them pushed as well.
+fn diagnostics_mode(ctx: &ServerContext, settings: &Settings) -> DiagnosticsMode {
+    if pull_diagnostics::supports_pull_diagnostics(&ctx.client_capabilities_json) {
+        DiagnosticsMode::Off
+    } else {
+        settings.diagnostics_mode
+    }
+}
+
+fn did_open_text_document(ctx: &mut ServerContext, params: lsp_types::DidOpenTextDocumentParams) {
+    let uri = params.text_document.uri;
+    let text = params.text_document.text;
+    let document = Document {
+        version: params.text_document.version,
+        text: text.clone(),
+    };
+    ctx.documents.insert(uri.clone(), document);
+    ctx.workspace_symbols.update(&uri, &text);
+    ctx.hover_cache = None;
+    let settings = settings_for(ctx, &uri);
+    if !is_analyzable(ctx, &uri, &text, &settings) {
+        return;
+    }
+    match diagnostics_mode(ctx, &settings) {
+        DiagnosticsMode::OnChange | DiagnosticsMode::OnSave => ctx.diag.check(uri, text, settings),
+        DiagnosticsMode::Off => ctx.diag.update(uri, text, settings),
+    }
+}
+
+fn did_change_text_document(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidChangeTextDocumentParams,
+) {
+    let uri = params.text_document.uri;
+    ctx.hover_cache = None;
+    let document = ctx.documents.entry(uri.clone()).or_default();
+    if let Some(version) = params.text_document.version {
+        document.version = version;
+    }
+    // Some clients send no changes just to bump the version.
+    if params.content_changes.is_empty() {
+        log::debug!("No content changes: {} (version {})", uri, document.version);
+        return;
+    }
+    apply_content_changes(&mut document.text, &params.content_changes);
+    let text = document.text.clone();
+    ctx.workspace_symbols.update(&uri, &text);
+    let settings = settings_for(ctx, &uri);
+    if !is_analyzable(ctx, &uri, &text, &settings) {
+        return;
+    }
+    match diagnostics_mode(ctx, &settings) {
+        DiagnosticsMode::OnChange => ctx.diag.check(uri, text, settings),
+        DiagnosticsMode::OnSave | DiagnosticsMode::Off => ctx.diag.update(uri, text, settings),
+    }
+}
+
+fn did_close_text_document(ctx: &mut ServerContext, params: lsp_types::DidCloseTextDocumentParams) {
+    ctx.documents.remove(&params.text_document.uri);
+    ctx.oversized_documents.remove(&params.text_document.uri);
+    ctx.lexers.remove(&params.text_document.uri);
+    ctx.workspace_symbols.close(&params.text_document.uri);
+    ctx.hover_cache = None;
+    ctx.diag.close(params.text_document.uri);
+}
+
+fn did_save_text_document(ctx: &mut ServerContext, params: lsp_types::DidSaveTextDocumentParams) {
+    // The text is only included when the client supports `didSave` and we
+    // asked for it in the server capabilities.
+    let text = match params.text {
+        Some(text) => text,
+        None => return,
+    };
+    let uri = params.text_document.uri;
+    ctx.hover_cache = None;
+    let settings = settings_for(ctx, &uri);
+    if !is_analyzable(ctx, &uri, &text, &settings) {
+        return;
+    }
+    match diagnostics_mode(ctx, &settings) {
+        DiagnosticsMode::OnChange | DiagnosticsMode::OnSave => ctx.diag.check(uri, text, settings),
+        DiagnosticsMode::Off => ctx.diag.update(uri, text, settings),
+    }
+}
+
+// Tells the diagnostics thread about the root path and the other workspace
+// folders.
+fn update_workspace_folders(ctx: &mut ServerContext) {
+    let folders = ctx
+        .workspace_folders
+        .iter()
+        .filter(|folder| **folder != ctx.root_path)
+        .cloned()
+        .collect();
+    ctx.diag
+        .update_workspace_folders(ctx.root_path.clone(), folders);
+}
+
+fn did_change_workspace_folders(
+    ctx: &mut ServerContext,
+    params: lsp_types::DidChangeWorkspaceFoldersParams,
+) {
+    let removed: Vec<PathBuf> = params
+        .event
+        .removed
+        .iter()
+        .filter_map(|folder| folder.uri.to_file_path().ok())
+        .collect();
+    ctx.workspace_folders
+        .retain(|folder| !removed.contains(folder));
+    for folder in params.event.added {
+        match folder.uri.to_file_path() {
+            Ok(path) if !ctx.workspace_folders.contains(&path) => ctx.workspace_folders.push(path),
+            Ok(_) => (),
+            Err(_) => log::warn!("Ignored non-file workspace folder: {}", folder.uri),
+        }
+    }
+    // The root path is just the first folder. When it's removed, the next one
+    // takes over so imports no longer resolve against the removed folder.
+    if !ctx.workspace_folders.contains(&ctx.root_path) {
+        ctx.root_path = ctx.workspace_folders.first().cloned().unwrap_or_default();
+    }
+    update_workspace_folders(ctx);
+    start_workspace_symbol_build(ctx);
+    ctx.hover_cache = None;
+}
+
+fn is_chromium_src_dir(path: &PathBuf) -> bool {
+    // The root is named `src`.
+    if !path.file_name().map(|name| name == "src").unwrap_or(false) {
+        return false;
+    }
+
+    // Check if the parent directory contains `.gclient`.
+    match path.parent() {
+        Some(parent) => parent.join(".gclient").is_file(),
+        None => false,
+    }
+}
+
+fn find_chromium_src_dir(mut path: PathBuf) -> PathBuf {
+    if is_chromium_src_dir(&path) {
+        return path;
+    }
+
+    let original = path.clone();
+    while path.pop() {
+        if is_chromium_src_dir(&path) {
+            return path;
+        }
+    }
+    original
+}
+
+fn get_root_path(params: &lsp_types::InitializeParams) -> Option<PathBuf> {
+    let uri = match params.root_uri {
+        Some(ref uri) => uri,
+        None => return None,
+    };
+    let path = match uri.to_file_path() {
+        Ok(path) => path,
+        Err(_) => return None,
+    };
+
+    // Try to find chromium's `src` directory and use it if exists.
+    let path = find_chromium_src_dir(path);
+    Some(path)
+}
+
+/// A language server with an explicit configuration. Use this to run the
+/// server without the `initialize` handshake, e.g. in test harnesses.
+pub struct Server {
+    root_path: PathBuf,
+    workspace_folders: Vec<PathBuf>,
+    settings: Settings,
+    initialization_options: Value,
+    client_capabilities: lsp_types::ClientCapabilities,
+    client_capabilities_json: Value,
+    capabilities: lsp_types::ServerCapabilities,
+    // The client process ID.
+    parent_process_id: Option<u64>,
+    published_diagnostics: PublishedDiagnostics,
+}
+
+impl Server {
+    pub fn new(settings: Settings) -> Server {
+        let client_capabilities = lsp_types::ClientCapabilities {
+            workspace: None,
+            text_document: None,
+            window: None,
+            experimental: None,
+        };
+        let capabilities = super::initialization::create_server_capabilities(&client_capabilities);
+        Server {
+            root_path: PathBuf::new(),
+            workspace_folders: Vec::new(),
+            settings: settings,
+            initialization_options: Value::Null,
+            client_capabilities: client_capabilities,
+            client_capabilities_json: Value::Null,
+            capabilities: capabilities,
+            parent_process_id: None,
+            published_diagnostics: PublishedDiagnostics::default(),
+        }
+    }
+
+    /// Sets the workspace root path.
+    pub fn root_path(mut self, root_path: PathBuf) -> Server {
+        self.root_path = root_path;
+        self
+    }
+
+    /// Sets workspace folders. Imports are resolved against them in addition
+    /// to the root path.
+    pub fn workspace_folders(mut self, workspace_folders: Vec<PathBuf>) -> Server {
+        self.workspace_folders = workspace_folders;
+        self
+    }
+
+    /// Sets the client process ID. The server exits when the process is gone
+    /// unless `watchParentProcess` is disabled.
+    pub fn parent_process_id(mut self, pid: u64) -> Server {
+        self.parent_process_id = Some(pid);
+        self
+    }
+
+    /// Returns a handle to query diagnostics which the server published. The
+    /// handle stays valid while the server runs.
+    pub fn published_diagnostics(&self) -> PublishedDiagnostics {
+        self.published_diagnostics.clone()
+    }
+
+    /// Runs the message loop. Unlike `start()`, this doesn't expect the
+    /// `initialize` handshake. Returns exit code.
+    pub fn run<R, W>(self, reader: R, writer: W) -> anyhow::Result<i32>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        self.run_loop(BufReader::new(reader), BufWriter::new(writer))
+    }
+
+    fn run_loop<R, W>(self, reader: BufReader<R>, writer: W) -> anyhow::Result<i32>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (event_sender, events) = channel();
+        if let (true, Some(pid)) = (self.settings.watch_parent_process, self.parent_process_id) {
+            let event_sender = event_sender.clone();
+            start_parent_watchdog(pid, ProcessProbe, WATCH_INTERVAL, move || {
+                // The loop may be gone already.
+                let _ = event_sender.send(LoopEvent::ParentExited);
+            });
+        }
+
+        let msg_sender_thread = start_message_sender_thread(writer);
+        let diag = start_diagnostics_thread(
+            self.root_path.clone(),
+            self.settings.clone(),
+            msg_sender_thread.get_sender(),
+            self.published_diagnostics,
+        );
+
+        let mut ctx = ServerContext::new(
+            self.root_path,
+            self.workspace_folders,
+            self.settings,
+            self.initialization_options,
+            self.client_capabilities,
+            self.client_capabilities_json,
+            self.capabilities,
+            msg_sender_thread.get_sender(),
+            diag,
+        );
+        start_reader_thread(
+            reader,
+            ctx.max_content_length.clone(),
+            ctx.cancellations.clone(),
+            event_sender,
+        );
+        let result = serve(&mut ctx, &events);
+
+        // Let the diagnostics thread finish its work, then flush messages
+        // which are still queued before returning.
+        let ServerContext {
+            diag, msg_sender, ..
+        } = ctx;
+        drop(msg_sender);
+        diag.join();
+        msg_sender_thread.join();
+        result
+    }
+}
+
+// Handles messages until the server exits.
+fn serve(ctx: &mut ServerContext, events: &Receiver<LoopEvent>) -> anyhow::Result<i32> {
+    update_workspace_folders(ctx);
+    start_workspace_symbol_build(ctx);
+    request_configuration(ctx);
+    let mut queue = VecDeque::new();
+    let mut broken = None;
+    loop {
+        if broken.is_none() {
+            read_messages(ctx, events, &mut queue, &mut broken)?;
+        }
+        if let Some(exit_code) = ctx.exit_code {
+            return Ok(exit_code);
+        }
+        let message = match queue.pop_front() {
+            Some(message) => message,
+            None => match broken.take() {
+                Some(err) => return Err(err),
+                None => continue,
+            },
+        };
+        match message {
+            Message::Request(request) => handle_request(ctx, request)?,
+            Message::Notofication(notification) => handle_notification(ctx, notification)?,
+            Message::Response(response) => handle_response(ctx, response),
+        };
+
+        if let Some(exit_code) = ctx.exit_code {
+            return Ok(exit_code);
+        }
+    }
+}
+
+// Returns exit code.
+pub fn start<R, W>(reader: R, writer: W) -> anyhow::Result<i32>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    start_with(BufReader::new(reader), BufWriter::new(writer))
+}
+
+/// Same as `start()` but takes a buffered reader. The reader isn't wrapped
+/// again, so the server can tell whether more messages have already arrived.
+/// Returns exit code.
+pub fn start_with<R, W>(mut reader: BufReader<R>, mut writer: W) -> anyhow::Result<i32>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let (params, client_capabilities_json, capabilities) =
+        match super::initialization::initialize(&mut reader, &mut writer)? {
+            Initialization::Initialized(params, client_capabilities_json, capabilities) => {
+                (params, client_capabilities_json, capabilities)
+            }
+            Initialization::Exited(exit_code) => return Ok(exit_code),
+        };
+
+    let workspace_folders: Vec<PathBuf> = params
+        .workspace_folders
+        .iter()
+        .flatten()
+        .filter_map(|folder| folder.uri.to_file_path().ok())
+        .collect();
+    // Clients which support workspace folders may not send `rootUri`.
+    let root_path = get_root_path(&params)
+        .or_else(|| workspace_folders.first().cloned())
+        .unwrap_or(PathBuf::new());
+    let initialization_options = params.initialization_options.unwrap_or(Value::Null);
+    let settings = settings::from_initialization_options(Some(initialization_options.clone()));
+
+    let server = Server {
+        root_path: root_path,
+        workspace_folders: workspace_folders,
+        settings: settings,
+        initialization_options: initialization_options,
+        client_capabilities: params.capabilities,
+        client_capabilities_json: client_capabilities_json,
+        capabilities: capabilities,
+        parent_process_id: params.process_id,
+        published_diagnostics: PublishedDiagnostics::default(),
+    };
+    server.run_loop(reader, writer)
+}
+
+/// Listens on `addr` and runs the server over the first accepted connection.
+/// Returns exit code.
+pub fn start_tcp(addr: SocketAddr) -> anyhow::Result<i32> {
+    let listener = TcpListener::bind(addr)?;
+    start_tcp_with_listener(listener)
+}
+
+fn start_tcp_with_listener(listener: TcpListener) -> anyhow::Result<i32> {
+    log::info!("Listening on {}", listener.local_addr()?);
+    let (stream, peer_addr) = listener.accept()?;
+    log::info!("Accepted a connection from {}", peer_addr);
+    let reader = BufReader::new(stream.try_clone()?);
+    start_with(reader, BufWriter::new(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::config::CONFIG_FILE_NAME;
+    use super::super::protocol::{self, read_message, write_notification, write_request};
+    use super::*;
+
+    use std::io::BufRead;
+
+    use lsp_types::notification::*;
+    use lsp_types::request::*;
+    use pipe::{pipe, PipeReader};
+
+    fn create_test_context() -> (ServerContext, BufReader<PipeReader>) {
+        create_test_context_with_settings(Settings::default())
+    }
+
+    fn create_test_context_with_settings(
+        settings: Settings,
+    ) -> (ServerContext, BufReader<PipeReader>) {
+        create_test_context_with_root(PathBuf::from("testdata"), settings)
+    }
+
+    fn create_test_context_with_root(
+        root_path: PathBuf,
+        settings: Settings,
+    ) -> (ServerContext, BufReader<PipeReader>) {
+        let (r, w) = pipe();
+        let msg_sender_thread = start_message_sender_thread(w);
+        let diag = start_diagnostics_thread(
+            root_path.clone(),
+            settings.clone(),
+            msg_sender_thread.get_sender(),
+            PublishedDiagnostics::default(),
+        );
+        let client_capabilities = serde_json::from_value(serde_json::json!({})).unwrap();
+        let capabilities =
+            super::super::initialization::create_server_capabilities(&client_capabilities);
+        let ctx = ServerContext::new(
+            root_path,
+            Vec::new(),
+            settings,
+            Value::Null,
+            client_capabilities,
+            Value::Null,
+            capabilities,
+            msg_sender_thread.get_sender(),
+            diag,
+        );
+        (ctx, BufReader::new(r))
+    }
+
+    fn create_uri(path: &str) -> lsp_types::Url {
+        let path = std::path::Path::new(path).canonicalize().unwrap();
+        lsp_types::Url::from_file_path(path).unwrap()
+    }
+
+    fn read_response(r: &mut impl BufRead) -> protocol::ResponseMessage {
+        loop {
+            match read_message(r).unwrap() {
+                protocol::Message::Response(msg) => return msg,
+                // Skip notifications such as diagnostics.
+                _ => continue,
+            }
+        }
+    }
+
+    fn read_notification(r: &mut impl BufRead) -> protocol::NotificationMessage {
+        loop {
+            match read_message(r).unwrap() {
+                protocol::Message::Notofication(msg) => return msg,
+                _ => continue,
+            }
+        }
+    }
+
+    #[test]
+    fn test_server_run_without_handshake() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let handle = std::thread::spawn(move || {
+            Server::new(Settings::default())
+                .root_path(PathBuf::from("testdata"))
+                .run(reader, w)
+        });
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": create_uri("testdata/my_service.mojom") },
+            "position": { "line": 4, "character": 28 },
+        });
+        write_request(
+            &mut writer,
+            RequestId::Number(1),
+            GotoDefinition::METHOD,
+            params,
+        )
+        .unwrap();
+
+        let mut r = BufReader::new(r);
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        let loc: lsp_types::Location = serde_json::from_value(res.result.unwrap()).unwrap();
+        assert_eq!(create_uri("testdata/my_interface.mojom"), loc.uri);
+
+        write_request(
+            &mut writer,
+            RequestId::Number(2),
+            Shutdown::METHOD,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(2), res.id);
+        write_notification(&mut writer, Exit::METHOD, serde_json::Value::Null).unwrap();
+
+        let status = handle.join().unwrap();
+        assert_eq!(0, status.unwrap());
+    }
+
+    #[test]
+    fn test_exit_when_parent_process_is_gone() {
+        // Keep `writer` open so that the server doesn't see EOF.
+        let (reader, _writer) = pipe();
+        let (r, w) = pipe();
+        let server = Server::new(Settings::default())
+            .root_path(PathBuf::from("testdata"))
+            // No process has this ID.
+            .parent_process_id(u32::MAX as u64);
+        let handle = std::thread::spawn(move || server.run(reader, w));
+
+        let status = handle.join().unwrap();
+        assert_eq!(1, status.unwrap());
+        // The server flushed and closed the output.
+        let mut output = Vec::new();
+        BufReader::new(r).read_to_end(&mut output).unwrap();
+    }
+
+    #[test]
+    fn test_skip_large_and_empty_messages() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let mut settings = Settings::default();
+        settings.max_content_length = 128;
+        let server = Server::new(settings).root_path(PathBuf::from("testdata"));
+        let handle = std::thread::spawn(move || server.run(reader, w));
+
+        let text = "/".repeat(256);
+        let params =
+            serde_json::json!({ "textDocument": { "uri": "file:///a.mojom" }, "text": text });
+        write_notification(&mut writer, DidSaveTextDocument::METHOD, params).unwrap();
+        writer.write_all(b"Content-Length: 0\r\n\r\n").unwrap();
+        write_request(
+            &mut writer,
+            RequestId::Number(1),
+            Shutdown::METHOD,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        let mut r = BufReader::new(r);
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        write_notification(&mut writer, Exit::METHOD, serde_json::Value::Null).unwrap();
+
+        let status = handle.join().unwrap();
+        assert_eq!(0, status.unwrap());
+    }
+
+    #[test]
+    fn test_published_diagnostics() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let server = Server::new(Settings::default()).root_path(PathBuf::from("testdata"));
+        let published = server.published_diagnostics();
+        let handle = std::thread::spawn(move || server.run(reader, w));
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+        assert!(published.diagnostics_for(&uri).is_empty());
+
+        let params = serde_json::json!({
+            "textDocument": {
+                "uri": uri,
+                "languageId": "mojom",
+                "version": 1,
+                "text": "module foo\nstruct Foo {};\n",
+            },
+        });
+        write_notification(&mut writer, DidOpenTextDocument::METHOD, params).unwrap();
+        let mut r = BufReader::new(r);
+        let msg = read_notification(&mut r);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert_eq!(1, params.diagnostics.len());
+        assert_eq!(params.diagnostics, published.diagnostics_for(&uri));
+
+        let params = serde_json::json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": "module foo;\nstruct Foo {};\n" }],
+        });
+        write_notification(&mut writer, DidChangeTextDocument::METHOD, params).unwrap();
+        let msg = read_notification(&mut r);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert!(params.diagnostics.is_empty());
+        assert!(published.diagnostics_for(&uri).is_empty());
+
+        write_request(
+            &mut writer,
+            RequestId::Number(1),
+            Shutdown::METHOD,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        read_response(&mut r);
+        write_notification(&mut writer, Exit::METHOD, serde_json::Value::Null).unwrap();
+        assert_eq!(0, handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_workspace_symbol() {
+        let root_path = PathBuf::from("testdata/workspace").canonicalize().unwrap();
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let server = Server::new(Settings::default()).root_path(root_path.clone());
+        let handle = std::thread::spawn(move || server.run(reader, w));
+        let mut r = BufReader::new(r);
+
+        // Files are scanned in the background. Only opened documents are
+        // searched until the scan finishes.
+        let mut symbols: Vec<lsp_types::SymbolInformation> = Vec::new();
+        for id in 1..100 {
+            let params = serde_json::json!({ "query": "Point" });
+            write_request(
+                &mut writer,
+                RequestId::Number(id),
+                WorkspaceSymbol::METHOD,
+                params,
+            )
+            .unwrap();
+            let res = read_response(&mut r);
+            symbols = serde_json::from_value(res.result.unwrap()).unwrap();
+            if !symbols.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(1, symbols.len());
+        let uri = Uri::from_file_path(root_path.join("point.mojom")).unwrap();
+        assert_eq!(uri, symbols[0].location.uri);
+
+        write_request(
+            &mut writer,
+            RequestId::Number(100),
+            Shutdown::METHOD,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        read_response(&mut r);
+        write_notification(&mut writer, Exit::METHOD, serde_json::Value::Null).unwrap();
+        assert_eq!(0, handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+
+        // Send everything at once so that the server reads the cancellation
+        // before handling the request.
+        let mut buf = Vec::new();
+        let params = serde_json::json!({
+            "textDocument": { "uri": create_uri("testdata/my_service.mojom") },
+            "position": { "line": 4, "character": 28 },
+        });
+        write_request(
+            &mut buf,
+            RequestId::Number(1),
+            GotoDefinition::METHOD,
+            params,
+        )
+        .unwrap();
+        let params = serde_json::json!({ "id": 1 });
+        write_notification(&mut buf, Cancel::METHOD, params).unwrap();
+        write_request(
+            &mut buf,
+            RequestId::Number(2),
+            Shutdown::METHOD,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+        write_notification(&mut buf, Exit::METHOD, serde_json::Value::Null).unwrap();
+        writer.write_all(&buf).unwrap();
+
+        let handle = std::thread::spawn(move || {
+            Server::new(Settings::default())
+                .root_path(PathBuf::from("testdata"))
+                .run(reader, w)
+        });
+
+        let mut r = BufReader::new(r);
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        assert!(res.result.is_none());
+        let code: i32 = ErrorCodes::RequestCancelled.into();
+        assert_eq!(code, res.error.unwrap().code);
+
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(2), res.id);
+        assert!(res.error.is_none());
+
+        let status = handle.join().unwrap();
+        assert_eq!(0, status.unwrap());
+    }
+
+    #[test]
+    fn test_diagnostics_on_save() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::OnSave;
+        let (mut ctx, mut r) = create_test_context_with_settings(settings);
+        let uri = create_uri("testdata/my_interface.mojom");
+
+        let msg = NotificationMessage {
+            method: DidChangeTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "interface {" }],
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let msg = NotificationMessage {
+            method: DidSaveTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri },
+                "text": "interface MyInterface {};",
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        // The first published diagnostics should come from the save, not from
+        // the change.
+        let msg = read_notification(&mut r);
+        assert_eq!(PublishDiagnostics::METHOD, msg.method);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert!(params.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_did_change_workspace_folders() {
+        let (mut ctx, mut r) = create_test_context();
+        let uri = create_uri("testdata/workspace/sub/canvas.mojom");
+        let text = std::fs::read_to_string(uri.to_file_path().unwrap()).unwrap();
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": text,
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        let msg = read_notification(&mut r);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert!(params.diagnostics[0]
+            .message
+            .starts_with("Import not found"));
+
+        // On `Point` of `workspace.Point`.
+        let params = lsp_types::TextDocumentPositionParams::new(
+            lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            lsp_types::Position::new(5, 18),
+        );
+        let res = goto_definition_request(&mut ctx, params.clone()).unwrap();
+        assert!(res.is_null());
+
+        let folder = create_uri("testdata/workspace");
+        let msg = NotificationMessage {
+            method: DidChangeWorkspaceFolders::METHOD.to_owned(),
+            params: serde_json::json!({
+                "event": {
+                    "added": [{ "uri": folder, "name": "workspace" }],
+                    "removed": [],
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        let res = goto_definition_request(&mut ctx, params.clone()).unwrap();
+        let location: lsp_types::Location = serde_json::from_value(res).unwrap();
+        assert_eq!(create_uri("testdata/workspace/point.mojom"), location.uri);
+
+        let msg = NotificationMessage {
+            method: DidChangeWorkspaceFolders::METHOD.to_owned(),
+            params: serde_json::json!({
+                "event": {
+                    "added": [],
+                    "removed": [{ "uri": folder, "name": "workspace" }],
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        assert_eq!(vec![PathBuf::from("testdata")], ctx.workspace_folders);
+        let res = goto_definition_request(&mut ctx, params).unwrap();
+        assert!(res.is_null());
+    }
+
+    #[test]
+    fn test_remove_root_workspace_folder() {
+        let root_path = PathBuf::from("testdata/workspace").canonicalize().unwrap();
+        let (mut ctx, mut r) =
+            create_test_context_with_root(root_path.clone(), Settings::default());
+        assert_eq!(vec![root_path.clone()], ctx.workspace_folders);
+
+        let uri = create_uri("testdata/workspace/sub/canvas.mojom");
+        let text = std::fs::read_to_string(uri.to_file_path().unwrap()).unwrap();
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": text,
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        let msg = read_notification(&mut r);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert!(params.diagnostics.is_empty());
+
+        // On `Point` of `workspace.Point`.
+        let params = lsp_types::TextDocumentPositionParams::new(
+            lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            lsp_types::Position::new(5, 18),
+        );
+        let res = goto_definition_request(&mut ctx, params.clone()).unwrap();
+        let location: lsp_types::Location = serde_json::from_value(res).unwrap();
+        assert_eq!(create_uri("testdata/workspace/point.mojom"), location.uri);
+
+        let folder = create_uri("testdata/workspace");
+        let msg = NotificationMessage {
+            method: DidChangeWorkspaceFolders::METHOD.to_owned(),
+            params: serde_json::json!({
+                "event": {
+                    "added": [],
+                    "removed": [{ "uri": folder, "name": "workspace" }],
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        assert!(ctx.workspace_folders.is_empty());
+        assert_eq!(PathBuf::new(), ctx.root_path);
+        let res = goto_definition_request(&mut ctx, params).unwrap();
+        assert!(res.is_null());
+
+        // The removed folder is no longer indexed.
+        ctx.workspace_symbols.wait_for_build();
+        let params = serde_json::from_value(serde_json::json!({ "query": "Point" })).unwrap();
+        let res = workspace_symbol_request(&mut ctx, params).unwrap();
+        let symbols: Vec<lsp_types::SymbolInformation> = serde_json::from_value(res).unwrap();
+        assert!(symbols
+            .iter()
+            .all(|symbol| symbol.location.uri != create_uri("testdata/workspace/point.mojom")));
+    }
+
+    #[test]
+    fn test_hover_cache() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, _r) = create_test_context_with_settings(settings);
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "struct Foo {};\nstruct Bar {\n  Foo foo;\n};",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let hover = |ctx: &mut ServerContext, line: u64, character: u64| {
+            let params = lsp_types::TextDocumentPositionParams::new(
+                lsp_types::TextDocumentIdentifier::new(uri.clone()),
+                lsp_types::Position::new(line, character),
+            );
+            hover_request(ctx, params).unwrap()
+        };
+        let first = hover(&mut ctx, 2, 2);
+        assert!(!first.is_null());
+        assert_eq!(1, ctx.hover_computations);
+        // The same position and another position in the same token.
+        assert_eq!(first, hover(&mut ctx, 2, 2));
+        assert_eq!(first, hover(&mut ctx, 2, 4));
+        assert_eq!(1, ctx.hover_computations);
+
+        // Another token.
+        hover(&mut ctx, 0, 8);
+        assert_eq!(2, ctx.hover_computations);
+        hover(&mut ctx, 2, 3);
+        assert_eq!(3, ctx.hover_computations);
+
+        // Changes invalidate the cache.
+        let msg = NotificationMessage {
+            method: DidChangeTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "struct Foo {};\nstruct Baz {\n  Foo foo;\n};" }],
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        assert_eq!(first, hover(&mut ctx, 2, 3));
+        assert_eq!(4, ctx.hover_computations);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, _r) = create_test_context_with_settings(settings);
+        let snapshot = serde_json::to_value(ctx.snapshot()).unwrap();
+        let expected = serde_json::json!({
+            "shuttingDown": false,
+            "documents": {},
+            "oversizedDocuments": [],
+            "pendingRequests": 0,
+            "indexedSymbols": [],
+        });
+        assert_eq!(expected, snapshot);
+
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": "file:///foo.mojom",
+                    "languageId": "mojom",
+                    "version": 3,
+                    "text": "module foo;\ninterface Foo { enum E {}; };\nstruct Bar {};",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let snapshot = serde_json::to_value(ctx.snapshot()).unwrap();
+        let expected = serde_json::json!({
+            "shuttingDown": false,
+            "documents": { "file:///foo.mojom": 3 },
+            "oversizedDocuments": [],
+            "pendingRequests": 0,
+            "indexedSymbols": ["foo.Foo", "foo.Bar"],
+        });
+        assert_eq!(expected, snapshot);
+    }
+
+    #[test]
+    fn test_syntax_error_diagnostics() {
+        let (mut ctx, mut r) = create_test_context();
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+
+        // Missing a semicolon after the struct.
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "module foo;\nstruct Foo {}\ninterface Bar {};\n",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let msg = read_notification(&mut r);
+        assert_eq!(PublishDiagnostics::METHOD, msg.method);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert_eq!(1, params.diagnostics.len());
+        let diagnostic = &params.diagnostics[0];
+        assert_eq!(
+            Some(lsp_types::DiagnosticSeverity::Error),
+            diagnostic.severity
+        );
+        // The error is reported at the token which follows the missing semicolon.
+        assert_eq!(lsp_types::Position::new(2, 0), diagnostic.range.start);
+
+        // Fixing the error clears the diagnostic.
+        let msg = NotificationMessage {
+            method: DidChangeTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "module foo;\nstruct Foo {};\ninterface Bar {};\n" }],
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let msg = read_notification(&mut r);
+        let params: lsp_types::PublishDiagnosticsParams =
+            serde_json::from_value(msg.params).unwrap();
+        assert!(params.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_empty_content_changes() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, _r) = create_test_context_with_settings(settings);
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "struct Foo {};",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let msg = NotificationMessage {
+            method: DidChangeTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [],
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let document = ctx.documents.get(&uri).unwrap();
+        assert_eq!(2, document.version);
+        assert_eq!("struct Foo {};", document.text);
+    }
+
+    #[test]
+    fn test_incremental_sync() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, _r) = create_test_context_with_settings(settings);
+        let incremental_uri = Uri::parse("file:///incremental.mojom").unwrap();
+        let full_uri = Uri::parse("file:///full.mojom").unwrap();
+
+        let text = "module foo;\n\n// \u{1F600} comment\nstruct Foo {\n  int32 bar;\n};\n";
+        for uri in &[&incremental_uri, &full_uri] {
+            let msg = NotificationMessage {
+                method: DidOpenTextDocument::METHOD.to_owned(),
+                params: serde_json::json!({
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": "mojom",
+                        "version": 1,
+                        "text": text,
+                    },
+                }),
+            };
+            handle_notification(&mut ctx, msg).unwrap();
+        }
+
+        let edits = vec![
+            // Insertion after a surrogate pair, which is two UTF-16 code units.
+            (
+                serde_json::json!({ "start": { "line": 2, "character": 6 }, "end": { "line": 2, "character": 6 } }),
+                "nice ",
+                "module foo;\n\n// \u{1F600} nice comment\nstruct Foo {\n  int32 bar;\n};\n",
+            ),
+            // Deletion.
+            (
+                serde_json::json!({ "start": { "line": 0, "character": 10 }, "end": { "line": 1, "character": 0 } }),
+                "",
+                "module foo\n// \u{1F600} nice comment\nstruct Foo {\n  int32 bar;\n};\n",
+            ),
+            // Multi-line replacement.
+            (
+                serde_json::json!({ "start": { "line": 0, "character": 10 }, "end": { "line": 3, "character": 7 } }),
+                ";\ninterface Bar {\n  Baz();\n};\nstruct Foo {\n  int32",
+                "module foo;\ninterface Bar {\n  Baz();\n};\nstruct Foo {\n  int32 bar;\n};\n",
+            ),
+        ];
+        for (i, (range, new_text, expected)) in edits.into_iter().enumerate() {
+            let version = i + 2;
+            let msg = NotificationMessage {
+                method: DidChangeTextDocument::METHOD.to_owned(),
+                params: serde_json::json!({
+                    "textDocument": { "uri": incremental_uri, "version": version },
+                    "contentChanges": [{ "range": range, "text": new_text }],
+                }),
+            };
+            handle_notification(&mut ctx, msg).unwrap();
+            let msg = NotificationMessage {
+                method: DidChangeTextDocument::METHOD.to_owned(),
+                params: serde_json::json!({
+                    "textDocument": { "uri": full_uri, "version": version },
+                    "contentChanges": [{ "text": expected }],
+                }),
+            };
+            handle_notification(&mut ctx, msg).unwrap();
+
+            let incremental = ctx.documents.get(&incremental_uri).unwrap();
+            let full = ctx.documents.get(&full_uri).unwrap();
+            assert_eq!(full.text, incremental.text);
+            assert_eq!(full.version, incremental.version);
+        }
+    }
+
+    #[test]
+    fn test_pull_diagnostics() {
+        use super::super::pull_diagnostics::DocumentDiagnosticReport;
+
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, mut r) = create_test_context_with_settings(settings);
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "module a;\nmodule b;",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: DOCUMENT_DIAGNOSTIC_METHOD.to_owned(),
+            params: serde_json::json!({ "textDocument": { "uri": uri } }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let report: DocumentDiagnosticReport = serde_json::from_value(res.result.unwrap()).unwrap();
+        let result_id = match report {
+            DocumentDiagnosticReport::Full { result_id, items } => {
+                assert_eq!(1, items.len());
+                assert!(items[0].message.contains("more than one module"));
+                result_id
+            }
+            _ => panic!("Expected a full report"),
+        };
+
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: DOCUMENT_DIAGNOSTIC_METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri },
+                "previousResultId": result_id,
+            }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let report: DocumentDiagnosticReport = serde_json::from_value(res.result.unwrap()).unwrap();
+        assert_eq!(
+            DocumentDiagnosticReport::Unchanged {
+                result_id: result_id
+            },
+            report
+        );
+    }
+
+    #[test]
+    fn test_pull_diagnostics_client() {
+        use super::super::pull_diagnostics::DocumentDiagnosticReport;
+
+        let (mut ctx, mut r) = create_test_context();
+        ctx.client_capabilities_json = serde_json::json!({ "textDocument": { "diagnostic": {} } });
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "module a;\nmodule b;",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        // Unopened documents are read from disk.
+        let unopened = create_uri("testdata/workspace/point.mojom");
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: DOCUMENT_DIAGNOSTIC_METHOD.to_owned(),
+            params: serde_json::json!({ "textDocument": { "uri": unopened } }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        // Diagnostics of the opened document aren't pushed.
+        let res = match read_message(&mut r).unwrap() {
+            protocol::Message::Response(res) => res,
+            msg => panic!("Expected a response but got {:?}", msg),
+        };
+        let report: DocumentDiagnosticReport = serde_json::from_value(res.result.unwrap()).unwrap();
+        match report {
+            DocumentDiagnosticReport::Full { items, .. } => assert!(items.is_empty()),
+            _ => panic!("Expected a full report"),
+        }
+
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: DOCUMENT_DIAGNOSTIC_METHOD.to_owned(),
+            params: serde_json::json!({ "textDocument": { "uri": "file:///nonexistent.mojom" } }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let code: i32 = ErrorCodes::InvalidParams.into();
+        assert_eq!(code, res.error.unwrap().code);
+    }
+
+    #[test]
+    fn test_will_save_wait_until() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        settings.format_on_save = true;
+        let (mut ctx, mut r) = create_test_context_with_settings(settings);
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "module  foo;\nstruct Foo{int32 a;\n    string b;};\n",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let params = serde_json::json!({ "textDocument": { "uri": uri }, "reason": 1 });
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: WillSaveWaitUntil::METHOD.to_owned(),
+            params: params.clone(),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let edits: Vec<lsp_types::TextEdit> = serde_json::from_value(res.result.unwrap()).unwrap();
+        assert_eq!(1, edits.len());
+        assert_eq!(lsp_types::Position::new(0, 0), edits[0].range.start);
+        assert_eq!(
+            "module foo;\n\nstruct Foo {\n  int32 a;\n  string b;\n};\n",
+            edits[0].new_text
+        );
+
+        ctx.base_settings.format_on_save = false;
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: WillSaveWaitUntil::METHOD.to_owned(),
+            params: params,
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        // `null` results are deserialized as `None`.
+        assert!(res.result.is_none());
+        assert!(res.error.is_none());
+    }
+
+    #[test]
+    fn test_code_action_tab_indentation() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, mut r) = create_test_context_with_settings(settings);
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": "struct S {\n\tint32 a;\n  int32 b;\n\tint32 c;\n};",
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: CodeActionRequest::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri },
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": 3, "character": 0 },
+                },
+                "context": { "diagnostics": [] },
+            }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let actions: Vec<lsp_types::CodeAction> =
+            serde_json::from_value(res.result.unwrap()).unwrap();
+        assert_eq!(2, actions.len());
+        for (action, line) in actions.iter().zip([1, 3].iter()) {
+            let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+            let edits = &changes[&uri];
+            assert_eq!(1, edits.len());
+            assert_eq!(lsp_types::Position::new(*line, 0), edits[0].range.start);
+            assert_eq!(lsp_types::Position::new(*line, 1), edits[0].range.end);
+            assert_eq!("  ", edits[0].new_text);
+            assert_eq!(1, action.diagnostics.as_ref().unwrap().len());
+        }
+
+        // No actions for correctly indented lines.
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: CodeActionRequest::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri },
+                "range": {
+                    "start": { "line": 2, "character": 0 },
+                    "end": { "line": 2, "character": 0 },
+                },
+                "context": { "diagnostics": [] },
+            }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(serde_json::json!([]), res.result.unwrap());
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let mut settings = Settings::default();
+        settings.max_file_size_bytes = Some(32);
+        let (mut ctx, mut r) = create_test_context_with_settings(settings);
+
+        let open = |uri: &str, text: &str| NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": text,
+                },
+            }),
+        };
+
+        let large_text = "interface MyInterface { Foo(int32 a); };";
+        handle_notification(&mut ctx, open("file:///large.mojom", large_text)).unwrap();
+        // Opening the same document again doesn't log anything.
+        handle_notification(&mut ctx, open("file:///large.mojom", large_text)).unwrap();
+        handle_notification(&mut ctx, open("file:///small.mojom", "struct {")).unwrap();
+
+        let msg = read_notification(&mut r);
+        assert_eq!(LogMessage::METHOD, msg.method);
+        let params: lsp_types::LogMessageParams = serde_json::from_value(msg.params).unwrap();
+        assert!(params.message.contains("file:///large.mojom"));
+
+        let read_diagnostics = |r: &mut BufReader<PipeReader>| {
+            let msg = read_notification(r);
+            assert_eq!(PublishDiagnostics::METHOD, msg.method);
+            let params: lsp_types::PublishDiagnosticsParams =
+                serde_json::from_value(msg.params).unwrap();
+            params
+        };
+        let params = read_diagnostics(&mut r);
+        assert_eq!("file:///large.mojom", params.uri.as_str());
+        assert!(params.diagnostics.is_empty());
+
+        let params = read_diagnostics(&mut r);
+        assert_eq!("file:///small.mojom", params.uri.as_str());
+        assert_eq!(1, params.diagnostics.len());
+
+        // Diagnostics are cleared when the document becomes too large.
+        let msg = NotificationMessage {
+            method: DidChangeTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": "file:///small.mojom", "version": 2 },
+                "contentChanges": [{ "text": large_text }],
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        let msg = read_notification(&mut r);
+        assert_eq!(LogMessage::METHOD, msg.method);
+        let params = read_diagnostics(&mut r);
+        assert_eq!("file:///small.mojom", params.uri.as_str());
+        assert!(params.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_config_file_include_dirs() {
+        let root = std::env::temp_dir().join(format!(
+            "mojom-lsp-test-config-include-dirs-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("project/src")).unwrap();
+        std::fs::create_dir_all(root.join("project/deps")).unwrap();
+        std::fs::create_dir_all(root.join("other")).unwrap();
+        std::fs::write(root.join("project/deps/dep.mojom"), "struct Dep {};").unwrap();
+        let text = "import \"dep.mojom\";\nstruct Foo { Dep dep; };";
+        std::fs::write(root.join("project/src/foo.mojom"), text).unwrap();
+        std::fs::write(root.join("other/foo.mojom"), text).unwrap();
+
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, mut r) = create_test_context_with_settings(settings);
+
+        let goto_dep = |ctx: &mut ServerContext, r: &mut BufReader<PipeReader>, path: PathBuf| {
+            let uri = Uri::from_file_path(path).unwrap();
+            let msg = NotificationMessage {
+                method: DidOpenTextDocument::METHOD.to_owned(),
+                params: serde_json::json!({
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": "mojom",
+                        "version": 1,
+                        "text": text,
+                    },
+                }),
+            };
+            handle_notification(ctx, msg).unwrap();
+            let msg = RequestMessage {
+                id: RequestId::Number(1),
+                method: GotoDefinition::METHOD.to_owned(),
+                params: serde_json::json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": 1, "character": 14 },
+                }),
+            };
+            handle_request(ctx, msg).unwrap();
+            read_response(r).result.unwrap_or(Value::Null)
+        };
+
+        // No config file.
+        let res = goto_dep(&mut ctx, &mut r, root.join("other/foo.mojom"));
+        assert_eq!(Value::Null, res);
+
+        let config = serde_json::json!({ "includeDirs": ["deps"] }).to_string();
+        std::fs::write(root.join("project").join(CONFIG_FILE_NAME), config).unwrap();
+        let res = goto_dep(&mut ctx, &mut r, root.join("project/src/foo.mojom"));
+        let loc: lsp_types::Location = serde_json::from_value(res).unwrap();
+        let expected = root.join("project/deps/dep.mojom").canonicalize().unwrap();
+        assert_eq!(expected, loc.uri.to_file_path().unwrap());
+
+        // The config file doesn't apply to documents outside of the project.
+        let res = goto_dep(&mut ctx, &mut r, root.join("other/foo.mojom"));
+        assert_eq!(Value::Null, res);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_goto_definition_link() {
+        let (mut ctx, mut r) = create_test_context();
+        ctx.client_capabilities = serde_json::from_value(serde_json::json!({
+            "textDocument": { "definition": { "linkSupport": true } },
+        }))
+        .unwrap();
+
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: GotoDefinition::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": create_uri("testdata/my_service.mojom") },
+                "position": { "line": 4, "character": 28 },
+            }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let links: Vec<lsp_types::LocationLink> =
+            serde_json::from_value(res.result.unwrap()).unwrap();
+        assert_eq!(1, links.len());
+        let link = &links[0];
+        assert_eq!(
+            Some(lsp_types::Range::new(
+                lsp_types::Position::new(4, 25),
+                lsp_types::Position::new(4, 36)
+            )),
+            link.origin_selection_range
+        );
+        assert_eq!(create_uri("testdata/my_interface.mojom"), link.target_uri);
+        assert_eq!(
+            lsp_types::Range::new(
+                lsp_types::Position::new(3, 10),
+                lsp_types::Position::new(3, 21)
+            ),
+            link.target_selection_range
+        );
+    }
+
+    #[test]
+    fn test_response_to_server_request() {
+        use std::sync::{Arc, Mutex};
+
+        let (mut ctx, mut r) = create_test_context();
+        let received = Arc::new(Mutex::new(None));
+        let received_clone = received.clone();
+        send_request(
+            &mut ctx,
+            "workspace/configuration",
+            serde_json::json!({ "items": [] }),
+            Box::new(move |_ctx, res| *received_clone.lock().unwrap() = Some(res)),
+        );
+        let req = match read_message(&mut r).unwrap() {
+            protocol::Message::Request(req) => req,
+            msg => panic!("Expected a request but got {:?}", msg),
+        };
+        assert_eq!("workspace/configuration", req.method);
+
+        // Unknown IDs are ignored.
+        let res = ResponseMessage {
+            id: RequestId::String("unknown".to_owned()),
+            result: Some(serde_json::json!([])),
+            error: None,
+        };
+        handle_response(&mut ctx, res);
+        assert!(received.lock().unwrap().is_none());
+
+        let res = ResponseMessage {
+            id: req.id.clone(),
+            result: Some(serde_json::json!([{ "diagnosticsMode": "off" }])),
+            error: None,
+        };
+        handle_response(&mut ctx, res);
+        let res = received.lock().unwrap().take().unwrap();
+        assert_eq!(
+            serde_json::json!([{ "diagnosticsMode": "off" }]),
+            res.unwrap()
+        );
+        assert!(ctx.pending_requests.is_empty());
+    }
+
+    #[test]
+    fn test_workspace_configuration() {
+        let (mut ctx, mut r) = create_test_context();
+        let did_change_configuration = || NotificationMessage {
+            method: DidChangeConfiguration::METHOD.to_owned(),
+            params: serde_json::json!({ "settings": {} }),
+        };
+        // Clients which don't support `workspace/configuration` aren't asked.
+        handle_notification(&mut ctx, did_change_configuration()).unwrap();
+        assert!(ctx.pending_requests.is_empty());
+
+        ctx.client_capabilities =
+            serde_json::from_value(serde_json::json!({ "workspace": { "configuration": true } }))
+                .unwrap();
+        handle_notification(&mut ctx, did_change_configuration()).unwrap();
+        let req = match read_message(&mut r).unwrap() {
+            protocol::Message::Request(req) => req,
+            msg => panic!("Expected a request but got {:?}", msg),
+        };
+        assert_eq!(WorkspaceConfiguration::METHOD, req.method);
+        assert_eq!(
+            serde_json::json!({ "items": [{ "section": "mojom" }] }),
+            req.params
+        );
+
+        let res = ResponseMessage {
+            id: req.id,
+            result: Some(serde_json::json!([{ "diagnosticsMode": "off" }])),
+            error: None,
+        };
+        handle_response(&mut ctx, res);
+        assert_eq!(DiagnosticsMode::Off, ctx.base_settings.diagnostics_mode);
+        // The configuration takes precedence over project config files.
+        assert_eq!(
+            serde_json::json!({ "diagnosticsMode": "off" }),
+            ctx.initialization_options
+        );
+    }
+
+    #[test]
+    fn test_disabled_request() {
+        let (mut ctx, mut r) = create_test_context();
+        let params = serde_json::json!({
+            "textDocument": { "uri": create_uri("testdata/my_interface.mojom") },
+            "position": { "line": 3, "character": 12 },
+        });
+
+        // References isn't enabled.
+        ctx.capabilities.references_provider = None;
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: References::METHOD.to_owned(),
+            params: params.clone(),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        let code: i32 = ErrorCodes::MethodNotFound.into();
+        assert_eq!(code, res.error.unwrap().code);
+
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: GotoDefinition::METHOD.to_owned(),
+            params: params,
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(2), res.id);
+        assert!(res.error.is_none());
+
+        // Documents aren't formatted on save unless it's advertised.
+        ctx.capabilities.text_document_sync = None;
+        let msg = RequestMessage {
+            id: RequestId::Number(3),
+            method: WillSaveWaitUntil::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": create_uri("testdata/my_interface.mojom") },
+                "reason": 1,
+            }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(3), res.id);
+        assert_eq!(code, res.error.unwrap().code);
+    }
+
+    #[test]
+    fn test_workspace_symbol_build_progress() {
+        let root = PathBuf::from("testdata/workspace").canonicalize().unwrap();
+        let (mut ctx, mut r) = create_test_context_with_root(root.clone(), Settings::default());
+        ctx.workspace_folders = vec![root];
+        ctx.client_capabilities_json =
+            serde_json::json!({ "window": { "workDoneProgress": true } });
+        start_workspace_symbol_build(&mut ctx);
+
+        let req = match read_message(&mut r).unwrap() {
+            protocol::Message::Request(req) => req,
+            msg => panic!("Expected a request but got {:?}", msg),
+        };
+        assert_eq!("window/workDoneProgress/create", req.method);
+        let token = req.params["token"].clone();
+        let mut values = Vec::new();
+        loop {
+            let msg = read_notification(&mut r);
+            assert_eq!("$/progress", msg.method);
+            assert_eq!(token, msg.params["token"]);
+            let value = msg.params["value"].clone();
+            let kind = value["kind"].as_str().unwrap().to_owned();
+            values.push(value);
+            if kind == "end" {
+                break;
+            }
+        }
+        assert_eq!("begin", values[0]["kind"]);
+        // The file count isn't known while directories are walked.
+        assert_eq!("Found 4 files", values[1]["message"]);
+        assert!(values[1].get("percentage").is_none());
+        assert_eq!(0, values[2]["percentage"]);
+        assert_eq!(100, values[values.len() - 2]["percentage"]);
+
+        // Without client support, the scan isn't reported.
+        ctx.client_capabilities_json = Value::Null;
+        start_workspace_symbol_build(&mut ctx);
+        ctx.workspace_symbols.wait_for_build();
+        assert_eq!(1, ctx.pending_requests.len());
+    }
+
+    #[test]
+    fn test_workspace_symbol_work_done_token() {
+        let root = PathBuf::from("testdata/workspace").canonicalize().unwrap();
+        let (mut ctx, mut r) = create_test_context_with_root(root.clone(), Settings::default());
+        ctx.workspace_folders = vec![root];
+        // The client supports work done progress but provides its own token.
+        ctx.client_capabilities_json =
+            serde_json::json!({ "window": { "workDoneProgress": true } });
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: WorkspaceSymbol::METHOD.to_owned(),
+            params: serde_json::json!({ "query": "Point", "workDoneToken": "symbols" }),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let mut kinds = Vec::new();
+        let res = loop {
+            match read_message(&mut r).unwrap() {
+                protocol::Message::Notofication(msg) if msg.method == "$/progress" => {
+                    assert_eq!("symbols", msg.params["token"]);
+                    kinds.push(msg.params["value"]["kind"].as_str().unwrap().to_owned());
+                }
+                protocol::Message::Response(res) => break res,
+                msg => panic!("Unexpected message: {:?}", msg),
+            }
+        };
+        assert_eq!(Some("begin"), kinds.first().map(String::as_str));
+        assert_eq!(Some("end"), kinds.last().map(String::as_str));
+        // The scan finished before the response.
+        let symbols = res.result.unwrap();
+        assert_eq!("Point", symbols[0]["name"]);
+        assert!(ctx.pending_requests.is_empty());
+    }
+
+    #[test]
+    fn test_references_partial_results() {
+        let (mut ctx, mut r) = create_test_context();
+        let params = serde_json::json!({
+            "textDocument": { "uri": create_uri("testdata/my_interface.mojom") },
+            "position": { "line": 3, "character": 12 },
+            "context": { "includeDeclaration": true },
+        });
+
+        // Without a token, all references are in the response.
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: References::METHOD.to_owned(),
+            params: params.clone(),
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(2, res.result.unwrap().as_array().unwrap().len());
+
+        // With a token, references are reported per file.
+        let mut params_with_token = params.clone();
+        params_with_token["partialResultToken"] = serde_json::json!("refs");
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: References::METHOD.to_owned(),
+            params: params_with_token,
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let mut batches = Vec::new();
+        let res = loop {
+            match read_message(&mut r).unwrap() {
+                protocol::Message::Notofication(msg) if msg.method == "$/progress" => {
+                    assert_eq!("refs", msg.params["token"]);
+                    batches.push(msg.params["value"].as_array().unwrap().len());
+                }
+                protocol::Message::Response(res) => break res,
+                _ => continue,
+            }
+        };
+        assert_eq!(vec![1, 1], batches);
+        assert_eq!(serde_json::json!([]), res.result.unwrap());
+
+        // Cancelled while running.
+        ctx.cancellations
+            .lock()
+            .unwrap()
+            .insert(RequestId::Number(3));
+        let msg = RequestMessage {
+            id: RequestId::Number(3),
+            method: References::METHOD.to_owned(),
+            params: params,
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        let code: i32 = ErrorCodes::RequestCancelled.into();
+        assert_eq!(code, res.error.unwrap().code);
+    }
+
+    #[test]
+    fn test_unknown_methods() {
+        let (reader, mut writer) = pipe();
+        let (r, w) = pipe();
+        let server = Server::new(Settings::default()).root_path(PathBuf::from("testdata"));
+        let handle = std::thread::spawn(move || server.run(reader, w));
+        let mut r = BufReader::new(r);
+
+        write_request(&mut writer, RequestId::Number(1), "foo/bar", Value::Null).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        let code: i32 = ErrorCodes::MethodNotFound.into();
+        assert_eq!(code, res.error.unwrap().code);
+
+        // The server keeps running after an unknown notification.
+        write_notification(&mut writer, "foo/baz", Value::Null).unwrap();
+        write_request(
+            &mut writer,
+            RequestId::Number(2),
+            Shutdown::METHOD,
+            Value::Null,
+        )
+        .unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(2), res.id);
+        assert!(res.error.is_none());
+        write_notification(&mut writer, Exit::METHOD, Value::Null).unwrap();
+        assert_eq!(0, handle.join().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_request_panic() {
+        let (mut ctx, mut r) = create_test_context();
+        let msg = RequestMessage {
+            id: RequestId::Number(1),
+            method: PANIC_FOR_TEST_METHOD.to_owned(),
+            params: Value::Null,
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        let err = res.error.unwrap();
+        let code: i32 = ErrorCodes::InternalError.into();
+        assert_eq!(code, err.code);
+        // Panic details aren't sent to the client.
+        assert!(!err.message.contains("Deliberate panic"));
+
+        // The server keeps handling requests.
+        let msg = RequestMessage {
+            id: RequestId::Number(2),
+            method: Shutdown::METHOD.to_owned(),
+            params: Value::Null,
+        };
+        handle_request(&mut ctx, msg).unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(2), res.id);
+        assert!(res.error.is_none());
+    }
+
+    #[test]
+    fn test_notification_panic() {
+        let (mut ctx, _r) = create_test_context();
+        let msg = NotificationMessage {
+            method: PANIC_FOR_TEST_METHOD.to_owned(),
+            params: Value::Null,
+        };
+        assert!(handle_notification(&mut ctx, msg).is_ok());
+        assert!(ctx.exit_code.is_none());
+    }
+
+    #[test]
+    fn test_diagnostics_thread_panic() {
+        let mut settings = Settings::default();
+        settings.diagnostics_mode = DiagnosticsMode::Off;
+        let (mut ctx, _r) = create_test_context_with_settings(settings);
+        let uri = Uri::parse("file:///foo.mojom").unwrap();
+        let text = "struct Foo {};\nstruct Bar {\n  Foo foo;\n};";
+        let msg = NotificationMessage {
+            method: DidOpenTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "mojom",
+                    "version": 1,
+                    "text": text,
+                },
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+
+        // The requester gets an empty result.
+        assert!(ctx.diag.panic_for_test().is_empty());
+
+        // The thread keeps handling messages.
+        let msg = NotificationMessage {
+            method: DidChangeTextDocument::METHOD.to_owned(),
+            params: serde_json::json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": text }],
+            }),
+        };
+        handle_notification(&mut ctx, msg).unwrap();
+        let hover = ctx.diag.hover(uri.clone(), lsp_types::Position::new(2, 2));
+        assert!(hover.is_some());
+    }
+
+    #[test]
+    fn test_start_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || start_tcp_with_listener(listener));
+
+        let stream = std::net::TcpStream::connect(addr).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut r = BufReader::new(stream);
+        let params = serde_json::json!({
+            "processId": null,
+            "rootUri": null,
+            "capabilities": {},
+        });
+        write_request(
+            &mut writer,
+            RequestId::Number(1),
+            Initialize::METHOD,
+            params,
+        )
+        .unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(1), res.id);
+        let res: lsp_types::InitializeResult = serde_json::from_value(res.result.unwrap()).unwrap();
+        assert_eq!(Some(true), res.capabilities.hover_provider);
+
+        write_notification(&mut writer, Initialized::METHOD, Value::Null).unwrap();
+        write_request(
+            &mut writer,
+            RequestId::Number(2),
+            Shutdown::METHOD,
+            Value::Null,
+        )
+        .unwrap();
+        let res = read_response(&mut r);
+        assert_eq!(RequestId::Number(2), res.id);
+        write_notification(&mut writer, Exit::METHOD, Value::Null).unwrap();
+
+        assert_eq!(0, handle.join().unwrap().unwrap());
+    }
 
     #[test]
     fn test_server_init() {
@@ -294,7 +3147,7 @@ mod tests {
 
         write_request(
             &mut writer,
-            1,
+            RequestId::Number(1),
             lsp_types::request::Initialize::METHOD,
             params,
         )
@@ -304,7 +3157,7 @@ mod tests {
         let msg = read_message(&mut r).unwrap();
         match msg {
             protocol::Message::Response(msg) => {
-                assert_eq!(1, msg.id);
+                assert_eq!(RequestId::Number(1), msg.id);
             }
             _ => unreachable!(),
         }
@@ -318,7 +3171,7 @@ mod tests {
 
         write_request(
             &mut writer,
-            2,
+            RequestId::Number(2),
             lsp_types::request::Shutdown::METHOD,
             serde_json::Value::Null,
         )
@@ -327,7 +3180,7 @@ mod tests {
         let msg = read_message(&mut r).unwrap();
         match msg {
             protocol::Message::Response(msg) => {
-                assert_eq!(2, msg.id);
+                assert_eq!(RequestId::Number(2), msg.id);
             }
             _ => unreachable!(),
         }
@@ -345,4 +3198,21 @@ mod tests {
         let status = handle.join().unwrap();
         assert!(status.is_ok());
     }
+
+    #[test]
+    fn test_exit_before_initialize() {
+        let (reader, mut writer) = pipe();
+        let (_r, w) = pipe();
+        let handle = std::thread::spawn(move || start(reader, w));
+
+        write_notification(
+            &mut writer,
+            lsp_types::notification::Exit::METHOD,
+            serde_json::Value::Null,
+        )
+        .unwrap();
+
+        let status = handle.join().unwrap();
+        assert_eq!(1, status.unwrap());
+    }
 }
@@ -0,0 +1,307 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Controls when diagnostics are published.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticsMode {
+    /// Publish diagnostics whenever a document is opened, changed or saved.
+    OnChange,
+    /// Publish diagnostics only when a document is opened or saved.
+    OnSave,
+    /// Never publish diagnostics.
+    Off,
+}
+
+/// How documents are formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FormatMode {
+    /// Reformat whole documents in the canonical style.
+    Canonical,
+    /// Only normalize indentation and trailing whitespaces.
+    WhitespaceOnly,
+}
+
+/// Naming conventions for identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NamingConvention {
+    /// e.g. `result_code`
+    SnakeCase,
+    /// e.g. `resultCode`
+    CamelCase,
+}
+
+impl NamingConvention {
+    /// Converts `name` to follow this convention.
+    pub fn apply(&self, name: &str) -> String {
+        let mut converted = String::new();
+        match self {
+            NamingConvention::SnakeCase => {
+                for (i, ch) in name.char_indices() {
+                    if ch.is_ascii_uppercase() && i > 0 && !converted.ends_with('_') {
+                        converted.push('_');
+                    }
+                    converted.push(ch.to_ascii_lowercase());
+                }
+            }
+            NamingConvention::CamelCase => {
+                let mut upper = false;
+                for ch in name.chars() {
+                    if ch == '_' && !converted.is_empty() {
+                        upper = true;
+                    } else if upper {
+                        converted.push(ch.to_ascii_uppercase());
+                        upper = false;
+                    } else if converted.is_empty() {
+                        converted.push(ch.to_ascii_lowercase());
+                    } else {
+                        converted.push(ch);
+                    }
+                }
+            }
+        }
+        converted
+    }
+}
+
+/// Server settings. Clients can specify these as `initializationOptions` in
+/// the `initialize` request. Projects can also specify these in a config
+/// file. See config.rs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Directories which contain generated bindings, relative to the root
+    /// path.
+    pub generated_dirs: Vec<PathBuf>,
+    pub diagnostics_mode: DiagnosticsMode,
+    /// Warn when a method has more parameters than this.
+    pub max_method_params: usize,
+    /// Warn when a method ordinal is larger than this.
+    pub max_method_ordinal: u32,
+    /// Hint empty interface and struct declarations.
+    pub lint_empty_declarations: bool,
+    /// Exit when the client process (`processId` in the `initialize` request)
+    /// is gone.
+    pub watch_parent_process: bool,
+    /// Skip analyzing documents larger than this. `None` means no limit.
+    pub max_file_size_bytes: Option<u64>,
+    /// Additional directories to resolve imports against. Relative paths are
+    /// relative to the root path.
+    pub include_dirs: Vec<PathBuf>,
+    /// Hint lines longer than this, in UTF-16 code units. `None` disables the
+    /// lint.
+    pub max_line_length: Option<usize>,
+    /// Hint response parameters which don't follow this convention. `None`
+    /// disables the lint.
+    pub response_param_naming: Option<NamingConvention>,
+    pub format_mode: FormatMode,
+    /// Reorder struct fields by their ordinals when formatting in the
+    /// canonical style. Structs which have fields without ordinals are left
+    /// untouched.
+    pub sort_struct_fields_by_ordinal: bool,
+    /// The number of blank lines between top-level declarations when
+    /// formatting in the canonical style.
+    pub blank_lines_between_declarations: usize,
+    /// Format documents when they are saved. Requires clients which support
+    /// `textDocument/willSaveWaitUntil`.
+    pub format_on_save: bool,
+    /// Complete declarations and nested modules after a module name followed
+    /// by a dot, e.g. `foo.bar.`.
+    pub complete_module_paths: bool,
+    /// Paths which aren't scanned when the server walks workspace folders,
+    /// relative to each folder. `*` matches any characters in a path
+    /// component and `**` matches any number of directories.
+    pub exclude_globs: Vec<String>,
+    /// Skip messages from the client whose body is larger than this, in
+    /// bytes.
+    pub max_content_length: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            generated_dirs: vec![PathBuf::from("out/Default/gen")],
+            diagnostics_mode: DiagnosticsMode::OnChange,
+            max_method_params: 16,
+            max_method_ordinal: 1024,
+            lint_empty_declarations: false,
+            watch_parent_process: true,
+            max_file_size_bytes: None,
+            include_dirs: Vec::new(),
+            max_line_length: None,
+            response_param_naming: None,
+            format_mode: FormatMode::Canonical,
+            sort_struct_fields_by_ordinal: false,
+            blank_lines_between_declarations: 1,
+            format_on_save: false,
+            complete_module_paths: true,
+            exclude_globs: Vec::new(),
+            max_content_length: super::protocol::MAX_CONTENT_LENGTH,
+        }
+    }
+}
+
+pub(crate) fn from_initialization_options(options: Option<Value>) -> Settings {
+    let options = match options {
+        Some(options) => options,
+        None => return Settings::default(),
+    };
+    match serde_json::from_value::<Settings>(options) {
+        Ok(settings) => settings,
+        Err(err) => {
+            log::warn!("Invalid initialization options: {}", err);
+            Settings::default()
+        }
+    }
+}
+
+/// Applies a project config on top of `base`. `initialization_options` take
+/// precedence over the config. Invalid values are reported and ignored.
+pub(crate) fn with_config(
+    base: &Settings,
+    config: &Value,
+    initialization_options: &Value,
+) -> Settings {
+    let mut settings = base.clone();
+    for overrides in &[config, initialization_options] {
+        let overrides = match overrides.as_object() {
+            Some(overrides) => overrides,
+            None => continue,
+        };
+        for (key, value) in overrides {
+            let mut merged = serde_json::to_value(&settings).unwrap();
+            merged
+                .as_object_mut()
+                .unwrap()
+                .insert(key.clone(), value.clone());
+            match serde_json::from_value::<Settings>(merged) {
+                Ok(merged) => settings = merged,
+                Err(err) => log::warn!("Invalid config `{}`: {}", key, err),
+            }
+        }
+    }
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_initialization_options() {
+        let settings = from_initialization_options(None);
+        assert_eq!(
+            vec![PathBuf::from("out/Default/gen")],
+            settings.generated_dirs
+        );
+
+        let options = serde_json::json!({ "generatedDirs": ["out/Release/gen"] });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(
+            vec![PathBuf::from("out/Release/gen")],
+            settings.generated_dirs
+        );
+
+        let options = serde_json::json!({ "diagnosticsMode": "onSave" });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(DiagnosticsMode::OnSave, settings.diagnostics_mode);
+
+        let options = serde_json::json!({ "maxMethodParams": 4, "maxMethodOrdinal": 64 });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(4, settings.max_method_params);
+        assert_eq!(64, settings.max_method_ordinal);
+
+        let options = serde_json::json!({ "formatMode": "whitespaceOnly" });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(FormatMode::WhitespaceOnly, settings.format_mode);
+        assert!(!settings.format_on_save);
+
+        let options = serde_json::json!({ "formatOnSave": true });
+        let settings = from_initialization_options(Some(options));
+        assert!(settings.format_on_save);
+        assert!(settings.complete_module_paths);
+
+        let options = serde_json::json!({ "completeModulePaths": false });
+        let settings = from_initialization_options(Some(options));
+        assert!(!settings.complete_module_paths);
+
+        let options = serde_json::json!({ "maxFileSizeBytes": 1024 });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(Some(1024), settings.max_file_size_bytes);
+
+        let options = serde_json::json!({ "maxContentLength": 1024 });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(1024, settings.max_content_length);
+
+        let options = serde_json::json!({ "generatedDirs": 42 });
+        let settings = from_initialization_options(Some(options));
+        assert_eq!(
+            vec![PathBuf::from("out/Default/gen")],
+            settings.generated_dirs
+        );
+    }
+
+    #[test]
+    fn test_naming_convention() {
+        let snake = NamingConvention::SnakeCase;
+        assert_eq!("result_code", snake.apply("resultCode"));
+        assert_eq!("result_code", snake.apply("ResultCode"));
+        assert_eq!("result_code", snake.apply("result_code"));
+        assert_eq!("url", snake.apply("url"));
+
+        let camel = NamingConvention::CamelCase;
+        assert_eq!("resultCode", camel.apply("result_code"));
+        assert_eq!("resultCode", camel.apply("ResultCode"));
+        assert_eq!("resultCode", camel.apply("resultCode"));
+        assert_eq!("_private", camel.apply("_private"));
+    }
+
+    #[test]
+    fn test_with_config() {
+        let base = Settings::default();
+        let config = serde_json::json!({
+            "includeDirs": ["/src/third_party"],
+            "lintEmptyDeclarations": true,
+            "maxMethodParams": 4,
+        });
+        let options = serde_json::json!({ "maxMethodParams": 8 });
+        let settings = with_config(&base, &config, &options);
+        assert_eq!(
+            vec![PathBuf::from("/src/third_party")],
+            settings.include_dirs
+        );
+        assert!(settings.lint_empty_declarations);
+        assert_eq!(8, settings.max_method_params);
+
+        let config = serde_json::json!({ "maxMethodParams": "many" });
+        let settings = with_config(&base, &config, &Value::Null);
+        assert_eq!(base, settings);
+
+        // Valid keys are kept even if another key is invalid.
+        let config = serde_json::json!({
+            "lintEmptyDeclarations": true,
+            "maxMethodParams": "many",
+        });
+        let settings = with_config(&base, &config, &Value::Null);
+        assert!(settings.lint_empty_declarations);
+        assert_eq!(base.max_method_params, settings.max_method_params);
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Watches the client process and exits the server when it disappears.
+// https://microsoft.github.io/language-server-protocol/specification#initialize
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Checks whether a process is still running.
+pub(crate) trait LivenessProbe: Send {
+    fn is_alive(&mut self, pid: u64) -> bool;
+}
+
+/// Asks the OS whether a process exists.
+pub(crate) struct ProcessProbe;
+
+impl LivenessProbe for ProcessProbe {
+    #[cfg(target_os = "linux")]
+    fn is_alive(&mut self, pid: u64) -> bool {
+        std::path::Path::new(&format!("/proc/{}", pid)).exists()
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    fn is_alive(&mut self, pid: u64) -> bool {
+        // `kill -0` doesn't send a signal but checks that the process exists.
+        std::process::Command::new("kill")
+            .arg("-0")
+            .arg(pid.to_string())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(true)
+    }
+
+    #[cfg(windows)]
+    fn is_alive(&mut self, pid: u64) -> bool {
+        let output = std::process::Command::new("tasklist")
+            .arg("/NH")
+            .arg("/FI")
+            .arg(format!("PID eq {}", pid))
+            .output();
+        match output {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()),
+            // Assume the process is alive when we can't tell.
+            Err(_) => true,
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn is_alive(&mut self, _pid: u64) -> bool {
+        true
+    }
+}
+
+pub(crate) const WATCH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Starts a thread which polls `probe` every `interval` and calls `on_exit`
+/// once the process `pid` is gone.
+pub(crate) fn start_parent_watchdog<P, F>(
+    pid: u64,
+    mut probe: P,
+    interval: Duration,
+    on_exit: F,
+) -> JoinHandle<()>
+where
+    P: LivenessProbe + 'static,
+    F: FnOnce() + Send + 'static,
+{
+    thread::spawn(move || {
+        while probe.is_alive(pid) {
+            thread::sleep(interval);
+        }
+        log::info!("Parent process {} is gone", pid);
+        on_exit();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::channel;
+
+    // Reports that the process is alive `remaining` more times.
+    struct MockProbe {
+        remaining: usize,
+    }
+
+    impl LivenessProbe for MockProbe {
+        fn is_alive(&mut self, _pid: u64) -> bool {
+            if self.remaining == 0 {
+                return false;
+            }
+            self.remaining -= 1;
+            true
+        }
+    }
+
+    #[test]
+    fn test_parent_watchdog() {
+        let (sender, receiver) = channel();
+        let probe = MockProbe { remaining: 2 };
+        let handle = start_parent_watchdog(42, probe, Duration::from_millis(1), move || {
+            sender.send(()).unwrap();
+        });
+        handle.join().unwrap();
+        assert!(receiver.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_process_probe() {
+        let mut probe = ProcessProbe;
+        assert!(probe.is_alive(std::process::id() as u64));
+    }
+}
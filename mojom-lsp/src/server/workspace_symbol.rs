@@ -0,0 +1,647 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use lsp_types::{SymbolInformation, SymbolKind, Url as Uri};
+use serde_json::Value;
+
+use super::diagnostic::walk_mojom_files;
+use super::document_symbol::{document_symbols, flatten_symbols};
+
+// The maximum number of symbols returned for a query. Clients show results
+// as the user types, so an empty query shouldn't return the whole workspace.
+const MAX_WORKSPACE_SYMBOLS: usize = 100;
+
+// The number of files found between progress reports while directories are
+// walked.
+const FOUND_REPORT_INTERVAL: usize = 100;
+
+/// Progress of a scan. Directories are walked before files are indexed, so
+/// the total number of files is unknown until indexing starts.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ScanProgress {
+    Begin,
+    /// The number of files found so far.
+    Found(usize),
+    Indexed {
+        indexed: usize,
+        total: usize,
+    },
+    End,
+}
+
+impl ScanProgress {
+    /// Returns the value of a `$/progress` notification for work done
+    /// progress. `percentage` is left out until the total is known.
+    pub(crate) fn to_work_done_progress(&self) -> Value {
+        match self {
+            ScanProgress::Begin => serde_json::json!({
+                "kind": "begin",
+                "title": "Indexing mojom files",
+            }),
+            ScanProgress::Found(found) => serde_json::json!({
+                "kind": "report",
+                "message": format!("Found {} files", found),
+            }),
+            ScanProgress::Indexed { indexed, total } => {
+                let percentage = if *total == 0 {
+                    100
+                } else {
+                    indexed * 100 / total
+                };
+                serde_json::json!({
+                    "kind": "report",
+                    "message": format!("{}/{} files", indexed, total),
+                    "percentage": percentage,
+                })
+            }
+            ScanProgress::End => serde_json::json!({ "kind": "end" }),
+        }
+    }
+}
+
+/// Receives progress of a scan in the scanning thread.
+pub(crate) type ProgressReporter = Box<dyn FnMut(ScanProgress) + Send>;
+
+// Where a running scan reports its progress. Shared with the index so that
+// a request can take over the progress.
+#[derive(Default)]
+struct ScanReporter {
+    reporter: Option<ProgressReporter>,
+    // The last report other than `Begin` and `End`.
+    last: Option<ScanProgress>,
+}
+
+impl ScanReporter {
+    fn report(&mut self, progress: ScanProgress) {
+        match progress {
+            ScanProgress::Begin => (),
+            ScanProgress::End => {
+                if let Some(mut reporter) = self.reporter.take() {
+                    reporter(ScanProgress::End);
+                }
+                return;
+            }
+            _ => self.last = Some(progress.clone()),
+        }
+        if let Some(reporter) = self.reporter.as_mut() {
+            reporter(progress);
+        }
+    }
+
+    // Ends the progress of the current reporter, and reports to `reporter`
+    // from the last report.
+    fn replace(&mut self, mut reporter: ProgressReporter) {
+        if let Some(mut previous) = self.reporter.take() {
+            previous(ScanProgress::End);
+        }
+        reporter(ScanProgress::Begin);
+        if let Some(last) = self.last.clone() {
+            reporter(last);
+        }
+        self.reporter = Some(reporter);
+    }
+}
+
+// A searchable declaration.
+struct Declaration {
+    // The module and enclosing declarations, e.g. `foo.bar.Baz` for `Kind` in
+    // `interface Baz` of `module foo.bar`. Empty when there are none.
+    scope: String,
+    symbol: SymbolInformation,
+}
+
+type Declarations = HashMap<Uri, Vec<Declaration>>;
+
+enum BuildState {
+    NotStarted,
+    // Files are being scanned in a background thread.
+    Building(Receiver<Declarations>, Arc<Mutex<ScanReporter>>),
+    Done,
+}
+
+/// Declarations in mojom files under the workspace roots. Files are scanned
+/// in a background thread so that large workspaces don't block other
+/// requests. Until the scan finishes, only opened documents are searched.
+pub(crate) struct WorkspaceSymbolIndex {
+    state: BuildState,
+    // Declarations in files on the disk.
+    files: Declarations,
+    // Declarations in opened documents, which may have unsaved changes. They
+    // take precedence over `files`.
+    documents: Declarations,
+}
+
+impl Default for WorkspaceSymbolIndex {
+    fn default() -> Self {
+        WorkspaceSymbolIndex {
+            state: BuildState::NotStarted,
+            files: HashMap::new(),
+            documents: HashMap::new(),
+        }
+    }
+}
+
+// Returns the key of `uri` in the index. Clients may open a file with a URI
+// which differs from the path found by scanning, e.g. through a symbolic
+// link, so keys are canonical paths.
+fn index_key(uri: &Uri) -> Uri {
+    uri.to_file_path()
+        .ok()
+        .and_then(|path| path.canonicalize().ok())
+        .and_then(|path| Uri::from_file_path(path).ok())
+        .unwrap_or_else(|| uri.clone())
+}
+
+// Walks all roots before indexing files so that progress of indexing can be
+// reported in percentage.
+fn scan_files(
+    roots: &[PathBuf],
+    exclude_globs: &[String],
+    report: &mut dyn FnMut(ScanProgress),
+) -> Declarations {
+    let mut paths = Vec::new();
+    for root in roots {
+        walk_mojom_files(root, exclude_globs, &mut |path| {
+            paths.push(path);
+            if paths.len() % FOUND_REPORT_INTERVAL == 0 {
+                report(ScanProgress::Found(paths.len()));
+            }
+        });
+        report(ScanProgress::Found(paths.len()));
+    }
+
+    let total = paths.len();
+    let mut files = HashMap::new();
+    let mut reported_percentage = None;
+    for (indexed, path) in paths.iter().enumerate() {
+        let percentage = indexed * 100 / total;
+        if reported_percentage != Some(percentage) {
+            reported_percentage = Some(percentage);
+            report(ScanProgress::Indexed {
+                indexed: indexed,
+                total: total,
+            });
+        }
+        let uri = match path
+            .canonicalize()
+            .ok()
+            .and_then(|path| Uri::from_file_path(path).ok())
+        {
+            Some(uri) => uri,
+            None => continue,
+        };
+        if let Ok(text) = std::fs::read_to_string(path) {
+            files.insert(uri.clone(), declarations(&uri, &text));
+        }
+    }
+    report(ScanProgress::Indexed {
+        indexed: total,
+        total: total,
+    });
+    files
+}
+
+// Returns declarations in `text` which can be searched in the workspace.
+// Members like fields and methods aren't included.
+fn declarations(uri: &Uri, text: &str) -> Vec<Declaration> {
+    let symbols = flatten_symbols(uri, document_symbols(uri.clone(), text.to_owned()));
+    let module = symbols
+        .iter()
+        .find(|symbol| symbol.kind == SymbolKind::Module)
+        .map(|symbol| symbol.name.clone());
+    symbols
+        .into_iter()
+        .filter(|symbol| {
+            matches!(
+                symbol.kind,
+                SymbolKind::Interface
+                    | SymbolKind::Struct
+                    | SymbolKind::Enum
+                    | SymbolKind::Constant
+            )
+        })
+        .map(|symbol| Declaration {
+            scope: module
+                .iter()
+                .chain(symbol.container_name.iter())
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>()
+                .join("."),
+            symbol: symbol,
+        })
+        .collect()
+}
+
+// Returns true when `scope` ends with the components of `query_scope`, e.g.
+// `foo.bar` ends with `bar` but not with `ar`.
+fn scope_matches(scope: &str, query_scope: &str) -> bool {
+    scope == query_scope
+        || scope
+            .strip_suffix(query_scope)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// Same as match_score() but a dotted query like `foo.bar.Baz` only matches
+// declarations in the scope `foo.bar` or a scope which ends with it.
+fn declaration_score(declaration: &Declaration, query: &str) -> Option<usize> {
+    match query.rsplit_once('.') {
+        Some((query_scope, name)) => {
+            if !scope_matches(&declaration.scope, query_scope) {
+                return None;
+            }
+            match_score(&declaration.symbol.name, name)
+        }
+        None => match_score(&declaration.symbol.name, query),
+    }
+}
+
+// Returns how well `name` matches `query`. Smaller is better. Returns None
+// when the characters of `query` don't appear in `name` in order. Matching
+// is case insensitive.
+fn match_score(name: &str, query: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    let query = query.to_lowercase();
+    if name.starts_with(&query) {
+        return Some(0);
+    }
+    if name.contains(&query) {
+        return Some(1);
+    }
+    let mut chars = name.chars();
+    if query.chars().all(|ch| chars.any(|c| c == ch)) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+impl WorkspaceSymbolIndex {
+    /// Starts scanning files under `roots` in a background thread. Paths
+    /// matching `exclude_globs` aren't indexed. The scan is reported to
+    /// `reporter` from the thread.
+    pub(crate) fn start_build(
+        &mut self,
+        roots: Vec<PathBuf>,
+        exclude_globs: Vec<String>,
+        reporter: Option<ProgressReporter>,
+    ) {
+        let (sender, receiver) = channel();
+        let scan_reporter = Arc::new(Mutex::new(ScanReporter {
+            reporter: reporter,
+            last: None,
+        }));
+        let thread_reporter = scan_reporter.clone();
+        std::thread::spawn(move || {
+            let mut report = |progress| thread_reporter.lock().unwrap().report(progress);
+            report(ScanProgress::Begin);
+            let files = scan_files(&roots, &exclude_globs, &mut report);
+            report(ScanProgress::End);
+            // The index may be dropped before the scan finishes.
+            let _ = sender.send(files);
+        });
+        self.state = BuildState::Building(receiver, scan_reporter);
+    }
+
+    // Takes the result of the scan if it has finished.
+    fn poll_build(&mut self) {
+        if let BuildState::Building(receiver, _) = &self.state {
+            match receiver.try_recv() {
+                Ok(files) => {
+                    self.files = files;
+                    self.state = BuildState::Done;
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => self.state = BuildState::Done,
+            }
+        }
+    }
+
+    // Blocks until the scan finishes.
+    pub(crate) fn wait_for_build(&mut self) {
+        if let BuildState::Building(receiver, scan_reporter) = &self.state {
+            if let Ok(files) = receiver.recv() {
+                self.files = files;
+            }
+            // The reporter may have been replaced after the scan ended.
+            scan_reporter.lock().unwrap().report(ScanProgress::End);
+            self.state = BuildState::Done;
+        }
+    }
+
+    /// Blocks until the scan finishes, reporting the progress to `reporter`.
+    /// The scan is started if it hasn't been. Nothing is reported when the
+    /// scan has finished already.
+    pub(crate) fn wait_for_build_with_progress(
+        &mut self,
+        roots: &[PathBuf],
+        exclude_globs: &[String],
+        reporter: ProgressReporter,
+    ) {
+        self.poll_build();
+        match &self.state {
+            BuildState::NotStarted => {
+                self.start_build(roots.to_vec(), exclude_globs.to_vec(), Some(reporter))
+            }
+            BuildState::Building(_, scan_reporter) => {
+                scan_reporter.lock().unwrap().replace(reporter)
+            }
+            BuildState::Done => return,
+        }
+        self.wait_for_build();
+    }
+
+    /// Re-indexes an opened document `uri` with `text`.
+    pub(crate) fn update(&mut self, uri: &Uri, text: &str) {
+        self.documents
+            .insert(index_key(uri), declarations(uri, text));
+    }
+
+    /// Re-indexes `uri` from the disk after the document is closed, possibly
+    /// without saving.
+    pub(crate) fn close(&mut self, uri: &Uri) {
+        let key = index_key(uri);
+        self.documents.remove(&key);
+        let text = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| std::fs::read_to_string(path).ok());
+        match text {
+            Some(text) => {
+                self.files.insert(key, declarations(uri, &text));
+            }
+            None => {
+                self.files.remove(&key);
+            }
+        }
+    }
+
+    /// Returns declarations whose names match `query`, best matches first.
+    /// A query with dots, e.g. `foo.bar.Baz`, is scoped to the module or the
+    /// declaration before the last dot. Starts scanning files under `roots`
+    /// on the first call unless `start_build()` was called.
+    pub(crate) fn query(
+        &mut self,
+        roots: &[PathBuf],
+        exclude_globs: &[String],
+        query: &str,
+    ) -> Vec<SymbolInformation> {
+        if let BuildState::NotStarted = self.state {
+            self.start_build(roots.to_vec(), exclude_globs.to_vec(), None);
+        }
+        self.poll_build();
+        let documents = &self.documents;
+        let files = self
+            .files
+            .iter()
+            .filter(|(uri, _)| !documents.contains_key(uri))
+            .map(|(_, symbols)| symbols);
+        let mut matches = documents
+            .values()
+            .chain(files)
+            .flatten()
+            .filter_map(|declaration| {
+                declaration_score(declaration, query).map(|score| (score, &declaration.symbol))
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|(score1, symbol1), (score2, symbol2)| {
+            let key1 = (score1, &symbol1.name, symbol1.location.uri.as_str());
+            let key2 = (score2, &symbol2.name, symbol2.location.uri.as_str());
+            key1.cmp(&key2)
+        });
+        matches
+            .into_iter()
+            .take(MAX_WORKSPACE_SYMBOLS)
+            .map(|(_, symbol)| symbol.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(symbols: &[SymbolInformation]) -> Vec<&str> {
+        symbols.iter().map(|symbol| symbol.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_match_score() {
+        assert_eq!(Some(0), match_score("MyInterface", "my"));
+        assert_eq!(Some(1), match_score("MyInterface", "INTER"));
+        assert_eq!(Some(2), match_score("MyInterface", "mif"));
+        assert_eq!(None, match_score("MyInterface", "fim"));
+        assert_eq!(Some(0), match_score("MyInterface", ""));
+    }
+
+    #[test]
+    fn test_workspace_symbols() {
+        let root = PathBuf::from("testdata/workspace").canonicalize().unwrap();
+        let point_uri = Uri::from_file_path(root.join("point.mojom")).unwrap();
+        let canvas_uri = Uri::from_file_path(root.join("sub/canvas.mojom")).unwrap();
+        let roots = vec![root.clone()];
+        let mut index = WorkspaceSymbolIndex::default();
+        index.start_build(roots.clone(), Vec::new(), None);
+        index.wait_for_build();
+
+        let symbols = index.query(&roots, &[], "Point");
+        assert_eq!(vec!["Point"], names(&symbols));
+        assert_eq!(point_uri, symbols[0].location.uri);
+        assert_eq!(2, symbols[0].location.range.start.line);
+
+        let symbols = index.query(&roots, &[], "cnvs");
+        assert_eq!(vec!["Canvas"], names(&symbols));
+        assert_eq!(canvas_uri, symbols[0].location.uri);
+
+        // Unsaved changes.
+        index.update(&canvas_uri, "interface Painter {};");
+        assert!(index.query(&roots, &[], "Canvas").is_empty());
+        assert_eq!(vec!["Painter"], names(&index.query(&roots, &[], "paint")));
+        index.close(&canvas_uri);
+        assert_eq!(vec!["Canvas"], names(&index.query(&roots, &[], "Canvas")));
+        assert!(index.query(&roots, &[], "paint").is_empty());
+    }
+
+    #[test]
+    fn test_workspace_symbols_opened_before_build() {
+        let root = PathBuf::from("testdata/workspace").canonicalize().unwrap();
+        let roots = vec![root.clone()];
+        let mut index = WorkspaceSymbolIndex::default();
+        // A non-canonical URI of a file which is also found by the scan.
+        let uri = Uri::from_file_path(root.join("sub/../point.mojom")).unwrap();
+        index.update(&uri, "struct Point {};\nstruct Unsaved {};");
+        index.start_build(roots.clone(), Vec::new(), None);
+        index.wait_for_build();
+
+        // No duplicates.
+        let symbols = index.query(&roots, &[], "Point");
+        assert_eq!(vec!["Point"], names(&symbols));
+        assert_eq!(uri, symbols[0].location.uri);
+        assert_eq!(vec!["Unsaved"], names(&index.query(&roots, &[], "Unsaved")));
+    }
+
+    #[test]
+    fn test_workspace_symbols_qualified_query() {
+        let mut index = WorkspaceSymbolIndex::default();
+        let uri1 = Uri::parse("file:///foo.mojom").unwrap();
+        index.update(
+            &uri1,
+            "module foo.bar;\nstruct Baz {};\ninterface Qux { enum Baz { kA }; };",
+        );
+        let uri2 = Uri::parse("file:///other.mojom").unwrap();
+        index.update(&uri2, "module other;\nstruct Baz {};");
+
+        assert_eq!(3, index.query(&[], &[], "Baz").len());
+
+        let symbols = index.query(&[], &[], "foo.bar.Baz");
+        assert_eq!(vec!["Baz"], names(&symbols));
+        assert_eq!(uri1, symbols[0].location.uri);
+        assert_eq!(1, symbols[0].location.range.start.line);
+
+        // Partially qualified names and nested declarations.
+        assert_eq!(1, index.query(&[], &[], "bar.Baz").len());
+        assert!(index.query(&[], &[], "ar.Baz").is_empty());
+        let symbols = index.query(&[], &[], "foo.bar.Qux.Baz");
+        assert_eq!(vec!["Baz"], names(&symbols));
+        assert_eq!(SymbolKind::Enum, symbols[0].kind);
+        let symbols = index.query(&[], &[], "other.");
+        assert_eq!(vec!["Baz"], names(&symbols));
+        assert_eq!(uri2, symbols[0].location.uri);
+    }
+
+    #[test]
+    fn test_workspace_symbols_exclude_globs() {
+        let roots = vec![PathBuf::from("testdata/exclude")];
+        let mut index = WorkspaceSymbolIndex::default();
+        index.start_build(roots.clone(), vec!["third_party".to_owned()], None);
+        index.wait_for_build();
+        let symbols = index.query(&roots, &[], "");
+        assert_eq!(vec!["A", "B"], names(&symbols));
+    }
+
+    #[test]
+    fn test_scan_progress() {
+        let roots = vec![PathBuf::from("testdata/workspace")];
+        let mut reports = Vec::new();
+        let files = scan_files(&roots, &[], &mut |progress| {
+            reports.push(progress.to_work_done_progress())
+        });
+        assert_eq!(4, files.len());
+
+        // Reports before the total is known have no percentage.
+        let expected = serde_json::json!({ "kind": "report", "message": "Found 4 files" });
+        assert_eq!(expected, reports[0]);
+        let percentages = reports[1..]
+            .iter()
+            .map(|report| report["percentage"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 25, 50, 75, 100], percentages);
+        assert_eq!("4/4 files", reports.last().unwrap()["message"]);
+
+        // No files to index.
+        let mut reports = Vec::new();
+        scan_files(
+            &[PathBuf::from("testdata/nonexistent")],
+            &[],
+            &mut |progress| reports.push(progress),
+        );
+        let expected = vec![
+            ScanProgress::Found(0),
+            ScanProgress::Indexed {
+                indexed: 0,
+                total: 0,
+            },
+        ];
+        assert_eq!(expected, reports);
+        assert_eq!(100, reports[1].to_work_done_progress()["percentage"]);
+    }
+
+    #[test]
+    fn test_wait_for_build_with_progress() {
+        let roots = vec![PathBuf::from("testdata/workspace")];
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reporter = |name: &'static str| -> ProgressReporter {
+            let reports = reports.clone();
+            Box::new(move |progress| reports.lock().unwrap().push((name, progress)))
+        };
+
+        let mut index = WorkspaceSymbolIndex::default();
+        index.wait_for_build_with_progress(&roots, &[], reporter("request"));
+        let reported = std::mem::take(&mut *reports.lock().unwrap());
+        assert!(reported.iter().all(|(name, _)| *name == "request"));
+        assert_eq!(ScanProgress::Begin, reported[0].1);
+        assert_eq!(ScanProgress::End, reported.last().unwrap().1);
+        assert_eq!(1, names(&index.query(&roots, &[], "Point")).len());
+
+        // Nothing to report after the scan.
+        index.wait_for_build_with_progress(&roots, &[], reporter("request"));
+        assert!(reports.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_reporter_replace() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reporter = |name: &'static str| -> ProgressReporter {
+            let reports = reports.clone();
+            Box::new(move |progress| reports.lock().unwrap().push((name, progress)))
+        };
+        let mut scan_reporter = ScanReporter {
+            reporter: Some(reporter("scan")),
+            last: None,
+        };
+        scan_reporter.report(ScanProgress::Begin);
+        scan_reporter.report(ScanProgress::Found(3));
+        // A request takes over from the last report.
+        scan_reporter.replace(reporter("request"));
+        scan_reporter.report(ScanProgress::Indexed {
+            indexed: 0,
+            total: 3,
+        });
+        scan_reporter.report(ScanProgress::End);
+        // The progress was ended already.
+        scan_reporter.report(ScanProgress::End);
+        let expected = vec![
+            ("scan", ScanProgress::Begin),
+            ("scan", ScanProgress::Found(3)),
+            ("scan", ScanProgress::End),
+            ("request", ScanProgress::Begin),
+            ("request", ScanProgress::Found(3)),
+            (
+                "request",
+                ScanProgress::Indexed {
+                    indexed: 0,
+                    total: 3,
+                },
+            ),
+            ("request", ScanProgress::End),
+        ];
+        assert_eq!(expected, *reports.lock().unwrap());
+    }
+
+    #[test]
+    fn test_workspace_symbols_empty_query() {
+        let text = (0..MAX_WORKSPACE_SYMBOLS + 10)
+            .map(|i| format!("struct S{} {{}};\n", i))
+            .collect::<String>();
+        let uri = Uri::parse("file:///many.mojom").unwrap();
+        let mut index = WorkspaceSymbolIndex::default();
+        index.update(&uri, &text);
+        let symbols = index.query(&[], &[], "");
+        assert_eq!(MAX_WORKSPACE_SYMBOLS, symbols.len());
+    }
+}
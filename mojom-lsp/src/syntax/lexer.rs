@@ -0,0 +1,404 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use super::syntax::Range;
+
+/// Kinds of lexemes. Everything which isn't a comment or a string literal is
+/// `Code`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LexemeKind {
+    /// `// ...` up to, but not including, the line break.
+    LineComment,
+    /// `/* ... */`. Unterminated block comments extend to the end of the text.
+    BlockComment,
+    /// `"..."`. Unterminated string literals end at the line break.
+    String,
+    Code,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexeme {
+    pub kind: LexemeKind,
+    pub range: Range,
+}
+
+/// Splits `text` into comments, string literals and code between them. Unlike
+/// `parse`, this never fails so that it can be used on incomplete text. The
+/// lexemes cover the whole text.
+pub fn lex(text: &str) -> Vec<Lexeme> {
+    let mut lexemes = Vec::new();
+    let mut code_start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        let next = chars.peek().map(|&(_, ch)| ch);
+        let kind = match ch {
+            '/' if next == Some('/') => {
+                while chars.next_if(|&(_, ch)| ch != '\n').is_some() {}
+                LexemeKind::LineComment
+            }
+            '/' if next == Some('*') => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, ch) in &mut chars {
+                    if prev == '*' && ch == '/' {
+                        break;
+                    }
+                    prev = ch;
+                }
+                LexemeKind::BlockComment
+            }
+            '"' => {
+                while let Some((_, ch)) = chars.next_if(|&(_, ch)| ch != '\n') {
+                    match ch {
+                        '\\' => {
+                            chars.next_if(|&(_, ch)| ch != '\n');
+                        }
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+                LexemeKind::String
+            }
+            _ => continue,
+        };
+        if code_start < start {
+            lexemes.push(Lexeme {
+                kind: LexemeKind::Code,
+                range: Range {
+                    start: code_start,
+                    end: start,
+                },
+            });
+        }
+        let end = chars.peek().map(|&(i, _)| i).unwrap_or(text.len());
+        lexemes.push(Lexeme {
+            kind: kind,
+            range: Range {
+                start: start,
+                end: end,
+            },
+        });
+        code_start = end;
+    }
+    if code_start < text.len() {
+        lexemes.push(Lexeme {
+            kind: LexemeKind::Code,
+            range: Range {
+                start: code_start,
+                end: text.len(),
+            },
+        });
+    }
+    lexemes
+}
+
+/// Returns the kind of the lexeme which text typed at `offset` belongs to.
+/// For example, `offset` right after a closing quote is in `Code`, but
+/// `offset` at the end of a line comment is in the comment.
+pub fn lexeme_kind_at(text: &str, offset: usize) -> LexemeKind {
+    let text = &text[..offset];
+    let last = match lex(text).pop() {
+        Some(lexeme) => lexeme,
+        None => return LexemeKind::Code,
+    };
+    let lexeme_text = &text[last.range.start..last.range.end];
+    let terminated = match last.kind {
+        LexemeKind::Code => return LexemeKind::Code,
+        LexemeKind::LineComment => false,
+        LexemeKind::BlockComment => lexeme_text.len() >= 4 && lexeme_text.ends_with("*/"),
+        LexemeKind::String => {
+            // The closing quote isn't escaped by an odd number of backslashes.
+            let content = &lexeme_text[1..];
+            content.strip_suffix('"').is_some_and(|content| {
+                let backslashes = content.len() - content.trim_end_matches('\\').len();
+                backslashes % 2 == 0
+            })
+        }
+    };
+    if terminated {
+        LexemeKind::Code
+    } else {
+        last.kind
+    }
+}
+
+/// Lexemes of a document which are cached per line so that only edited lines
+/// are lexed again. Block comments are the only lexemes which span lines, so
+/// a line can be lexed from whether it starts in a block comment.
+#[derive(Debug, Default)]
+pub struct LineLexer {
+    lines: Vec<LexedLine>,
+}
+
+#[derive(Debug)]
+struct LexedLine {
+    // Includes the line break.
+    text: String,
+    starts_in_comment: bool,
+    ends_in_comment: bool,
+    // Ranges are relative to the start of the line.
+    lexemes: Vec<Lexeme>,
+}
+
+impl LexedLine {
+    fn new(text: &str, starts_in_comment: bool) -> LexedLine {
+        let mut lexemes = Vec::new();
+        let mut rest = 0;
+        if starts_in_comment {
+            rest = match text.find("*/") {
+                Some(i) => i + 2,
+                None => text.len(),
+            };
+            if rest > 0 {
+                lexemes.push(Lexeme {
+                    kind: LexemeKind::BlockComment,
+                    range: Range {
+                        start: 0,
+                        end: rest,
+                    },
+                });
+            }
+            if !text[..rest].ends_with("*/") {
+                return LexedLine {
+                    text: text.to_owned(),
+                    starts_in_comment: starts_in_comment,
+                    ends_in_comment: true,
+                    lexemes: lexemes,
+                };
+            }
+        }
+
+        let rest_lexemes = lex(&text[rest..]);
+        let ends_in_comment = rest_lexemes.last().is_some_and(|last| {
+            let comment = &text[rest + last.range.start..rest + last.range.end];
+            last.kind == LexemeKind::BlockComment
+                && !(comment.len() >= 4 && comment.ends_with("*/"))
+        });
+        lexemes.extend(rest_lexemes.into_iter().map(|lexeme| Lexeme {
+            kind: lexeme.kind,
+            range: Range {
+                start: rest + lexeme.range.start,
+                end: rest + lexeme.range.end,
+            },
+        }));
+        LexedLine {
+            text: text.to_owned(),
+            starts_in_comment: starts_in_comment,
+            ends_in_comment: ends_in_comment,
+            lexemes: lexemes,
+        }
+    }
+}
+
+impl LineLexer {
+    /// Updates the cache to `text`. Lines which didn't change are reused
+    /// unless an edit above them opened or closed a block comment. Returns
+    /// the number of lines which were lexed.
+    pub fn update(&mut self, text: &str) -> usize {
+        let new_lines: Vec<&str> = text.split_inclusive('\n').collect();
+        let old_lines = std::mem::take(&mut self.lines);
+        let max_common = std::cmp::min(old_lines.len(), new_lines.len());
+        let prefix = old_lines
+            .iter()
+            .zip(&new_lines)
+            .take_while(|(old, new)| old.text == **new)
+            .count();
+        let suffix = old_lines[prefix..]
+            .iter()
+            .rev()
+            .zip(new_lines[prefix..].iter().rev())
+            .take(max_common - prefix)
+            .take_while(|(old, new)| old.text == **new)
+            .count();
+
+        let edited = old_lines.len() - prefix - suffix;
+        let mut lexed = 0;
+        let mut old_lines = old_lines.into_iter();
+        self.lines.extend(old_lines.by_ref().take(prefix));
+        let mut in_comment = self.lines.last().is_some_and(|line| line.ends_in_comment);
+        for new in &new_lines[prefix..new_lines.len() - suffix] {
+            let line = LexedLine::new(new, in_comment);
+            in_comment = line.ends_in_comment;
+            self.lines.push(line);
+            lexed += 1;
+        }
+        // Unchanged lines below the edit are lexed again until the block
+        // comment state at the start of the line matches.
+        for old in old_lines.skip(edited) {
+            let line = if old.starts_in_comment == in_comment {
+                old
+            } else {
+                lexed += 1;
+                LexedLine::new(&old.text, in_comment)
+            };
+            in_comment = line.ends_in_comment;
+            self.lines.push(line);
+        }
+        lexed
+    }
+
+    /// Returns lexemes of the text given to the last `update()`. Unlike
+    /// `lex`, lexemes are split at line breaks.
+    pub fn lexemes(&self) -> Vec<Lexeme> {
+        let mut lexemes = Vec::new();
+        let mut line_start = 0;
+        for line in &self.lines {
+            lexemes.extend(line.lexemes.iter().map(|lexeme| Lexeme {
+                kind: lexeme.kind,
+                range: Range {
+                    start: line_start + lexeme.range.start,
+                    end: line_start + lexeme.range.end,
+                },
+            }));
+            line_start += line.text.len();
+        }
+        lexemes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(LexemeKind, &str)> {
+        lex(text)
+            .into_iter()
+            .map(|lexeme| (lexeme.kind, &text[lexeme.range.start..lexeme.range.end]))
+            .collect()
+    }
+
+    #[test]
+    fn test_lex() {
+        assert_eq!(
+            vec![
+                (LexemeKind::Code, "import "),
+                (LexemeKind::String, "\"a/\\\"b.mojom\""),
+                (LexemeKind::Code, "; "),
+                (LexemeKind::LineComment, "// \"x\" /*"),
+                (LexemeKind::Code, "\nstruct "),
+                (LexemeKind::BlockComment, "/* a\n// b */"),
+                (LexemeKind::Code, " Foo {};"),
+            ],
+            kinds("import \"a/\\\"b.mojom\"; // \"x\" /*\nstruct /* a\n// b */ Foo {};")
+        );
+        // Unterminated string literals end at the line break.
+        assert_eq!(
+            vec![
+                (LexemeKind::String, "\"abc"),
+                (LexemeKind::Code, "\n"),
+                (LexemeKind::String, "\"\\"),
+                (LexemeKind::Code, "\nx"),
+            ],
+            kinds("\"abc\n\"\\\nx")
+        );
+        // Unterminated block comments extend to the end.
+        assert_eq!(
+            vec![
+                (LexemeKind::Code, "a "),
+                (LexemeKind::BlockComment, "/* b\nc */"),
+            ],
+            kinds("a /* b\nc */")
+        );
+        assert_eq!(vec![(LexemeKind::BlockComment, "/*/")], kinds("/*/"));
+        assert!(lex("").is_empty());
+    }
+
+    #[test]
+    fn test_lexeme_kind_at() {
+        let text = "a // b\n\"c\" /* d */ \"e\\\"";
+        assert_eq!(LexemeKind::Code, lexeme_kind_at(text, 0));
+        assert_eq!(LexemeKind::Code, lexeme_kind_at(text, 2));
+        assert_eq!(LexemeKind::LineComment, lexeme_kind_at(text, 4));
+        assert_eq!(LexemeKind::LineComment, lexeme_kind_at(text, 6));
+        assert_eq!(LexemeKind::Code, lexeme_kind_at(text, 7));
+        assert_eq!(LexemeKind::String, lexeme_kind_at(text, 8));
+        assert_eq!(LexemeKind::String, lexeme_kind_at(text, 9));
+        assert_eq!(LexemeKind::Code, lexeme_kind_at(text, 10));
+        assert_eq!(LexemeKind::BlockComment, lexeme_kind_at(text, 13));
+        assert_eq!(LexemeKind::BlockComment, lexeme_kind_at(text, 17));
+        assert_eq!(LexemeKind::Code, lexeme_kind_at(text, 18));
+        // The last quote is escaped.
+        assert_eq!(LexemeKind::String, lexeme_kind_at(text, text.len()));
+        assert_eq!(LexemeKind::Code, lexeme_kind_at("\"\\\\\"", 4));
+    }
+
+    // Returns the kind of each byte so that lexemes split at line breaks can
+    // be compared with `lex`.
+    fn byte_kinds(lexemes: &[Lexeme], len: usize) -> Vec<LexemeKind> {
+        let mut kinds = vec![LexemeKind::Code; len];
+        for lexeme in lexemes {
+            for kind in &mut kinds[lexeme.range.start..lexeme.range.end] {
+                *kind = lexeme.kind;
+            }
+        }
+        kinds
+    }
+
+    #[test]
+    fn test_line_lexer() {
+        let mut lexer = LineLexer::default();
+        let text = "a \"b\"\nc // d\ne /* f\ng */ h\ni\nj\n";
+        assert_eq!(6, lexer.update(text));
+        assert_eq!(
+            byte_kinds(&lex(text), text.len()),
+            byte_kinds(&lexer.lexemes(), text.len())
+        );
+        assert_eq!(0, lexer.update(text));
+
+        // Only the edited line is lexed again.
+        let text = "a \"b\"\nc // x\ne /* f\ng */ h\ni\nj\n";
+        assert_eq!(1, lexer.update(text));
+        assert_eq!(
+            byte_kinds(&lex(text), text.len()),
+            byte_kinds(&lexer.lexemes(), text.len())
+        );
+        let text = "a \"b\"\nc // x\nnew\ne /* f\ng */ h\ni\nj\n";
+        assert_eq!(1, lexer.update(text));
+        assert_eq!(
+            byte_kinds(&lex(text), text.len()),
+            byte_kinds(&lexer.lexemes(), text.len())
+        );
+
+        // Opening a block comment lexes following lines until it's closed.
+        let text = "a \"b\"\nc /* x\nnew\ne /* f\ng */ h\ni\nj\n";
+        assert_eq!(3, lexer.update(text));
+        assert_eq!(
+            byte_kinds(&lex(text), text.len()),
+            byte_kinds(&lexer.lexemes(), text.len())
+        );
+        // An unterminated block comment extends to the end.
+        let text = "a \"b\"\nc /* x\nnew\ne f\ng */ h\ni\nj\n";
+        assert_eq!(1, lexer.update(text));
+        let text = "a \"b\"\nc /* x\nnew\ne f\ng h\ni\nj\n";
+        assert_eq!(3, lexer.update(text));
+        assert_eq!(
+            byte_kinds(&lex(text), text.len()),
+            byte_kinds(&lexer.lexemes(), text.len())
+        );
+        assert_eq!(
+            Some(LexemeKind::BlockComment),
+            lexer.lexemes().last().map(|l| l.kind)
+        );
+
+        // Closing it lexes them again.
+        let text = "a \"b\"\nc /* x */\nnew\ne f\ng h\ni\nj\n";
+        assert_eq!(6, lexer.update(text));
+        assert_eq!(
+            byte_kinds(&lex(text), text.len()),
+            byte_kinds(&lexer.lexemes(), text.len())
+        );
+
+        assert_eq!(0, lexer.update(""));
+        assert!(lexer.lexemes().is_empty());
+    }
+}
@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod lexer;
 mod parser;
 mod syntax;
 mod traverse;
 mod typespec;
 
+pub use lexer::{lex, lexeme_kind_at, Lexeme, LexemeKind, LineLexer};
 pub use syntax::*;
 pub use traverse::{preorder, Traversal};
-pub use typespec::typespec;
+pub use typespec::{fixed_array_sizes, typespec, TypeName, TypeSpec};
@@ -22,6 +22,12 @@ pub struct Range {
     pub end: usize,
 }
 
+/// Returns the text in `range`, or an empty string when `range` is out of
+/// `text` or isn't on character boundaries.
+pub fn partial_text<'a>(text: &'a str, range: &Range) -> &'a str {
+    text.get(range.start..range.end).unwrap_or("")
+}
+
 impl<'a> From<Span<'a>> for Range {
     fn from(span: Span<'a>) -> Range {
         Range {
@@ -41,6 +47,45 @@ fn skip_attribute_list(pairs: &mut Pairs) {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: Range,
+    pub value: Option<Range>,
+}
+
+fn into_attribute(mut pairs: Pairs) -> Attribute {
+    let name = consume_as_range(&mut pairs);
+    let value = match pairs.next() {
+        Some(_equal) => Some(consume_as_range(&mut pairs)),
+        None => None,
+    };
+    Attribute {
+        name: name,
+        value: value,
+    }
+}
+
+/// Returns true when `attributes` contains an attribute named `name`.
+pub fn has_attribute(text: &str, attributes: &[Attribute], name: &str) -> bool {
+    attributes
+        .iter()
+        .any(|attribute| partial_text(text, &attribute.name) == name)
+}
+
+// Consumes attribute list if exists.
+fn consume_attribute_list(pairs: &mut Pairs) -> Vec<Attribute> {
+    let mut attributes = Vec::new();
+    if pairs.peek().unwrap().as_rule() != Rule::attribute_section {
+        return attributes;
+    }
+    for item in pairs.next().unwrap().into_inner() {
+        if item.as_rule() == Rule::attribute {
+            attributes.push(into_attribute(item.into_inner()));
+        }
+    }
+    attributes
+}
+
 fn consume_semicolon(pairs: &mut Pairs) {
     match pairs.next().unwrap().as_rule() {
         Rule::t_semicolon => (),
@@ -80,13 +125,14 @@ fn into_import(mut pairs: Pairs) -> Import {
 
 #[derive(Debug, PartialEq)]
 pub struct Const {
+    pub attributes: Vec<Attribute>,
     pub typ: Range,
     pub name: Range,
     pub value: Range,
 }
 
 fn into_const(mut pairs: Pairs) -> Const {
-    skip_attribute_list(&mut pairs);
+    let attributes = consume_attribute_list(&mut pairs);
     consume_token(Rule::t_const, &mut pairs);
     let pair = pairs.next().unwrap();
     let typ = pair.as_span().into();
@@ -95,6 +141,7 @@ fn into_const(mut pairs: Pairs) -> Const {
     let value = consume_as_range(&mut pairs);
     consume_semicolon(&mut pairs);
     Const {
+        attributes: attributes,
         typ: typ,
         name: name,
         value: value,
@@ -103,32 +150,44 @@ fn into_const(mut pairs: Pairs) -> Const {
 
 #[derive(Debug, PartialEq)]
 pub struct EnumValue {
+    pub attributes: Vec<Attribute>,
     pub name: Range,
+    /// Not allowed in valid mojom. See the grammar.
+    pub ordinal: Option<Range>,
     pub value: Option<Range>,
 }
 
 fn into_enum_value(mut pairs: Pairs) -> EnumValue {
-    skip_attribute_list(&mut pairs);
+    let attributes = consume_attribute_list(&mut pairs);
     let name = consume_as_range(&mut pairs);
+    let ordinal = match pairs.peek().map(|item| item.as_rule()) {
+        Some(Rule::ordinal_value) => pairs.next().map(|item| item.as_span().into()),
+        _ => None,
+    };
     // The next item should be t_equal when it's Some(item).
     if let Some(item) = pairs.next() {
         assert!(item.as_rule() == Rule::t_equal);
     }
     let value = pairs.next().map(|item| item.as_span().into());
     EnumValue {
+        attributes: attributes,
         name: name,
+        ordinal: ordinal,
         value: value,
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Enum {
+    /// The whole enum statement.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
     pub name: Range,
     pub values: Vec<EnumValue>,
 }
 
-fn into_enum(mut pairs: Pairs) -> Enum {
-    skip_attribute_list(&mut pairs);
+fn into_enum(range: Range, mut pairs: Pairs) -> Enum {
+    let attributes = consume_attribute_list(&mut pairs);
     let name = consume_as_range(&mut pairs);
     let mut values = Vec::new();
     for item in pairs {
@@ -151,6 +210,8 @@ fn into_enum(mut pairs: Pairs) -> Enum {
         }
     }
     Enum {
+        range: range,
+        attributes: attributes,
         name: name,
         values: values,
     }
@@ -158,17 +219,22 @@ fn into_enum(mut pairs: Pairs) -> Enum {
 
 #[derive(Debug, PartialEq)]
 pub struct StructField {
+    /// The whole field statement.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
     pub typ: Range,
     pub name: Range,
     pub ordinal: Option<Range>,
     pub default: Option<Range>,
 }
 
-fn into_struct_field(mut pairs: Pairs) -> StructField {
-    skip_attribute_list(&mut pairs);
+fn into_struct_field(range: Range, mut pairs: Pairs) -> StructField {
+    let attributes = consume_attribute_list(&mut pairs);
     let typ = consume_as_range(&mut pairs);
     let name = consume_as_range(&mut pairs);
     let mut res = StructField {
+        range: range,
+        attributes: attributes,
         typ: typ,
         name: name,
         ordinal: None,
@@ -198,6 +264,9 @@ pub enum StructBody {
 
 #[derive(Debug, PartialEq)]
 pub struct Struct {
+    /// The whole struct statement.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
     pub name: Range,
     pub members: Vec<StructBody>,
 }
@@ -213,8 +282,14 @@ fn into_struct_members(mut pairs: Pairs) -> Vec<StructBody> {
         let struct_item = item.into_inner().next().unwrap();
         let member = match struct_item.as_rule() {
             Rule::const_stmt => StructBody::Const(into_const(struct_item.into_inner())),
-            Rule::enum_stmt => StructBody::Enum(into_enum(struct_item.into_inner())),
-            Rule::struct_field => StructBody::Field(into_struct_field(struct_item.into_inner())),
+            Rule::enum_stmt => StructBody::Enum(into_enum(
+                struct_item.as_span().into(),
+                struct_item.into_inner(),
+            )),
+            Rule::struct_field => StructBody::Field(into_struct_field(
+                struct_item.as_span().into(),
+                struct_item.into_inner(),
+            )),
             _ => unreachable!(),
         };
         members.push(member);
@@ -222,14 +297,16 @@ fn into_struct_members(mut pairs: Pairs) -> Vec<StructBody> {
     members
 }
 
-fn into_struct(mut pairs: Pairs) -> Struct {
-    skip_attribute_list(&mut pairs);
+fn into_struct(range: Range, mut pairs: Pairs) -> Struct {
+    let attributes = consume_attribute_list(&mut pairs);
     consume_token(Rule::t_struct, &mut pairs);
     let name = consume_as_range(&mut pairs);
     let item = pairs.next().unwrap();
     match item.as_rule() {
         Rule::t_semicolon => {
             return Struct {
+                range: range,
+                attributes: attributes,
                 name: name,
                 members: Vec::new(),
             };
@@ -238,6 +315,8 @@ fn into_struct(mut pairs: Pairs) -> Struct {
             let members = into_struct_members(item.into_inner());
             consume_semicolon(&mut pairs);
             return Struct {
+                range: range,
+                attributes: attributes,
                 name: name,
                 members: members,
             };
@@ -248,13 +327,14 @@ fn into_struct(mut pairs: Pairs) -> Struct {
 
 #[derive(Debug, PartialEq)]
 pub struct UnionField {
+    pub attributes: Vec<Attribute>,
     pub typ: Range,
     pub name: Range,
     pub ordinal: Option<Range>,
 }
 
 fn into_union_field(mut pairs: Pairs) -> UnionField {
-    skip_attribute_list(&mut pairs);
+    let attributes = consume_attribute_list(&mut pairs);
     let typ = consume_as_range(&mut pairs);
     let name = consume_as_range(&mut pairs);
     let mut ordinal = None;
@@ -266,6 +346,7 @@ fn into_union_field(mut pairs: Pairs) -> UnionField {
         }
     }
     UnionField {
+        attributes: attributes,
         typ: typ,
         name: name,
         ordinal: ordinal,
@@ -274,12 +355,15 @@ fn into_union_field(mut pairs: Pairs) -> UnionField {
 
 #[derive(Debug, PartialEq)]
 pub struct Union {
+    /// The whole union statement.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
     pub name: Range,
     pub fields: Vec<UnionField>,
 }
 
-fn into_union(mut pairs: Pairs) -> Union {
-    skip_attribute_list(&mut pairs);
+fn into_union(range: Range, mut pairs: Pairs) -> Union {
+    let attributes = consume_attribute_list(&mut pairs);
     consume_token(Rule::t_union, &mut pairs);
     let name = consume_as_range(&mut pairs);
     consume_token(Rule::t_lbrace, &mut pairs);
@@ -295,6 +379,8 @@ fn into_union(mut pairs: Pairs) -> Union {
     }
     consume_semicolon(&mut pairs);
     Union {
+        range: range,
+        attributes: attributes,
         name: name,
         fields: fields,
     }
@@ -302,16 +388,19 @@ fn into_union(mut pairs: Pairs) -> Union {
 
 #[derive(Debug, PartialEq)]
 pub struct Parameter {
+    pub attributes: Vec<Attribute>,
     pub typ: Range,
     pub name: Range,
     pub ordinal: Option<Range>,
 }
 
 fn into_parameter(mut pairs: Pairs) -> Parameter {
+    let attributes = consume_attribute_list(&mut pairs);
     let typ = consume_as_range(&mut pairs);
     let name = consume_as_range(&mut pairs);
     let ordinal = pairs.next().map(|ord| ord.as_span().into());
     Parameter {
+        attributes: attributes,
         typ: typ,
         name: name,
         ordinal: ordinal,
@@ -346,14 +435,17 @@ fn into_response(mut pairs: Pairs) -> Response {
 
 #[derive(Debug, PartialEq)]
 pub struct Method {
+    /// The whole method statement.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
     pub name: Range,
     pub ordinal: Option<Range>,
     pub params: Vec<Parameter>,
     pub response: Option<Response>,
 }
 
-fn into_method(mut pairs: Pairs) -> Method {
-    skip_attribute_list(&mut pairs);
+fn into_method(range: Range, mut pairs: Pairs) -> Method {
+    let attributes = consume_attribute_list(&mut pairs);
     let name = consume_as_range(&mut pairs);
     let ordinal = match pairs.peek().unwrap().as_rule() {
         Rule::ordinal_value => pairs.next().map(|ord| ord.as_span().into()),
@@ -369,6 +461,8 @@ fn into_method(mut pairs: Pairs) -> Method {
         }
     }
     Method {
+        range: range,
+        attributes: attributes,
         name: name,
         ordinal: ordinal,
         params: params,
@@ -387,20 +481,27 @@ fn into_interface_member(mut pairs: Pairs) -> InterfaceMember {
     let member = pairs.next().unwrap();
     match member.as_rule() {
         Rule::const_stmt => InterfaceMember::Const(into_const(member.into_inner())),
-        Rule::enum_stmt => InterfaceMember::Enum(into_enum(member.into_inner())),
-        Rule::method_stmt => InterfaceMember::Method(into_method(member.into_inner())),
+        Rule::enum_stmt => {
+            InterfaceMember::Enum(into_enum(member.as_span().into(), member.into_inner()))
+        }
+        Rule::method_stmt => {
+            InterfaceMember::Method(into_method(member.as_span().into(), member.into_inner()))
+        }
         _ => unreachable!(),
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Interface {
+    /// The whole interface statement.
+    pub range: Range,
+    pub attributes: Vec<Attribute>,
     pub name: Range,
     pub members: Vec<InterfaceMember>,
 }
 
-fn into_interface(mut pairs: Pairs) -> Interface {
-    skip_attribute_list(&mut pairs);
+fn into_interface(range: Range, mut pairs: Pairs) -> Interface {
+    let attributes = consume_attribute_list(&mut pairs);
     consume_token(Rule::t_interface, &mut pairs);
     let name = consume_as_range(&mut pairs);
     consume_token(Rule::t_lbrace, &mut pairs);
@@ -419,6 +520,8 @@ fn into_interface(mut pairs: Pairs) -> Interface {
     }
     consume_semicolon(&mut pairs);
     Interface {
+        range: range,
+        attributes: attributes,
         name: name,
         members: members,
     }
@@ -437,13 +540,14 @@ pub enum Statement {
 
 fn into_statement(mut pairs: Pairs) -> Statement {
     let stmt = pairs.next().unwrap();
+    let range = stmt.as_span().into();
     match stmt.as_rule() {
         Rule::module_stmt => Statement::Module(into_module(stmt.into_inner())),
         Rule::import_stmt => Statement::Import(into_import(stmt.into_inner())),
-        Rule::interface => Statement::Interface(into_interface(stmt.into_inner())),
-        Rule::struct_stmt => Statement::Struct(into_struct(stmt.into_inner())),
-        Rule::union_stmt => Statement::Union(into_union(stmt.into_inner())),
-        Rule::enum_stmt => Statement::Enum(into_enum(stmt.into_inner())),
+        Rule::interface => Statement::Interface(into_interface(range, stmt.into_inner())),
+        Rule::struct_stmt => Statement::Struct(into_struct(range, stmt.into_inner())),
+        Rule::union_stmt => Statement::Union(into_union(range, stmt.into_inner())),
+        Rule::enum_stmt => Statement::Enum(into_enum(range, stmt.into_inner())),
         Rule::const_stmt => Statement::Const(into_const(stmt.into_inner())),
         _ => unreachable!(),
     }
@@ -482,21 +586,31 @@ pub struct SyntaxError<'a> {
     input: &'a str,
     pest_err: PestError,
     span: (usize, usize),
+    // Overrides the message of `pest_err` when set.
+    message: Option<String>,
 }
 
 impl<'a> SyntaxError<'a> {
     /// Returns `start` and `end` positions of the error.
     pub fn range(&self) -> (LineCol, LineCol) {
         let (start, end) = self.span;
-        let start = line_col(&self.input, start).unwrap();
-        let end = line_col(&self.input, end).unwrap();
+        let start = line_col(&self.input, start);
+        let end = line_col(&self.input, end);
         (start, end)
     }
+
+    /// Returns the byte offset where the error starts.
+    pub fn offset(&self) -> usize {
+        self.span.0
+    }
 }
 
 impl<'a> std::fmt::Display for SyntaxError<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.pest_err)
+        match self.message {
+            Some(ref message) => write!(f, "{}", message),
+            None => write!(f, "{}", self.pest_err),
+        }
     }
 }
 
@@ -506,7 +620,7 @@ fn find_token_end_position(input: &str, start: usize) -> usize {
         if ch == ' ' || ch == '\r' || ch == '\n' {
             break;
         }
-        end += 1;
+        end += ch.len_utf8();
     }
 
     if end > input.len() {
@@ -515,6 +629,44 @@ fn find_token_end_position(input: &str, start: usize) -> usize {
     end
 }
 
+/// Returns true when `ch` can appear in an identifier. Mojom identifiers
+/// consist of ASCII letters, digits and underscores.
+pub fn is_identifier_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+// Returns true when `ch` can't appear in mojom outside comments and string
+// literals.
+fn is_illegal_char(ch: char) -> bool {
+    !is_identifier_char(ch) && !ch.is_ascii_whitespace() && !"{}()[]<>;,.=@?&+-\"/*:".contains(ch)
+}
+
+// Identifiers consist of ASCII letters, digits and underscores. Returns the
+// span and the message of an error when the character at `offset` is an
+// illegal character in an identifier.
+fn check_identifier_char(input: &str, offset: usize) -> Option<((usize, usize), String)> {
+    let ch = input[offset..].chars().next()?;
+    if !is_illegal_char(ch) {
+        return None;
+    }
+    let start = input[..offset]
+        .char_indices()
+        .rev()
+        .find(|&(_, ch)| !is_identifier_char(ch) && !is_illegal_char(ch))
+        .map(|(i, ch)| i + ch.len_utf8())
+        .unwrap_or(0);
+    let end = input[offset..]
+        .find(|ch| !is_identifier_char(ch) && !is_illegal_char(ch))
+        .map(|i| offset + i)
+        .unwrap_or(input.len());
+    let message = format!(
+        "Illegal character '{}' in identifier `{}`. Identifiers can contain only ASCII letters, digits and underscores.",
+        ch,
+        &input[start..end]
+    );
+    Some(((offset, offset + ch.len_utf8()), message))
+}
+
 impl<'a> SyntaxError<'a> {
     fn new(input: &str, err: PestError) -> SyntaxError {
         let span = match &err.location {
@@ -524,10 +676,15 @@ impl<'a> SyntaxError<'a> {
             }
             pest::error::InputLocation::Span((start, end)) => (*start, *end),
         };
+        let (span, message) = match check_identifier_char(input, span.0) {
+            Some((span, message)) => (span, Some(message)),
+            None => (span, None),
+        };
         SyntaxError {
             input: input,
             pest_err: err,
             span: span,
+            message: message,
         }
     }
 }
@@ -578,14 +735,18 @@ pub fn parse(input: &str) -> Result<MojomFile, SyntaxError> {
     Ok(mojom)
 }
 
-/// Converts `offset` to LineCol in `text`.
-pub fn line_col(text: &str, offset: usize) -> Option<LineCol> {
-    Position::new(text, offset)
-        .map(|p| p.line_col())
-        .map(|(line, col)| LineCol {
-            line: line - 1,
-            col: col - 1,
-        })
+/// Converts `offset` to LineCol in `text`. Offsets past the end of `text`
+/// are clamped to the end, and offsets inside a character to its start.
+pub fn line_col(text: &str, offset: usize) -> LineCol {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let (line, col) = Position::new(text, offset).unwrap().line_col();
+    LineCol {
+        line: line - 1,
+        col: col - 1,
+    }
 }
 
 #[cfg(test)]
@@ -767,7 +928,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_enum(parsed.into_inner());
+        let stmt = into_enum(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyEnum", partial_text(&input, &stmt.name));
         let values = &stmt.values;
         assert_eq!(3, values.len());
@@ -780,12 +941,30 @@ mod tests {
             partial_text(&input, values[2].value.as_ref().unwrap())
         );
 
+        let input = "enum MyEnum { kOne@0, kTwo@1=2 };";
+        let parsed = MojomParser::parse(Rule::enum_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_enum(parsed.as_span().into(), parsed.into_inner());
+        let values = &stmt.values;
+        assert_eq!(2, values.len());
+        assert_eq!(
+            "@0",
+            partial_text(&input, values[0].ordinal.as_ref().unwrap())
+        );
+        assert_eq!(
+            "@1",
+            partial_text(&input, values[1].ordinal.as_ref().unwrap())
+        );
+        assert_eq!("2", partial_text(&input, values[1].value.as_ref().unwrap()));
+
         let input = "enum MyEnum {};";
         let parsed = MojomParser::parse(Rule::enum_stmt, &input)
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_enum(parsed.into_inner());
+        let stmt = into_enum(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyEnum", partial_text(&input, &stmt.name));
         assert_eq!(0, stmt.values.len());
 
@@ -794,9 +973,25 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_enum(parsed.into_inner());
+        let stmt = into_enum(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyEnum", partial_text(&input, &stmt.name));
         assert_eq!(0, stmt.values.len());
+        assert_eq!("Native", partial_text(&input, &stmt.attributes[0].name));
+
+        let input = "[Extensible] enum MyEnum { [Default] kOne, kTwo };";
+        let parsed = MojomParser::parse(Rule::enum_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_enum(parsed.as_span().into(), parsed.into_inner());
+        assert_eq!("Extensible", partial_text(&input, &stmt.attributes[0].name));
+        let values = &stmt.values;
+        assert_eq!(1, values[0].attributes.len());
+        assert_eq!(
+            "Default",
+            partial_text(&input, &values[0].attributes[0].name)
+        );
+        assert!(values[1].attributes.is_empty());
     }
 
     #[test]
@@ -806,7 +1001,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_method(parsed.into_inner());
+        let stmt = into_method(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyMethod", partial_text(&input, &stmt.name));
         let params = &stmt.params;
         assert_eq!(2, params.len());
@@ -824,7 +1019,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_method(parsed.into_inner());
+        let stmt = into_method(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyMethod2", partial_text(&input, &stmt.name));
         assert_eq!(0, stmt.params.len());
         assert!(stmt.response.is_none());
@@ -834,7 +1029,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_method(parsed.into_inner());
+        let stmt = into_method(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyMethod3", partial_text(&input, &stmt.name));
         assert_eq!(1, stmt.params.len());
         let params = &stmt.params;
@@ -856,7 +1051,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_struct(parsed.into_inner());
+        let stmt = into_struct(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyStruct", partial_text(&input, &stmt.name));
         let members = &stmt.members;
         assert_eq!(4, members.len());
@@ -890,9 +1085,12 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_struct(parsed.into_inner());
+        let stmt = into_struct(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyStruct", partial_text(&input, &stmt.name));
         assert_eq!(0, stmt.members.len());
+        assert_eq!(1, stmt.attributes.len());
+        assert_eq!("Native", partial_text(&input, &stmt.attributes[0].name));
+        assert!(stmt.attributes[0].value.is_none());
     }
 
     #[test]
@@ -905,7 +1103,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let intr = into_interface(parsed.into_inner());
+        let intr = into_interface(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyInterface", partial_text(&input, &intr.name));
         let members = &intr.members;
         assert_eq!(2, members.len());
@@ -923,6 +1121,47 @@ mod tests {
         assert_eq!("MyEnum", partial_text(&input, &member.name));
     }
 
+    #[test]
+    fn test_attributes() {
+        let input = "[Stable, Uuid=\"a-b-c\", MinVersion=1] interface MyInterface {};";
+        let parsed = MojomParser::parse(Rule::interface, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let intr = into_interface(parsed.as_span().into(), parsed.into_inner());
+        let attributes = &intr.attributes;
+        assert_eq!(3, attributes.len());
+        assert_eq!("Stable", partial_text(&input, &attributes[0].name));
+        assert!(attributes[0].value.is_none());
+        assert_eq!("Uuid", partial_text(&input, &attributes[1].name));
+        let value = attributes[1].value.as_ref().unwrap();
+        assert_eq!("\"a-b-c\"", partial_text(&input, value));
+        assert_eq!("MinVersion", partial_text(&input, &attributes[2].name));
+        let value = attributes[2].value.as_ref().unwrap();
+        assert_eq!("1", partial_text(&input, value));
+
+        let input = "[] union MyUnion { int8 a; };";
+        let parsed = MojomParser::parse(Rule::union_stmt, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let stmt = into_union(parsed.as_span().into(), parsed.into_inner());
+        assert!(stmt.attributes.is_empty());
+
+        let input = "[MinVersion=2] int32 my_field;";
+        let parsed = MojomParser::parse(Rule::struct_field, &input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let field = into_struct_field(parsed.as_span().into(), parsed.into_inner());
+        assert_eq!(1, field.attributes.len());
+        assert_eq!(
+            "MinVersion",
+            partial_text(&input, &field.attributes[0].name)
+        );
+        assert_eq!("my_field", partial_text(&input, &field.name));
+    }
+
     #[test]
     fn test_union_stmt() {
         let input = "union MyUnion {
@@ -934,7 +1173,7 @@ mod tests {
             .unwrap()
             .next()
             .unwrap();
-        let stmt = into_union(parsed.into_inner());
+        let stmt = into_union(parsed.as_span().into(), parsed.into_inner());
         assert_eq!("MyUnion", partial_text(&input, &stmt.name));
         let fields = &stmt.fields;
         assert_eq!(3, fields.len());
@@ -995,4 +1234,49 @@ mod tests {
         let res = parse(input).unwrap();
         assert_eq!(16, res.stmts.len());
     }
+
+    #[test]
+    fn test_identifier_chars() {
+        assert!(parse("struct My_Struct2 { int32 _field; };").is_ok());
+
+        let input = "struct S { int32 my$field; };";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            "Illegal character '$' in identifier `my$field`. \
+             Identifiers can contain only ASCII letters, digits and underscores.",
+            err.to_string()
+        );
+        let (start, end) = err.range();
+        assert_eq!((0, 19), (start.line, start.col));
+        assert_eq!((0, 20), (end.line, end.col));
+
+        // Identifiers are ASCII only.
+        let input = "struct Straße {};";
+        let err = parse(input).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Illegal character 'ß' in identifier `Straße`."));
+        let (start, end) = err.range();
+        assert_eq!((0, 11), (start.line, start.col));
+        assert_eq!((0, 12), (end.line, end.col));
+
+        // Other errors are reported as is.
+        let err = parse("struct S {").unwrap_err();
+        assert!(!err.to_string().starts_with("Illegal character"));
+    }
+
+    #[test]
+    fn test_line_col() {
+        let text = "ab\nü\n";
+        let pos = line_col(text, 3);
+        assert_eq!((1, 0), (pos.line, pos.col));
+        // In the middle of `ü`.
+        let pos = line_col(text, 4);
+        assert_eq!((1, 0), (pos.line, pos.col));
+        // Out of range offsets are clamped to the end.
+        let pos = line_col(text, 100);
+        assert_eq!((2, 0), (pos.line, pos.col));
+        let pos = line_col("ab", 100);
+        assert_eq!((0, 2), (pos.line, pos.col));
+    }
 }
@@ -15,6 +15,7 @@
 use pest::Parser;
 
 use super::parser::{consume_token, MojomParser, Pairs, Rule};
+use super::syntax::Range;
 
 // TODO: Support pending_receiver<T> and pending_remote<T>.
 #[derive(Debug, PartialEq)]
@@ -49,6 +50,11 @@ fn into_basic_name(mut pairs: Pairs) -> TypeName {
             TypeName::Associated(ident)
         }
         Rule::identifier => TypeName::BasicTypeName(item.as_str().to_owned()),
+        // e.g. `pending_remote<Foo>` as a whole.
+        Rule::remote_type
+        | Rule::receiver_type
+        | Rule::associated_remote_type
+        | Rule::associated_receiver_type => TypeName::BasicTypeName(item.as_str().to_owned()),
         _ => unreachable!(),
     }
 }
@@ -66,7 +72,9 @@ fn into_fixed_array(mut pairs: Pairs) -> TypeName {
     consume_token(Rule::t_langlebracket, &mut pairs);
     let type_spec = into_type_spec(pairs.next().unwrap().into_inner());
     consume_token(Rule::t_comma, &mut pairs);
-    let size = pairs.next().unwrap().as_str().parse::<u64>().unwrap();
+    // Invalid sizes (e.g. negative values) are represented as 0. These are
+    // reported by semantic checks.
+    let size = pairs.next().unwrap().as_str().parse::<u64>().unwrap_or(0);
     consume_token(Rule::t_ranglebracket, &mut pairs);
     TypeName::FixedArray(Box::new(type_spec), size)
 }
@@ -136,6 +144,18 @@ pub fn typespec(input: &str) -> anyhow::Result<TypeSpec> {
     Ok(into_type_spec(inner))
 }
 
+/// Returns the ranges of fixed array sizes in `input`. e.g. The range of `16`
+/// in `array<uint8, 16>`.
+pub fn fixed_array_sizes(input: &str) -> anyhow::Result<Vec<Range>> {
+    let pairs = MojomParser::parse(Rule::type_spec, input)?;
+    let sizes = pairs
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::fixed_array_size)
+        .map(|pair| pair.as_span().into())
+        .collect();
+    Ok(sizes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,6 +191,18 @@ mod tests {
             _ => panic!("Expected handle"),
         };
 
+        let res = typespec("pending_remote<Foo>").unwrap();
+        assert_eq!(
+            TypeName::BasicTypeName("pending_remote<Foo>".to_owned()),
+            res.type_name
+        );
+        let res = typespec("pending_associated_receiver<Foo>?").unwrap();
+        assert!(res.is_nullable);
+        assert_eq!(
+            TypeName::BasicTypeName("pending_associated_receiver<Foo>".to_owned()),
+            res.type_name
+        );
+
         let res = typespec("array<uint8>").unwrap();
         match res.type_name {
             TypeName::Array(inner_type) => {
@@ -234,8 +266,26 @@ mod tests {
             _ => panic!("Expected associated type"),
         };
 
+        let res = typespec("array<int32, -1>").unwrap();
+        match res.type_name {
+            TypeName::FixedArray(_, size) => assert_eq!(0, size),
+            _ => panic!("Expected fixed array"),
+        };
+
         let input = "array<int32>?";
         let res = typespec(&input).unwrap();
         assert!(res.is_nullable);
     }
+
+    #[test]
+    fn test_fixed_array_sizes() {
+        let input = "map<string, array<array<uint8, 4>, 16>>";
+        let sizes = fixed_array_sizes(input).unwrap();
+        assert_eq!(2, sizes.len());
+        assert_eq!("4", &input[sizes[0].start..sizes[0].end]);
+        assert_eq!("16", &input[sizes[1].start..sizes[1].end]);
+
+        let sizes = fixed_array_sizes("array<int32>").unwrap();
+        assert!(sizes.is_empty());
+    }
 }